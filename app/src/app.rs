@@ -58,6 +58,14 @@ impl AsgardApp {
         ));
         storage.lock().await.initialize().await?;
 
+        // Apply encryption at rest; existing data is converted in the background
+        let reencode = storage.lock().await.set_encryption(config.encryption_at_rest());
+        tokio::spawn(async move {
+            if let Ok(Err(e)) = reencode.await {
+                error!("Failed to re-encode stored messages: {}", e);
+            }
+        });
+
         // Initialize search index
         // let search_index = Arc::new(Mutex::new(
         //     TantivySearchIndex::new(config.search_index_dir())?
@@ -0,0 +1,290 @@
+//! Attachment strip shown below a message body
+
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Button, FileChooserAction, FileChooserNative, FlowBox, Image, Label, Orientation, ResponseType, SelectionMode, Window};
+use asgard_core::attachments::{safe_filename, save_to_dir};
+use asgard_core::message::Attachment;
+use asgard_core::storage::usage::format_bytes;
+use asgard_core::sync::SyncManager;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Edge length of attachment icons and thumbnails
+const THUMBNAIL_SIZE: i32 = 48;
+/// Largest attachment downloaded just to show a thumbnail
+const MAX_PREVIEW_SIZE: usize = 10 * 1024 * 1024;
+/// Directory below the system temp dir for opened attachments and previews
+const TEMP_DIR: &str = "asgard-attachments";
+
+/// Attachments of a message with open, save and save-all actions
+pub struct AttachmentStrip {
+    /// Main widget container
+    pub widget: GtkBox,
+}
+
+impl AttachmentStrip {
+    /// Create a strip for `attachments`, downloading content through
+    /// `sync_manager` when it is not stored with the message
+    pub fn new(attachments: &[Attachment], sync_manager: Option<Arc<Mutex<SyncManager>>>) -> Self {
+        let widget = GtkBox::new(Orientation::Vertical, 6);
+        widget.add_css_class("attachment-strip");
+
+        // Summary | spacer | Save All
+        let total: usize = attachments.iter().map(|a| a.size).sum();
+        let noun = if attachments.len() == 1 { "attachment" } else { "attachments" };
+        let summary = Label::new(Some(&format!("{} {} ({})", attachments.len(), noun, format_bytes(total as u64))));
+        summary.add_css_class("attachment-summary");
+
+        let spacer = GtkBox::new(Orientation::Horizontal, 0);
+        spacer.set_hexpand(true);
+
+        let save_all = Button::with_label("Save All");
+        save_all.add_css_class("flat");
+        {
+            let attachments = attachments.to_vec();
+            let sync_manager = sync_manager.clone();
+            save_all.connect_clicked(move |button| {
+                Self::save_all(button.upcast_ref(), attachments.clone(), sync_manager.clone());
+            });
+        }
+
+        let header = GtkBox::new(Orientation::Horizontal, 8);
+        header.append(&summary);
+        header.append(&spacer);
+        header.append(&save_all);
+
+        let items = FlowBox::new();
+        items.set_selection_mode(SelectionMode::None);
+        items.set_max_children_per_line(4);
+        items.set_column_spacing(8);
+        items.set_row_spacing(8);
+        for attachment in attachments {
+            items.insert(&Self::create_item(attachment, &sync_manager), -1);
+        }
+
+        widget.append(&header);
+        widget.append(&items);
+
+        Self { widget }
+    }
+
+    /// Icon or thumbnail, name, size and actions of one attachment
+    fn create_item(attachment: &Attachment, sync_manager: &Option<Arc<Mutex<SyncManager>>>) -> GtkBox {
+        let item = GtkBox::new(Orientation::Horizontal, 8);
+        item.add_css_class("attachment-item");
+        item.set_tooltip_text(Some(&format!("{} ({})", attachment.filename, attachment.mime_type)));
+
+        let content_type = gtk4::gio::content_type_from_mime_type(&attachment.mime_type)
+            .unwrap_or_else(|| "application/octet-stream".into());
+        let image = Image::from_gicon(&gtk4::gio::content_type_get_icon(&content_type));
+        image.set_pixel_size(THUMBNAIL_SIZE);
+        image.add_css_class("attachment-thumbnail");
+        if attachment.size <= MAX_PREVIEW_SIZE && (attachment.is_image() || attachment.is_pdf()) {
+            Self::load_thumbnail(&image, attachment, sync_manager);
+        }
+
+        let name = Label::builder()
+            .label(&attachment.filename)
+            .xalign(0.0)
+            .max_width_chars(24)
+            .ellipsize(gtk4::pango::EllipsizeMode::Middle)
+            .build();
+        name.add_css_class("attachment-name");
+
+        let size = Label::builder()
+            .label(&format_bytes(attachment.size as u64))
+            .xalign(0.0)
+            .build();
+        size.add_css_class("attachment-size");
+
+        let text = GtkBox::new(Orientation::Vertical, 2);
+        text.set_hexpand(true);
+        text.set_valign(gtk4::Align::Center);
+        text.append(&name);
+        text.append(&size);
+
+        let open = Button::from_icon_name("document-open-symbolic");
+        open.add_css_class("flat");
+        open.set_tooltip_text(Some("Open"));
+        open.set_valign(gtk4::Align::Center);
+        {
+            let attachment = attachment.clone();
+            let sync_manager = sync_manager.clone();
+            open.connect_clicked(move |_| {
+                let filename = attachment.filename.clone();
+                let dir = std::env::temp_dir().join(TEMP_DIR).join(attachment.id.to_string());
+                Self::load(&attachment, &sync_manager, move |content| {
+                    match save_over(&dir, &filename, &content) {
+                        Ok(path) => {
+                            if let Err(e) = open::that(&path) {
+                                tracing::warn!("Failed to open {}: {}", path.display(), e);
+                            }
+                        }
+                        Err(e) => tracing::warn!("Failed to write {}: {}", filename, e),
+                    }
+                });
+            });
+        }
+
+        let save = Button::from_icon_name("document-save-symbolic");
+        save.add_css_class("flat");
+        save.set_tooltip_text(Some("Save"));
+        save.set_valign(gtk4::Align::Center);
+        {
+            let attachment = attachment.clone();
+            let sync_manager = sync_manager.clone();
+            save.connect_clicked(move |button| {
+                let chooser = FileChooserNative::new(
+                    Some("Save Attachment"),
+                    button.root().and_downcast::<Window>().as_ref(),
+                    FileChooserAction::Save,
+                    Some("_Save"),
+                    Some("_Cancel"),
+                );
+                chooser.set_current_name(&safe_filename(&attachment.filename));
+
+                let attachment = attachment.clone();
+                let sync_manager = sync_manager.clone();
+                run_chooser(chooser, move |path| {
+                    Self::load(&attachment, &sync_manager, move |content| {
+                        if let Err(e) = std::fs::write(&path, content) {
+                            tracing::warn!("Failed to save {}: {}", path.display(), e);
+                        }
+                    });
+                });
+            });
+        }
+
+        item.append(&image);
+        item.append(&text);
+        item.append(&open);
+        item.append(&save);
+        item
+    }
+
+    /// Ask for a folder and save every attachment into it
+    fn save_all(widget: &gtk4::Widget, attachments: Vec<Attachment>, sync_manager: Option<Arc<Mutex<SyncManager>>>) {
+        let chooser = FileChooserNative::new(
+            Some("Save All Attachments"),
+            widget.root().and_downcast::<Window>().as_ref(),
+            FileChooserAction::SelectFolder,
+            Some("_Save"),
+            Some("_Cancel"),
+        );
+
+        run_chooser(chooser, move |dir| {
+            for attachment in &attachments {
+                let dir = dir.clone();
+                let filename = attachment.filename.clone();
+                Self::load(attachment, &sync_manager, move |content| {
+                    if let Err(e) = save_to_dir(&dir, &filename, &content) {
+                        tracing::warn!("Failed to save {}: {}", filename, e);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Replace the icon with a preview of an image or the first PDF page
+    fn load_thumbnail(image: &Image, attachment: &Attachment, sync_manager: &Option<Arc<Mutex<SyncManager>>>) {
+        let image = image.clone();
+        if attachment.is_image() {
+            Self::load(attachment, sync_manager, move |content| {
+                match gtk4::gdk::Texture::from_bytes(&gtk4::glib::Bytes::from_owned(content)) {
+                    Ok(texture) => image.set_from_paintable(Some(&texture)),
+                    Err(e) => tracing::debug!("No thumbnail for image: {}", e),
+                }
+            });
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(TEMP_DIR).join(attachment.id.to_string());
+        Self::load(attachment, sync_manager, move |content| {
+            gtk4::glib::MainContext::default().spawn_local(async move {
+                let result = tokio::spawn(async move { render_pdf_page(&dir, &content).await }).await;
+                match result {
+                    Ok(Ok(path)) => match gtk4::gdk::Texture::from_filename(&path) {
+                        Ok(texture) => image.set_from_paintable(Some(&texture)),
+                        Err(e) => tracing::debug!("No thumbnail for PDF: {}", e),
+                    },
+                    Ok(Err(e)) => tracing::debug!("No thumbnail for PDF: {}", e),
+                    Err(e) => tracing::warn!("PDF thumbnail task failed: {}", e),
+                }
+            });
+        });
+    }
+
+    /// Get the content of `attachment` and hand it to `done` on the main loop
+    fn load(attachment: &Attachment, sync_manager: &Option<Arc<Mutex<SyncManager>>>, done: impl FnOnce(Vec<u8>) + 'static) {
+        if let Some(content) = attachment.content.as_ref().filter(|content| attachment.matches_hash(content)) {
+            done(content.clone());
+            return;
+        }
+        let Some(sync_manager) = sync_manager.clone() else {
+            tracing::warn!("Attachment {} is not available offline", attachment.filename);
+            return;
+        };
+
+        let attachment = attachment.clone();
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let filename = attachment.filename.clone();
+            let result = tokio::spawn(async move {
+                let sync_manager = sync_manager.lock().await;
+                sync_manager.attachment_content(&attachment).await
+            }).await;
+
+            match result {
+                Ok(Ok(content)) => done(content),
+                Ok(Err(e)) => tracing::warn!("Failed to download {}: {}", filename, e),
+                Err(e) => tracing::warn!("Attachment download task failed: {}", e),
+            }
+        });
+    }
+}
+
+/// Show a native file chooser and call `on_accept` with the chosen path
+fn run_chooser(chooser: FileChooserNative, on_accept: impl Fn(PathBuf) + 'static) {
+    // Native dialogs are not kept alive by GTK, so hold on to it until it
+    // answers
+    let keep_alive = Rc::new(RefCell::new(Some(chooser.clone())));
+    chooser.connect_response(move |chooser, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                on_accept(path);
+            }
+        }
+        keep_alive.borrow_mut().take();
+    });
+    chooser.show();
+}
+
+/// Write `content` to `dir`, replacing an earlier copy opened from there
+fn save_over(dir: &Path, filename: &str, content: &[u8]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(safe_filename(filename));
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Render the first page of a PDF to a PNG next to it with `pdftoppm`
+async fn render_pdf_page(dir: &Path, content: &[u8]) -> std::io::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let input = dir.join("preview.pdf");
+    tokio::fs::write(&input, content).await?;
+
+    let prefix = dir.join("preview");
+    let status = tokio::process::Command::new("pdftoppm")
+        .args(["-png", "-f", "1", "-l", "1", "-singlefile", "-scale-to"])
+        .arg((THUMBNAIL_SIZE * 2).to_string())
+        .arg(&input)
+        .arg(&prefix)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("pdftoppm exited with {}", status)));
+    }
+    Ok(prefix.with_extension("png"))
+}
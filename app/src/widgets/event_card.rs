@@ -0,0 +1,318 @@
+//! Meeting invitation card shown above a message body
+
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Button, Label, Orientation, ToggleButton};
+use asgard_core::ical::{Calendar, CalendarChange, Event, EventTime, Method, PartStat, Participant};
+use asgard_core::message::Message;
+use asgard_core::sync::SyncManager;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Attendees listed before the rest is summarized
+const MAX_ATTENDEES: usize = 8;
+
+/// When, where, organizer and attendees of a meeting, with Accept,
+/// Tentative and Decline for invitations and a button adding it to the
+/// account's calendar
+pub struct EventCard {
+    /// Main widget container
+    pub widget: GtkBox,
+}
+
+impl EventCard {
+    /// Card for the calendar data in `message`, if it has any
+    ///
+    /// A calendar attachment that sync left on the server is downloaded
+    /// through `sync_manager` and the card filled in when it arrives.
+    pub fn for_message(message: &Message, sync_manager: Option<Arc<Mutex<SyncManager>>>) -> Option<Self> {
+        let widget = GtkBox::new(Orientation::Vertical, 6);
+        widget.add_css_class("event-card");
+        let card = Self { widget };
+        let recipients: Vec<String> = message.recipient_emails().into_iter().map(str::to_string).collect();
+
+        if let Some(content) = message.calendar_content() {
+            match Calendar::parse(&String::from_utf8_lossy(content)) {
+                Ok(calendar) => card.fill(&calendar, message.id, &recipients, sync_manager),
+                Err(e) => {
+                    tracing::warn!("Failed to parse calendar in message {}: {}", message.id, e);
+                    return None;
+                }
+            }
+            return Some(card);
+        }
+
+        if !message.attachments.iter().any(|a| a.is_calendar()) {
+            return None;
+        }
+        let sync_manager = sync_manager?;
+        let message_id = message.id;
+        let widget = card.widget.clone();
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let loader = sync_manager.clone();
+            let result = tokio::spawn(async move {
+                let sync_manager = loader.lock().await;
+                sync_manager.message_calendar(message_id).await
+            }).await;
+
+            match result {
+                Ok(Ok(Some(calendar))) => Self { widget }.fill(&calendar, message_id, &recipients, Some(sync_manager)),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to load calendar of message {}: {}", message_id, e),
+                Err(e) => tracing::warn!("Calendar download task failed: {}", e),
+            }
+        });
+        Some(card)
+    }
+
+    /// Show the main event of `calendar`
+    fn fill(&self, calendar: &Calendar, message_id: Uuid, recipients: &[String], sync_manager: Option<Arc<Mutex<SyncManager>>>) {
+        let Some(event) = calendar.event() else {
+            self.widget.set_visible(false);
+            return;
+        };
+
+        let cancelled = calendar.method == Some(Method::Cancel) || event.is_cancelled();
+        let kind = match &calendar.method {
+            _ if cancelled => "Cancelled event",
+            Some(Method::Request) if event.sequence > 0 => "Updated invitation",
+            Some(Method::Request) => "Invitation",
+            Some(Method::Reply) => "Reply",
+            _ => "Event",
+        };
+        let kind = Label::builder().label(kind).xalign(0.0).build();
+        kind.add_css_class("event-kind");
+
+        let title = Label::builder()
+            .label(event.title())
+            .xalign(0.0)
+            .wrap(true)
+            .build();
+        title.add_css_class("event-title");
+        if cancelled {
+            title.add_css_class("event-cancelled");
+        }
+
+        self.widget.append(&kind);
+        self.widget.append(&title);
+
+        if let Some(when) = format_when(event) {
+            self.widget.append(&detail("When", &when));
+        }
+        if let Some(rule) = &event.recurrence {
+            self.widget.append(&detail("Repeats", &rule.describe()));
+        }
+        if let Some(location) = &event.location {
+            self.widget.append(&detail("Where", location));
+        }
+        if let Some(organizer) = &event.organizer {
+            self.widget.append(&detail("Organizer", &describe_participant(organizer, false)));
+        }
+        if !event.attendees.is_empty() {
+            let mut names: Vec<String> = event.attendees.iter()
+                .take(MAX_ATTENDEES)
+                .map(|attendee| describe_participant(attendee, calendar.method != Some(Method::Reply)))
+                .collect();
+            if event.attendees.len() > MAX_ATTENDEES {
+                names.push(format!("and {} more", event.attendees.len() - MAX_ATTENDEES));
+            }
+            self.widget.append(&detail("Attendees", &names.join("\n")));
+        }
+
+        if calendar.method == Some(Method::Reply) {
+            for attendee in &event.attendees {
+                let answer = Label::builder()
+                    .label(format!("{} has answered: {}", attendee.display_name(), attendee.status.label()))
+                    .xalign(0.0)
+                    .build();
+                answer.add_css_class("event-answer");
+                self.widget.append(&answer);
+            }
+        }
+
+        let Some(sync_manager) = sync_manager else {
+            return;
+        };
+        if calendar.method == Some(Method::Request) && !cancelled {
+            let own = event.attendees.iter()
+                .find(|attendee| recipients.iter().any(|email| attendee.is(email)))
+                .map(|attendee| attendee.status);
+            self.widget.append(&Self::create_rsvp(message_id, own, sync_manager.clone()));
+        }
+        if calendar.method != Some(Method::Reply) {
+            self.widget.append(&Self::create_add_button(message_id, calendar.method == Some(Method::Cancel), sync_manager));
+        }
+    }
+
+    /// Button writing the event, or its cancellation, to the calendar set
+    /// in the account
+    fn create_add_button(message_id: Uuid, cancellation: bool, sync_manager: Arc<Mutex<SyncManager>>) -> Button {
+        let button = Button::with_label(if cancellation { "Remove from calendar" } else { "Add to calendar" });
+        button.set_halign(gtk4::Align::Start);
+        button.add_css_class("event-add");
+
+        button.connect_clicked(move |button| {
+            let button = button.clone();
+            let sync_manager = sync_manager.clone();
+            button.set_sensitive(false);
+
+            gtk4::glib::MainContext::default().spawn_local(async move {
+                let result = tokio::spawn(async move {
+                    let sync_manager = sync_manager.lock().await;
+                    sync_manager.add_to_calendar(message_id).await
+                }).await;
+
+                match result {
+                    Ok(Ok(change)) => {
+                        button.set_label(match change {
+                            CalendarChange::Added => "Added to calendar",
+                            CalendarChange::Updated => "Calendar updated",
+                            CalendarChange::Removed => "Removed from calendar",
+                            CalendarChange::Unchanged => "Calendar is up to date",
+                        });
+                        return;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!("Failed to add message {} to the calendar: {}", message_id, e);
+                        button.set_tooltip_text(Some(&e.to_string()));
+                    }
+                    Err(e) => tracing::warn!("Calendar update task failed: {}", e),
+                }
+                button.set_sensitive(true);
+            });
+        });
+        button
+    }
+
+    /// Accept, Tentative and Decline buttons sending the iTIP reply
+    fn create_rsvp(message_id: Uuid, current: Option<PartStat>, sync_manager: Arc<Mutex<SyncManager>>) -> GtkBox {
+        let row = GtkBox::new(Orientation::Horizontal, 0);
+        row.add_css_class("linked");
+        row.add_css_class("event-rsvp");
+
+        let answers = [PartStat::Accepted, PartStat::Tentative, PartStat::Declined];
+        let buttons: Vec<ToggleButton> = answers.iter()
+            .map(|status| {
+                let label = match status {
+                    PartStat::Accepted => "Accept",
+                    PartStat::Declined => "Decline",
+                    other => other.label(),
+                };
+                let button = ToggleButton::with_label(label);
+                button.set_active(current == Some(*status));
+                button
+            })
+            .collect();
+        for button in &buttons[1..] {
+            button.set_group(Some(&buttons[0]));
+        }
+
+        for (button, status) in buttons.iter().zip(answers) {
+            let buttons = buttons.clone();
+            let sync_manager = sync_manager.clone();
+            button.connect_clicked(move |_| {
+                let buttons = buttons.clone();
+                let sync_manager = sync_manager.clone();
+                for button in &buttons {
+                    button.set_sensitive(false);
+                }
+
+                gtk4::glib::MainContext::default().spawn_local(async move {
+                    let result = tokio::spawn(async move {
+                        let sync_manager = sync_manager.lock().await;
+                        sync_manager.respond_to_invitation(message_id, status).await
+                    }).await;
+
+                    match result {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => tracing::warn!("Failed to answer invitation in message {}: {}", message_id, e),
+                        Err(e) => tracing::warn!("Invitation reply task failed: {}", e),
+                    }
+                    for button in &buttons {
+                        button.set_sensitive(true);
+                    }
+                });
+            });
+            row.append(button);
+        }
+
+        row
+    }
+}
+
+/// "Label: value" row of the card
+fn detail(name: &str, value: &str) -> GtkBox {
+    let row = GtkBox::new(Orientation::Horizontal, 12);
+
+    let name = Label::builder().label(name).xalign(0.0).yalign(0.0).width_chars(10).build();
+    name.add_css_class("event-detail-name");
+
+    let value = Label::builder()
+        .label(value)
+        .xalign(0.0)
+        .wrap(true)
+        .selectable(true)
+        .hexpand(true)
+        .build();
+
+    row.append(&name);
+    row.append(&value);
+    row
+}
+
+/// Name and address of a participant, with the answer when `with_status`
+fn describe_participant(participant: &Participant, with_status: bool) -> String {
+    let mut text = match &participant.name {
+        Some(name) => format!("{} <{}>", name, participant.email),
+        None => participant.email.clone(),
+    };
+    if with_status && participant.status != PartStat::NeedsAction {
+        text.push_str(&format!(" ({})", participant.status.label()));
+    }
+    text
+}
+
+/// Start and end of an event in local time, e.g.
+/// "Tuesday, October 20, 2026, 10:00 – 11:00"
+fn format_when(event: &Event) -> Option<String> {
+    let start = event.start?;
+    if let EventTime::Date(first) = start {
+        // All-day events end the day before DTEND
+        let last = match event.end {
+            Some(EventTime::Date(end)) if end.previous_day().is_some_and(|last| last > first) => end.previous_day(),
+            _ => None,
+        };
+        let first = local_time(&start)?.format("%A, %B %-d, %Y").ok()?;
+        return Some(match last.and_then(|last| local_time(&EventTime::Date(last))) {
+            Some(last) => format!("{} – {} (all day)", first, last.format("%A, %B %-d, %Y").ok()?),
+            None => format!("{} (all day)", first),
+        });
+    }
+
+    let begin = local_time(&start)?;
+    let text = begin.format("%A, %B %-d, %Y, %H:%M").ok()?.to_string();
+    let Some(end) = event.end.as_ref().and_then(local_time) else {
+        return Some(text);
+    };
+    let same_day = (begin.year(), begin.day_of_year()) == (end.year(), end.day_of_year());
+    let end = end.format(if same_day { "%H:%M" } else { "%A, %B %-d, %Y, %H:%M" }).ok()?;
+    Some(format!("{} – {}", text, end))
+}
+
+/// An event time in the local time zone
+fn local_time(time: &EventTime) -> Option<gtk4::glib::DateTime> {
+    match *time {
+        EventTime::Date(date) => {
+            gtk4::glib::DateTime::from_local(date.year(), date.month() as i32, date.day() as i32, 0, 0, 0.0).ok()
+        }
+        EventTime::DateTime(datetime) => gtk4::glib::DateTime::from_unix_local(datetime.unix_timestamp()).ok(),
+        EventTime::Floating(datetime) => gtk4::glib::DateTime::from_local(
+            datetime.year(),
+            datetime.month() as i32,
+            datetime.day() as i32,
+            datetime.hour() as i32,
+            datetime.minute() as i32,
+            datetime.second() as f64,
+        ).ok(),
+    }
+}
@@ -0,0 +1,195 @@
+//! Sandboxed web view for HTML message bodies
+//!
+//! JavaScript, plugins, storage and navigation are all off: the view only
+//! ever shows the document it was given. Clicked links open in the default
+//! browser instead, unless a link handler is connected.
+
+use gtk4::prelude::*;
+use libadwaita::StyleManager;
+use std::cell::RefCell;
+use std::rc::Rc;
+use webkit6::prelude::*;
+use webkit6::{NavigationPolicyDecision, NavigationType, NetworkSession, PolicyDecisionType, Settings, WebView};
+
+/// Base URI of loaded documents; the only navigation the view performs
+const BASE_URI: &str = "about:blank";
+
+/// Schemes handed to the desktop when a link is clicked
+const OPENABLE_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+/// Initial height before the document is laid out
+const MIN_HEIGHT: i32 = 480;
+
+/// Web view showing one HTML body
+#[derive(Clone)]
+pub struct HtmlView {
+    web_view: WebView,
+    /// Last HTML loaded and its image sources, re-rendered when the color
+    /// scheme changes
+    html: Rc<RefCell<Option<(String, String)>>>,
+    /// Called with clicked links instead of opening them directly
+    link_handler: Rc<RefCell<Option<Box<dyn Fn(&str)>>>>,
+}
+
+impl HtmlView {
+    /// Create an empty view
+    pub fn new() -> Self {
+        let settings = Settings::new();
+        settings.set_enable_javascript(false);
+        settings.set_enable_javascript_markup(false);
+        settings.set_javascript_can_open_windows_automatically(false);
+        settings.set_enable_developer_extras(false);
+        settings.set_enable_html5_database(false);
+        settings.set_enable_html5_local_storage(false);
+        settings.set_enable_page_cache(false);
+        settings.set_enable_webgl(false);
+        settings.set_enable_webaudio(false);
+        settings.set_enable_media(false);
+        settings.set_allow_file_access_from_file_urls(false);
+        settings.set_allow_universal_access_from_file_urls(false);
+        settings.set_enable_back_forward_navigation_gestures(false);
+
+        // No cookies, cache or credentials shared with anything else
+        let web_view = WebView::builder()
+            .network_session(&NetworkSession::new_ephemeral())
+            .settings(&settings)
+            .build();
+        web_view.add_css_class("msg-html");
+        web_view.set_hexpand(true);
+        web_view.set_vexpand(true);
+        web_view.set_size_request(-1, MIN_HEIGHT);
+        web_view.set_background_color(&gtk4::gdk::RGBA::new(0.0, 0.0, 0.0, 0.0));
+
+        let link_handler: Rc<RefCell<Option<Box<dyn Fn(&str)>>>> = Rc::new(RefCell::new(None));
+        let handler_clone = link_handler.clone();
+        web_view.connect_decide_policy(move |_, decision, decision_type| {
+            if !matches!(decision_type, PolicyDecisionType::NavigationAction | PolicyDecisionType::NewWindowAction) {
+                return false;
+            }
+
+            let action = decision.downcast_ref::<NavigationPolicyDecision>()
+                .and_then(|decision| decision.navigation_action());
+            if let Some(mut action) = action {
+                let uri = action.request().and_then(|request| request.uri());
+                match uri {
+                    Some(uri) if uri == BASE_URI && decision_type == PolicyDecisionType::NavigationAction => {
+                        return false;
+                    }
+                    Some(uri) if action.navigation_type() == NavigationType::LinkClicked => {
+                        match handler_clone.borrow().as_ref() {
+                            Some(handler) => handler(&uri),
+                            None => open_link(&uri),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            decision.ignore();
+            true
+        });
+        // No "Open in new window", "Inspect element" or reload
+        web_view.connect_context_menu(|_, _, _| true);
+
+        let html: Rc<RefCell<Option<(String, String)>>> = Rc::new(RefCell::new(None));
+        let view = web_view.downgrade();
+        let html_clone = html.clone();
+        let handler = StyleManager::default().connect_dark_notify(move |style| {
+            if let (Some(view), Some((html, img_src))) = (view.upgrade(), html_clone.borrow().as_ref()) {
+                view.load_html(&wrap_document(html, img_src, style.is_dark()), Some(BASE_URI));
+            }
+        });
+        let handler = RefCell::new(Some(handler));
+        web_view.connect_destroy(move |_| {
+            if let Some(handler) = handler.take() {
+                StyleManager::default().disconnect(handler);
+            }
+        });
+
+        Self { web_view, html, link_handler }
+    }
+
+    /// Show `html`, styled for the current color scheme
+    ///
+    /// `cid:` URLs should already be resolved. Remote images load only with
+    /// `load_remote`, or from `trusted_domains` and their subdomains.
+    pub fn load_html(&self, html: &str, load_remote: bool, trusted_domains: &[String]) {
+        let img_src = image_sources(load_remote, trusted_domains);
+        let dark = StyleManager::default().is_dark();
+        self.web_view.load_html(&wrap_document(html, &img_src, dark), Some(BASE_URI));
+        *self.html.borrow_mut() = Some((html.to_string(), img_src));
+    }
+
+    /// Handle clicked links with `f` instead of opening them
+    pub fn connect_link_clicked<F: Fn(&str) + 'static>(&self, f: F) {
+        *self.link_handler.borrow_mut() = Some(Box::new(f));
+    }
+
+    /// The GTK widget
+    pub fn widget(&self) -> &WebView {
+        &self.web_view
+    }
+}
+
+impl Default for HtmlView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Open a link in the default handler
+pub fn open_link(uri: &str) {
+    let scheme = uri.split(':').next().unwrap_or_default().to_ascii_lowercase();
+    if !OPENABLE_SCHEMES.contains(&scheme.as_str()) {
+        tracing::warn!("Not opening link with scheme {}", scheme);
+        return;
+    }
+    if let Err(e) = open::that(uri) {
+        tracing::warn!("Failed to open {}: {}", uri, e);
+    }
+}
+
+/// `img-src` of the content security policy
+fn image_sources(load_remote: bool, trusted_domains: &[String]) -> String {
+    let mut sources = vec!["data:".to_string()];
+    if load_remote {
+        sources.extend(["http:".to_string(), "https:".to_string()]);
+    } else {
+        // Hosts only; anything else would end or extend the policy
+        let domains = trusted_domains.iter()
+            .filter(|domain| domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']')));
+        for domain in domains {
+            for scheme in ["http", "https"] {
+                sources.push(format!("{}://{}", scheme, domain));
+                sources.push(format!("{}://*.{}", scheme, domain));
+            }
+        }
+    }
+    sources.join(" ")
+}
+
+/// Prefix the message with a content security policy and reader styles
+fn wrap_document(html: &str, img_src: &str, dark: bool) -> String {
+    let (scheme, text, link, quote) = if dark {
+        ("dark", "#ffffff", "#78aeed", "rgba(255, 255, 255, 0.3)")
+    } else {
+        ("light", "#2e3436", "#1c71d8", "rgba(0, 0, 0, 0.2)")
+    };
+
+    format!(
+        r#"<meta http-equiv="Content-Security-Policy" content="default-src 'none'; img-src {img_src}; style-src 'unsafe-inline'; font-src data:">
+<meta name="color-scheme" content="{scheme}">
+<style>
+  :root {{ color-scheme: {scheme}; }}
+  html, body {{ margin: 0; padding: 0; background: transparent; color: {text};
+               font-family: "Cantarell", "Inter", system-ui, sans-serif; font-size: 14px; line-height: 1.5;
+               overflow-wrap: anywhere; }}
+  a {{ color: {link}; }}
+  img {{ max-width: 100%; height: auto; }}
+  pre {{ white-space: pre-wrap; }}
+  blockquote {{ margin: 0 0 0 4px; padding-left: 12px; border-left: 3px solid {quote}; }}
+  img.remote-blocked {{ min-width: 24px; min-height: 24px; outline: 1px dashed {quote}; }}
+</style>
+{html}"#,
+    )
+}
@@ -1,13 +1,38 @@
 //! Mailbox tree widget for sidebar navigation
 
 use gtk4::prelude::*;
-use gtk4::{Box as GtkBox, ListBox, ListBoxRow, Label, Orientation, Image, ScrolledWindow};
+use gtk4::{Box as GtkBox, ListBox, ListBoxRow, Label, Orientation, Image, ScrolledWindow, Popover, Button, Entry};
 // use libadwaita::prelude::*;
 // use libadwaita::ActionRow;
+use asgard_core::error::AsgardResult;
+use asgard_core::mailbox::{Mailbox, MailboxFlags, MailboxHierarchy, MailboxTreeNode, MailboxType};
 use asgard_core::storage::StorageManager;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Folder management action requested from the mailbox context menu
+#[derive(Debug, Clone)]
+pub enum MailboxAction {
+    /// Create a folder under `parent_id` (or at the top level)
+    Create {
+        account_id: Uuid,
+        parent_id: Option<Uuid>,
+        name: String,
+    },
+    /// Rename a folder
+    Rename { mailbox_id: Uuid, new_name: String },
+    /// Delete a folder and its children
+    Delete { mailbox_id: Uuid },
+    /// Subscribe to or unsubscribe from a folder
+    SetSubscribed { mailbox_id: Uuid, subscribed: bool },
+}
+
+type ActionHandler = Rc<RefCell<Option<Rc<dyn Fn(MailboxAction)>>>>;
+type SelectionHandler = Rc<RefCell<Option<Rc<dyn Fn(Uuid)>>>>;
 
 /// Mailbox tree widget for the sidebar
 pub struct MailboxTree {
@@ -19,6 +44,12 @@ pub struct MailboxTree {
     storage: Arc<Mutex<StorageManager>>,
     /// Accordion states (mailbox_name -> expanded)
     accordion_states: HashMap<String, bool>,
+    /// Section title and rows rendered for each account, so a section can be rebuilt
+    account_rows: Rc<RefCell<HashMap<Uuid, (String, Vec<ListBoxRow>)>>>,
+    /// Folder management callback
+    action_handler: ActionHandler,
+    /// Folder selection callback
+    selection_handler: SelectionHandler,
 }
 
 impl MailboxTree {
@@ -45,6 +76,164 @@ impl MailboxTree {
             list_box,
             storage,
             accordion_states: HashMap::new(),
+            account_rows: Rc::new(RefCell::new(HashMap::new())),
+            action_handler: Rc::new(RefCell::new(None)),
+            selection_handler: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Set the callback for folder management actions
+    pub fn connect_mailbox_action<F>(&self, callback: F)
+    where
+        F: Fn(MailboxAction) + 'static,
+    {
+        *self.action_handler.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Set the callback for a folder being selected, called with its ID
+    pub fn connect_mailbox_selected<F>(&self, callback: F)
+    where
+        F: Fn(Uuid) + 'static,
+    {
+        *self.selection_handler.borrow_mut() = Some(Rc::new(callback));
+    }
+
+    /// Reload the folders of an account from storage
+    pub async fn load_account(&self, title: &str, account_id: Uuid) -> AsgardResult<()> {
+        let mailboxes = {
+            let storage = self.storage.lock().await;
+            storage.database().get_mailboxes(account_id).await?
+        };
+
+        let mut hierarchy = MailboxHierarchy::new();
+        for mailbox in mailboxes {
+            hierarchy.add_mailbox(mailbox);
+        }
+
+        self.build_account(title, account_id, &hierarchy.build_tree(account_id));
+        Ok(())
+    }
+
+    /// Reload the sections of every account in storage
+    pub async fn refresh_accounts(&self) {
+        let accounts = {
+            let storage = self.storage.lock().await;
+            storage.database().get_accounts().await
+        };
+        let accounts = match accounts {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                tracing::warn!("Failed to load accounts: {}", e);
+                return;
+            }
+        };
+
+        for account in accounts {
+            let title = match account.display_name() {
+                "" => account.email().to_uppercase(),
+                name => name.to_uppercase(),
+            };
+            if let Err(e) = self.load_account(&title, account.id).await {
+                tracing::warn!("Failed to reload folders for {}: {}", account.email(), e);
+            }
+        }
+    }
+
+    /// Render the folder tree of an account, replacing any previous rows
+    pub fn build_account(&self, title: &str, account_id: Uuid, nodes: &[MailboxTreeNode]) {
+        if let Some((_, rows)) = self.account_rows.borrow_mut().remove(&account_id) {
+            for row in rows {
+                self.list_box.remove(&row);
+            }
+        }
+
+        let mut rows = vec![self.add_section_header(title)];
+        for node in nodes {
+            self.add_mailbox_node(node, 0, &mut rows);
+        }
+
+        // Account context menu creates top-level folders
+        let header = rows[0].clone();
+        let handler = self.action_handler.clone();
+        let gesture = gtk4::GestureClick::new();
+        gesture.set_button(gtk4::gdk::BUTTON_SECONDARY);
+        gesture.connect_pressed(move |gesture, _, _, _| {
+            gesture.set_state(gtk4::EventSequenceState::Claimed);
+            let popover = Popover::new();
+            popover.set_parent(&header);
+            show_name_form(&popover, "New Folder", "", handler.clone(), move |name| {
+                MailboxAction::Create { account_id, parent_id: None, name }
+            });
+            popover.connect_closed(|popover| popover.unparent());
+            popover.popup();
+        });
+        rows[0].add_controller(gesture);
+
+        self.account_rows.borrow_mut().insert(account_id, (title.to_string(), rows));
+    }
+
+    fn add_mailbox_node(&self, node: &MailboxTreeNode, depth: i32, rows: &mut Vec<ListBoxRow>) {
+        let mailbox = node.mailbox();
+        let row = ListBoxRow::new();
+        row.set_activatable(mailbox.can_select());
+        row.set_selectable(mailbox.can_select());
+
+        let container = GtkBox::new(Orientation::Horizontal, 8);
+        container.set_margin_start(24 + depth * 16);
+        container.set_margin_end(12);
+        container.set_margin_top(6);
+        container.set_margin_bottom(6);
+
+        let icon = Image::from_icon_name(mailbox_icon_name(mailbox));
+        icon.set_icon_size(gtk4::IconSize::Normal);
+        icon.add_css_class("mailbox-icon");
+        container.append(&icon);
+
+        let label = Label::builder()
+            .label(mailbox.display_name())
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        if !mailbox.can_select() {
+            label.add_css_class("dim-label");
+        }
+        container.append(&label);
+
+        if mailbox.stats.unread_messages > 0 {
+            let badge = Label::new(Some(&mailbox.stats.unread_messages.to_string()));
+            badge.add_css_class("count-badge");
+            container.append(&badge);
+        }
+
+        row.set_child(Some(&container));
+        self.list_box.append(&row);
+
+        let gesture = gtk4::GestureClick::new();
+        gesture.set_button(gtk4::gdk::BUTTON_SECONDARY);
+        let row_clone = row.clone();
+        let mailbox_clone = mailbox.clone();
+        let handler = self.action_handler.clone();
+        gesture.connect_pressed(move |gesture, _, _, _| {
+            gesture.set_state(gtk4::EventSequenceState::Claimed);
+            show_context_menu(&row_clone, &mailbox_clone, handler.clone());
+        });
+        row.add_controller(gesture);
+
+        if mailbox.can_select() {
+            let gesture = gtk4::GestureClick::new();
+            let mailbox_id = mailbox.id;
+            let handler = self.selection_handler.clone();
+            gesture.connect_pressed(move |_, _, _, _| {
+                if let Some(callback) = handler.borrow().clone() {
+                    callback(mailbox_id);
+                }
+            });
+            row.add_controller(gesture);
+        }
+
+        rows.push(row);
+        for child in node.children() {
+            self.add_mailbox_node(child, depth + 1, rows);
         }
     }
     
@@ -95,7 +284,7 @@ impl MailboxTree {
         self.add_sidebar_item("Archive", "mail-archive-symbolic", false, "demo:ARCHIVE", false, false);
     }
     
-    fn add_section_header(&self, title: &str) {
+    fn add_section_header(&self, title: &str) -> ListBoxRow {
         let header = Label::new(Some(title));
         header.add_css_class("sidebar-section");
         header.set_xalign(0.0);
@@ -109,6 +298,7 @@ impl MailboxTree {
         row.set_selectable(false);
         row.set_activatable(false);
         self.list_box.append(&row);
+        row
     }
     
     fn add_sidebar_item(
@@ -262,6 +452,160 @@ impl MailboxTree {
     }
 }
 
+fn mailbox_icon_name(mailbox: &Mailbox) -> &'static str {
+    match mailbox.mailbox_type() {
+        MailboxType::Inbox => "mail-inbox-symbolic",
+        MailboxType::Sent => "mail-sent-symbolic",
+        MailboxType::Drafts => "mail-drafts-symbolic",
+        MailboxType::Trash => "user-trash-symbolic",
+        MailboxType::Spam => "mail-junk-symbolic",
+        MailboxType::Archive | MailboxType::All => "mail-archive-symbolic",
+        MailboxType::Flagged => "flag-symbolic",
+        MailboxType::Label => "tag-symbolic",
+        MailboxType::Custom => "folder-symbolic",
+    }
+}
+
+fn dispatch(handler: &ActionHandler, action: MailboxAction) {
+    let callback = handler.borrow().clone();
+    match callback {
+        Some(callback) => callback(action),
+        None => tracing::error!("Mailbox action dropped, no handler connected: {:?}", action),
+    }
+}
+
+fn show_context_menu(row: &ListBoxRow, mailbox: &Mailbox, handler: ActionHandler) {
+    let popover = Popover::new();
+    popover.set_parent(row);
+    popover.set_has_arrow(false);
+
+    let menu = GtkBox::new(Orientation::Vertical, 0);
+    let new_button = Button::with_label("New Folder…");
+    let rename_button = Button::with_label("Rename…");
+    let subscribed = mailbox.has_flag(MailboxFlags::Subscribed);
+    let subscribe_button = Button::with_label(if subscribed { "Unsubscribe" } else { "Subscribe" });
+    let delete_button = Button::with_label("Delete…");
+
+    for button in [&new_button, &rename_button, &subscribe_button, &delete_button] {
+        button.add_css_class("flat");
+        button.set_halign(gtk4::Align::Fill);
+        menu.append(button);
+    }
+
+    new_button.set_sensitive(!mailbox.has_flag(MailboxFlags::NoInferiors));
+    rename_button.set_sensitive(!mailbox.is_system());
+    delete_button.set_sensitive(!mailbox.is_system());
+    subscribe_button.set_sensitive(!mailbox.has_flag(MailboxFlags::NonExistent));
+    popover.set_child(Some(&menu));
+
+    let account_id = mailbox.account_id();
+    let mailbox_id = mailbox.id;
+
+    let popover_clone = popover.clone();
+    let handler_clone = handler.clone();
+    new_button.connect_clicked(move |_| {
+        show_name_form(&popover_clone, "New Folder", "", handler_clone.clone(), move |name| {
+            MailboxAction::Create { account_id, parent_id: Some(mailbox_id), name }
+        });
+    });
+
+    let popover_clone = popover.clone();
+    let handler_clone = handler.clone();
+    let current_name = mailbox.display_name().to_string();
+    rename_button.connect_clicked(move |_| {
+        show_name_form(&popover_clone, "Rename", &current_name, handler_clone.clone(), move |new_name| {
+            MailboxAction::Rename { mailbox_id, new_name }
+        });
+    });
+
+    let popover_clone = popover.clone();
+    let handler_clone = handler.clone();
+    subscribe_button.connect_clicked(move |_| {
+        popover_clone.popdown();
+        dispatch(&handler_clone, MailboxAction::SetSubscribed { mailbox_id, subscribed: !subscribed });
+    });
+
+    let popover_clone = popover.clone();
+    let display_name = mailbox.display_name().to_string();
+    delete_button.connect_clicked(move |_| {
+        let confirm = GtkBox::new(Orientation::Vertical, 8);
+        confirm.set_margin_start(8);
+        confirm.set_margin_end(8);
+        confirm.set_margin_top(8);
+        confirm.set_margin_bottom(8);
+
+        let label = Label::new(Some(&format!("Delete “{}” and all folders inside it?", display_name)));
+        label.set_wrap(true);
+        confirm.append(&label);
+
+        let delete = Button::with_label("Delete");
+        delete.add_css_class("destructive-action");
+        confirm.append(&delete);
+
+        let popover_inner = popover_clone.clone();
+        let handler_inner = handler.clone();
+        delete.connect_clicked(move |_| {
+            popover_inner.popdown();
+            dispatch(&handler_inner, MailboxAction::Delete { mailbox_id });
+        });
+        popover_clone.set_child(Some(&confirm));
+    });
+
+    popover.connect_closed(|popover| popover.unparent());
+    popover.popup();
+}
+
+fn show_name_form<F>(popover: &Popover, title: &str, initial: &str, handler: ActionHandler, make_action: F)
+where
+    F: Fn(String) -> MailboxAction + 'static,
+{
+    let form = GtkBox::new(Orientation::Vertical, 8);
+    form.set_margin_start(8);
+    form.set_margin_end(8);
+    form.set_margin_top(8);
+    form.set_margin_bottom(8);
+
+    let label = Label::new(Some(title));
+    label.set_xalign(0.0);
+    label.add_css_class("heading");
+    form.append(&label);
+
+    let entry = Entry::new();
+    entry.set_text(initial);
+    entry.set_placeholder_text(Some("Folder name"));
+    form.append(&entry);
+
+    let button = Button::with_label(title);
+    button.add_css_class("suggested-action");
+    button.set_sensitive(!initial.trim().is_empty());
+    form.append(&button);
+
+    let button_clone = button.clone();
+    entry.connect_changed(move |entry| {
+        button_clone.set_sensitive(!entry.text().trim().is_empty());
+    });
+
+    let submit = Rc::new(move |entry: &Entry, popover: &Popover| {
+        let name = entry.text().trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        popover.popdown();
+        dispatch(&handler, make_action(name));
+    });
+
+    let popover_clone = popover.clone();
+    let entry_clone = entry.clone();
+    let submit_clone = submit.clone();
+    button.connect_clicked(move |_| submit_clone(&entry_clone, &popover_clone));
+
+    let popover_clone = popover.clone();
+    entry.connect_activate(move |entry| submit(entry, &popover_clone));
+
+    popover.set_child(Some(&form));
+    entry.grab_focus();
+}
+
 impl Clone for MailboxTree {
     fn clone(&self) -> Self {
         Self {
@@ -269,6 +613,9 @@ impl Clone for MailboxTree {
             list_box: self.list_box.clone(),
             storage: self.storage.clone(),
             accordion_states: self.accordion_states.clone(),
+            account_rows: self.account_rows.clone(),
+            action_handler: self.action_handler.clone(),
+            selection_handler: self.selection_handler.clone(),
         }
     }
 }
\ No newline at end of file
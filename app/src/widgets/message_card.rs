@@ -10,6 +10,8 @@ pub struct MessageCard {
     pub collapsed_revealer: Revealer,
     pub chevron: Label,
     pub body_label: Label,
+    /// Rendered HTML body, shown instead of `body_label` when expanded
+    pub body_html: Option<Widget>,
 }
 
 impl MessageCard {
@@ -21,6 +23,7 @@ impl MessageCard {
         bcc: &[String],
         date: &OffsetDateTime,
         body_text: &str,
+        body_html: Option<&str>,
         has_attachments: bool,
         expanded: bool,
     ) -> Self {
@@ -39,7 +42,8 @@ impl MessageCard {
             body_text.lines().next().unwrap_or("").to_string()
         };
         
-        let (body, body_label) = Self::build_body_webview(&display_text);
+        let (body, body_label, html_widget) = Self::build_body_webview(&display_text, body_html);
+        Self::show_html(&body_label, html_widget.as_ref(), expanded);
         
         // Add CSS classes for styling control
         if expanded {
@@ -69,6 +73,7 @@ impl MessageCard {
         let chevron_clone = chevron.clone();
         let body_clone = body.clone();
         let body_label_clone = body_label.clone();
+        let html_clone = html_widget.clone();
         let body_text_clone = body_text.to_string();
         
         // Add a gesture to handle clicks on header
@@ -86,6 +91,7 @@ impl MessageCard {
                 // Update body content to show only first line
                 let first_line = body_text_clone.lines().next().unwrap_or("");
                 body_label_clone.set_label(first_line);
+                Self::show_html(&body_label_clone, html_clone.as_ref(), false);
             } else {
                 body_clone.remove_css_class("collapsed");
                 body_clone.add_css_class("expanded");
@@ -93,6 +99,7 @@ impl MessageCard {
                 
                 // Update body content to show full text
                 body_label_clone.set_label(&body_text_clone);
+                Self::show_html(&body_label_clone, html_clone.as_ref(), true);
             }
         });
         header.add_controller(header_gesture);
@@ -102,6 +109,7 @@ impl MessageCard {
         let chevron_clone2 = chevron.clone();
         let body_clone2 = body.clone();
         let body_label_clone2 = body_label.clone();
+        let html_clone2 = html_widget.clone();
         let body_text_clone2 = body_text.to_string();
         
         // Add a gesture to handle clicks on body
@@ -117,6 +125,7 @@ impl MessageCard {
                 
                 // Update body content to show full text
                 body_label_clone2.set_label(&body_text_clone2);
+                Self::show_html(&body_label_clone2, html_clone2.as_ref(), true);
             }
         });
         body.add_controller(body_gesture);
@@ -125,7 +134,7 @@ impl MessageCard {
         root.append(&separator);
         root.append(&revealer);
 
-        Self { root, header, revealer, collapsed_revealer, chevron, body_label }
+        Self { root, header, revealer, collapsed_revealer, chevron, body_label, body_html: html_widget }
     }
 
     pub fn new(
@@ -455,7 +464,11 @@ impl MessageCard {
         (header, chevron)
     }
 
-    fn build_body_webview(body_text: &str) -> (Box, Label) {
+    /// Body with the plain text, plus a sandboxed web view for `body_html`
+    /// when the build has WebKit
+    ///
+    /// `cid:` URLs in `body_html` should already be resolved.
+    fn build_body_webview(body_text: &str, body_html: Option<&str>) -> (Box, Label, Option<Widget>) {
         let body_container = Box::new(Orientation::Vertical, 0);
         body_container.add_css_class("msg-body");
         body_container.set_vexpand(false);
@@ -472,7 +485,30 @@ impl MessageCard {
         body_label.set_hexpand(false);
         
         body_container.append(&body_label);
-        (body_container, body_label)
+
+        #[cfg(feature = "webkit")]
+        let html_widget = body_html.map(|html| {
+            let html_view = crate::widgets::HtmlView::new();
+            html_view.load_html(html, false, &[]);
+            let widget = html_view.widget().clone().upcast::<Widget>();
+            body_container.append(&widget);
+            widget
+        });
+        #[cfg(not(feature = "webkit"))]
+        let html_widget = {
+            let _ = body_html;
+            None
+        };
+
+        (body_container, body_label, html_widget)
+    }
+
+    /// Show the web view when expanded and the text preview otherwise
+    fn show_html(body_label: &Label, html: Option<&Widget>, expanded: bool) {
+        if let Some(html) = html {
+            html.set_visible(expanded);
+            body_label.set_visible(!expanded);
+        }
     }
 
     fn build_collapsed_preview(body_text: &str) -> Box {
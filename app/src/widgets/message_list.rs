@@ -82,16 +82,19 @@ impl MessageList {
     
     /// Update messages for a specific mailbox
     pub fn update_messages(&self, _mailbox_name: &str) {
+        // Create demo messages
+        self.show_messages(self.create_demo_messages());
+    }
+
+    /// Show `messages` grouped into threads, replacing the current list
+    pub fn show_messages(&self, messages: Vec<Message>) {
         // Clear existing items
         while let Some(child) = self.list_box.first_child() {
             self.list_box.remove(&child);
         }
-        
-        // Create demo messages
-        let demo_messages = self.create_demo_messages();
-        
+
         // Group messages into threads
-        let threads = group_into_threads(demo_messages);
+        let threads = group_into_threads(messages);
         
         // Add threads to the message list
         for (i, thread) in threads.iter().enumerate() {
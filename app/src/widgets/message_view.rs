@@ -4,9 +4,27 @@ use gtk4::prelude::*;
 use gtk4::{Box as GtkBox, Label, Orientation, Align, Button, ScrolledWindow, Separator, DrawingArea};
 // use libadwaita::prelude::*;
 // use libadwaita::Avatar;
+use asgard_core::authenticity::{Authenticity, AuthenticityChecker, DisplayNameSpoof, Verdict};
+use asgard_core::config::{Config, SecurityConfig};
+use asgard_core::dkim::DkimVerification;
+use asgard_core::html;
+use asgard_core::links::LinkChecker;
 use asgard_core::message::Message;
+use asgard_core::openpgp::{PgpKeyring, PgpStatus, SignatureStatus};
+use asgard_core::sanitizer::{HtmlSanitizer, SanitizedHtml, Tracker};
+use asgard_core::smime::{SmimeContext, SmimeSignature, SmimeStatus};
+use asgard_core::source::MessageSource;
+use asgard_core::storage::StorageManager;
+use asgard_core::sync::SyncManager;
+use crate::widgets::{AttachmentStrip, EventCard};
+use crate::windows::source_window::SourceWindow;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
 use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
 
 /// Message view widget for the right pane
 pub struct MessageView {
@@ -22,6 +40,96 @@ pub struct MessageView {
     cards: GtkBox,
     /// Current message
     current_message: RefCell<Option<Message>>,
+    /// OpenPGP keyring for verifying and decrypting messages
+    keyring: Option<Arc<PgpKeyring>>,
+    /// S/MIME context for verifying and decrypting messages
+    smime: Option<Arc<SmimeContext>>,
+    /// Sanitizer applied to HTML bodies before display
+    sanitizer: HtmlSanitizer,
+    /// Checks clicked links in HTML bodies for phishing signs
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    link_checker: LinkChecker,
+    /// Settings holding the remote content allow lists
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    config: Rc<RefCell<Config>>,
+    /// Storage recording the trackers removed from messages
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    storage: Option<Arc<Mutex<StorageManager>>>,
+    /// Fetches message bodies, sources and attachments not kept during sync
+    sync_manager: Option<Arc<Mutex<SyncManager>>>,
+    /// Sender authenticity checks, shared so contacts loaded later apply
+    authenticity: Rc<RefCell<AuthenticityChecker>>,
+    /// Local DKIM results by message
+    dkim_results: Rc<RefCell<HashMap<Uuid, Vec<DkimVerification>>>>,
+    /// Message on display, shared with clones waiting on background work
+    displayed: Rc<RefCell<Option<Uuid>>>,
+}
+
+/// Signature, encryption or sender authenticity state shown in a message
+/// header
+struct SecurityBadge {
+    /// Text of the badge
+    summary: String,
+    /// CSS class for the badge color
+    level: &'static str,
+    /// Details shown on hover
+    tooltip: Option<String>,
+}
+
+impl SecurityBadge {
+    fn from_pgp(status: &PgpStatus) -> Self {
+        let level = if status.error.is_some() || matches!(status.signature, SignatureStatus::Invalid(_)) {
+            "security-error"
+        } else if status.is_trusted() || (status.decrypted && status.signature == SignatureStatus::Unsigned) {
+            "security-trusted"
+        } else {
+            "security-warning"
+        };
+        let tooltip = match (&status.error, &status.signature) {
+            (Some(error), _) => Some(error.clone()),
+            (None, SignatureStatus::Valid { fingerprint, .. }) => Some(format!("OpenPGP key fingerprint {}", fingerprint)),
+            _ => None,
+        };
+
+        Self { summary: status.summary(), level, tooltip }
+    }
+
+    fn from_smime(status: &SmimeStatus) -> Self {
+        let level = if status.error.is_some() || matches!(status.signature, SmimeSignature::Invalid(_)) {
+            "security-error"
+        } else if status.is_trusted() || (status.decrypted && status.signature == SmimeSignature::Unsigned) {
+            "security-trusted"
+        } else {
+            "security-warning"
+        };
+        let tooltip = match (&status.error, &status.signature) {
+            (Some(error), _) => Some(error.clone()),
+            (None, SmimeSignature::Untrusted { reason, .. }) => Some(reason.clone()),
+            _ => None,
+        };
+
+        Self { summary: status.summary(), level, tooltip }
+    }
+
+    fn from_authenticity(authenticity: &Authenticity) -> Self {
+        let level = match authenticity.verdict {
+            Verdict::Pass => "security-trusted",
+            Verdict::Fail => "security-error",
+            Verdict::Neutral | Verdict::Unknown => "security-warning",
+        };
+        let details = authenticity.details();
+        let tooltip = (!details.is_empty()).then(|| details.join("\n"));
+
+        Self { summary: authenticity.summary(), level, tooltip }
+    }
+
+    fn from_spoof(spoof: &DisplayNameSpoof) -> Self {
+        Self {
+            summary: "Possible impersonation".to_string(),
+            level: "security-error",
+            tooltip: Some(spoof.to_string()),
+        }
+    }
 }
 
 impl MessageView {
@@ -73,18 +181,81 @@ impl MessageView {
             scroller,
             cards,
             current_message: RefCell::new(None),
+            keyring: None,
+            smime: None,
+            sanitizer: HtmlSanitizer::default(),
+            link_checker: LinkChecker::default(),
+            config: Rc::new(RefCell::new(Config::default())),
+            storage: None,
+            sync_manager: None,
+            authenticity: Rc::new(RefCell::new(AuthenticityChecker::default())),
+            dkim_results: Rc::new(RefCell::new(HashMap::new())),
+            displayed: Rc::new(RefCell::new(None)),
         }
     }
+
+    /// Verify and decrypt OpenPGP messages with `keyring`
+    pub fn set_keyring(&mut self, keyring: Arc<PgpKeyring>) {
+        self.keyring = Some(keyring);
+    }
+
+    /// Verify and decrypt S/MIME messages with `smime`
+    pub fn set_smime(&mut self, smime: Arc<SmimeContext>) {
+        self.smime = Some(smime);
+    }
+
+    /// Sanitize HTML bodies and load remote content following `config`
+    ///
+    /// Remote content allowed from the view is saved back to the
+    /// configuration file.
+    pub fn set_config(&mut self, config: Config) {
+        self.sanitizer = HtmlSanitizer::new(&config.security);
+        self.link_checker = LinkChecker::new(&config.security);
+        *self.authenticity.borrow_mut() = AuthenticityChecker::new(&config.security);
+        self.config = Rc::new(RefCell::new(config));
+    }
+
+    /// Record trackers removed from messages in `storage`, and check
+    /// display names against the contacts found there
+    ///
+    /// Call after [`Self::set_config`], which resets the contacts.
+    pub fn set_storage(&mut self, storage: Arc<Mutex<StorageManager>>) {
+        self.storage = Some(storage.clone());
+
+        let authenticity = self.authenticity.clone();
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let result = tokio::spawn(async move {
+                let storage = storage.lock().await;
+                storage.database().get_known_contacts().await
+            }).await;
+
+            match result {
+                Ok(Ok(contacts)) => authenticity.borrow_mut().set_contacts(contacts),
+                Ok(Err(e)) => tracing::warn!("Failed to load contacts: {}", e),
+                Err(e) => tracing::warn!("Contact loading task failed: {}", e),
+            }
+        });
+    }
     
+    /// Fetch message bodies, sources and attachments from the server
+    /// through `sync_manager` when they were not kept during sync
+    pub fn set_sync_manager(&mut self, sync_manager: Arc<Mutex<SyncManager>>) {
+        self.sync_manager = Some(sync_manager);
+    }
+
     /// Show a message in the view
     pub fn show_message(&self, message: &Message) {
         *self.current_message.borrow_mut() = Some(message.clone());
-        self.update_message_display(message);
+        self.update_message_display(message, false);
+        if !message.missing_body_parts().is_empty() {
+            self.load_body(message.id);
+        }
     }
     
     /// Clear the message view
     pub fn clear(&self) {
         *self.current_message.borrow_mut() = None;
+        *self.displayed.borrow_mut() = None;
         
         // Clear all message cards
         while let Some(child) = self.cards.first_child() {
@@ -95,7 +266,7 @@ impl MessageView {
         self.meta_count.set_text("No messages selected");
     }
     
-    fn update_message_display(&self, message: &Message) {
+    fn update_message_display(&self, message: &Message, load_remote: bool) {
         // Clear existing cards
         while let Some(child) = self.cards.first_child() {
             self.cards.remove(&child);
@@ -103,36 +274,73 @@ impl MessageView {
         
         // Update meta count
         self.meta_count.set_text("1 message");
-        
+        *self.displayed.borrow_mut() = Some(message.id);
+
+        // Sender authenticity, from the message as received
+        let mut badges = Vec::new();
+        let dkim_results = self.dkim_results.borrow().get(&message.id).cloned();
+        let authenticity = self.authenticity.borrow().evaluate(message, dkim_results.as_deref().unwrap_or_default());
+        if authenticity.verdict != Verdict::Unknown {
+            badges.push(SecurityBadge::from_authenticity(&authenticity));
+        }
+        if let Some(spoof) = &authenticity.spoof {
+            badges.push(SecurityBadge::from_spoof(spoof));
+        }
+        if dkim_results.is_none() && self.authenticity.borrow().verifies_dkim() {
+            self.verify_dkim(message, load_remote);
+        }
+
+        // Verify and decrypt OpenPGP and S/MIME content for display only
+        let mut message = message.clone();
+        if let Some(smime) = &self.smime {
+            let status = smime.process(&mut message);
+            if status.is_protected() {
+                badges.push(SecurityBadge::from_smime(&status));
+            }
+        }
+        if let Some(keyring) = &self.keyring {
+            let status = keyring.process(&mut message);
+            if status.is_protected() {
+                badges.push(SecurityBadge::from_pgp(&status));
+            }
+        }
+
         // Create message card
-        let card = self.create_message_card(message);
+        let card = self.create_message_card(&message, &badges, load_remote);
         self.cards.append(&card);
     }
     
-    fn create_message_card(&self, message: &Message) -> GtkBox {
+    fn create_message_card(&self, message: &Message, badges: &[SecurityBadge], load_remote: bool) -> GtkBox {
         let root = GtkBox::new(Orientation::Vertical, 8);
         root.add_css_class("msg-card");
         root.set_vexpand(false);
         root.set_hexpand(false);
 
         // Header
-        let header = self.create_message_header(message);
+        let header = self.create_message_header(message, badges);
         
         // Separator
         let separator = Separator::new(Orientation::Horizontal);
         separator.add_css_class("card-sep");
 
         // Message body
-        let body = self.create_message_body(message);
+        let body = self.create_message_body(message, load_remote);
 
         root.append(&header);
         root.append(&separator);
+        if let Some(event) = EventCard::for_message(message, self.sync_manager.clone()) {
+            root.append(&event.widget);
+        }
         root.append(&body);
+        if message.has_attachments() {
+            let attachments = AttachmentStrip::new(&message.attachments, self.sync_manager.clone());
+            root.append(&attachments.widget);
+        }
 
         root
     }
     
-    fn create_message_header(&self, message: &Message) -> GtkBox {
+    fn create_message_header(&self, message: &Message, badges: &[SecurityBadge]) -> GtkBox {
         let header = GtkBox::new(Orientation::Horizontal, 8);
         
         // Avatar (36px circle with initials)
@@ -168,6 +376,9 @@ impl MessageView {
         left_content.append(&name_label);
         left_content.append(&subject_label);
         left_content.append(&recipient_container);
+        if !message.labels.is_empty() {
+            left_content.append(&self.create_label_chips(message));
+        }
 
         let spacer = GtkBox::new(Orientation::Horizontal, 0);
         spacer.set_hexpand(true);
@@ -176,6 +387,22 @@ impl MessageView {
         right_content.add_css_class("right_content");
         right_content.set_halign(Align::End);
         right_content.append(&when_label);
+        for badge in badges {
+            right_content.append(&self.create_security_badge(badge));
+        }
+
+        let show_original = Button::with_label("Show original");
+        show_original.add_css_class("flat");
+        show_original.add_css_class("hdr-action");
+        show_original.set_halign(Align::End);
+        {
+            let view = self.clone();
+            let message = message.clone();
+            show_original.connect_clicked(move |button| {
+                view.show_original(button.upcast_ref(), &message);
+            });
+        }
+        right_content.append(&show_original);
         
         header.append(&avatar);
         header.append(&left_content);
@@ -185,11 +412,58 @@ impl MessageView {
         header
     }
     
-    fn create_message_body(&self, message: &Message) -> GtkBox {
+    fn create_security_badge(&self, security: &SecurityBadge) -> Label {
+        let badge = Label::builder()
+            .label(&security.summary)
+            .xalign(1.0)
+            .build();
+        badge.add_css_class("hdr-security");
+        badge.add_css_class(security.level);
+        badge.set_tooltip_text(security.tooltip.as_deref());
+
+        badge
+    }
+    
+    fn create_message_body(&self, message: &Message, load_remote: bool) -> GtkBox {
         let body_container = GtkBox::new(Orientation::Vertical, 0);
         body_container.add_css_class("msg-body");
         body_container.set_vexpand(false);
         body_container.set_hexpand(false);
+
+        #[cfg(feature = "webkit")]
+        if let Some(content) = message.html_content() {
+            let sender = message.headers.from.first().map(|from| from.email.clone()).unwrap_or_default();
+            let load_remote = load_remote || self.config.borrow().security.allows_remote_content(&sender);
+            let sanitized = self.sanitizer.sanitize(&String::from_utf8_lossy(content), load_remote);
+            self.record_trackers(message.id, &sanitized.trackers);
+            if !sanitized.blocked_remote.is_empty() || !sanitized.trackers.is_empty() {
+                body_container.append(&self.create_remote_content_bar(message, &sender, &sanitized));
+            }
+
+            let html_view = crate::widgets::HtmlView::new();
+            html_view.load_html(
+                &html::resolve_cid_urls(&sanitized.html, message),
+                load_remote,
+                self.sanitizer.trusted_domains(),
+            );
+            let links = self.link_checker.check_html(&sanitized.html);
+            let checker = self.link_checker.clone();
+            let view = html_view.widget().clone();
+            html_view.connect_link_clicked(move |uri| {
+                let check = links.get(&asgard_core::links::normalize_url(uri))
+                    .cloned()
+                    .unwrap_or_else(|| checker.check(uri, ""));
+                if check.is_suspicious() {
+                    confirm_link(view.upcast_ref(), check);
+                } else {
+                    crate::widgets::html_view::open_link(&check.target);
+                }
+            });
+            body_container.append(html_view.widget());
+            return body_container;
+        }
+        #[cfg(not(feature = "webkit"))]
+        let _ = load_remote;
         
         let body_text = self.get_message_body(message);
         let body_label = Label::builder()
@@ -206,6 +480,232 @@ impl MessageView {
         body_container
     }
     
+    /// Bar above an HTML body saying what was blocked, with buttons to
+    /// load remote content once or always for the sender or their domain
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    fn create_remote_content_bar(&self, message: &Message, sender: &str, sanitized: &SanitizedHtml) -> GtkBox {
+        let bar = GtkBox::new(Orientation::Horizontal, 8);
+        bar.add_css_class("remote-content-bar");
+
+        let mut summary = Vec::new();
+        if !sanitized.blocked_remote.is_empty() {
+            summary.push("Remote content was blocked to protect your privacy.".to_string());
+        }
+        match sanitized.trackers.len() {
+            0 => {}
+            1 => summary.push("1 tracker was removed.".to_string()),
+            count => summary.push(format!("{} trackers were removed.", count)),
+        }
+        let label = Label::builder()
+            .label(&summary.join(" "))
+            .xalign(0.0)
+            .wrap(true)
+            .hexpand(true)
+            .build();
+        if !sanitized.trackers.is_empty() {
+            let trackers: Vec<String> = sanitized.trackers.iter()
+                .map(|tracker| tracker.service.clone().unwrap_or_else(|| tracker.url.clone()))
+                .collect();
+            label.set_tooltip_text(Some(&trackers.join("\n")));
+        }
+        bar.append(&label);
+
+        if sanitized.blocked_remote.is_empty() {
+            return bar;
+        }
+
+        let load_button = Button::with_label("Load remote content");
+        let view = self.clone();
+        let message_clone = message.clone();
+        load_button.connect_clicked(move |_| view.update_message_display(&message_clone, true));
+        bar.append(&load_button);
+
+        if let Some((_, domain)) = sender.rsplit_once('@') {
+            let sender_button = Button::with_label(&format!("Always from {}", sender));
+            sender_button.add_css_class("flat");
+            let view = self.clone();
+            let message_clone = message.clone();
+            let sender_clone = sender.to_string();
+            sender_button.connect_clicked(move |_| {
+                view.allow_remote_content(|security| security.allow_remote_content_from_sender(&sender_clone));
+                view.update_message_display(&message_clone, true);
+            });
+            bar.append(&sender_button);
+
+            let domain_button = Button::with_label(&format!("Always from {}", domain));
+            domain_button.add_css_class("flat");
+            let view = self.clone();
+            let message_clone = message.clone();
+            let domain = domain.to_string();
+            domain_button.connect_clicked(move |_| {
+                view.allow_remote_content(|security| security.allow_remote_content_from_domain(&domain));
+                view.update_message_display(&message_clone, true);
+            });
+            bar.append(&domain_button);
+        }
+
+        bar
+    }
+
+    /// Gmail labels of a message, each with a button that removes it
+    fn create_label_chips(&self, message: &Message) -> GtkBox {
+        let chips = GtkBox::new(Orientation::Horizontal, 4);
+        chips.add_css_class("hdr-labels");
+
+        for label in &message.labels {
+            let chip = Button::with_label(&format!("{} ×", label));
+            chip.add_css_class("pill");
+            chip.add_css_class("flat");
+            chip.set_tooltip_text(Some(&format!("Remove label “{}”", label)));
+            chip.set_sensitive(self.sync_manager.is_some());
+
+            let view = self.clone();
+            let message_id = message.id;
+            let label = label.clone();
+            chip.connect_clicked(move |_| view.remove_label(message_id, label.clone()));
+            chips.append(&chip);
+        }
+
+        chips
+    }
+
+    /// Remove a label from a message on the server, then show the result
+    fn remove_label(&self, message_id: Uuid, label: String) {
+        let Some(sync_manager) = self.sync_manager.clone() else {
+            return;
+        };
+        let view = self.clone();
+
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let result = tokio::spawn(async move {
+                let sync_manager = sync_manager.lock().await;
+                sync_manager.update_message_labels(message_id, &[], &[label]).await
+            }).await;
+
+            match result {
+                Ok(Ok(message)) => {
+                    if *view.displayed.borrow() == Some(message_id) {
+                        view.update_message_display(&message, false);
+                    }
+                }
+                Ok(Err(e)) => tracing::warn!("Failed to update labels of message {}: {}", message_id, e),
+                Err(e) => tracing::warn!("Label update task failed: {}", e),
+            }
+        });
+    }
+
+    /// Open the original source of `message`, fetching it if needed
+    fn show_original(&self, widget: &gtk4::Widget, message: &Message) {
+        let parent = widget.root().and_downcast::<gtk4::Window>();
+        let subject = message.headers.subject.clone();
+        if let Some(raw) = message.raw_content.clone() {
+            SourceWindow::new(parent.as_ref(), &subject, MessageSource::new(raw)).present();
+            return;
+        }
+
+        let Some(sync_manager) = self.sync_manager.clone() else {
+            tracing::warn!("No source available for message {}", message.id);
+            return;
+        };
+        let message_id = message.id;
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let result = tokio::spawn(async move {
+                let sync_manager = sync_manager.lock().await;
+                sync_manager.message_source(message_id).await
+            }).await;
+
+            match result {
+                Ok(Ok(raw)) => SourceWindow::new(parent.as_ref(), &subject, MessageSource::new(raw)).present(),
+                Ok(Err(e)) => tracing::warn!("Failed to fetch source of message {}: {}", message_id, e),
+                Err(e) => tracing::warn!("Source fetching task failed: {}", e),
+            }
+        });
+    }
+
+    /// Download bodies that sync left on the server and show them
+    fn load_body(&self, message_id: Uuid) {
+        let Some(sync_manager) = self.sync_manager.clone() else {
+            return;
+        };
+        let view = self.clone();
+
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let result = tokio::spawn(async move {
+                let sync_manager = sync_manager.lock().await;
+                sync_manager.load_message_body(message_id).await
+            }).await;
+
+            match result {
+                Ok(Ok(message)) => {
+                    if *view.displayed.borrow() == Some(message_id) {
+                        view.update_message_display(&message, false);
+                    }
+                }
+                Ok(Err(e)) => tracing::warn!("Failed to download body of message {}: {}", message_id, e),
+                Err(e) => tracing::warn!("Body download task failed: {}", e),
+            }
+        });
+    }
+
+    /// Verify DKIM signatures in the background, then show the message
+    /// again if it is still on display
+    fn verify_dkim(&self, message: &Message, load_remote: bool) {
+        let checker = self.authenticity.borrow().clone();
+        let view = self.clone();
+        let message = message.clone();
+
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let message_clone = message.clone();
+            let result = tokio::spawn(async move { checker.verify_dkim(&message_clone).await }).await;
+
+            match result {
+                Ok(results) => {
+                    let changed = !results.is_empty();
+                    view.dkim_results.borrow_mut().insert(message.id, results);
+                    if changed && *view.displayed.borrow() == Some(message.id) {
+                        view.update_message_display(&message, load_remote);
+                    }
+                }
+                Err(e) => tracing::warn!("DKIM verification task failed: {}", e),
+            }
+        });
+    }
+
+    /// Update the remote content allow lists and save the configuration
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    fn allow_remote_content(&self, update: impl FnOnce(&mut SecurityConfig)) {
+        let mut config = self.config.borrow_mut();
+        update(&mut config.security);
+        if let Err(e) = config.save(&config.config_file_path()) {
+            tracing::warn!("Failed to save remote content settings: {}", e);
+        }
+    }
+
+    /// Remember which trackers were removed from a message
+    #[cfg_attr(not(feature = "webkit"), allow(dead_code))]
+    fn record_trackers(&self, message_id: Uuid, trackers: &[Tracker]) {
+        let Some(storage) = self.storage.clone() else {
+            return;
+        };
+        if trackers.is_empty() {
+            return;
+        }
+
+        let trackers = trackers.to_vec();
+        gtk4::glib::MainContext::default().spawn_local(async move {
+            let result = tokio::spawn(async move {
+                let storage = storage.lock().await;
+                storage.database().save_blocked_trackers(message_id, &trackers).await
+            }).await;
+
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to record blocked trackers: {}", e),
+                Err(e) => tracing::warn!("Tracker recording task failed: {}", e),
+            }
+        });
+    }
+    
     fn create_avatar_widget(&self, sender: &str) -> gtk4::Widget {
         // Create a simple colored box with initials for now
         let drawing = DrawingArea::new();
@@ -275,6 +775,13 @@ impl MessageView {
     }
     
     fn get_message_body(&self, message: &Message) -> String {
+        if let Some(text) = message.text_content() {
+            return String::from_utf8_lossy(text).into_owned();
+        }
+        if let Some(content) = message.html_content() {
+            return html::to_plain_text(&String::from_utf8_lossy(content));
+        }
+
         // For demo purposes, return a sample body based on subject
         match message.headers.subject.as_str() {
             "Welcome to Asgard Mail" => "Welcome to Asgard Mail! This is a demo message to show you how the application works. You can compose, read, and manage your emails with this modern interface.\n\nThis email client is designed to be intuitive and efficient, with a clean interface inspired by Apple Mail. You can organize your emails into folders, search through your messages, and manage multiple email accounts.\n\nThank you for trying out Asgard Mail!".to_string(),
@@ -392,6 +899,41 @@ impl Clone for MessageView {
             scroller: self.scroller.clone(),
             cards: self.cards.clone(),
             current_message: RefCell::new(None),
+            keyring: self.keyring.clone(),
+            smime: self.smime.clone(),
+            sanitizer: self.sanitizer.clone(),
+            link_checker: self.link_checker.clone(),
+            config: self.config.clone(),
+            storage: self.storage.clone(),
+            sync_manager: self.sync_manager.clone(),
+            authenticity: self.authenticity.clone(),
+            dkim_results: self.dkim_results.clone(),
+            displayed: self.displayed.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Ask before opening a suspicious link, listing why it looks wrong
+#[cfg(feature = "webkit")]
+fn confirm_link(widget: &gtk4::Widget, check: asgard_core::links::LinkCheck) {
+    use libadwaita::prelude::*;
+
+    let parent = widget.root().and_downcast::<gtk4::Window>();
+    let mut body: Vec<String> = check.warnings.iter().map(|warning| format!("• {}", warning)).collect();
+    if check.is_redirected() {
+        body.push(format!("\nThe link redirects to:\n{}", check.target));
+    } else {
+        body.push(format!("\n{}", check.target));
+    }
+
+    let dialog = libadwaita::MessageDialog::new(parent.as_ref(), Some("Open Suspicious Link?"), Some(&body.join("\n")));
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("open", "Open Link");
+    dialog.set_response_appearance("open", libadwaita::ResponseAppearance::Destructive);
+    dialog.set_default_response(Some("cancel"));
+    dialog.set_close_response("cancel");
+    dialog.connect_response(Some("open"), move |_, _| {
+        crate::widgets::html_view::open_link(&check.target);
+    });
+    dialog.present();
+}
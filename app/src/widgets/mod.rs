@@ -1,5 +1,9 @@
 //! Reusable UI widgets for Asgard Mail
 
+pub mod attachment_strip;
+pub mod event_card;
+#[cfg(feature = "webkit")]
+pub mod html_view;
 pub mod mailbox_tree;
 pub mod message_list;
 pub mod message_view;
@@ -7,7 +11,11 @@ pub mod message_card;
 pub mod search_bar;
 pub mod status_bar;
 
-pub use mailbox_tree::MailboxTree;
+pub use attachment_strip::AttachmentStrip;
+pub use event_card::EventCard;
+#[cfg(feature = "webkit")]
+pub use html_view::HtmlView;
+pub use mailbox_tree::{MailboxAction, MailboxTree};
 pub use message_list::MessageList;
 pub use message_view::MessageView;
 // pub use message_card::MessageCard;
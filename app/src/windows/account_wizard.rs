@@ -1,7 +1,7 @@
 //! Account wizard for adding email accounts
 
 use gtk4::prelude::*;
-use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, Entry, Label, ComboBoxText, Separator};
+use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, CheckButton, Entry, Label, LinkButton, ComboBoxText, Separator, Spinner};
 // use libadwaita::prelude::*;
 use asgard_core::error::AsgardResult;
 
@@ -17,6 +17,14 @@ pub struct AccountWizard {
     password_entry: Entry,
     /// Provider combo box
     provider_combo: ComboBoxText,
+    /// Sign in from another device using a device code
+    device_flow_check: CheckButton,
+    /// Device code instructions, shown while waiting for approval
+    device_code_box: GtkBox,
+    /// User code to enter on the verification page
+    user_code_label: Label,
+    /// Verification page link
+    verification_link: LinkButton,
 }
 
 impl AccountWizard {
@@ -74,7 +82,7 @@ impl AccountWizard {
         provider_label.set_xalign(0.0);
         let provider_combo = ComboBoxText::new();
         provider_combo.append_text("Gmail");
-        provider_combo.append_text("Outlook");
+        provider_combo.append_text("Microsoft 365");
         provider_combo.append_text("Yahoo");
         provider_combo.append_text("Other");
         provider_combo.set_active(Some(0)); // Select Gmail by default
@@ -82,6 +90,57 @@ impl AccountWizard {
         content_box.append(&provider_label);
         content_box.append(&provider_combo);
 
+        // Device code sign-in, for machines without a local browser
+        let device_flow_check = CheckButton::with_label("Sign in from another device");
+        device_flow_check.set_sensitive(false);
+        content_box.append(&device_flow_check);
+
+        let device_code_box = GtkBox::new(Orientation::Vertical, 6);
+        device_code_box.set_visible(false);
+
+        let device_hint_label = Label::new(Some("On any device, open the page below and enter this code:"));
+        device_hint_label.set_wrap(true);
+        device_hint_label.set_xalign(0.0);
+        device_code_box.append(&device_hint_label);
+
+        let user_code_label = Label::new(None);
+        user_code_label.add_css_class("title-1");
+        user_code_label.set_selectable(true);
+        device_code_box.append(&user_code_label);
+
+        let verification_link = LinkButton::new("https://microsoft.com/devicelogin");
+        device_code_box.append(&verification_link);
+
+        let waiting_box = GtkBox::new(Orientation::Horizontal, 6);
+        waiting_box.set_halign(gtk4::Align::Center);
+        let spinner = Spinner::new();
+        spinner.start();
+        waiting_box.append(&spinner);
+        let waiting_label = Label::new(Some("Waiting for approval…"));
+        waiting_label.add_css_class("dim-label");
+        waiting_box.append(&waiting_label);
+        device_code_box.append(&waiting_box);
+
+        content_box.append(&device_code_box);
+
+        // Only Microsoft 365 offers the device code flow for mail scopes
+        let check_clone = device_flow_check.clone();
+        provider_combo.connect_changed(move |combo| {
+            let supported = combo.active_text().map(|text| text == "Microsoft 365").unwrap_or(false);
+            check_clone.set_sensitive(supported);
+            if !supported {
+                check_clone.set_active(false);
+            }
+        });
+
+        // The password is not used when signing in with a device code
+        let password_label_clone = password_label.clone();
+        let password_entry_clone = password_entry.clone();
+        device_flow_check.connect_toggled(move |check| {
+            password_label_clone.set_visible(!check.is_active());
+            password_entry_clone.set_visible(!check.is_active());
+        });
+
         // Separator
         let separator2 = Separator::new(Orientation::Horizontal);
         content_box.append(&separator2);
@@ -121,9 +180,31 @@ impl AccountWizard {
             email_entry,
             password_entry,
             provider_combo,
+            device_flow_check,
+            device_code_box,
+            user_code_label,
+            verification_link,
         })
     }
 
+    /// Check if the user chose to sign in from another device
+    pub fn uses_device_flow(&self) -> bool {
+        self.device_flow_check.is_active()
+    }
+
+    /// Show the device code and verification page while polling for approval
+    pub fn show_device_code(&self, user_code: &str, verification_uri: &str) {
+        self.user_code_label.set_text(user_code);
+        self.verification_link.set_uri(verification_uri);
+        self.verification_link.set_label(verification_uri);
+        self.device_code_box.set_visible(true);
+    }
+
+    /// Hide the device code once approval finished or failed
+    pub fn hide_device_code(&self) {
+        self.device_code_box.set_visible(false);
+    }
+
     /// Show the account wizard
     pub fn show(&self) {
         self.window.present();
@@ -153,6 +234,10 @@ impl Clone for AccountWizard {
             email_entry: self.email_entry.clone(),
             password_entry: self.password_entry.clone(),
             provider_combo: self.provider_combo.clone(),
+            device_flow_check: self.device_flow_check.clone(),
+            device_code_box: self.device_code_box.clone(),
+            user_code_label: self.user_code_label.clone(),
+            verification_link: self.verification_link.clone(),
         }
     }
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
 //! Compose window for writing emails
 
 use gtk4::prelude::*;
-use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, Entry, TextView, Label, ScrolledWindow};
+use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, Entry, TextView, Label, ScrolledWindow, ToggleButton};
 // use libadwaita::prelude::*;
 use asgard_core::error::AsgardResult;
+use asgard_core::autocrypt::Recommendation;
+use asgard_core::openpgp::ComposeOptions;
 
 /// Compose window for writing emails
 pub struct ComposeWindow {
@@ -19,6 +21,12 @@ pub struct ComposeWindow {
     message_text: TextView,
     /// Send button
     send_button: Button,
+    /// OpenPGP sign toggle
+    sign_toggle: ToggleButton,
+    /// OpenPGP encrypt toggle
+    encrypt_toggle: ToggleButton,
+    /// Use S/MIME instead of OpenPGP
+    smime_toggle: ToggleButton,
 }
 
 impl ComposeWindow {
@@ -65,6 +73,25 @@ impl ComposeWindow {
         let button_box = GtkBox::new(Orientation::Horizontal, 8);
         button_box.set_halign(gtk4::Align::End);
 
+        // Signing and encryption toggles
+        let sign_toggle = ToggleButton::with_label("Sign");
+        sign_toggle.set_tooltip_text(Some("Sign with your OpenPGP key"));
+        let encrypt_toggle = ToggleButton::with_label("Encrypt");
+        encrypt_toggle.set_tooltip_text(Some("Encrypt to all recipients with OpenPGP"));
+        let smime_toggle = ToggleButton::with_label("S/MIME");
+        smime_toggle.set_tooltip_text(Some("Sign and encrypt with your S/MIME certificate instead of OpenPGP"));
+
+        let sign_clone = sign_toggle.clone();
+        let encrypt_clone = encrypt_toggle.clone();
+        smime_toggle.connect_toggled(move |toggle| {
+            let method = if toggle.is_active() { "S/MIME certificate" } else { "OpenPGP key" };
+            sign_clone.set_tooltip_text(Some(&format!("Sign with your {}", method)));
+            encrypt_clone.set_tooltip_text(Some(&format!("Encrypt to all recipients' {}s", method)));
+        });
+
+        let spacer = GtkBox::new(Orientation::Horizontal, 0);
+        spacer.set_hexpand(true);
+
         let send_button = Button::with_label("Send");
         send_button.add_css_class("suggested-action");
         
@@ -87,6 +114,13 @@ impl ComposeWindow {
         button_box.append(&cancel_button);
         button_box.append(&send_button);
 
+        let action_bar = GtkBox::new(Orientation::Horizontal, 8);
+        action_bar.append(&sign_toggle);
+        action_bar.append(&encrypt_toggle);
+        action_bar.append(&smime_toggle);
+        action_bar.append(&spacer);
+        action_bar.append(&button_box);
+
         // Assemble the layout
         content_box.append(&to_label);
         content_box.append(&to_entry);
@@ -94,7 +128,7 @@ impl ComposeWindow {
         content_box.append(&subject_entry);
         content_box.append(&message_label);
         content_box.append(&message_scrolled);
-        content_box.append(&button_box);
+        content_box.append(&action_bar);
 
         window.set_child(Some(&content_box));
 
@@ -105,9 +139,35 @@ impl ComposeWindow {
             subject_entry,
             message_text,
             send_button,
+            sign_toggle,
+            encrypt_toggle,
+            smime_toggle,
         })
     }
 
+    /// Options chosen with the sign, encrypt and S/MIME toggles
+    pub fn compose_options(&self) -> ComposeOptions {
+        ComposeOptions {
+            sign: self.sign_toggle.is_active(),
+            encrypt: self.encrypt_toggle.is_active(),
+            smime: self.smime_toggle.is_active(),
+        }
+    }
+
+    /// Set the encrypt toggle from the Autocrypt recommendation for the recipients
+    ///
+    /// Only `Encrypt` turns encryption on; the user can still override it.
+    pub fn apply_recommendation(&self, recommendation: Recommendation) {
+        let tooltip = match recommendation {
+            Recommendation::Disable => "Not every recipient has an encryption key",
+            Recommendation::Discourage => "Some recipients may not be able to read an encrypted message",
+            Recommendation::Available => "Encrypt to all recipients with OpenPGP",
+            Recommendation::Encrypt => "Encrypted because all recipients prefer it",
+        };
+        self.encrypt_toggle.set_active(recommendation == Recommendation::Encrypt);
+        self.encrypt_toggle.set_tooltip_text(Some(tooltip));
+    }
+
     /// Show the compose window
     pub fn show(&self) {
         self.window.present();
@@ -138,6 +198,9 @@ impl Clone for ComposeWindow {
             subject_entry: self.subject_entry.clone(),
             message_text: self.message_text.clone(),
             send_button: self.send_button.clone(),
+            sign_toggle: self.sign_toggle.clone(),
+            encrypt_toggle: self.encrypt_toggle.clone(),
+            smime_toggle: self.smime_toggle.clone(),
         }
     }
 }
\ No newline at end of file
@@ -1,9 +1,13 @@
 //! Main window for Asgard Mail
 
 use crate::notifications::NotificationManager;
-use crate::widgets::{MailboxTree, MessageList, MessageView, SearchBar, StatusBar};
+use crate::widgets::{MailboxAction, MailboxTree, MessageList, MessageView, SearchBar, StatusBar};
+use crate::windows::PreferencesWindow;
+use asgard_core::autocrypt::AutocryptAccount;
 use asgard_core::error::AsgardResult;
 use asgard_core::config::Config;
+use asgard_core::openpgp::PgpKeyring;
+use asgard_core::smime::SmimeContext;
 use asgard_core::storage::StorageManager;
 // use asgard_core::search::TantivySearchIndex;
 use asgard_core::sync::SyncManager;
@@ -11,9 +15,10 @@ use asgard_core::sync::SyncManager;
 use gtk4::prelude::*;
 use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Paned, Label, Button, HeaderBar, Align};
 // use libadwaita::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-// use std::rc::Rc;
 // use std::cell::RefCell;
 
 /// Main application window
@@ -54,10 +59,10 @@ impl MainWindow {
         config: Config,
         storage: Arc<Mutex<StorageManager>>,
         // search_index: Arc<Mutex<TantivySearchIndex>>,
-        _sync_manager: Arc<Mutex<SyncManager>>,
+        sync_manager: Arc<Mutex<SyncManager>>,
         // token_manager: TokenManager,
         _notification_manager: NotificationManager,
-        _demo_mode: bool,
+        demo_mode: bool,
     ) -> AsgardResult<Self> {
         // Create application window
         let window = ApplicationWindow::builder()
@@ -153,6 +158,10 @@ impl MainWindow {
         search_button.set_tooltip_text(Some("Search"));
         search_button.add_css_class("flat");
 
+        let preferences_button = Button::from_icon_name("preferences-system-symbolic");
+        preferences_button.set_tooltip_text(Some("Preferences"));
+        preferences_button.add_css_class("flat");
+
         // Create header container
         let header_container = GtkBox::new(Orientation::Horizontal, 0);
         header_container.set_hexpand(true);
@@ -176,6 +185,7 @@ impl MainWindow {
         header_right.append(&archive_button);
         header_right.append(&delete_button);
         header_right.append(&search_button);
+        header_right.append(&preferences_button);
 
         // Add sections to container
         header_container.append(&header_left);
@@ -209,12 +219,170 @@ impl MainWindow {
         // Create widgets
         let mailbox_tree = MailboxTree::new(storage.clone());
         let message_list = MessageList::new(storage.clone());
-        let message_view = MessageView::new();
+        let mut message_view = MessageView::new();
+        match PgpKeyring::default_dir().and_then(PgpKeyring::open) {
+            Ok(mut keyring) => {
+                // Mail encrypted to an account's Autocrypt key must be readable too
+                for account in Self::autocrypt_accounts(&storage).await {
+                    if let Err(e) = keyring.add_secret_key(account.secret_key().clone()) {
+                        tracing::warn!("Autocrypt key of {} unusable: {}", account.email(), e);
+                    }
+                }
+                message_view.set_keyring(Arc::new(keyring));
+            }
+            Err(e) => tracing::warn!("OpenPGP keyring unavailable: {}", e),
+        }
+        match SmimeContext::default_dir().and_then(|dir| SmimeContext::open(&config.security, None, dir)) {
+            Ok(smime) => message_view.set_smime(Arc::new(smime)),
+            Err(e) => tracing::warn!("S/MIME trust store unavailable: {}", e),
+        }
+        message_view.set_config(config.clone());
+        message_view.set_storage(storage.clone());
+        message_view.set_sync_manager(sync_manager.clone());
         let search_bar = SearchBar::new();
         let status_bar = StatusBar::new();
         
-        // Build the mailbox tree with demo data
-        mailbox_tree.build_demo();
+        // Build the mailbox tree from the stored accounts
+        if demo_mode {
+            mailbox_tree.build_demo();
+        } else {
+            mailbox_tree.refresh_accounts().await;
+        }
+
+        // Preferences, with the storage usage of every account
+        let preferences_storage = storage.clone();
+        let preferences_sync_manager = sync_manager.clone();
+        let encryption_enabled = Rc::new(Cell::new(config.encryption_at_rest()));
+        preferences_button.connect_clicked(move |_| {
+            let preferences = match PreferencesWindow::new() {
+                Ok(preferences) => preferences,
+                Err(e) => {
+                    tracing::error!("Failed to open preferences: {}", e);
+                    return;
+                }
+            };
+            preferences.set_encryption_active(encryption_enabled.get());
+            preferences.show();
+
+            // Switching encryption at rest re-encodes the stored mail
+            let encryption_storage = preferences_storage.clone();
+            let encryption_enabled = encryption_enabled.clone();
+            let encryption_preferences = preferences.clone();
+            preferences.connect_encryption_changed(move |enabled| {
+                encryption_enabled.set(enabled);
+                encryption_preferences.set_encryption_status(
+                    if enabled { "Encrypting stored mail…" } else { "Decrypting stored mail…" },
+                );
+
+                let storage = encryption_storage.clone();
+                let preferences = encryption_preferences.clone();
+                gtk4::glib::MainContext::default().spawn_local(async move {
+                    let result = match tokio::spawn(async move { storage.lock().await.set_encryption(enabled) }).await {
+                        Ok(reencode) => reencode.await,
+                        Err(e) => Err(e),
+                    };
+                    let status = match result {
+                        Ok(Ok(0)) => "Stored mail already matches this setting".to_string(),
+                        Ok(Ok(converted)) => format!("Re-encoded {} stored item(s)", converted),
+                        Ok(Err(e)) => {
+                            tracing::warn!("Failed to re-encode stored messages: {}", e);
+                            format!("Failed to re-encode stored mail: {}", e)
+                        }
+                        Err(e) => {
+                            tracing::error!("Re-encoding task failed: {}", e);
+                            "Failed to re-encode stored mail".to_string()
+                        }
+                    };
+                    preferences.set_encryption_status(&status);
+                });
+            });
+
+            let storage = preferences_storage.clone();
+            let sync_manager = preferences_sync_manager.clone();
+            gtk4::glib::MainContext::default().spawn_local(async move {
+                let accounts = tokio::spawn(async move {
+                    let storage = storage.lock().await;
+                    storage.database().get_accounts().await
+                }).await;
+                let accounts = match accounts {
+                    Ok(Ok(accounts)) => accounts,
+                    Ok(Err(e)) => {
+                        tracing::warn!("Failed to load accounts: {}", e);
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::error!("Account loading task failed: {}", e);
+                        return;
+                    }
+                };
+
+                for account in accounts {
+                    let sync_manager = sync_manager.clone();
+                    let account_id = account.id;
+                    let result = tokio::spawn(async move {
+                        sync_manager.lock().await.refresh_storage_usage(account_id).await
+                    }).await;
+
+                    match result {
+                        Ok(Ok(report)) => preferences.set_storage_report(account.email(), &report),
+                        Ok(Err(e)) => tracing::warn!("Failed to refresh storage usage of {}: {}", account.email(), e),
+                        Err(e) => tracing::error!("Storage usage task failed: {}", e),
+                    }
+                }
+            });
+        });
+
+        // Selecting a folder lists its messages
+        let selection_sync_manager = sync_manager.clone();
+        let list_clone = message_list.clone();
+        mailbox_tree.connect_mailbox_selected(move |mailbox_id| {
+            let sync_manager = selection_sync_manager.clone();
+            let message_list = list_clone.clone();
+            gtk4::glib::MainContext::default().spawn_local(async move {
+                let result = tokio::spawn(async move {
+                    let manager = sync_manager.lock().await;
+                    manager.mailbox_messages(mailbox_id, None, None).await
+                }).await;
+
+                match result {
+                    Ok(Ok(messages)) => message_list.show_messages(messages),
+                    Ok(Err(e)) => tracing::warn!("Failed to load messages of mailbox {}: {}", mailbox_id, e),
+                    Err(e) => tracing::error!("Message loading task failed: {}", e),
+                }
+            });
+        });
+
+        // Folder management from the mailbox tree context menu
+        let tree_clone = mailbox_tree.clone();
+        mailbox_tree.connect_mailbox_action(move |action| {
+            let sync_manager = sync_manager.clone();
+            let tree = tree_clone.clone();
+            gtk4::glib::MainContext::default().spawn_local(async move {
+                let result = tokio::spawn(async move {
+                    let manager = sync_manager.lock().await;
+                    match action {
+                        MailboxAction::Create { account_id, parent_id, name } => {
+                            manager.create_mailbox(account_id, parent_id, &name).await.map(|_| ())
+                        }
+                        MailboxAction::Rename { mailbox_id, new_name } => {
+                            manager.rename_mailbox(mailbox_id, &new_name).await
+                        }
+                        MailboxAction::Delete { mailbox_id } => {
+                            manager.delete_mailbox(mailbox_id).await
+                        }
+                        MailboxAction::SetSubscribed { mailbox_id, subscribed } => {
+                            manager.set_mailbox_subscribed(mailbox_id, subscribed).await
+                        }
+                    }
+                }).await;
+
+                match result {
+                    Ok(Ok(())) => tree.refresh_accounts().await,
+                    Ok(Err(e)) => tracing::warn!("Mailbox action failed: {}", e),
+                    Err(e) => tracing::error!("Mailbox action task failed: {}", e),
+                }
+            });
+        });
         
         // Update the message list with demo data
         message_list.update_messages("demo:INBOX");
@@ -270,6 +438,27 @@ impl MainWindow {
         })
     }
 
+    /// Autocrypt keys of all accounts, generating missing ones
+    async fn autocrypt_accounts(storage: &Arc<Mutex<StorageManager>>) -> Vec<AutocryptAccount> {
+        let storage = storage.lock().await;
+        let database = storage.database();
+        let accounts = match database.get_accounts().await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                tracing::warn!("Cannot list accounts for Autocrypt: {}", e);
+                return Vec::new();
+            }
+        };
+
+        accounts.iter()
+            .filter_map(|account| {
+                AutocryptAccount::load_or_generate(database.secrets(), account.id, account.email())
+                    .map_err(|e| tracing::warn!("Autocrypt key unavailable for {}: {}", account.email(), e))
+                    .ok()
+            })
+            .collect()
+    }
+
     /// Show the main window
     pub fn show(&self) {
         self.window.present();
@@ -4,8 +4,9 @@ pub mod main_window;
 pub mod compose_window;
 pub mod preferences_window;
 pub mod account_wizard;
+pub mod source_window;
 
 pub use main_window::MainWindow;
 // pub use compose_window::ComposeWindow;
-// pub use preferences_window::PreferencesWindow;
+pub use preferences_window::PreferencesWindow;
 // pub use account_wizard::AccountWizard;
@@ -1,9 +1,16 @@
 //! Preferences window for application settings
 
 use gtk4::prelude::*;
-use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, Label, Switch, Separator};
+use gtk4::{ApplicationWindow, Box as GtkBox, Orientation, Button, Label, Switch, Separator, LevelBar};
 // use libadwaita::prelude::*;
 use asgard_core::error::AsgardResult;
+use asgard_core::storage::usage::{format_bytes, StorageReport, QUOTA_WARNING_THRESHOLD};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Number of folders and messages listed in the storage view
+const STORAGE_TOP_ENTRIES: usize = 5;
 
 /// Preferences window for application settings
 pub struct PreferencesWindow {
@@ -11,6 +18,14 @@ pub struct PreferencesWindow {
     window: ApplicationWindow,
     /// Main content box
     content_box: GtkBox,
+    /// Encryption at rest toggle
+    encryption_switch: Switch,
+    /// Progress and result of re-encoding stored mail
+    encryption_status: Label,
+    /// Storage usage section
+    storage_box: GtkBox,
+    /// Storage report of each account, by account name
+    storage_sections: Rc<RefCell<HashMap<String, GtkBox>>>,
 }
 
 impl PreferencesWindow {
@@ -74,6 +89,50 @@ impl PreferencesWindow {
         let separator2 = Separator::new(Orientation::Horizontal);
         content_box.append(&separator2);
 
+        // Security section
+        let security_label = Label::new(Some("Security"));
+        security_label.add_css_class("title-2");
+        content_box.append(&security_label);
+
+        // Encryption at rest setting
+        let encryption_box = GtkBox::new(Orientation::Horizontal, 12);
+        let encryption_label = Label::new(Some("Encrypt stored mail"));
+        encryption_label.set_hexpand(true);
+        encryption_label.set_xalign(0.0);
+
+        let encryption_switch = Switch::new();
+
+        encryption_box.append(&encryption_label);
+        encryption_box.append(&encryption_switch);
+        content_box.append(&encryption_box);
+
+        let encryption_status = Label::new(None);
+        encryption_status.add_css_class("dim-label");
+        encryption_status.set_xalign(0.0);
+        encryption_status.set_wrap(true);
+        encryption_status.set_visible(false);
+        content_box.append(&encryption_status);
+
+        // Separator
+        let separator3 = Separator::new(Orientation::Horizontal);
+        content_box.append(&separator3);
+
+        // Storage section
+        let storage_label = Label::new(Some("Storage"));
+        storage_label.add_css_class("title-2");
+        content_box.append(&storage_label);
+
+        let storage_box = GtkBox::new(Orientation::Vertical, 8);
+        let storage_placeholder = Label::new(Some("Checking storage usage…"));
+        storage_placeholder.add_css_class("dim-label");
+        storage_placeholder.set_xalign(0.0);
+        storage_box.append(&storage_placeholder);
+        content_box.append(&storage_box);
+
+        // Separator
+        let separator4 = Separator::new(Orientation::Horizontal);
+        content_box.append(&separator4);
+
         // Buttons
         let button_box = GtkBox::new(Orientation::Horizontal, 8);
         button_box.set_halign(gtk4::Align::End);
@@ -95,9 +154,103 @@ impl PreferencesWindow {
         Ok(Self {
             window,
             content_box,
+            encryption_switch,
+            encryption_status,
+            storage_box,
+            storage_sections: Rc::new(RefCell::new(HashMap::new())),
         })
     }
 
+    /// Set the encryption switch without notifying the change handler
+    ///
+    /// Call before [`PreferencesWindow::connect_encryption_changed`].
+    pub fn set_encryption_active(&self, enabled: bool) {
+        self.encryption_switch.set_active(enabled);
+    }
+
+    /// Call `callback` with the new setting when the encryption switch is toggled
+    pub fn connect_encryption_changed<F>(&self, callback: F)
+    where
+        F: Fn(bool) + 'static,
+    {
+        self.encryption_switch.connect_active_notify(move |switch| callback(switch.is_active()));
+    }
+
+    /// Show the progress or result of re-encoding stored mail
+    pub fn set_encryption_status(&self, status: &str) {
+        self.encryption_status.set_text(status);
+        self.encryption_status.set_visible(true);
+    }
+
+    /// Show the storage usage of an account, replacing its previous report
+    pub fn set_storage_report(&self, account_name: &str, report: &StorageReport) {
+        let mut sections = self.storage_sections.borrow_mut();
+        if sections.is_empty() {
+            while let Some(child) = self.storage_box.first_child() {
+                self.storage_box.remove(&child);
+            }
+        }
+        let section = sections.entry(account_name.to_string()).or_insert_with(|| {
+            let section = GtkBox::new(Orientation::Vertical, 8);
+            self.storage_box.append(&section);
+            section
+        });
+        while let Some(child) = section.first_child() {
+            section.remove(&child);
+        }
+
+        let account_label = Label::new(Some(account_name));
+        account_label.add_css_class("heading");
+        account_label.set_xalign(0.0);
+        section.append(&account_label);
+
+        let usage_text = match report.limit_bytes {
+            Some(limit) => format!("{} of {} used", format_bytes(report.used_bytes), format_bytes(limit)),
+            None => format!("{} used", format_bytes(report.used_bytes)),
+        };
+        let usage_label = Label::new(Some(&usage_text));
+        usage_label.set_xalign(0.0);
+        section.append(&usage_label);
+
+        if let Some(ratio) = report.usage_ratio() {
+            let level_bar = LevelBar::for_interval(0.0, 1.0);
+            level_bar.add_offset_value("storage-warning", QUOTA_WARNING_THRESHOLD);
+            level_bar.set_value(ratio.min(1.0));
+            section.append(&level_bar);
+        }
+
+        if report.is_near_quota() {
+            let warning = Label::new(Some(
+                "Your mailbox is almost full. Delete large messages or empty the trash to keep receiving mail.",
+            ));
+            warning.add_css_class("warning");
+            warning.set_wrap(true);
+            warning.set_xalign(0.0);
+            section.append(&warning);
+        }
+
+        let folders_label = Label::new(Some("Largest folders"));
+        folders_label.add_css_class("dim-label");
+        folders_label.set_xalign(0.0);
+        section.append(&folders_label);
+
+        for mailbox in report.mailboxes.iter().take(STORAGE_TOP_ENTRIES) {
+            section.append(&storage_row(mailbox.display_name(), mailbox.stats.size));
+        }
+
+        if !report.largest_messages.is_empty() {
+            let messages_label = Label::new(Some("Largest messages"));
+            messages_label.add_css_class("dim-label");
+            messages_label.set_xalign(0.0);
+            section.append(&messages_label);
+
+            for message in report.largest_messages.iter().take(STORAGE_TOP_ENTRIES) {
+                let subject = if message.subject().is_empty() { "(No subject)" } else { message.subject() };
+                section.append(&storage_row(subject, message.size as u64));
+            }
+        }
+    }
+
     /// Show the preferences window
     pub fn show(&self) {
         self.window.present();
@@ -119,11 +272,31 @@ impl PreferencesWindow {
     }
 }
 
+fn storage_row(name: &str, size: u64) -> GtkBox {
+    let row = GtkBox::new(Orientation::Horizontal, 12);
+
+    let name_label = Label::new(Some(name));
+    name_label.set_hexpand(true);
+    name_label.set_xalign(0.0);
+    name_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+
+    let size_label = Label::new(Some(&format_bytes(size)));
+    size_label.add_css_class("dim-label");
+
+    row.append(&name_label);
+    row.append(&size_label);
+    row
+}
+
 impl Clone for PreferencesWindow {
     fn clone(&self) -> Self {
         Self {
             window: self.window.clone(),
             content_box: self.content_box.clone(),
+            encryption_switch: self.encryption_switch.clone(),
+            encryption_status: self.encryption_status.clone(),
+            storage_box: self.storage_box.clone(),
+            storage_sections: self.storage_sections.clone(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,156 @@
+//! Window showing the original source of a message
+
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Button, FileChooserAction, FileChooserNative, Label, Orientation, ResponseType, ScrolledWindow, TextBuffer, TextTag, TextView, ToggleButton, Window};
+use asgard_core::source::{eml_filename, MessageSource};
+use asgard_core::storage::usage::format_bytes;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// "Show original" window with highlighted headers and the body either
+/// as transferred or decoded
+pub struct SourceWindow {
+    /// GTK window
+    window: Window,
+}
+
+impl SourceWindow {
+    /// Create a window showing `source` for the message titled `subject`
+    pub fn new(parent: Option<&Window>, subject: &str, source: MessageSource) -> Self {
+        let window = Window::builder()
+            .title(format!("Original Message – {}", subject))
+            .default_width(800)
+            .default_height(600)
+            .build();
+        window.set_transient_for(parent);
+
+        let buffer = TextBuffer::new(None);
+        buffer.tag_table().add(&TextTag::builder().name("header-name").weight(700).foreground("#1c71d8").build());
+        buffer.tag_table().add(&TextTag::builder().name("header-value").foreground("#613583").build());
+        buffer.tag_table().add(&TextTag::builder().name("notice").style(gtk4::pango::Style::Italic).foreground("#c01c28").build());
+
+        let text_view = TextView::builder()
+            .buffer(&buffer)
+            .editable(false)
+            .monospace(true)
+            .wrap_mode(gtk4::WrapMode::WordChar)
+            .left_margin(12)
+            .right_margin(12)
+            .top_margin(12)
+            .bottom_margin(12)
+            .build();
+        text_view.add_css_class("message-source");
+
+        let scroller = ScrolledWindow::new();
+        scroller.set_vexpand(true);
+        scroller.set_child(Some(&text_view));
+
+        // Toolbar: size | spacer | decoded toggle | save
+        let toolbar = GtkBox::new(Orientation::Horizontal, 8);
+        toolbar.set_margin_start(12);
+        toolbar.set_margin_end(12);
+        toolbar.set_margin_top(8);
+        toolbar.set_margin_bottom(8);
+
+        let size_label = Label::new(Some(&format_bytes(source.len() as u64)));
+        size_label.add_css_class("dim-label");
+
+        let spacer = GtkBox::new(Orientation::Horizontal, 0);
+        spacer.set_hexpand(true);
+
+        let decoded = ToggleButton::with_label("Decoded");
+        decoded.set_tooltip_text(Some("Show the body with transfer encodings and charsets decoded"));
+
+        let save = Button::with_label("Save as .eml");
+        save.add_css_class("suggested-action");
+
+        toolbar.append(&size_label);
+        toolbar.append(&spacer);
+        toolbar.append(&decoded);
+        toolbar.append(&save);
+
+        let content = GtkBox::new(Orientation::Vertical, 0);
+        content.append(&toolbar);
+        content.append(&scroller);
+        window.set_child(Some(&content));
+
+        let source = Rc::new(source);
+        Self::fill_buffer(&buffer, &source, false);
+        {
+            let source = source.clone();
+            decoded.connect_toggled(move |button| {
+                Self::fill_buffer(&buffer, &source, button.is_active());
+            });
+        }
+        {
+            let window = window.clone();
+            let filename = eml_filename(subject);
+            save.connect_clicked(move |_| {
+                Self::save(&window, &filename, source.clone());
+            });
+        }
+
+        Self { window }
+    }
+
+    /// Show the window
+    pub fn present(&self) {
+        self.window.present();
+    }
+
+    /// Write the headers and the requested body view into `buffer`
+    fn fill_buffer(buffer: &TextBuffer, source: &MessageSource, decoded: bool) {
+        buffer.set_text("");
+        let mut end = buffer.end_iter();
+
+        for field in source.headers() {
+            if field.name.is_empty() {
+                buffer.insert(&mut end, &field.value);
+            } else {
+                buffer.insert_with_tags_by_name(&mut end, &format!("{}:", field.name), &["header-name"]);
+                buffer.insert_with_tags_by_name(&mut end, &field.value, &["header-value"]);
+            }
+            buffer.insert(&mut end, "\n");
+        }
+        buffer.insert(&mut end, "\n");
+
+        if !decoded {
+            buffer.insert(&mut end, &source.body());
+            return;
+        }
+        match source.decoded_body() {
+            Ok(body) => buffer.insert(&mut end, &body),
+            Err(e) => {
+                buffer.insert_with_tags_by_name(&mut end, &format!("Could not decode the message: {}\n\n", e), &["notice"]);
+                buffer.insert(&mut end, &source.body());
+            }
+        }
+    }
+
+    /// Ask for a location and write the unmodified source there
+    fn save(window: &Window, filename: &str, source: Rc<MessageSource>) {
+        let chooser = FileChooserNative::new(
+            Some("Save Message"),
+            Some(window),
+            FileChooserAction::Save,
+            Some("_Save"),
+            Some("_Cancel"),
+        );
+        chooser.set_current_name(filename);
+
+        // Native dialogs are not kept alive by GTK, so hold on to it until
+        // it answers
+        let keep_alive = Rc::new(RefCell::new(Some(chooser.clone())));
+        chooser.connect_response(move |chooser, response| {
+            if response == ResponseType::Accept {
+                if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                    if let Err(e) = std::fs::write(&path, source.raw()) {
+                        tracing::warn!("Failed to save message to {}: {}", path.display(), e);
+                    }
+                }
+            }
+            keep_alive.borrow_mut().take();
+        });
+        chooser.show();
+    }
+}
@@ -2,6 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
@@ -13,6 +14,8 @@ use crate::error::{AsgardError, AsgardResult};
 pub enum AccountType {
     /// Gmail account with OAuth2
     Gmail,
+    /// Microsoft 365 / Outlook.com account with OAuth2
+    Microsoft365,
     /// Generic IMAP/SMTP account
     ImapSmtp,
     /// POP3 account
@@ -23,6 +26,7 @@ impl std::fmt::Display for AccountType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AccountType::Gmail => write!(f, "Gmail"),
+            AccountType::Microsoft365 => write!(f, "Microsoft 365"),
             AccountType::ImapSmtp => write!(f, "IMAP/SMTP"),
             AccountType::Pop3 => write!(f, "POP3"),
         }
@@ -39,6 +43,8 @@ pub enum AccountStatus {
     Paused,
     /// Account has authentication issues
     AuthError,
+    /// OAuth refresh token was revoked; the user must sign in again
+    ReauthRequired,
     /// Account has connection issues
     ConnectionError,
     /// Account is disabled
@@ -51,12 +57,16 @@ impl std::fmt::Display for AccountStatus {
             AccountStatus::Active => write!(f, "Active"),
             AccountStatus::Paused => write!(f, "Paused"),
             AccountStatus::AuthError => write!(f, "Authentication Error"),
+            AccountStatus::ReauthRequired => write!(f, "Sign-in Required"),
             AccountStatus::ConnectionError => write!(f, "Connection Error"),
             AccountStatus::Disabled => write!(f, "Disabled"),
         }
     }
 }
 
+/// Account setting with the Microsoft Entra tenant used for sign-in
+pub const MICROSOFT_TENANT_SETTING: &str = "microsoft_tenant";
+
 /// IMAP/SMTP server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
@@ -84,16 +94,20 @@ pub enum AuthMethod {
     AppPassword,
 }
 
-/// Gmail OAuth2 configuration
+/// OAuth2 client configuration
+///
+/// Named for Gmail, where it was first used; Microsoft 365 accounts store
+/// their credentials here too. In memory the secrets are plain values;
+/// the database only stores [`crate::secrets::SecretStore`] references.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GmailOAuthConfig {
     /// OAuth2 client ID
     pub client_id: String,
-    /// OAuth2 client secret
+    /// OAuth2 client secret (empty for public clients)
     pub client_secret: String,
-    /// OAuth2 access token (encrypted)
+    /// OAuth2 access token
     pub access_token: Option<String>,
-    /// OAuth2 refresh token (encrypted)
+    /// OAuth2 refresh token
     pub refresh_token: Option<String>,
     /// Token expiration time
     pub token_expires_at: Option<OffsetDateTime>,
@@ -116,14 +130,37 @@ pub struct AccountConfig {
     pub smtp: Option<ServerConfig>,
     /// POP3 server configuration
     pub pop3: Option<ServerConfig>,
-    /// Gmail OAuth2 configuration
+    /// OAuth2 configuration (Gmail and Microsoft 365)
     pub gmail_oauth: Option<GmailOAuthConfig>,
     /// Sync settings
     pub sync_settings: SyncSettings,
+    /// Calendar that meeting invitations are added to
+    #[serde(default)]
+    pub calendar: Option<CalendarTarget>,
     /// Account-specific settings
     pub settings: HashMap<String, serde_json::Value>,
 }
 
+/// Calendar that "Add to calendar" writes meeting invitations to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CalendarTarget {
+    /// Local iCalendar file holding all events
+    File {
+        /// Path of the `.ics` file, created on first use
+        path: PathBuf,
+    },
+    /// CalDAV collection, one resource per event
+    CalDav {
+        /// URL of the calendar collection
+        url: String,
+        /// User name for HTTP basic authentication
+        username: Option<String>,
+        /// Password (a secret reference when stored)
+        password: Option<String>,
+    },
+}
+
 /// Sync settings for an account
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncSettings {
@@ -139,10 +176,19 @@ pub struct SyncSettings {
     pub delete_after_sync: bool,
     /// Sync folders/labels
     pub sync_folders: bool,
-    /// Sync attachments
+    /// Download attachments with prefetched bodies; otherwise they are
+    /// fetched only when opened or saved
     pub sync_attachments: bool,
-    /// Maximum attachment size in bytes
+    /// Largest attachment downloaded during sync, in bytes
     pub max_attachment_size: usize,
+    /// Number of most recent messages per mailbox whose bodies are
+    /// downloaded during sync; other bodies are fetched when opened
+    #[serde(default = "default_prefetch_bodies")]
+    pub prefetch_bodies: usize,
+}
+
+fn default_prefetch_bodies() -> usize {
+    50
 }
 
 impl Default for SyncSettings {
@@ -156,6 +202,7 @@ impl Default for SyncSettings {
             sync_folders: true,
             sync_attachments: true,
             max_attachment_size: 25 * 1024 * 1024, // 25MB
+            prefetch_bodies: default_prefetch_bodies(),
         }
     }
 }
@@ -194,6 +241,9 @@ pub struct AccountStats {
     pub last_message_received: Option<OffsetDateTime>,
     /// Total storage used in bytes
     pub storage_used: u64,
+    /// Storage quota in bytes, if the server reports one
+    #[serde(default)]
+    pub storage_quota: Option<u64>,
     /// Number of sync operations
     pub sync_count: u64,
     /// Number of failed syncs
@@ -235,6 +285,56 @@ impl Account {
                 pop3: None,
                 gmail_oauth: Some(oauth_config),
                 sync_settings: SyncSettings::default(),
+                calendar: None,
+                settings: HashMap::new(),
+            },
+            status: AccountStatus::Active,
+            last_sync: None,
+            last_error: None,
+            created_at: OffsetDateTime::now_utc(),
+            updated_at: OffsetDateTime::now_utc(),
+            stats: AccountStats::default(),
+        })
+    }
+
+    /// Create a new Microsoft 365 account
+    ///
+    /// IMAP and SMTP both authenticate with XOAUTH2 using tokens from the
+    /// Microsoft identity platform.
+    pub fn new_microsoft365(
+        email: String,
+        display_name: Option<String>,
+        oauth_config: GmailOAuthConfig,
+    ) -> AsgardResult<Self> {
+        // Basic email validation - replace with proper validation later
+        if !email.contains('@') {
+            return Err(AsgardError::validation("Invalid email address"));
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            config: AccountConfig {
+                account_type: AccountType::Microsoft365,
+                display_name: display_name.unwrap_or_else(|| email.to_string()),
+                email: email.to_string(),
+                imap: Some(ServerConfig {
+                    host: "outlook.office365.com".to_string(),
+                    port: 993,
+                    use_tls: true,
+                    use_starttls: false,
+                    auth_method: AuthMethod::OAuth2,
+                }),
+                smtp: Some(ServerConfig {
+                    host: "smtp.office365.com".to_string(),
+                    port: 587,
+                    use_tls: false,
+                    use_starttls: true,
+                    auth_method: AuthMethod::OAuth2,
+                }),
+                pop3: None,
+                gmail_oauth: Some(oauth_config),
+                sync_settings: SyncSettings::default(),
+                calendar: None,
                 settings: HashMap::new(),
             },
             status: AccountStatus::Active,
@@ -269,6 +369,7 @@ impl Account {
                 pop3: None,
                 gmail_oauth: None,
                 sync_settings: SyncSettings::default(),
+                calendar: None,
                 settings: HashMap::new(),
             },
             status: AccountStatus::Active,
@@ -302,6 +403,7 @@ impl Account {
                 pop3: Some(pop3_config),
                 gmail_oauth: None,
                 sync_settings: SyncSettings::default(),
+                calendar: None,
                 settings: HashMap::new(),
             },
             status: AccountStatus::Active,
@@ -337,10 +439,15 @@ impl Account {
     pub fn has_errors(&self) -> bool {
         matches!(
             self.status,
-            AccountStatus::AuthError | AccountStatus::ConnectionError
+            AccountStatus::AuthError | AccountStatus::ReauthRequired | AccountStatus::ConnectionError
         )
     }
 
+    /// Check if the user must sign in again before the account can sync
+    pub fn needs_reauth(&self) -> bool {
+        self.status == AccountStatus::ReauthRequired
+    }
+
     /// Update the account status
     pub fn update_status(&mut self, status: AccountStatus) {
         self.status = status;
@@ -385,6 +492,26 @@ impl Account {
         self.config.gmail_oauth.as_ref()
     }
 
+    /// Get the OAuth2 configuration for any OAuth-backed account
+    pub fn oauth_config(&self) -> Option<&GmailOAuthConfig> {
+        self.config.gmail_oauth.as_ref()
+    }
+
+    /// Get the OAuth2 token endpoint used to refresh this account's tokens
+    pub fn oauth_token_url(&self) -> Option<String> {
+        match self.config.account_type {
+            AccountType::Gmail => Some(crate::gmail::xoauth2::GOOGLE_TOKEN_URL.to_string()),
+            AccountType::Microsoft365 => {
+                let tenant = self.config.settings
+                    .get(MICROSOFT_TENANT_SETTING)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("common");
+                Some(format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", tenant))
+            }
+            AccountType::ImapSmtp | AccountType::Pop3 => None,
+        }
+    }
+
     /// Check if this is a Gmail account
     pub fn is_gmail(&self) -> bool {
         self.config.account_type == AccountType::Gmail
@@ -395,6 +522,11 @@ impl Account {
         self.config.account_type == AccountType::ImapSmtp
     }
 
+    /// Check if this is a Microsoft 365 account
+    pub fn is_microsoft365(&self) -> bool {
+        self.config.account_type == AccountType::Microsoft365
+    }
+
     /// Check if this is a POP3 account
     pub fn is_pop3(&self) -> bool {
         self.config.account_type == AccountType::Pop3
@@ -419,6 +551,14 @@ impl Account {
                     return Err(AsgardError::validation("Gmail account requires IMAP and SMTP configuration"));
                 }
             }
+            AccountType::Microsoft365 => {
+                if self.config.gmail_oauth.is_none() {
+                    return Err(AsgardError::validation("Microsoft 365 account requires OAuth configuration"));
+                }
+                if self.config.imap.is_none() || self.config.smtp.is_none() {
+                    return Err(AsgardError::validation("Microsoft 365 account requires IMAP and SMTP configuration"));
+                }
+            }
             AccountType::ImapSmtp => {
                 if self.config.imap.is_none() || self.config.smtp.is_none() {
                     return Err(AsgardError::validation("IMAP/SMTP account requires both IMAP and SMTP configuration"));
@@ -463,6 +603,38 @@ mod tests {
         assert!(account.is_active());
     }
 
+    #[test]
+    fn test_microsoft365_account_creation() {
+        let oauth_config = GmailOAuthConfig {
+            client_id: "test-client-id".to_string(),
+            client_secret: String::new(),
+            access_token: None,
+            refresh_token: None,
+            token_expires_at: None,
+            scopes: vec!["https://outlook.office.com/IMAP.AccessAsUser.All".to_string()],
+        };
+
+        let account = Account::new_microsoft365(
+            "test@contoso.com".to_string(),
+            None,
+            oauth_config,
+        ).unwrap();
+
+        assert_eq!(account.account_type(), AccountType::Microsoft365);
+        assert!(account.is_microsoft365());
+        assert!(!account.is_gmail());
+        assert!(account.oauth_config().is_some());
+        assert_eq!(account.imap_config().unwrap().host, "outlook.office365.com");
+        assert_eq!(account.smtp_config().unwrap().host, "smtp.office365.com");
+        assert!(matches!(account.imap_config().unwrap().auth_method, AuthMethod::OAuth2));
+        assert!(account.validate().is_ok());
+        assert_eq!(serde_json::to_string(&AccountType::Microsoft365).unwrap(), "\"microsoft365\"");
+        assert_eq!(
+            account.oauth_token_url().as_deref(),
+            Some("https://login.microsoftonline.com/common/oauth2/v2.0/token")
+        );
+    }
+
     #[test]
     fn test_imap_smtp_account_creation() {
         let imap_config = ServerConfig {
@@ -0,0 +1,87 @@
+//! Saving attachments to disk
+//!
+//! File names come from the sender, so they are reduced to a single path
+//! component before use and never overwrite an existing file.
+
+use crate::error::AsgardResult;
+use std::path::{Path, PathBuf};
+
+/// Name used when an attachment's file name is unusable
+const FALLBACK_NAME: &str = "attachment";
+
+/// Reduce a sender-supplied file name to a safe single path component
+pub fn safe_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_control() || ":*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+
+    if name.is_empty() {
+        FALLBACK_NAME.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Path in `dir` for `filename` that does not exist yet
+///
+/// Clashing names get a counter before the extension: `report (2).pdf`.
+pub fn unique_path(dir: &Path, filename: &str) -> PathBuf {
+    let filename = safe_filename(filename);
+    let candidate = dir.join(&filename);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{}", extension)),
+        _ => (filename.clone(), String::new()),
+    };
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("unbounded counter")
+}
+
+/// Write `content` to a new file named after `filename` in `dir`
+pub fn save_to_dir(dir: &Path, filename: &str, content: &[u8]) -> AsgardResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = unique_path(dir, filename);
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_safe_filename() {
+        assert_eq!(safe_filename("report.pdf"), "report.pdf");
+        assert_eq!(safe_filename("../../.bashrc"), "bashrc");
+        assert_eq!(safe_filename("C:\\Users\\x\\evil.exe"), "evil.exe");
+        assert_eq!(safe_filename("a:b?.txt"), "a_b_.txt");
+        assert_eq!(safe_filename(".."), FALLBACK_NAME);
+        assert_eq!(safe_filename(""), FALLBACK_NAME);
+    }
+
+    #[test]
+    fn test_save_keeps_existing_files() {
+        let dir = TempDir::new().unwrap();
+
+        let first = save_to_dir(dir.path(), "report.pdf", b"one").unwrap();
+        let second = save_to_dir(dir.path(), "report.pdf", b"two").unwrap();
+        let third = save_to_dir(dir.path(), "README", b"three").unwrap();
+        let fourth = save_to_dir(dir.path(), "README", b"four").unwrap();
+
+        assert_eq!(first, dir.path().join("report.pdf"));
+        assert_eq!(second, dir.path().join("report (2).pdf"));
+        assert_eq!(fourth, dir.path().join("README (2)"));
+        assert_eq!(std::fs::read(&first).unwrap(), b"one");
+        assert_eq!(std::fs::read(&second).unwrap(), b"two");
+        assert_eq!(std::fs::read(&third).unwrap(), b"three");
+    }
+}
@@ -0,0 +1,639 @@
+//! Sender authenticity from Authentication-Results, ARC and DKIM
+//!
+//! The receiving server records its SPF, DKIM and DMARC checks in an
+//! `Authentication-Results` header (RFC 8601), and forwarders keep earlier
+//! results in an ARC chain (RFC 8617). Senders can add such headers
+//! themselves, so only the topmost one, or those of configured servers, are
+//! believed. DKIM signatures can additionally be verified locally.
+
+use crate::config::SecurityConfig;
+use crate::dkim::{header_fields, DkimStatus, DkimVerification, DkimVerifier};
+use crate::message::{EmailAddress, Message};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Headers read for the verdict, and kept in [`MessageHeaders::custom`]
+/// when a message is parsed
+///
+/// [`MessageHeaders::custom`]: crate::message::MessageHeaders::custom
+pub const AUTHENTICATION_HEADERS: &[&str] = &["Authentication-Results", "ARC-Authentication-Results"];
+
+/// Result of one authentication method
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthOutcome {
+    /// The check succeeded
+    Pass,
+    /// The check failed
+    Fail,
+    /// SPF: probably not authorized
+    SoftFail,
+    /// The domain makes no assertion
+    Neutral,
+    /// Nothing to check
+    None,
+    /// A transient error, such as a DNS timeout
+    TempError,
+    /// A permanent error, such as a malformed record
+    PermError,
+    /// Rejected by local policy
+    Policy,
+}
+
+impl AuthOutcome {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "pass" => Some(Self::Pass),
+            "fail" | "hardfail" => Some(Self::Fail),
+            "softfail" => Some(Self::SoftFail),
+            "neutral" => Some(Self::Neutral),
+            "none" => Some(Self::None),
+            "temperror" => Some(Self::TempError),
+            "permerror" => Some(Self::PermError),
+            "policy" => Some(Self::Policy),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AuthOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::SoftFail => "softfail",
+            Self::Neutral => "neutral",
+            Self::None => "none",
+            Self::TempError => "temperror",
+            Self::PermError => "permerror",
+            Self::Policy => "policy",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One `method=result` entry with its properties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodResult {
+    /// Method name, such as `dkim` or `spf`
+    pub method: String,
+    /// Result
+    pub outcome: AuthOutcome,
+    /// Properties such as `header.d` or `smtp.mailfrom`, and `reason`
+    pub properties: Vec<(String, String)>,
+}
+
+impl MethodResult {
+    /// Value of the property `name`
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parsed `Authentication-Results` header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticationResults {
+    /// Server that performed the checks
+    pub authserv_id: String,
+    /// Results in header order
+    pub results: Vec<MethodResult>,
+}
+
+impl AuthenticationResults {
+    /// Parse a header value
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = strip_comments(value);
+        let mut segments = split_outside_quotes(&value, |c| c == ';').into_iter();
+        let authserv_id = segments.next()?.split_whitespace().next()?.to_ascii_lowercase();
+
+        let results = segments.filter_map(|segment| {
+            let mut tokens = tokens(&segment).into_iter();
+            let (method, outcome) = tokens.next()?.split_once('=').map(|(method, outcome)| {
+                (method.split('/').next().unwrap_or_default().trim().to_ascii_lowercase(), outcome.to_string())
+            })?;
+            let outcome = AuthOutcome::parse(&outcome)?;
+            let properties = tokens
+                .filter_map(|token| {
+                    token.split_once('=').map(|(name, value)| (name.to_ascii_lowercase(), unquote(value)))
+                })
+                .collect();
+            Some(MethodResult { method, outcome, properties })
+        }).collect();
+
+        Some(Self { authserv_id, results })
+    }
+
+    /// Results of `method`
+    pub fn method<'a>(&'a self, method: &'a str) -> impl Iterator<Item = &'a MethodResult> + 'a {
+        self.results.iter().filter(move |result| result.method == method)
+    }
+
+    /// Result of `method`: a pass if any instance passed, else the first
+    fn outcome(&self, method: &str) -> Option<AuthOutcome> {
+        let mut results = self.method(method).map(|result| result.outcome).peekable();
+        let first = *results.peek()?;
+        Some(if results.any(|outcome| outcome == AuthOutcome::Pass) { AuthOutcome::Pass } else { first })
+    }
+}
+
+/// Overall sender authenticity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// The sender's domain is confirmed
+    Pass,
+    /// The message failed the checks its domain asks for
+    Fail,
+    /// Checks ran but confirmed nothing
+    Neutral,
+    /// No authentication information
+    Unknown,
+}
+
+/// The display name impersonates someone else
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayNameSpoof {
+    /// Display name as written
+    pub name: String,
+    /// Address the name belongs to or shows
+    pub claimed: String,
+    /// Address the message is actually from
+    pub actual: String,
+}
+
+impl fmt::Display for DisplayNameSpoof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.to_ascii_lowercase().contains(&self.claimed.to_ascii_lowercase()) {
+            write!(f, "The sender name shows {} but the message is from {}", self.claimed, self.actual)
+        } else {
+            write!(f, "{} is the name of your contact {}, but the message is from {}", self.name, self.claimed, self.actual)
+        }
+    }
+}
+
+/// Authenticity of one message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Authenticity {
+    /// Overall verdict
+    pub verdict: Verdict,
+    /// Domain of the From address
+    pub domain: Option<String>,
+    /// Server whose results were used
+    pub authserv_id: Option<String>,
+    /// DMARC result
+    pub dmarc: Option<AuthOutcome>,
+    /// DKIM result reported by the server
+    pub dkim: Option<AuthOutcome>,
+    /// SPF result
+    pub spf: Option<AuthOutcome>,
+    /// ARC chain result
+    pub arc: Option<AuthOutcome>,
+    /// The sender was confirmed before a forwarder, through ARC
+    pub forwarded: bool,
+    /// Locally verified DKIM signatures
+    pub local_dkim: Vec<DkimVerification>,
+    /// Display name impersonating a contact
+    pub spoof: Option<DisplayNameSpoof>,
+}
+
+impl Authenticity {
+    /// Short text for a badge
+    pub fn summary(&self) -> String {
+        let domain = self.domain.as_deref().unwrap_or("the sender");
+        match self.verdict {
+            Verdict::Pass if self.forwarded => format!("Verified {} (forwarded)", domain),
+            Verdict::Pass => format!("Verified {}", domain),
+            Verdict::Fail => "Sender authentication failed".to_string(),
+            Verdict::Neutral => "Sender not verified".to_string(),
+            Verdict::Unknown => "No sender authentication".to_string(),
+        }
+    }
+
+    /// One line per check, for a tooltip
+    pub fn details(&self) -> Vec<String> {
+        let mut details = Vec::new();
+        for (name, outcome) in [("DMARC", self.dmarc), ("DKIM", self.dkim), ("SPF", self.spf), ("ARC", self.arc)] {
+            if let Some(outcome) = outcome {
+                details.push(format!("{}: {}", name, outcome));
+            }
+        }
+        if let Some(server) = &self.authserv_id {
+            details.push(format!("Checked by {}", server));
+        }
+        for verification in &self.local_dkim {
+            let status = match &verification.status {
+                DkimStatus::Pass => "valid".to_string(),
+                DkimStatus::Fail(reason) | DkimStatus::TempError(reason) | DkimStatus::PermError(reason) => reason.to_lowercase(),
+            };
+            details.push(format!("DKIM signature of {}: {}", verification.domain, status));
+        }
+        details
+    }
+}
+
+/// Computes [`Authenticity`], configured from [`SecurityConfig`]
+#[derive(Clone)]
+pub struct AuthenticityChecker {
+    /// Servers whose results are believed; the topmost header when empty
+    authserv_ids: Vec<String>,
+    /// Known correspondents, for display name spoofing
+    contacts: Vec<EmailAddress>,
+    /// Local DKIM verification, when enabled
+    verifier: Option<DkimVerifier>,
+}
+
+impl AuthenticityChecker {
+    /// Create a checker following `config`
+    pub fn new(config: &SecurityConfig) -> Self {
+        Self {
+            authserv_ids: config.authserv_ids.iter().map(|id| id.trim().to_ascii_lowercase()).collect(),
+            contacts: Vec::new(),
+            verifier: config.verify_dkim.then(DkimVerifier::default),
+        }
+    }
+
+    /// Verify DKIM signatures locally with `verifier`
+    pub fn with_verifier(mut self, verifier: DkimVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Warn when a display name matches one of `contacts` but the address
+    /// does not
+    pub fn set_contacts(&mut self, contacts: Vec<EmailAddress>) {
+        self.contacts = contacts;
+    }
+
+    /// Whether DKIM signatures are verified locally
+    pub fn verifies_dkim(&self) -> bool {
+        self.verifier.is_some()
+    }
+
+    /// Verify the DKIM signatures of `message` locally, if enabled and the
+    /// raw message is available
+    pub async fn verify_dkim(&self, message: &Message) -> Vec<DkimVerification> {
+        match (&self.verifier, &message.raw_content) {
+            (Some(verifier), Some(raw)) => verifier.verify(raw).await,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Authenticity of `message`, including local DKIM verification
+    pub async fn check(&self, message: &Message) -> Authenticity {
+        let local_dkim = self.verify_dkim(message).await;
+        self.evaluate(message, &local_dkim)
+    }
+
+    /// Authenticity of `message` from its headers and `local_dkim` results
+    pub fn evaluate(&self, message: &Message, local_dkim: &[DkimVerification]) -> Authenticity {
+        let headers = authentication_headers(message);
+        let from = message.headers.from.first();
+        let domain = from
+            .and_then(|from| from.email.rsplit_once('@'))
+            .map(|(_, domain)| domain.trim_end_matches('>').to_ascii_lowercase());
+        let is_aligned = |signer: &str| domain.as_deref().is_some_and(|domain| aligned(signer, domain));
+
+        let trusted = self.trusted_results(&headers);
+        let outcome = |method: &str| {
+            let outcomes: Vec<AuthOutcome> = trusted.iter().filter_map(|results| results.outcome(method)).collect();
+            outcomes.iter().find(|outcome| **outcome == AuthOutcome::Pass).or(outcomes.first()).copied()
+        };
+        let dmarc = outcome("dmarc");
+        let dkim = outcome("dkim");
+        let spf = outcome("spf");
+        let arc = outcome("arc");
+
+        let confirms = |results: &AuthenticationResults| {
+            results.outcome("dmarc") == Some(AuthOutcome::Pass)
+                || results.method("dkim").any(|result| {
+                    result.outcome == AuthOutcome::Pass && dkim_domain(result).is_some_and(|signer| is_aligned(&signer))
+                })
+        };
+        let local_pass = local_dkim.iter().any(|verification| verification.is_pass() && is_aligned(&verification.domain));
+        // The first hop's results, vouched for by a valid chain
+        let forwarded = arc == Some(AuthOutcome::Pass) && oldest_arc_results(&headers).as_ref().is_some_and(confirms);
+
+        let verdict = if trusted.iter().any(confirms) || local_pass || forwarded {
+            Verdict::Pass
+        } else if dmarc == Some(AuthOutcome::Fail)
+            || (dkim == Some(AuthOutcome::Fail) && spf == Some(AuthOutcome::Fail))
+        {
+            Verdict::Fail
+        } else if !trusted.is_empty() || !local_dkim.is_empty() {
+            Verdict::Neutral
+        } else {
+            Verdict::Unknown
+        };
+        let forwarded = forwarded && !trusted.iter().any(confirms) && !local_pass;
+
+        Authenticity {
+            verdict,
+            domain,
+            authserv_id: trusted.first().map(|results| results.authserv_id.clone()),
+            dmarc,
+            dkim,
+            spf,
+            arc,
+            forwarded,
+            local_dkim: local_dkim.to_vec(),
+            spoof: from.and_then(|from| display_name_spoof(from, &self.contacts)),
+        }
+    }
+
+    /// `Authentication-Results` headers added by our own servers
+    fn trusted_results(&self, headers: &[(String, String)]) -> Vec<AuthenticationResults> {
+        let all = headers.iter()
+            .filter(|(name, _)| name == "authentication-results")
+            .filter_map(|(_, value)| AuthenticationResults::parse(value));
+        if self.authserv_ids.is_empty() {
+            all.take(1).collect()
+        } else {
+            all.filter(|results| self.authserv_ids.contains(&results.authserv_id)).collect()
+        }
+    }
+}
+
+impl Default for AuthenticityChecker {
+    fn default() -> Self {
+        Self::new(&SecurityConfig::default())
+    }
+}
+
+/// Authentication headers in order, from the raw message or the headers
+/// kept when it was parsed
+fn authentication_headers(message: &Message) -> Vec<(String, String)> {
+    let wanted = |name: &str| AUTHENTICATION_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name));
+    match &message.raw_content {
+        Some(raw) => header_fields(raw).into_iter().filter(|(name, _)| wanted(name)).collect(),
+        None => message.headers.custom.iter()
+            .filter(|(name, _)| wanted(name))
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+            .collect(),
+    }
+}
+
+/// Results recorded by the first ARC participant
+fn oldest_arc_results(headers: &[(String, String)]) -> Option<AuthenticationResults> {
+    headers.iter()
+        .filter(|(name, _)| name == "arc-authentication-results")
+        .filter_map(|(_, value)| {
+            let (instance, rest) = value.split_once(';')?;
+            let instance: u32 = instance.trim().strip_prefix("i=")?.trim().parse().ok()?;
+            Some((instance, AuthenticationResults::parse(rest)?))
+        })
+        .min_by_key(|(instance, _)| *instance)
+        .map(|(_, results)| results)
+}
+
+/// Signing domain of a DKIM result
+fn dkim_domain(result: &MethodResult) -> Option<String> {
+    result.property("header.d")
+        .or_else(|| result.property("header.i").and_then(|identity| identity.rsplit_once('@').map(|(_, domain)| domain)))
+        .map(str::to_ascii_lowercase)
+}
+
+/// Relaxed DMARC alignment: the same domain or one within the other
+fn aligned(signer: &str, domain: &str) -> bool {
+    let signer = signer.trim_end_matches('.').to_ascii_lowercase();
+    let within = |child: &str, parent: &str| {
+        child.strip_suffix(parent).is_some_and(|prefix| prefix.ends_with('.'))
+    };
+    signer.contains('.') && (signer == domain || within(&signer, domain) || within(domain, &signer))
+}
+
+/// A display name showing another address, or naming a contact whose
+/// address differs
+fn display_name_spoof(from: &EmailAddress, contacts: &[EmailAddress]) -> Option<DisplayNameSpoof> {
+    let name = from.name.as_deref()?.trim();
+    let actual = from.email.to_ascii_lowercase();
+    let spoof = |claimed: &str| DisplayNameSpoof { name: name.to_string(), claimed: claimed.to_string(), actual: actual.clone() };
+
+    let shown = name
+        .split(|c: char| c.is_whitespace() || "<>()[]\"',;".contains(c))
+        .find(|word| word.contains('@') && word.contains('.'));
+    if let Some(shown) = shown {
+        return (!shown.eq_ignore_ascii_case(&actual)).then(|| spoof(shown));
+    }
+
+    let key = normalize_name(name);
+    if key.is_empty() || contacts.iter().any(|contact| contact.email.eq_ignore_ascii_case(&actual)) {
+        return None;
+    }
+    contacts.iter()
+        .find(|contact| contact.name.as_deref().map(normalize_name).as_deref() == Some(key.as_str()))
+        .map(|contact| spoof(&contact.email))
+}
+
+/// Lowercase name without quotes and with single spaces
+fn normalize_name(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Remove `(comments)`, which may nest, outside quoted strings
+fn strip_comments(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            escaped = false;
+            if depth == 0 {
+                result.push(c);
+            }
+            continue;
+        }
+        match c {
+            '\\' => {
+                escaped = true;
+                if depth == 0 {
+                    result.push(c);
+                }
+            }
+            '"' if depth == 0 => {
+                quoted = !quoted;
+                result.push(c);
+            }
+            '(' if !quoted => depth += 1,
+            ')' if !quoted && depth > 0 => {
+                depth -= 1;
+                // Keep the tokens on both sides apart
+                if depth == 0 {
+                    result.push(' ');
+                }
+            }
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Split on `separator` outside quoted strings
+fn split_outside_quotes(value: &str, separator: impl Fn(char) -> bool) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    for c in value.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if separator(c) && !quoted {
+            parts.push(String::new());
+        } else if let Some(part) = parts.last_mut() {
+            part.push(c);
+        }
+    }
+    parts.into_iter().map(|part| part.trim().to_string()).filter(|part| !part.is_empty()).collect()
+}
+
+/// Whitespace separated `name=value` tokens, joining `name = value`
+fn tokens(segment: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for word in split_outside_quotes(segment, char::is_whitespace) {
+        match tokens.last_mut() {
+            Some(last) if last.ends_with('=') || word.starts_with('=') => last.push_str(&word),
+            _ => tokens.push(word),
+        }
+    }
+    tokens
+}
+
+fn unquote(value: &str) -> String {
+    value.strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .map(|value| value.replace("\\\"", "\""))
+        .unwrap_or_else(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn message(from: &str, headers: &str) -> Message {
+        let raw = format!("{}From: {}\r\nSubject: Hi\r\n\r\nHello\r\n", headers, from);
+        Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_authentication_results() {
+        let results = AuthenticationResults::parse(
+            "mx.google.com;\r\n       dkim=pass header.i=@example.com header.s=s1 header.b=\"abc;def\";\r\n       \
+             spf=softfail (google.com: domain of transitioning a@example.org does not designate 192.0.2.1 as permitted sender) smtp.mailfrom=a@example.org;\r\n       \
+             dmarc = pass (p=REJECT sp=REJECT dis=NONE) header.from=example.com",
+        ).unwrap();
+        assert_eq!(results.authserv_id, "mx.google.com");
+        assert_eq!(results.results.len(), 3);
+        assert_eq!(results.results[0].method, "dkim");
+        assert_eq!(results.results[0].outcome, AuthOutcome::Pass);
+        assert_eq!(results.results[0].property("header.b"), Some("abc;def"));
+        assert_eq!(dkim_domain(&results.results[0]).as_deref(), Some("example.com"));
+        assert_eq!(results.outcome("spf"), Some(AuthOutcome::SoftFail));
+        assert_eq!(results.results[1].property("smtp.mailfrom"), Some("a@example.org"));
+        assert_eq!(results.outcome("dmarc"), Some(AuthOutcome::Pass));
+        assert_eq!(results.results[2].property("header.from"), Some("example.com"));
+
+        let results = AuthenticationResults::parse("example.org 1; none").unwrap();
+        assert_eq!(results.authserv_id, "example.org");
+        assert!(results.results.is_empty());
+
+        let results = AuthenticationResults::parse("mx; dkim=fail header.d=a.example; dkim=pass header.d=b.example").unwrap();
+        assert_eq!(results.outcome("dkim"), Some(AuthOutcome::Pass));
+        assert!(AuthenticationResults::parse("").is_none());
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let checker = AuthenticityChecker::default();
+        let verdict = |from: &str, headers: &str| checker.evaluate(&message(from, headers), &[]).verdict;
+
+        assert_eq!(verdict("a@example.com", ""), Verdict::Unknown);
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; dmarc=pass header.from=example.com\r\n"), Verdict::Pass);
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; dkim=pass header.d=mail.example.com\r\n"), Verdict::Pass);
+        // A signature by an unrelated domain proves nothing about the sender
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; dkim=pass header.d=bulk.example\r\n"), Verdict::Neutral);
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; dmarc=fail header.from=example.com\r\n"), Verdict::Fail);
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; dkim=fail; spf=fail\r\n"), Verdict::Fail);
+        assert_eq!(verdict("a@example.com", "Authentication-Results: mx.example.net; spf=pass smtp.mailfrom=example.com\r\n"), Verdict::Neutral);
+
+        // Only the topmost header is believed: the lower one came with the message
+        let forged = "Authentication-Results: mx.example.net; dmarc=fail header.from=example.com\r\n\
+                      Authentication-Results: mx.example.net; dmarc=pass header.from=example.com\r\n";
+        assert_eq!(verdict("a@example.com", forged), Verdict::Fail);
+
+        // Configured servers are believed wherever their headers are
+        let config = SecurityConfig { authserv_ids: vec!["MX.Example.NET".to_string()], ..SecurityConfig::default() };
+        let checker = AuthenticityChecker::new(&config);
+        let headers = "Authentication-Results: spam.example; dmarc=fail\r\n\
+                       Authentication-Results: mx.example.net; dmarc=pass\r\n";
+        let authenticity = checker.evaluate(&message("a@example.com", headers), &[]);
+        assert_eq!(authenticity.verdict, Verdict::Pass);
+        assert_eq!(authenticity.authserv_id.as_deref(), Some("mx.example.net"));
+        assert_eq!(authenticity.summary(), "Verified example.com");
+
+        // Headers kept from parsing are used without the raw message
+        let mut message = message("a@example.com", "");
+        message.raw_content = None;
+        message.headers.custom.insert("Authentication-Results".to_string(), "mx; dmarc=pass".to_string());
+        assert_eq!(AuthenticityChecker::default().evaluate(&message, &[]).verdict, Verdict::Pass);
+    }
+
+    #[test]
+    fn test_arc_and_local_dkim() {
+        let checker = AuthenticityChecker::default();
+        let forwarded = "Authentication-Results: mx.example.net; dmarc=fail header.from=example.com; arc=pass\r\n\
+                         ARC-Authentication-Results: i=2; lists.example.org; dmarc=fail\r\n\
+                         ARC-Authentication-Results: i=1; mx.example.com; dkim=pass header.d=example.com; dmarc=pass\r\n";
+        let authenticity = checker.evaluate(&message("a@example.com", forwarded), &[]);
+        assert_eq!(authenticity.verdict, Verdict::Pass);
+        assert!(authenticity.forwarded);
+        assert_eq!(authenticity.summary(), "Verified example.com (forwarded)");
+
+        // Without a valid chain the forwarder's claims are ignored
+        let broken = forwarded.replace("arc=pass", "arc=fail");
+        assert_eq!(checker.evaluate(&message("a@example.com", &broken), &[]).verdict, Verdict::Fail);
+
+        let verification = |domain: &str, status: DkimStatus| DkimVerification {
+            domain: domain.to_string(),
+            selector: "s1".to_string(),
+            status,
+        };
+        let plain = message("a@example.com", "");
+        let authenticity = checker.evaluate(&plain, &[verification("example.com", DkimStatus::Pass)]);
+        assert_eq!(authenticity.verdict, Verdict::Pass);
+        assert_eq!(authenticity.details(), ["DKIM signature of example.com: valid"]);
+        let authenticity = checker.evaluate(&plain, &[verification("other.example", DkimStatus::Pass)]);
+        assert_eq!(authenticity.verdict, Verdict::Neutral);
+        let authenticity = checker.evaluate(&plain, &[verification("example.com", DkimStatus::Fail("Body hash does not match".to_string()))]);
+        assert_eq!(authenticity.verdict, Verdict::Neutral);
+    }
+
+    #[test]
+    fn test_display_name_spoof() {
+        let mut checker = AuthenticityChecker::default();
+        checker.set_contacts(vec![
+            EmailAddress { name: Some("Jane Doe".to_string()), email: "jane@example.com".to_string() },
+            EmailAddress { name: Some("Jane Doe".to_string()), email: "jane@home.example".to_string() },
+        ]);
+        let spoof = |from: &str| checker.evaluate(&message(from, ""), &[]).spoof;
+
+        assert_eq!(spoof("Jane Doe <jane@example.com>"), None);
+        assert_eq!(spoof("Jane Doe <jane@home.example>"), None);
+        assert_eq!(spoof("John Roe <john@example.com>"), None);
+        assert_eq!(spoof("jane@example.com"), None);
+
+        let found = spoof("\"jane  DOE\" <ceo.jane@evil.example>").unwrap();
+        assert_eq!(found.claimed, "jane@example.com");
+        assert_eq!(found.actual, "ceo.jane@evil.example");
+        assert_eq!(found.to_string(), "jane  DOE is the name of your contact jane@example.com, but the message is from ceo.jane@evil.example");
+
+        let found = spoof("support@bank.example <phish@evil.example>").unwrap();
+        assert_eq!(found.claimed, "support@bank.example");
+        assert_eq!(found.to_string(), "The sender name shows support@bank.example but the message is from phish@evil.example");
+        assert_eq!(spoof("info@shop.example <INFO@shop.example>"), None);
+    }
+}
@@ -0,0 +1,847 @@
+//! Autocrypt Level 1 opportunistic encryption
+//!
+//! Every account gets its own OpenPGP key, advertised in an `Autocrypt:`
+//! header on outgoing mail. Keys seen on incoming mail are kept per peer
+//! and drive the encrypt recommendation shown while composing.
+
+use crate::error::{AsgardError, AsgardResult};
+use crate::message::{EmailAddress, Message};
+use crate::openpgp::{self, PgpKeyring, PgpKind};
+use crate::secrets::{SecretStore, AUTOCRYPT_KEY};
+use crate::storage::Database;
+use base64::{engine::general_purpose, Engine as _};
+use lettre::message::header::{self, Header, HeaderName, HeaderValue};
+use lettre::message::{MultiPart, SinglePart};
+use lettre::Message as LettreMessage;
+use pgp::crypto::sym::SymmetricKeyAlgorithm;
+use pgp::ser::Serialize as _;
+use pgp::types::{SecretKeyTrait, StringToKey};
+use pgp::{Deserializable, KeyType, Message as PgpMessage, SecretKeyParamsBuilder, SignedPublicKey, SignedSecretKey, SubkeyParamsBuilder};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+/// Header announcing the sender's key
+pub const AUTOCRYPT_HEADER: &str = "Autocrypt";
+
+/// Header carrying another recipient's key inside an encrypted message
+pub const GOSSIP_HEADER: &str = "Autocrypt-Gossip";
+
+/// Header marking an Autocrypt Setup Message
+pub const SETUP_MESSAGE_HEADER: &str = "Autocrypt-Setup-Message";
+
+/// MIME type of the Setup Message attachment
+pub const SETUP_MESSAGE_TYPE: &str = "application/autocrypt-setup";
+
+/// How long after the last Autocrypt header encryption is still recommended
+const DISCOURAGE_AFTER: Duration = Duration::days(35);
+
+/// Armor header carrying the prefer-encrypt setting in setup files
+const PREFER_ENCRYPT_ARMOR: &str = "Autocrypt-Prefer-Encrypt";
+
+/// Digits in a setup code
+const SETUP_CODE_DIGITS: usize = 36;
+
+/// Line length of folded key data
+const KEYDATA_LINE: usize = 76;
+
+/// Whether a user wants encryption whenever both sides can do it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreferEncrypt {
+    #[default]
+    NoPreference,
+    Mutual,
+}
+
+/// A parsed `Autocrypt:` or `Autocrypt-Gossip:` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutocryptHeader {
+    /// Address the key belongs to
+    pub addr: String,
+    /// Encryption preference of the key owner
+    pub prefer_encrypt: PreferEncrypt,
+    /// Binary OpenPGP transferable public key
+    pub keydata: Vec<u8>,
+}
+
+impl AutocryptHeader {
+    /// Header advertising `key` for `addr`
+    pub fn new(addr: &str, key: &SignedPublicKey, prefer_encrypt: PreferEncrypt) -> AsgardResult<Self> {
+        Ok(Self {
+            addr: addr.to_ascii_lowercase(),
+            prefer_encrypt,
+            keydata: key.to_bytes().map_err(pgp_error)?,
+        })
+    }
+
+    /// Parse a header value
+    ///
+    /// Unknown attributes make the header invalid unless they start with
+    /// an underscore, as required by Level 1.
+    pub fn parse(value: &str) -> AsgardResult<Self> {
+        let mut addr = None;
+        let mut prefer_encrypt = PreferEncrypt::NoPreference;
+        let mut keydata = None;
+
+        for attribute in value.split(';') {
+            let attribute = attribute.trim();
+            if attribute.is_empty() {
+                continue;
+            }
+            let (name, value) = attribute.split_once('=')
+                .ok_or_else(|| AsgardError::validation(format!("Malformed Autocrypt attribute: {}", attribute)))?;
+            match name.trim().to_ascii_lowercase().as_str() {
+                "addr" => addr = Some(value.trim().to_ascii_lowercase()),
+                "prefer-encrypt" if value.trim() == "mutual" => prefer_encrypt = PreferEncrypt::Mutual,
+                "prefer-encrypt" => {}
+                "keydata" => {
+                    let data: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+                    let data = general_purpose::STANDARD.decode(data)
+                        .map_err(|e| AsgardError::validation(format!("Invalid Autocrypt keydata: {}", e)))?;
+                    keydata = Some(data);
+                }
+                name if name.starts_with('_') => {}
+                name => return Err(AsgardError::validation(format!("Unknown Autocrypt attribute: {}", name))),
+            }
+        }
+
+        match (addr, keydata) {
+            (Some(addr), Some(keydata)) if !keydata.is_empty() => Ok(Self { addr, prefer_encrypt, keydata }),
+            _ => Err(AsgardError::validation("Autocrypt header needs addr and keydata")),
+        }
+    }
+
+    /// The advertised public key
+    pub fn public_key(&self) -> AsgardResult<SignedPublicKey> {
+        let key = SignedPublicKey::from_bytes(std::io::Cursor::new(&self.keydata)).map_err(pgp_error)?;
+        key.verify().map_err(pgp_error)?;
+        Ok(key)
+    }
+
+    /// Header value folded onto continuation lines
+    pub fn folded_value(&self) -> String {
+        let mut value = format!("addr={};", self.addr);
+        if self.prefer_encrypt == PreferEncrypt::Mutual {
+            value.push_str(" prefer-encrypt=mutual;");
+        }
+        value.push_str(" keydata=");
+
+        let keydata = general_purpose::STANDARD.encode(&self.keydata);
+        for chunk in keydata.as_bytes().chunks(KEYDATA_LINE) {
+            value.push_str("\r\n ");
+            value.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        }
+        value
+    }
+
+    fn unfolded_value(&self) -> String {
+        self.folded_value().replace("\r\n", "")
+    }
+}
+
+impl Header for AutocryptHeader {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str(AUTOCRYPT_HEADER)
+    }
+
+    fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        AutocryptHeader::parse(value).map_err(|e| e.to_string().into())
+    }
+
+    fn display(&self) -> HeaderValue {
+        // Already folded; base64 never needs RFC 2047 encoding
+        HeaderValue::dangerous_new_pre_encoded(Self::name(), self.unfolded_value(), self.folded_value())
+    }
+}
+
+/// `Autocrypt-Setup-Message: v1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SetupMessageHeader;
+
+impl Header for SetupMessageHeader {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str(SETUP_MESSAGE_HEADER)
+    }
+
+    fn parse(_value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "v1".to_string())
+    }
+}
+
+/// What we know about a correspondent's Autocrypt key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerState {
+    /// Peer address, lowercased like [`EmailAddress::email`] lookups
+    pub email: String,
+    /// Effective date of the newest message seen from the peer
+    pub last_seen: OffsetDateTime,
+    /// Effective date of the newest message with an Autocrypt header
+    pub autocrypt_timestamp: Option<OffsetDateTime>,
+    /// Key from the newest Autocrypt header
+    pub public_key: Option<Vec<u8>>,
+    /// Preference from the newest Autocrypt header
+    pub prefer_encrypt: PreferEncrypt,
+    /// Effective date of the newest gossiped key
+    pub gossip_timestamp: Option<OffsetDateTime>,
+    /// Key gossiped by other senders
+    pub gossip_key: Option<Vec<u8>>,
+}
+
+impl PeerState {
+    /// Empty state for `email`
+    pub fn new(email: &str) -> Self {
+        Self {
+            email: email.to_ascii_lowercase(),
+            last_seen: OffsetDateTime::UNIX_EPOCH,
+            autocrypt_timestamp: None,
+            public_key: None,
+            prefer_encrypt: PreferEncrypt::NoPreference,
+            gossip_timestamp: None,
+            gossip_key: None,
+        }
+    }
+
+    /// Apply a message from the peer (Level 1 section 2.3)
+    ///
+    /// Returns whether anything changed. Messages older than the last
+    /// Autocrypt header are ignored so reordered delivery cannot roll a
+    /// key back.
+    pub fn update(&mut self, header: Option<&AutocryptHeader>, effective_date: OffsetDateTime) -> bool {
+        if self.autocrypt_timestamp.is_some_and(|timestamp| effective_date <= timestamp) {
+            return false;
+        }
+
+        let mut changed = false;
+        if effective_date > self.last_seen {
+            self.last_seen = effective_date;
+            changed = true;
+        }
+        if let Some(header) = header {
+            self.autocrypt_timestamp = Some(effective_date);
+            self.public_key = Some(header.keydata.clone());
+            self.prefer_encrypt = header.prefer_encrypt;
+            changed = true;
+        }
+        changed
+    }
+
+    /// Apply a key gossiped in an encrypted message; returns whether it was newer
+    pub fn update_gossip(&mut self, header: &AutocryptHeader, effective_date: OffsetDateTime) -> bool {
+        if self.gossip_timestamp.is_some_and(|timestamp| effective_date <= timestamp) {
+            return false;
+        }
+        self.gossip_timestamp = Some(effective_date);
+        self.gossip_key = Some(header.keydata.clone());
+        true
+    }
+
+    /// Key to encrypt to: the peer's own, else a gossiped one
+    pub fn key(&self) -> Option<&[u8]> {
+        self.public_key.as_deref().or(self.gossip_key.as_deref())
+    }
+
+    /// Recommendation for this peer alone (Level 1 section 2.4.1)
+    pub fn preliminary_recommendation(&self) -> Recommendation {
+        match (&self.public_key, &self.gossip_key, self.autocrypt_timestamp) {
+            (None, None, _) => Recommendation::Disable,
+            (None, Some(_), _) => Recommendation::Discourage,
+            (Some(_), _, Some(timestamp)) if timestamp < self.last_seen - DISCOURAGE_AFTER => Recommendation::Discourage,
+            _ => Recommendation::Available,
+        }
+    }
+}
+
+/// Level 1 recommendation for the compose encrypt toggle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Recommendation {
+    /// Some recipient has no key; encryption is not possible
+    Disable,
+    /// Possible, but some recipient may not be able to read it
+    Discourage,
+    /// Possible; leave the toggle off
+    Available,
+    /// Turn the toggle on
+    Encrypt,
+}
+
+/// Final recommendation for a message (Level 1 section 2.4.2)
+///
+/// `peers` holds the state of every recipient other than ourselves, `None`
+/// where nothing is known.
+pub fn recommend(own: PreferEncrypt, peers: &[Option<&PeerState>], reply_to_encrypted: bool) -> Recommendation {
+    if peers.is_empty() {
+        return Recommendation::Disable;
+    }
+
+    let preliminary: Vec<Recommendation> = peers.iter()
+        .map(|peer| peer.map(PeerState::preliminary_recommendation).unwrap_or(Recommendation::Disable))
+        .collect();
+    if preliminary.contains(&Recommendation::Disable) {
+        return Recommendation::Disable;
+    }
+
+    let all_mutual = own == PreferEncrypt::Mutual
+        && peers.iter().flatten().all(|peer| peer.prefer_encrypt == PreferEncrypt::Mutual);
+    if all_mutual && preliminary.iter().all(|r| *r == Recommendation::Available) {
+        return Recommendation::Encrypt;
+    }
+    if reply_to_encrypted {
+        return Recommendation::Encrypt;
+    }
+    if preliminary.contains(&Recommendation::Discourage) {
+        Recommendation::Discourage
+    } else {
+        Recommendation::Available
+    }
+}
+
+/// The only valid `Autocrypt:` header of a message from `from`
+///
+/// Headers for other addresses are skipped; a message with more than one
+/// valid header counts as having none.
+pub fn incoming_header(headers: &[mailparse::MailHeader], from: &str) -> Option<AutocryptHeader> {
+    use mailparse::MailHeaderMap;
+
+    let mut valid = headers.get_all_values(AUTOCRYPT_HEADER).into_iter()
+        .filter_map(|value| AutocryptHeader::parse(&value).ok())
+        .filter(|header| header.addr.eq_ignore_ascii_case(from));
+    match (valid.next(), valid.next()) {
+        (Some(header), None) => Some(header),
+        _ => None,
+    }
+}
+
+/// Date used to order messages: the `Date` header, but never in the future
+pub fn effective_date(message: &Message) -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    message.headers.date.map(|date| date.min(now)).unwrap_or(now)
+}
+
+/// Update peer state from an incoming message
+///
+/// With `account` set, encrypted messages are decrypted to pick up keys
+/// gossiped to the other recipients.
+pub async fn process_incoming(database: &Database, message: &Message, account: Option<&AutocryptAccount>) -> AsgardResult<()> {
+    let Some(from) = message.headers.from.first() else {
+        return Ok(());
+    };
+    let Some(raw) = message.raw_content.as_deref() else {
+        return Ok(());
+    };
+    let parsed = mailparse::parse_mail(raw)?;
+    // Bounces quote headers of the original message
+    if parsed.ctype.mimetype.eq_ignore_ascii_case("multipart/report") {
+        return Ok(());
+    }
+
+    let date = effective_date(message);
+    let header = incoming_header(&parsed.headers, &from.email);
+    let existing = database.get_autocrypt_peer(message.account_id, &from.email).await?;
+    if existing.is_some() || header.is_some() {
+        let mut peer = existing.unwrap_or_else(|| PeerState::new(&from.email));
+        if peer.update(header.as_ref(), date) {
+            database.save_autocrypt_peer(message.account_id, &peer).await?;
+        }
+    }
+
+    let Some(account) = account else {
+        return Ok(());
+    };
+    if openpgp::detect(message) != PgpKind::MimeEncrypted {
+        return Ok(());
+    }
+    let keyring = account.keyring(None, &[])?;
+    let status = keyring.process(&mut message.clone());
+    for header in gossip_headers(&status.gossip, message, account.email()) {
+        let mut peer = database.get_autocrypt_peer(message.account_id, &header.addr).await?
+            .unwrap_or_else(|| PeerState::new(&header.addr));
+        if peer.update_gossip(&header, date) {
+            database.save_autocrypt_peer(message.account_id, &peer).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Gossip headers naming one of the message's recipients other than `own`
+pub fn gossip_headers(values: &[String], message: &Message, own: &str) -> Vec<AutocryptHeader> {
+    let recipients: Vec<&EmailAddress> = message.headers.to.iter().chain(&message.headers.cc).collect();
+    values.iter()
+        .filter_map(|value| AutocryptHeader::parse(value).ok())
+        .filter(|header| !header.addr.eq_ignore_ascii_case(own))
+        .filter(|header| recipients.iter().any(|addr| addr.email.eq_ignore_ascii_case(&header.addr)))
+        .collect()
+}
+
+/// Prepend `Autocrypt-Gossip:` headers to a MIME entity before encryption
+pub fn with_gossip(entity: &[u8], gossip: &[AutocryptHeader]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(entity.len());
+    for header in gossip {
+        // Gossip only carries keys, never the preference of their owner
+        let header = AutocryptHeader { prefer_encrypt: PreferEncrypt::NoPreference, ..header.clone() };
+        result.extend_from_slice(format!("{}: {}\r\n", GOSSIP_HEADER, header.folded_value()).as_bytes());
+    }
+    result.extend_from_slice(entity);
+    result
+}
+
+/// An account's own Autocrypt key and settings
+#[derive(Clone)]
+pub struct AutocryptAccount {
+    email: String,
+    key: SignedSecretKey,
+    prefer_encrypt: PreferEncrypt,
+}
+
+impl AutocryptAccount {
+    /// Generate a fresh Ed25519/Curve25519 key for `email`
+    pub fn generate(email: &str) -> AsgardResult<Self> {
+        let params = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id(format!("<{}>", email.to_ascii_lowercase()))
+            .subkey(SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .build()
+                .map_err(|e| AsgardError::crypto(format!("Invalid subkey parameters: {}", e)))?)
+            .build()
+            .map_err(|e| AsgardError::crypto(format!("Invalid key parameters: {}", e)))?;
+        let key = params.generate().map_err(pgp_error)?
+            .sign(String::new).map_err(pgp_error)?;
+
+        Ok(Self {
+            email: email.to_ascii_lowercase(),
+            key,
+            prefer_encrypt: PreferEncrypt::NoPreference,
+        })
+    }
+
+    /// Load the key of an account from the secret store
+    pub fn load(secrets: &SecretStore, account_id: Uuid) -> AsgardResult<Option<Self>> {
+        secrets.resolve(&SecretStore::reference(account_id, AUTOCRYPT_KEY))?
+            .map(|armored| Self::from_armored(&armored, None))
+            .transpose()
+    }
+
+    /// Load the key of an account, generating and storing one on first use
+    pub fn load_or_generate(secrets: &SecretStore, account_id: Uuid, email: &str) -> AsgardResult<Self> {
+        if let Some(account) = Self::load(secrets, account_id)? {
+            return Ok(account);
+        }
+        let account = Self::generate(email)?;
+        account.store(secrets, account_id)?;
+        Ok(account)
+    }
+
+    /// Save the key and preference in the secret store
+    pub fn store(&self, secrets: &SecretStore, account_id: Uuid) -> AsgardResult<()> {
+        secrets.store(&SecretStore::reference(account_id, AUTOCRYPT_KEY), &self.to_armored()?)
+    }
+
+    /// Address the key is for
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// Our encryption preference
+    pub fn prefer_encrypt(&self) -> PreferEncrypt {
+        self.prefer_encrypt
+    }
+
+    /// Change the encryption preference; call [`AutocryptAccount::store`] to keep it
+    pub fn set_prefer_encrypt(&mut self, prefer_encrypt: PreferEncrypt) {
+        self.prefer_encrypt = prefer_encrypt;
+    }
+
+    /// The secret key
+    pub fn secret_key(&self) -> &SignedSecretKey {
+        &self.key
+    }
+
+    /// The public half of the key
+    pub fn public_key(&self) -> AsgardResult<SignedPublicKey> {
+        self.key.public_key().sign(&self.key, String::new).map_err(pgp_error)
+    }
+
+    /// Our `Autocrypt:` header
+    pub fn header(&self) -> AsgardResult<AutocryptHeader> {
+        AutocryptHeader::new(&self.email, &self.public_key()?, self.prefer_encrypt)
+    }
+
+    /// Keyring with our key, the keys of `base` and the peers' Autocrypt keys
+    ///
+    /// Peer keys that cannot be parsed are skipped.
+    pub fn keyring(&self, base: Option<&PgpKeyring>, peers: &[PeerState]) -> AsgardResult<PgpKeyring> {
+        let mut keyring = base.cloned().unwrap_or_default();
+        keyring.add_secret_key(self.key.clone())?;
+        for peer in peers {
+            let key = peer.key()
+                .and_then(|keydata| SignedPublicKey::from_bytes(std::io::Cursor::new(keydata)).ok());
+            if let Some(key) = key {
+                if let Err(e) = keyring.add_public_key(key) {
+                    tracing::warn!("Skipping Autocrypt key of {}: {}", peer.email, e);
+                }
+            }
+        }
+        Ok(keyring)
+    }
+
+    fn to_armored(&self) -> AsgardResult<String> {
+        let mut headers = BTreeMap::new();
+        headers.insert(PREFER_ENCRYPT_ARMOR.to_string(), match self.prefer_encrypt {
+            PreferEncrypt::Mutual => "mutual".to_string(),
+            PreferEncrypt::NoPreference => "nopreference".to_string(),
+        });
+        self.key.to_armored_string(Some(&headers)).map_err(pgp_error)
+    }
+
+    fn from_armored(armored: &str, fallback_email: Option<&str>) -> AsgardResult<Self> {
+        let (key, headers) = SignedSecretKey::from_string(armored).map_err(pgp_error)?;
+        key.verify().map_err(pgp_error)?;
+
+        let email = key.details.users.iter()
+            .find_map(|user| openpgp::user_id_email(&openpgp::user_id(user)))
+            .or_else(|| fallback_email.map(str::to_ascii_lowercase))
+            .ok_or_else(|| AsgardError::validation("Key has no email address"))?;
+        let prefer_encrypt = match headers.get(PREFER_ENCRYPT_ARMOR).map(String::as_str) {
+            Some("mutual") => PreferEncrypt::Mutual,
+            _ => PreferEncrypt::NoPreference,
+        };
+
+        Ok(Self { email, key, prefer_encrypt })
+    }
+
+    // Setup Messages
+
+    /// Build an Autocrypt Setup Message carrying our secret key (Level 1 section 4.4)
+    ///
+    /// Returns the setup code to show the user and the message to send to
+    /// ourselves; the code is never part of the message.
+    pub fn setup_message(&self) -> AsgardResult<(String, LettreMessage)> {
+        let code = generate_setup_code();
+        let armored = self.encrypt_setup_file(&code)?;
+        let html = format!(
+            "<html><body><p>This is the Autocrypt Setup File used to transfer your end-to-end \
+             encryption key. To use it, open the Autocrypt Setup Message on your other device \
+             and enter the setup code shown on this device.</p><pre>\r\n{}</pre></body></html>\r\n",
+            armored
+        );
+
+        let mailbox: lettre::message::Mailbox = self.email.parse()?;
+        let message = LettreMessage::builder()
+            .from(mailbox.clone())
+            .to(mailbox)
+            .subject("Autocrypt Setup Message")
+            .header(SetupMessageHeader)
+            .multipart(MultiPart::mixed()
+                .singlepart(SinglePart::plain(
+                    "This message contains all information to transfer your Autocrypt settings \
+                     along with your secret key securely from your original device.\r\n\r\n\
+                     To set up your new device for Autocrypt, please follow the instructions \
+                     that should be presented by your new device.".to_string()))
+                .singlepart(SinglePart::builder()
+                    .header(header::ContentType::parse(SETUP_MESSAGE_TYPE)
+                        .map_err(|e| AsgardError::validation(e.to_string()))?)
+                    .header(header::ContentDisposition::attachment("autocrypt-setup-message.html"))
+                    .body(html)))?;
+
+        Ok((code, message))
+    }
+
+    /// Import the key from an Autocrypt Setup Message
+    ///
+    /// `setup_code` may be typed with or without the dashes.
+    pub fn from_setup_message(message: &Message, setup_code: &str) -> AsgardResult<Self> {
+        let armored = message.attachments.iter()
+            .filter(|attachment| attachment.mime_type == SETUP_MESSAGE_TYPE)
+            .filter_map(|attachment| attachment.content.as_deref())
+            .find_map(armored_block)
+            .ok_or_else(|| AsgardError::not_found("Message has no Autocrypt setup file"))?;
+
+        let code = normalize_setup_code(setup_code)?;
+        let (encrypted, _) = PgpMessage::from_string(&armored).map_err(pgp_error)?;
+        let decrypted = encrypted.decrypt_with_password(|| code)
+            .ok()
+            .and_then(|mut decrypter| decrypter.next())
+            .and_then(|decrypted| decrypted.ok())
+            .ok_or_else(|| AsgardError::crypto("Wrong setup code"))?;
+        let content = decrypted.decompress().map_err(pgp_error)?
+            .get_content().map_err(pgp_error)?
+            .ok_or_else(|| AsgardError::crypto("Setup file is empty"))?;
+        let content = String::from_utf8(content)
+            .map_err(|_| AsgardError::validation("Setup file does not contain an armored key"))?;
+
+        let fallback = message.headers.from.first().map(|addr| addr.email.as_str());
+        Self::from_armored(&content, fallback)
+    }
+
+    fn encrypt_setup_file(&self, code: &str) -> AsgardResult<String> {
+        let mut rng = rand::thread_rng();
+        let literal = PgpMessage::new_literal("", &self.to_armored()?);
+        let code = code.to_string();
+        let s2k = StringToKey::new_default(&mut rng);
+        let encrypted = literal
+            .encrypt_with_password(&mut rng, s2k, SymmetricKeyAlgorithm::AES128, || code.clone())
+            .map_err(pgp_error)?;
+
+        let mut headers = BTreeMap::new();
+        headers.insert("Passphrase-Format".to_string(), "numeric9x4".to_string());
+        headers.insert("Passphrase-Begin".to_string(), code[..2].to_string());
+        encrypted.to_armored_string(Some(&headers)).map_err(pgp_error)
+    }
+}
+
+/// Whether `message` is an Autocrypt Setup Message
+pub fn is_setup_message(message: &Message) -> bool {
+    use mailparse::MailHeaderMap;
+
+    let Some(raw) = message.raw_content.as_deref() else {
+        return false;
+    };
+    mailparse::parse_headers(raw)
+        .ok()
+        .and_then(|(headers, _)| headers.get_first_value(SETUP_MESSAGE_HEADER))
+        .is_some_and(|value| value.trim() == "v1")
+}
+
+/// Random setup code of 9 blocks of 4 digits
+fn generate_setup_code() -> String {
+    let mut rng = rand::thread_rng();
+    let digits: String = (0..SETUP_CODE_DIGITS)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10u8)))
+        .collect();
+    format_setup_code(&digits)
+}
+
+fn format_setup_code(digits: &str) -> String {
+    digits.as_bytes()
+        .chunks(4)
+        .map(|block| std::str::from_utf8(block).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Canonical dashed form of a setup code typed by the user
+fn normalize_setup_code(input: &str) -> AsgardResult<String> {
+    let digits: String = input.chars().filter(char::is_ascii_digit).collect();
+    if digits.len() != SETUP_CODE_DIGITS {
+        return Err(AsgardError::validation(format!("A setup code has {} digits", SETUP_CODE_DIGITS)));
+    }
+    Ok(format_setup_code(&digits))
+}
+
+/// The armored OpenPGP message inside a setup file
+fn armored_block(content: &[u8]) -> Option<String> {
+    const BEGIN: &str = "-----BEGIN PGP MESSAGE-----";
+    const END: &str = "-----END PGP MESSAGE-----";
+
+    let text = String::from_utf8_lossy(content);
+    let start = text.find(BEGIN)?;
+    let end = text[start..].find(END)? + start + END.len();
+    Some(text[start..end].to_string())
+}
+
+fn pgp_error(e: pgp::errors::Error) -> AsgardError {
+    AsgardError::crypto(format!("OpenPGP: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgp::types::KeyTrait;
+
+    fn message_from(raw: &str) -> Message {
+        Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), raw.as_bytes()).unwrap()
+    }
+
+    fn date(days: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::days(10_000 + days)
+    }
+
+    fn header_for(addr: &str, prefer_encrypt: PreferEncrypt) -> AutocryptHeader {
+        AutocryptHeader { addr: addr.to_string(), prefer_encrypt, keydata: vec![1, 2, 3] }
+    }
+
+    #[test]
+    fn test_header_parse_and_fold() {
+        let account = AutocryptAccount::generate("Alice@Example.com").unwrap();
+        let header = account.header().unwrap();
+        assert_eq!(header.addr, "alice@example.com");
+
+        let folded = header.folded_value();
+        assert!(folded.lines().all(|line| line.len() <= KEYDATA_LINE + 1));
+        let parsed = AutocryptHeader::parse(&folded).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(
+            parsed.public_key().unwrap().fingerprint(),
+            account.public_key().unwrap().fingerprint()
+        );
+
+        let parsed = AutocryptHeader::parse("addr=bob@example.com; prefer-encrypt=mutual; _extra=1; keydata=AQID").unwrap();
+        assert_eq!(parsed, header_for("bob@example.com", PreferEncrypt::Mutual));
+        assert!(AutocryptHeader::parse("addr=bob@example.com; critical=1; keydata=AQID").is_err());
+        assert!(AutocryptHeader::parse("addr=bob@example.com").is_err());
+        assert!(AutocryptHeader::parse("addr=bob@example.com; keydata=!!!").is_err());
+    }
+
+    #[test]
+    fn test_incoming_header_rules() {
+        let raw = "From: Bob <bob@example.com>\r\n\
+                   Autocrypt: addr=bob@example.com; keydata=AQID\r\n\
+                   Autocrypt: addr=mallory@example.com; keydata=AQID\r\n\
+                   Subject: hi\r\n\r\nbody";
+        let (headers, _) = mailparse::parse_headers(raw.as_bytes()).unwrap();
+        assert_eq!(incoming_header(&headers, "bob@example.com").unwrap().keydata, vec![1, 2, 3]);
+        assert!(incoming_header(&headers, "carol@example.com").is_none());
+
+        let twice = "Autocrypt: addr=bob@example.com; keydata=AQID\r\n\
+                     Autocrypt: addr=bob@example.com; keydata=BAUG\r\n\r\n";
+        let (headers, _) = mailparse::parse_headers(twice.as_bytes()).unwrap();
+        assert!(incoming_header(&headers, "bob@example.com").is_none());
+    }
+
+    #[test]
+    fn test_peer_state_update() {
+        let mut peer = PeerState::new("Bob@example.com");
+        assert_eq!(peer.preliminary_recommendation(), Recommendation::Disable);
+
+        assert!(peer.update(Some(&header_for("bob@example.com", PreferEncrypt::Mutual)), date(0)));
+        assert_eq!(peer.preliminary_recommendation(), Recommendation::Available);
+
+        // An older message cannot replace the key
+        let older = AutocryptHeader { keydata: vec![9], ..header_for("bob@example.com", PreferEncrypt::Mutual) };
+        assert!(!peer.update(Some(&older), date(-1)));
+        assert_eq!(peer.public_key.as_deref(), Some(&[1, 2, 3][..]));
+
+        // Mail without headers for a long time discourages encryption
+        assert!(peer.update(None, date(40)));
+        assert_eq!(peer.autocrypt_timestamp, Some(date(0)));
+        assert_eq!(peer.preliminary_recommendation(), Recommendation::Discourage);
+
+        let mut gossiped = PeerState::new("carol@example.com");
+        assert!(gossiped.update_gossip(&header_for("carol@example.com", PreferEncrypt::NoPreference), date(1)));
+        assert!(!gossiped.update_gossip(&header_for("carol@example.com", PreferEncrypt::NoPreference), date(0)));
+        assert_eq!(gossiped.preliminary_recommendation(), Recommendation::Discourage);
+    }
+
+    #[test]
+    fn test_recommendation() {
+        let mut mutual = PeerState::new("bob@example.com");
+        mutual.update(Some(&header_for("bob@example.com", PreferEncrypt::Mutual)), date(0));
+        let mut neutral = PeerState::new("carol@example.com");
+        neutral.update(Some(&header_for("carol@example.com", PreferEncrypt::NoPreference)), date(0));
+
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[Some(&mutual)], false), Recommendation::Encrypt);
+        assert_eq!(recommend(PreferEncrypt::NoPreference, &[Some(&mutual)], false), Recommendation::Available);
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[Some(&mutual), Some(&neutral)], false), Recommendation::Available);
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[Some(&mutual), Some(&neutral)], true), Recommendation::Encrypt);
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[Some(&mutual), None], true), Recommendation::Disable);
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[], false), Recommendation::Disable);
+
+        mutual.update(None, date(60));
+        assert_eq!(recommend(PreferEncrypt::Mutual, &[Some(&mutual)], false), Recommendation::Discourage);
+    }
+
+    #[test]
+    fn test_gossip_headers() {
+        let message = message_from("From: bob@example.com\r\n\
+                                    To: alice@example.com, carol@example.com\r\n\
+                                    Cc: dave@example.com\r\n\r\nhi");
+
+        let values = vec![
+            "addr=carol@example.com; keydata=AQID".to_string(),
+            "addr=dave@example.com; keydata=AQID".to_string(),
+            "addr=alice@example.com; keydata=AQID".to_string(),
+            "addr=eve@example.com; keydata=AQID".to_string(),
+        ];
+        let gossip: Vec<String> = gossip_headers(&values, &message, "alice@example.com")
+            .into_iter()
+            .map(|header| header.addr)
+            .collect();
+        assert_eq!(gossip, vec!["carol@example.com", "dave@example.com"]);
+
+        let entity = with_gossip(b"Content-Type: text/plain\r\n\r\nhi", &[header_for("carol@example.com", PreferEncrypt::Mutual)]);
+        let (parsed, _) = mailparse::parse_headers(&entity).unwrap();
+        use mailparse::MailHeaderMap;
+        let value = parsed.get_first_value(GOSSIP_HEADER).unwrap();
+        assert_eq!(AutocryptHeader::parse(&value).unwrap(), header_for("carol@example.com", PreferEncrypt::NoPreference));
+    }
+
+    #[test]
+    fn test_encrypt_to_autocrypt_peer_with_gossip() {
+        let alice = AutocryptAccount::generate("alice@example.com").unwrap();
+        let bob = AutocryptAccount::generate("bob@example.com").unwrap();
+        let mut peer = PeerState::new("bob@example.com");
+        peer.update(Some(&bob.header().unwrap()), date(0));
+
+        let sender = alice.keyring(None, &[peer]).unwrap();
+        assert!(sender.has_public_key("bob@example.com"));
+        assert!(sender.has_secret_key("alice@example.com"));
+
+        let gossip = [header_for("carol@example.com", PreferEncrypt::NoPreference)];
+        let entity = with_gossip(&SinglePart::plain("Hello Bob".to_string()).formatted(), &gossip);
+        let encrypted = sender.encrypt_entity(&["bob@example.com", "alice@example.com"], Some("alice@example.com"), &entity).unwrap();
+        let email = LettreMessage::builder()
+            .from("alice@example.com".parse().unwrap())
+            .to("bob@example.com".parse().unwrap())
+            .to("carol@example.com".parse().unwrap())
+            .header(alice.header().unwrap())
+            .subject("Autocrypt")
+            .multipart(encrypted)
+            .unwrap();
+        let message = message_from(std::str::from_utf8(&email.formatted()).unwrap());
+
+        let (headers, _) = mailparse::parse_headers(message.raw_content.as_deref().unwrap()).unwrap();
+        assert_eq!(incoming_header(&headers, "alice@example.com").unwrap(), alice.header().unwrap());
+
+        let status = bob.keyring(None, &[]).unwrap().process(&mut message.clone());
+        assert!(status.decrypted, "{:?}", status.error);
+        let gossip = gossip_headers(&status.gossip, &message, bob.email());
+        assert_eq!(gossip.len(), 1);
+        assert_eq!(gossip[0].addr, "carol@example.com");
+    }
+
+    #[test]
+    fn test_setup_message_round_trip() {
+        let mut account = AutocryptAccount::generate("alice@example.com").unwrap();
+        account.set_prefer_encrypt(PreferEncrypt::Mutual);
+
+        let (code, email) = account.setup_message().unwrap();
+        assert_eq!(code.len(), 44);
+        assert_eq!(code.split('-').count(), 9);
+
+        let raw = String::from_utf8(email.formatted()).unwrap();
+        assert!(raw.contains("Passphrase-Format: numeric9x4"));
+        assert!(!raw.contains("PRIVATE KEY"));
+        let message = message_from(&raw);
+        assert!(is_setup_message(&message));
+
+        assert!(AutocryptAccount::from_setup_message(&message, "1234").is_err());
+        let wrong = format_setup_code(&"1".repeat(SETUP_CODE_DIGITS));
+        assert!(AutocryptAccount::from_setup_message(&message, &wrong).is_err());
+
+        let imported = AutocryptAccount::from_setup_message(&message, &code.replace('-', " ")).unwrap();
+        assert_eq!(imported.email(), "alice@example.com");
+        assert_eq!(imported.prefer_encrypt(), PreferEncrypt::Mutual);
+        assert_eq!(
+            imported.public_key().unwrap().fingerprint(),
+            account.public_key().unwrap().fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_setup_code_format() {
+        let code = generate_setup_code();
+        assert!(code.split('-').all(|block| block.len() == 4 && block.chars().all(|c| c.is_ascii_digit())));
+        assert_eq!(normalize_setup_code(&code.replace('-', "")).unwrap(), code);
+        assert!(normalize_setup_code("12-34").is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! Adding meeting invitations to the user's calendar
+//!
+//! An account can name a calendar that invitations, updates and
+//! cancellations are written to: a local `.ics` file that calendar
+//! applications subscribe to, or a CalDAV collection holding one resource
+//! per event UID (RFC 4791). Either way the stored copy is updated with
+//! [`Calendar::apply`], so only newer versions of an event replace it.
+
+use crate::account::CalendarTarget;
+use crate::error::{AsgardError, AsgardResult};
+use crate::ical::{Calendar, CalendarChange};
+use reqwest::{header, Method, StatusCode};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use url::Url;
+
+/// Attempts at writing a CalDAV resource that keeps changing under us
+const MAX_CONFLICT_ATTEMPTS: usize = 3;
+
+/// Add the events of `invitation` to `target`, or remove them when it is a
+/// cancellation
+pub async fn add_to_calendar(target: &CalendarTarget, invitation: &Calendar) -> AsgardResult<CalendarChange> {
+    match target {
+        CalendarTarget::File { path } => IcsFile::new(path.clone()).apply(invitation).await,
+        CalendarTarget::CalDav { url, username, password } => {
+            CalDavCollection::new(url, username.clone(), password.clone())?.apply(invitation).await
+        }
+    }
+}
+
+/// Calendar kept in a local iCalendar file
+#[derive(Debug, Clone)]
+pub struct IcsFile {
+    path: PathBuf,
+}
+
+impl IcsFile {
+    /// Calendar file at `path`, created on first use
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Apply `invitation` to the file
+    ///
+    /// The file is rewritten through a temporary file and a rename, so
+    /// calendar applications never read half of it.
+    pub async fn apply(&self, invitation: &Calendar) -> AsgardResult<CalendarChange> {
+        let mut calendar = match tokio::fs::read_to_string(&self.path).await {
+            Ok(text) if text.trim().is_empty() => Calendar::default(),
+            Ok(text) => Calendar::parse(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Calendar::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let change = calendar.apply(invitation)?;
+        if change == CalendarChange::Unchanged {
+            return Ok(change);
+        }
+
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp = self.path.with_extension("ics.tmp");
+        tokio::fs::write(&temp, calendar.to_ics()).await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+        info!("Updated calendar file {}", self.path.display());
+        Ok(change)
+    }
+}
+
+/// CalDAV calendar collection
+///
+/// Each event is stored at `<collection>/<uid>.ics`. Writes are made
+/// conditional on the ETag read, and redone from a fresh copy when another
+/// client changed the resource in between. Events another client stored
+/// under a different name are not found.
+#[derive(Debug, Clone)]
+pub struct CalDavCollection {
+    client: reqwest::Client,
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl CalDavCollection {
+    /// Collection at `url`, with Basic authentication when `username` is set
+    pub fn new(url: &str, username: Option<String>, password: Option<String>) -> AsgardResult<Self> {
+        let mut url = Url::parse(url.trim())?;
+        if !matches!(url.scheme(), "http" | "https") || url.cannot_be_a_base() {
+            return Err(AsgardError::config(format!("Not a CalDAV collection URL: {}", url)));
+        }
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+
+        Ok(Self { client: reqwest::Client::new(), url, username, password })
+    }
+
+    /// Apply `invitation` to the collection, one resource per UID
+    pub async fn apply(&self, invitation: &Calendar) -> AsgardResult<CalendarChange> {
+        let mut result = CalendarChange::Unchanged;
+        for uid in invitation.uids() {
+            let change = self.apply_event(uid, &invitation.only(uid)).await?;
+            if result == CalendarChange::Unchanged {
+                result = change;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Apply the events with one UID to their resource
+    async fn apply_event(&self, uid: &str, invitation: &Calendar) -> AsgardResult<CalendarChange> {
+        let url = self.resource(uid);
+
+        for _ in 0..MAX_CONFLICT_ATTEMPTS {
+            let stored = self.fetch(&url).await?;
+            let exists = stored.is_some();
+            let (mut calendar, etag) = stored.unwrap_or_default();
+
+            let change = calendar.apply(invitation)?;
+            if change == CalendarChange::Unchanged {
+                return Ok(change);
+            }
+
+            let request = if calendar.events.is_empty() {
+                self.request(Method::DELETE, &url)
+            } else {
+                self.request(Method::PUT, &url)
+                    .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+                    .body(calendar.to_ics())
+            };
+            let request = match (etag, exists) {
+                (Some(etag), _) => request.header(header::IF_MATCH, etag),
+                (None, false) => request.header(header::IF_NONE_MATCH, "*"),
+                (None, true) => request,
+            };
+
+            let response = request.send().await?;
+            match response.status() {
+                status if status.is_success() => {
+                    info!("Updated CalDAV resource {}", url);
+                    return Ok(change);
+                }
+                StatusCode::PRECONDITION_FAILED => warn!("CalDAV resource {} changed while updating it, retrying", url),
+                status => return Err(AsgardError::network(format!("CalDAV server answered {} for {}", status, url))),
+            }
+        }
+
+        Err(AsgardError::invalid_state(format!("CalDAV resource {} kept changing while updating it", url)))
+    }
+
+    /// The stored calendar of a resource and its ETag, `None` if there is
+    /// no such resource
+    async fn fetch(&self, url: &Url) -> AsgardResult<Option<(Calendar, Option<String>)>> {
+        let response = self.request(Method::GET, url).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(None),
+            status if status.is_success() => {
+                let etag = response.headers()
+                    .get(header::ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(str::to_string);
+                let calendar = Calendar::parse(&response.text().await?)?;
+                Ok(Some((calendar, etag)))
+            }
+            status => Err(AsgardError::network(format!("CalDAV server answered {} for {}", status, url))),
+        }
+    }
+
+    /// URL of the resource holding the event `uid`
+    fn resource(&self, uid: &str) -> Url {
+        let mut url = self.url.clone();
+        // The URL was checked to have a path in new()
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&format!("{}.ics", uid));
+        }
+        url
+    }
+
+    fn request(&self, method: Method, url: &Url) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url.clone());
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn invitation(method: &str, sequence: u32, summary: &str) -> Calendar {
+        Calendar::parse(&format!(
+            "BEGIN:VCALENDAR\r\nPRODID:-//Test//EN\r\nVERSION:2.0\r\nMETHOD:{}\r\nBEGIN:VEVENT\r\n\
+             UID:weekly/sync@example.com\r\nSEQUENCE:{}\r\nDTSTAMP:20261001T090000Z\r\n\
+             DTSTART:20261020T100000Z\r\nDTEND:20261020T110000Z\r\nSUMMARY:{}\r\n\
+             ORGANIZER:mailto:alice@example.com\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+            method, sequence, summary
+        ))
+        .unwrap()
+    }
+
+    /// Resources of the WebDAV stand-in: path to body and ETag
+    #[derive(Default)]
+    struct Store {
+        resources: HashMap<String, (String, u32)>,
+        version: u32,
+        /// PUTs to refuse with 412 as if another client had won the race
+        conflicts: usize,
+        requests: Vec<String>,
+    }
+
+    /// Minimal WebDAV server honoring If-Match and If-None-Match
+    async fn webdav_server() -> (String, Arc<Mutex<Store>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let store = Arc::new(Mutex::new(Store::default()));
+        let state = store.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let mut buffer = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let head_end = loop {
+                    let n = socket.read(&mut chunk).await.unwrap_or(0);
+                    if n == 0 {
+                        break None;
+                    }
+                    buffer.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buffer).to_string();
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                            .unwrap_or(0);
+                        if buffer.len() >= head_end + 4 + length {
+                            break Some(head_end);
+                        }
+                    }
+                };
+                let Some(head_end) = head_end else { continue };
+
+                let request = String::from_utf8_lossy(&buffer).to_string();
+                let head = &request[..head_end];
+                let body = request[head_end + 4..].to_string();
+                let mut words = head.split_whitespace();
+                let (method, path) = (words.next().unwrap_or("").to_string(), words.next().unwrap_or("").to_string());
+                let header = |name: &str| {
+                    head.lines().find_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                    })
+                };
+
+                let (status, etag, response_body) = {
+                    let mut store = state.lock().unwrap();
+                    store.requests.push(request.clone());
+                    let current = store.resources.get(&path).map(|(_, version)| format!("\"{}\"", version));
+                    let precondition = match (header("If-Match"), header("If-None-Match")) {
+                        (Some(etag), _) => current.as_deref() == Some(etag.as_str()),
+                        (None, Some(_)) => current.is_none(),
+                        (None, None) => true,
+                    };
+
+                    match method.as_str() {
+                        "GET" => match store.resources.get(&path) {
+                            Some((text, version)) => (200, Some(format!("\"{}\"", version)), text.clone()),
+                            None => (404, None, String::new()),
+                        },
+                        "PUT" if store.conflicts > 0 => {
+                            store.conflicts -= 1;
+                            store.version += 1;
+                            let version = store.version;
+                            if let Some(resource) = store.resources.get_mut(&path) {
+                                resource.1 = version;
+                            }
+                            (412, None, String::new())
+                        }
+                        "PUT" | "DELETE" if !precondition => (412, None, String::new()),
+                        "PUT" => {
+                            store.version += 1;
+                            let version = store.version;
+                            let status = if current.is_some() { 204 } else { 201 };
+                            store.resources.insert(path.clone(), (body, version));
+                            (status, Some(format!("\"{}\"", version)), String::new())
+                        }
+                        "DELETE" => {
+                            store.resources.remove(&path);
+                            (204, None, String::new())
+                        }
+                        _ => (405, None, String::new()),
+                    }
+                };
+
+                let etag = etag.map(|etag| format!("ETag: {}\r\n", etag)).unwrap_or_default();
+                let response = format!(
+                    "HTTP/1.1 {} X\r\n{}Content-Type: text/calendar\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    etag,
+                    response_body.len(),
+                    response_body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (address, store)
+    }
+
+    #[tokio::test]
+    async fn test_ics_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("calendars").join("work.ics");
+        let target = CalendarTarget::File { path: path.clone() };
+
+        let change = add_to_calendar(&target, &invitation("REQUEST", 0, "Weekly sync")).await.unwrap();
+        assert_eq!(change, CalendarChange::Added);
+        let stored = Calendar::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored.method, None);
+        assert_eq!(stored.events.len(), 1);
+
+        // An update, then the original invitation opened late
+        let change = add_to_calendar(&target, &invitation("REQUEST", 2, "Weekly sync (moved)")).await.unwrap();
+        assert_eq!(change, CalendarChange::Updated);
+        let change = add_to_calendar(&target, &invitation("REQUEST", 0, "Weekly sync")).await.unwrap();
+        assert_eq!(change, CalendarChange::Unchanged);
+        let stored = Calendar::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored.events[0].title(), "Weekly sync (moved)");
+
+        let change = add_to_calendar(&target, &invitation("CANCEL", 3, "Weekly sync (moved)")).await.unwrap();
+        assert_eq!(change, CalendarChange::Removed);
+        let stored = Calendar::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(stored.events.is_empty());
+        assert!(!path.with_extension("ics.tmp").exists());
+
+        let mut reply = invitation("REQUEST", 3, "Weekly sync (moved)");
+        reply.method = Some(crate::ical::Method::Reply);
+        assert!(add_to_calendar(&target, &reply).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_caldav_collection() {
+        let (address, store) = webdav_server().await;
+        let target = CalendarTarget::CalDav {
+            url: format!("{}/calendars/alice/work", address),
+            username: Some("alice".to_string()),
+            password: Some("secret".to_string()),
+        };
+        let resource = "/calendars/alice/work/weekly%2Fsync@example.com.ics";
+
+        let change = add_to_calendar(&target, &invitation("REQUEST", 0, "Weekly sync")).await.unwrap();
+        assert_eq!(change, CalendarChange::Added);
+        {
+            let store = store.lock().unwrap();
+            let (text, _) = &store.resources[resource];
+            assert_eq!(Calendar::parse(text).unwrap().events[0].title(), "Weekly sync");
+            let put = store.requests.iter().find(|r| r.starts_with("PUT")).unwrap();
+            assert!(put.to_ascii_lowercase().contains("if-none-match: *"));
+            assert!(put.to_ascii_lowercase().contains("authorization: basic"));
+        }
+
+        let change = add_to_calendar(&target, &invitation("REQUEST", 1, "Weekly sync (moved)")).await.unwrap();
+        assert_eq!(change, CalendarChange::Updated);
+        let change = add_to_calendar(&target, &invitation("REQUEST", 0, "Weekly sync")).await.unwrap();
+        assert_eq!(change, CalendarChange::Unchanged);
+        {
+            let store = store.lock().unwrap();
+            let put = store.requests.iter().rev().find(|r| r.starts_with("PUT")).unwrap();
+            assert!(put.to_ascii_lowercase().contains("if-match: \""));
+            let (text, _) = &store.resources[resource];
+            assert_eq!(Calendar::parse(text).unwrap().events[0].title(), "Weekly sync (moved)");
+        }
+
+        let change = add_to_calendar(&target, &invitation("CANCEL", 2, "Weekly sync (moved)")).await.unwrap();
+        assert_eq!(change, CalendarChange::Removed);
+        assert!(store.lock().unwrap().resources.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_caldav_conflicts() {
+        let (address, store) = webdav_server().await;
+        let collection = CalDavCollection::new(&format!("{}/work/", address), None, None).unwrap();
+        collection.apply(&invitation("REQUEST", 0, "Weekly sync")).await.unwrap();
+
+        // Lost races are retried with the new ETag
+        store.lock().unwrap().conflicts = 1;
+        let change = collection.apply(&invitation("REQUEST", 1, "Weekly sync (moved)")).await.unwrap();
+        assert_eq!(change, CalendarChange::Updated);
+
+        store.lock().unwrap().conflicts = MAX_CONFLICT_ATTEMPTS;
+        assert!(collection.apply(&invitation("REQUEST", 2, "Weekly sync (again)")).await.is_err());
+        let store = store.lock().unwrap();
+        let (text, _) = store.resources.values().next().unwrap();
+        assert_eq!(Calendar::parse(text).unwrap().events[0].title(), "Weekly sync (moved)");
+        assert!(!store.requests.iter().any(|r| r.to_ascii_lowercase().contains("authorization")));
+
+        assert!(CalDavCollection::new("mailto:alice@example.com", None, None).is_err());
+    }
+}
@@ -169,6 +169,23 @@ pub struct SecurityConfig {
     pub trusted_domains: Vec<String>,
     /// Blocked domains
     pub blocked_domains: Vec<String>,
+    /// Senders whose remote content is always loaded
+    #[serde(default)]
+    pub remote_content_senders: Vec<String>,
+    /// Sender domains whose remote content is always loaded
+    #[serde(default)]
+    pub remote_content_domains: Vec<String>,
+    /// PEM file or directory of CA certificates trusted for S/MIME
+    /// (the system roots are used when unset)
+    #[serde(default)]
+    pub smime_trust_store: Option<PathBuf>,
+    /// Servers whose Authentication-Results headers are believed (only
+    /// the topmost header is used when empty)
+    #[serde(default)]
+    pub authserv_ids: Vec<String>,
+    /// Verify DKIM signatures locally, looking keys up in DNS
+    #[serde(default)]
+    pub verify_dkim: bool,
 }
 
 /// Notification configuration
@@ -296,6 +313,11 @@ impl Default for SecurityConfig {
             sanitize_html: true,
             trusted_domains: vec![],
             blocked_domains: vec![],
+            remote_content_senders: vec![],
+            remote_content_domains: vec![],
+            smime_trust_store: None,
+            authserv_ids: vec![],
+            verify_dkim: false,
         }
     }
 }
@@ -476,6 +498,14 @@ impl Config {
         self.app.data_dir.clone()
     }
 
+    /// Whether messages and cached files are encrypted at rest
+    ///
+    /// Either of `security.encrypt_cache` and `cache.enable_encryption`
+    /// turns it on.
+    pub fn encryption_at_rest(&self) -> bool {
+        self.security.encrypt_cache || self.cache.enable_encryption
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> AsgardResult<()> {
         // Validate directories
@@ -512,6 +542,41 @@ impl Config {
     }
 }
 
+impl SecurityConfig {
+    /// Whether remote content in mail from `sender` loads without asking
+    ///
+    /// A listed domain also covers its subdomains.
+    pub fn allows_remote_content(&self, sender: &str) -> bool {
+        let sender = sender.trim().to_ascii_lowercase();
+        if !self.block_remote_images || self.remote_content_senders.iter().any(|allowed| allowed.eq_ignore_ascii_case(&sender)) {
+            return true;
+        }
+        let Some((_, domain)) = sender.rsplit_once('@') else {
+            return false;
+        };
+        self.remote_content_domains.iter().any(|allowed| {
+            let allowed = allowed.trim().trim_start_matches('@').to_ascii_lowercase();
+            domain == allowed || domain.strip_suffix(allowed.as_str()).is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+
+    /// Always load remote content in mail from `sender`
+    pub fn allow_remote_content_from_sender(&mut self, sender: &str) {
+        let sender = sender.trim().to_ascii_lowercase();
+        if !sender.is_empty() && !self.remote_content_senders.contains(&sender) {
+            self.remote_content_senders.push(sender);
+        }
+    }
+
+    /// Always load remote content in mail from senders at `domain`
+    pub fn allow_remote_content_from_domain(&mut self, domain: &str) {
+        let domain = domain.trim().trim_start_matches('@').to_ascii_lowercase();
+        if !domain.is_empty() && !self.remote_content_domains.contains(&domain) {
+            self.remote_content_domains.push(domain);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -543,6 +608,28 @@ mod tests {
         assert_eq!(loaded_config.ui.theme, ThemePreference::Dark);
     }
 
+    #[test]
+    fn test_remote_content_allow_lists() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+
+        let mut config = Config::default();
+        assert!(!config.security.allows_remote_content("news@shop.example"));
+
+        config.security.allow_remote_content_from_sender("Alice@Example.com");
+        config.security.allow_remote_content_from_sender("alice@example.com");
+        config.security.allow_remote_content_from_domain("@shop.example");
+        config.save(&config_path).unwrap();
+
+        let security = Config::load(&config_path).unwrap().security;
+        assert_eq!(security.remote_content_senders, vec!["alice@example.com"]);
+        assert!(security.allows_remote_content("ALICE@example.com"));
+        assert!(!security.allows_remote_content("bob@example.com"));
+        assert!(security.allows_remote_content("news@shop.example"));
+        assert!(security.allows_remote_content("news@mail.shop.example"));
+        assert!(!security.allows_remote_content("news@othershop.example"));
+    }
+
     #[test]
     fn test_config_validation() {
         let mut config = Config::default();
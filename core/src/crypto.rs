@@ -3,7 +3,10 @@
 use crate::error::{AsgardError, AsgardResult};
 use sodiumoxide::crypto::secretbox;
 use sodiumoxide::crypto::pwhash;
+use base64::{engine::general_purpose, Engine as _};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Encryption key for sensitive data
 pub struct EncryptionKey {
@@ -34,6 +37,13 @@ impl EncryptionKey {
         }
     }
 
+    /// Create an encryption key from raw key bytes
+    pub fn from_bytes(bytes: &[u8]) -> AsgardResult<Self> {
+        Ok(Self {
+            key: secretbox::Key::from_slice(bytes).ok_or(AsgardError::crypto("Invalid key"))?,
+        })
+    }
+
     /// Get the key bytes
     pub fn as_bytes(&self) -> &[u8] {
         self.key.as_ref()
@@ -82,6 +92,40 @@ impl SecureStorage {
         }
     }
 
+    /// Load secure storage from a file written by [`SecureStorage::save`]
+    ///
+    /// A missing file gives empty storage. Values stay encrypted in memory
+    /// and are only decrypted by [`SecureStorage::retrieve`].
+    pub fn load(path: &Path, key: EncryptionKey) -> AsgardResult<Self> {
+        let mut storage = Self::new(key);
+        if !path.exists() {
+            return Ok(storage);
+        }
+
+        let encoded: HashMap<String, String> = serde_json::from_slice(&std::fs::read(path)?)?;
+        for (name, value) in encoded {
+            let encrypted = general_purpose::STANDARD
+                .decode(value)
+                .map_err(|_| AsgardError::crypto("Invalid secure storage file"))?;
+            storage.data.insert(name, encrypted);
+        }
+
+        Ok(storage)
+    }
+
+    /// Write the encrypted values to a file readable only by the user
+    pub fn save(&self, path: &Path) -> AsgardResult<()> {
+        let encoded: HashMap<&String, String> = self.data
+            .iter()
+            .map(|(name, value)| (name, general_purpose::STANDARD.encode(value)))
+            .collect();
+
+        let tmp_path = path.with_extension("tmp");
+        write_private(&tmp_path, &serde_json::to_vec(&encoded)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
     /// Store encrypted data
     pub fn store(&mut self, key: &str, value: &[u8]) -> AsgardResult<()> {
         let encrypted = self.key.encrypt(value)?;
@@ -120,6 +164,88 @@ impl SecureStorage {
     }
 }
 
+/// Marker prepended to content encrypted at rest
+pub const AT_REST_MAGIC: &[u8] = b"ASGENC1\0";
+
+/// Encryption of stored message content and cache files
+///
+/// Content written while encryption is enabled is tagged with
+/// [`AT_REST_MAGIC`], so plaintext and encrypted data can coexist while a
+/// migration runs and either can be read whatever the current setting.
+pub struct StorageCipher {
+    key: EncryptionKey,
+    enabled: AtomicBool,
+}
+
+impl StorageCipher {
+    /// Create a cipher; new content is encrypted only when `enabled`
+    pub fn new(key: EncryptionKey, enabled: bool) -> Self {
+        Self {
+            key,
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    /// Check whether new content gets encrypted
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Turn encryption of new content on or off
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Check whether stored content is encrypted
+    pub fn is_sealed(data: &[u8]) -> bool {
+        data.starts_with(AT_REST_MAGIC)
+    }
+
+    /// Encode content for storage according to the current setting
+    pub fn seal(&self, data: &[u8]) -> AsgardResult<Vec<u8>> {
+        if !self.is_enabled() {
+            return Ok(data.to_vec());
+        }
+
+        let encrypted = self.key.encrypt(data)?;
+        let mut sealed = Vec::with_capacity(AT_REST_MAGIC.len() + encrypted.len());
+        sealed.extend_from_slice(AT_REST_MAGIC);
+        sealed.extend_from_slice(&encrypted);
+        Ok(sealed)
+    }
+
+    /// Decode stored content, encrypted or not
+    pub fn open(&self, data: &[u8]) -> AsgardResult<Vec<u8>> {
+        match data.strip_prefix(AT_REST_MAGIC) {
+            Some(encrypted) => self.key.decrypt(encrypted),
+            None => Ok(data.to_vec()),
+        }
+    }
+
+    /// Check whether stored content needs re-encoding for the current setting
+    pub fn needs_reencode(&self, data: &[u8]) -> bool {
+        Self::is_sealed(data) != self.is_enabled()
+    }
+}
+
+/// Write a file with owner-only permissions
+pub fn write_private(path: &Path, contents: &[u8]) -> AsgardResult<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
 /// Password hashing utilities
 pub struct PasswordHasher;
 
@@ -277,6 +403,47 @@ mod tests {
         assert!(!storage.contains_key("nonexistent_key"));
     }
 
+    #[test]
+    fn test_secure_storage_save_and_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("secrets.enc");
+        let key = EncryptionKey::new();
+
+        let mut storage = SecureStorage::new(EncryptionKey::from_bytes(key.as_bytes()).unwrap());
+        storage.store("token", b"secret token").unwrap();
+        storage.save(&path).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("secret token"));
+
+        let loaded = SecureStorage::load(&path, key).unwrap();
+        assert_eq!(loaded.retrieve("token").unwrap().unwrap(), b"secret token");
+
+        let wrong_key = SecureStorage::load(&path, EncryptionKey::new()).unwrap();
+        assert!(wrong_key.retrieve("token").is_err());
+    }
+
+    #[test]
+    fn test_storage_cipher() {
+        let cipher = StorageCipher::new(EncryptionKey::new(), false);
+        let data = b"Subject: hello\r\n\r\nbody";
+
+        let plain = cipher.seal(data).unwrap();
+        assert_eq!(plain, data);
+        assert!(!cipher.needs_reencode(&plain));
+
+        cipher.set_enabled(true);
+        assert!(cipher.needs_reencode(&plain));
+        let sealed = cipher.seal(data).unwrap();
+        assert!(StorageCipher::is_sealed(&sealed));
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+
+        // Both encodings stay readable whatever the setting
+        cipher.set_enabled(false);
+        assert_eq!(cipher.open(&sealed).unwrap(), data);
+        assert_eq!(cipher.open(&plain).unwrap(), data);
+    }
+
     #[test]
     fn test_password_hashing() {
         let password = "test_password";
@@ -0,0 +1,936 @@
+//! DKIM signature verification
+//!
+//! Verifies `DKIM-Signature` headers (RFC 6376) signed with `rsa-sha256` or
+//! `ed25519-sha256` (RFC 8463). Public keys are looked up through a
+//! [`DnsResolver`]: [`SystemResolver`] asks the servers in
+//! `/etc/resolv.conf`, [`StaticResolver`] answers from a fixed set of
+//! records.
+
+use crate::error::{AsgardError, AsgardResult};
+use base64::{engine::general_purpose, Engine as _};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// Signatures verified per message at most
+const MAX_SIGNATURES: usize = 5;
+
+/// Smallest RSA key accepted (RFC 8301)
+const MIN_RSA_BITS: u32 = 1024;
+
+/// Resolver configuration read by [`SystemResolver::new`]
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Time allowed for each DNS server to answer
+const DNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// DNS record type of TXT records
+const TYPE_TXT: u16 = 16;
+
+/// UDP payload size announced with EDNS(0)
+const EDNS_PAYLOAD: u16 = 4096;
+
+/// TXT record lookups
+#[async_trait::async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// TXT records of `name`, each with its strings joined; empty when the
+    /// name does not exist
+    async fn txt(&self, name: &str) -> AsgardResult<Vec<String>>;
+}
+
+/// [`DnsResolver`] querying name servers directly over UDP, falling back
+/// to TCP for truncated answers
+#[derive(Debug, Clone)]
+pub struct SystemResolver {
+    servers: Vec<SocketAddr>,
+}
+
+impl SystemResolver {
+    /// Use the name servers of `/etc/resolv.conf`, or the local host when
+    /// none are configured
+    pub fn new() -> Self {
+        let servers = std::fs::read_to_string(RESOLV_CONF)
+            .map(|conf| parse_resolv_conf(&conf))
+            .unwrap_or_default();
+        if servers.is_empty() {
+            return Self::with_servers(vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53)]);
+        }
+        Self::with_servers(servers)
+    }
+
+    /// Use the given name servers, tried in order
+    pub fn with_servers(servers: Vec<SocketAddr>) -> Self {
+        Self { servers }
+    }
+}
+
+impl Default for SystemResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl DnsResolver for SystemResolver {
+    async fn txt(&self, name: &str) -> AsgardResult<Vec<String>> {
+        let id: u16 = rand::random();
+        let query = build_query(id, name)?;
+        let mut last_error = AsgardError::network(format!("No name server answered for {}", name));
+
+        for server in &self.servers {
+            let answer = match tokio::time::timeout(DNS_TIMEOUT, query_udp(*server, &query)).await {
+                Ok(Ok(answer)) => answer,
+                Ok(Err(e)) => {
+                    last_error = e;
+                    continue;
+                }
+                Err(_) => {
+                    last_error = AsgardError::timeout(format!("DNS query to {} timed out", server));
+                    continue;
+                }
+            };
+            let answer = match parse_response(id, &answer) {
+                Err(DnsAnswer::Truncated) => match tokio::time::timeout(DNS_TIMEOUT, query_tcp(*server, &query)).await {
+                    Ok(Ok(answer)) => parse_response(id, &answer),
+                    Ok(Err(e)) => Err(DnsAnswer::Failed(e.to_string())),
+                    Err(_) => Err(DnsAnswer::Failed(format!("DNS query to {} timed out", server))),
+                },
+                answer => answer,
+            };
+            match answer {
+                Ok(records) => return Ok(records),
+                Err(DnsAnswer::Truncated) => last_error = AsgardError::network("Truncated DNS answer"),
+                Err(DnsAnswer::Failed(e)) => last_error = AsgardError::network(e),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// [`DnsResolver`] answering from fixed records, for tests and offline use
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    records: HashMap<String, Vec<String>>,
+}
+
+impl StaticResolver {
+    /// Create a resolver without records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a TXT record for `name`
+    pub fn insert(&mut self, name: &str, record: &str) {
+        self.records.entry(name.trim_end_matches('.').to_ascii_lowercase())
+            .or_default()
+            .push(record.to_string());
+    }
+}
+
+#[async_trait::async_trait]
+impl DnsResolver for StaticResolver {
+    async fn txt(&self, name: &str) -> AsgardResult<Vec<String>> {
+        Ok(self.records.get(&name.trim_end_matches('.').to_ascii_lowercase()).cloned().unwrap_or_default())
+    }
+}
+
+/// Outcome of verifying one signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DkimStatus {
+    /// The signature is valid
+    Pass,
+    /// The signature or body hash does not match
+    Fail(String),
+    /// The key could not be fetched right now
+    TempError(String),
+    /// The signature or key is malformed, expired or unsupported
+    PermError(String),
+}
+
+/// Verification result of one `DKIM-Signature`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DkimVerification {
+    /// Signing domain (`d=`)
+    pub domain: String,
+    /// Key selector (`s=`)
+    pub selector: String,
+    /// Outcome
+    pub status: DkimStatus,
+}
+
+impl DkimVerification {
+    /// Whether the signature is valid
+    pub fn is_pass(&self) -> bool {
+        self.status == DkimStatus::Pass
+    }
+}
+
+/// DKIM verifier looking up keys with a [`DnsResolver`]
+#[derive(Clone)]
+pub struct DkimVerifier {
+    resolver: Arc<dyn DnsResolver>,
+}
+
+impl DkimVerifier {
+    /// Create a verifier using `resolver`
+    pub fn new(resolver: Arc<dyn DnsResolver>) -> Self {
+        Self { resolver }
+    }
+
+    /// Verify the signatures of the RFC 5322 message `raw`
+    pub async fn verify(&self, raw: &[u8]) -> Vec<DkimVerification> {
+        let (fields, body) = split_message(raw);
+        let mut results = Vec::new();
+
+        for field in fields.iter().filter(|field| field.name == "dkim-signature").take(MAX_SIGNATURES) {
+            let signature = match Signature::parse(&field.value()) {
+                Ok(signature) => signature,
+                Err((domain, selector, reason)) => {
+                    results.push(DkimVerification { domain, selector, status: DkimStatus::PermError(reason) });
+                    continue;
+                }
+            };
+            let status = self.verify_signature(&signature, field, &fields, &body).await;
+            results.push(DkimVerification { domain: signature.domain, selector: signature.selector, status });
+        }
+        results
+    }
+
+    async fn verify_signature(&self, signature: &Signature, field: &Field, fields: &[Field], body: &[u8]) -> DkimStatus {
+        if signature.expires.is_some_and(|expires| expires < OffsetDateTime::now_utc().unix_timestamp()) {
+            return DkimStatus::PermError("Signature expired".to_string());
+        }
+
+        let mut canonical_body = canonicalize_body(body, signature.body_canon);
+        if let Some(length) = signature.length {
+            if length > canonical_body.len() {
+                return DkimStatus::PermError("Body length tag exceeds the body".to_string());
+            }
+            canonical_body.truncate(length);
+        }
+        if Sha256::digest(&canonical_body).as_slice() != signature.body_hash.as_slice() {
+            return DkimStatus::Fail("Body hash does not match".to_string());
+        }
+
+        let name = format!("{}._domainkey.{}", signature.selector, signature.domain);
+        let records = match self.resolver.txt(&name).await {
+            Ok(records) => records,
+            Err(e) => return DkimStatus::TempError(format!("Key lookup failed: {}", e)),
+        };
+        let Some(record) = records.iter().find(|record| !record.trim().is_empty()) else {
+            return DkimStatus::PermError(format!("No key published at {}", name));
+        };
+        let key = match PublicKey::parse(record, signature.algorithm) {
+            Ok(key) => key,
+            Err(reason) => return DkimStatus::PermError(reason),
+        };
+        if key.strict && identity_domain(signature.identity.as_deref()).is_some_and(|domain| domain != signature.domain) {
+            return DkimStatus::PermError("Key does not allow subdomain identities".to_string());
+        }
+
+        let data = signed_data(signature, field, fields);
+        match key.verify(signature.algorithm, &data, &signature.signature) {
+            Ok(true) => DkimStatus::Pass,
+            Ok(false) => DkimStatus::Fail("Signature does not match".to_string()),
+            Err(e) => DkimStatus::PermError(e),
+        }
+    }
+}
+
+impl Default for DkimVerifier {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemResolver::new()))
+    }
+}
+
+/// Signature algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    RsaSha256,
+    Ed25519Sha256,
+}
+
+/// Canonicalization algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+/// Parsed `DKIM-Signature`
+#[derive(Debug)]
+struct Signature {
+    algorithm: Algorithm,
+    header_canon: Canonicalization,
+    body_canon: Canonicalization,
+    domain: String,
+    selector: String,
+    identity: Option<String>,
+    headers: Vec<String>,
+    body_hash: Vec<u8>,
+    signature: Vec<u8>,
+    length: Option<usize>,
+    expires: Option<i64>,
+}
+
+impl Signature {
+    /// Parse a header value; errors carry the domain and selector found
+    fn parse(value: &str) -> Result<Self, (String, String, String)> {
+        let tags = parse_tags(value);
+        let tag = |name: &str| tags.get(name).map(String::as_str);
+        let domain = tag("d").unwrap_or_default().to_ascii_lowercase();
+        let selector = tag("s").unwrap_or_default().to_ascii_lowercase();
+        let fail = |reason: &str| (domain.clone(), selector.clone(), reason.to_string());
+
+        if tag("v") != Some("1") {
+            return Err(fail("Unsupported signature version"));
+        }
+        if domain.is_empty() || selector.is_empty() {
+            return Err(fail("Missing signing domain or selector"));
+        }
+        let algorithm = match tag("a").map(str::to_ascii_lowercase).as_deref() {
+            Some("rsa-sha256") => Algorithm::RsaSha256,
+            Some("ed25519-sha256") => Algorithm::Ed25519Sha256,
+            Some("rsa-sha1") => return Err(fail("rsa-sha1 signatures are no longer accepted")),
+            _ => return Err(fail("Unsupported signature algorithm")),
+        };
+        let (header_canon, body_canon) = match tag("c").map(str::to_ascii_lowercase) {
+            None => (Canonicalization::Simple, Canonicalization::Simple),
+            Some(c) => {
+                let (header, body) = c.split_once('/').unwrap_or((&c, "simple"));
+                match (canonicalization(header), canonicalization(body)) {
+                    (Some(header), Some(body)) => (header, body),
+                    _ => return Err(fail("Unsupported canonicalization")),
+                }
+            }
+        };
+        let headers: Vec<String> = tag("h").unwrap_or_default()
+            .split(':')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if !headers.iter().any(|name| name == "from") {
+            return Err(fail("The From header is not signed"));
+        }
+        let body_hash = tag("bh").and_then(decode_base64).ok_or_else(|| fail("Malformed body hash"))?;
+        let signature = tag("b").and_then(decode_base64)
+            .filter(|signature| !signature.is_empty())
+            .ok_or_else(|| fail("Malformed signature"))?;
+        let length = match tag("l") {
+            Some(length) => Some(length.parse().map_err(|_| fail("Malformed body length"))?),
+            None => None,
+        };
+        let expires = match tag("x") {
+            Some(expires) => Some(expires.parse().map_err(|_| fail("Malformed expiration"))?),
+            None => None,
+        };
+        let identity = tag("i").map(str::to_string);
+        if identity_domain(identity.as_deref()).is_some_and(|identity| !in_domain(&identity, &domain)) {
+            return Err(fail("Identity is outside the signing domain"));
+        }
+
+        Ok(Self {
+            algorithm,
+            header_canon,
+            body_canon,
+            domain,
+            selector,
+            identity,
+            headers,
+            body_hash,
+            signature,
+            length,
+            expires,
+        })
+    }
+}
+
+/// Domain part of an `i=` identity
+fn identity_domain(identity: Option<&str>) -> Option<String> {
+    identity
+        .and_then(|identity| identity.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_ascii_lowercase())
+}
+
+/// Public key from a DKIM key record
+struct PublicKey {
+    key: PKey<Public>,
+    /// `t=s`: the identity must use the signing domain itself
+    strict: bool,
+}
+
+impl PublicKey {
+    fn parse(record: &str, algorithm: Algorithm) -> Result<Self, String> {
+        let tags = parse_tags(record);
+        if tags.get("v").is_some_and(|version| version != "DKIM1") {
+            return Err("Unsupported key record version".to_string());
+        }
+        if tags.get("h").is_some_and(|hashes| !hashes.split(':').any(|hash| hash.trim() == "sha256")) {
+            return Err("The key does not allow SHA-256".to_string());
+        }
+        let key_type = tags.get("k").map(|k| k.to_ascii_lowercase()).unwrap_or_else(|| "rsa".to_string());
+        let data = tags.get("p").ok_or("The key record has no key")?;
+        if data.is_empty() {
+            return Err("The key has been revoked".to_string());
+        }
+        let data = decode_base64(data).ok_or("Malformed key")?;
+
+        let key = match (key_type.as_str(), algorithm) {
+            ("rsa", Algorithm::RsaSha256) => {
+                let key = PKey::public_key_from_der(&data)
+                    .or_else(|_| Rsa::public_key_from_der_pkcs1(&data).and_then(PKey::from_rsa))
+                    .map_err(|_| "Malformed RSA key".to_string())?;
+                if key.bits() < MIN_RSA_BITS {
+                    return Err(format!("RSA key of {} bits is too short", key.bits()));
+                }
+                key
+            }
+            ("ed25519", Algorithm::Ed25519Sha256) => PKey::public_key_from_raw_bytes(&data, Id::ED25519)
+                .map_err(|_| "Malformed Ed25519 key".to_string())?,
+            _ => return Err("Key type does not match the signature algorithm".to_string()),
+        };
+        let strict = tags.get("t").is_some_and(|flags| flags.split(':').any(|flag| flag.trim() == "s"));
+        Ok(Self { key, strict })
+    }
+
+    fn verify(&self, algorithm: Algorithm, data: &[u8], signature: &[u8]) -> Result<bool, String> {
+        let result = match algorithm {
+            Algorithm::RsaSha256 => Verifier::new(MessageDigest::sha256(), &self.key)
+                .and_then(|mut verifier| {
+                    verifier.update(data)?;
+                    verifier.verify(signature)
+                }),
+            // Ed25519 signs the SHA-256 hash of the data (RFC 8463)
+            Algorithm::Ed25519Sha256 => Verifier::new_without_digest(&self.key)
+                .and_then(|mut verifier| verifier.verify_oneshot(signature, &Sha256::digest(data))),
+        };
+        result.map_err(|e| format!("Verification error: {}", e))
+    }
+}
+
+/// Header field with its lowercase name and raw text including the
+/// trailing CRLF
+#[derive(Debug)]
+struct Field {
+    name: String,
+    raw: String,
+}
+
+impl Field {
+    /// Unfolded value
+    fn value(&self) -> String {
+        let (_, value) = self.raw.split_once(':').unwrap_or_default();
+        value.replace("\r\n", "")
+    }
+}
+
+/// Split a message into header fields and body, with all line endings
+/// turned into CRLF
+fn split_message(raw: &[u8]) -> (Vec<Field>, Vec<u8>) {
+    let mut normalized = Vec::with_capacity(raw.len());
+    for (i, &byte) in raw.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || raw[i - 1] != b'\r') {
+            normalized.push(b'\r');
+        }
+        normalized.push(byte);
+    }
+
+    let (head, body) = match normalized.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(end) => (&normalized[..end + 2], normalized[end + 4..].to_vec()),
+        None => (&normalized[..], Vec::new()),
+    };
+    let head = String::from_utf8_lossy(head);
+
+    let mut fields: Vec<Field> = Vec::new();
+    for line in head.split_inclusive("\r\n") {
+        if line.starts_with([' ', '\t']) {
+            if let Some(field) = fields.last_mut() {
+                field.raw.push_str(line);
+            }
+        } else if let Some((name, _)) = line.split_once(':') {
+            fields.push(Field { name: name.trim().to_ascii_lowercase(), raw: line.to_string() });
+        }
+    }
+    (fields, body)
+}
+
+/// Header fields of `raw` as lowercase names and unfolded values
+pub(crate) fn header_fields(raw: &[u8]) -> Vec<(String, String)> {
+    let (fields, _) = split_message(raw);
+    fields.into_iter().map(|field| {
+        let value = field.value().trim().to_string();
+        (field.name, value)
+    }).collect()
+}
+
+/// Data covered by the signature: the signed headers, then the signature
+/// header itself with an empty `b=` and no final CRLF
+fn signed_data(signature: &Signature, field: &Field, fields: &[Field]) -> Vec<u8> {
+    let mut data = String::new();
+    // Each listed name takes the next instance from the bottom up
+    let mut used: HashMap<&str, usize> = HashMap::new();
+    for name in &signature.headers {
+        let count = used.entry(name.as_str()).or_default();
+        let instance = fields.iter().rev().filter(|field| &field.name == name).nth(*count);
+        *count += 1;
+        if let Some(instance) = instance {
+            data.push_str(&canonicalize_header(&instance.raw, signature.header_canon));
+        }
+    }
+
+    let own = canonicalize_header(&strip_signature(&field.raw), signature.header_canon);
+    data.push_str(own.strip_suffix("\r\n").unwrap_or(&own));
+    data.into_bytes()
+}
+
+/// Header field with the value of its `b=` tag removed
+fn strip_signature(raw: &str) -> String {
+    let Some(colon) = raw.find(':') else {
+        return raw.to_string();
+    };
+    let mut result = raw[..=colon].to_string();
+    let mut tags = raw[colon + 1..].split(';').peekable();
+    while let Some(tag) = tags.next() {
+        match tag.split_once('=') {
+            Some((name, value)) if name.trim() == "b" => {
+                result.push_str(name);
+                result.push('=');
+                // Keep the folding that ends the header
+                if tags.peek().is_none() && value.ends_with("\r\n") {
+                    result.push_str("\r\n");
+                }
+            }
+            _ => result.push_str(tag),
+        }
+        if tags.peek().is_some() {
+            result.push(';');
+        }
+    }
+    result
+}
+
+/// Canonical form of a raw header field
+fn canonicalize_header(raw: &str, canon: Canonicalization) -> String {
+    match canon {
+        Canonicalization::Simple => {
+            let mut raw = raw.to_string();
+            if !raw.ends_with("\r\n") {
+                raw.push_str("\r\n");
+            }
+            raw
+        }
+        Canonicalization::Relaxed => {
+            let (name, value) = raw.split_once(':').unwrap_or((raw, ""));
+            let value = compress_whitespace(&value.replace("\r\n", ""));
+            format!("{}:{}\r\n", name.trim().to_ascii_lowercase(), value.trim())
+        }
+    }
+}
+
+/// Canonical form of a CRLF body
+fn canonicalize_body(body: &[u8], canon: Canonicalization) -> Vec<u8> {
+    let body = match canon {
+        Canonicalization::Simple => body.to_vec(),
+        Canonicalization::Relaxed => {
+            let text = String::from_utf8_lossy(body);
+            text.split("\r\n")
+                .map(|line| compress_whitespace(line).trim_end().to_string())
+                .collect::<Vec<_>>()
+                .join("\r\n")
+                .into_bytes()
+        }
+    };
+
+    let mut end = body.len();
+    while end > 0 && matches!(body[end - 1], b'\r' | b'\n') {
+        end -= 1;
+    }
+    let mut result = body[..end].to_vec();
+    if !result.is_empty() || canon == Canonicalization::Simple {
+        result.extend_from_slice(b"\r\n");
+    }
+    result
+}
+
+/// Replace runs of spaces and tabs with a single space
+fn compress_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c == ' ' || c == '\t' {
+            if !in_space {
+                result.push(' ');
+            }
+            in_space = true;
+        } else {
+            result.push(c);
+            in_space = false;
+        }
+    }
+    result
+}
+
+fn canonicalization(name: &str) -> Option<Canonicalization> {
+    match name.trim() {
+        "simple" => Some(Canonicalization::Simple),
+        "relaxed" => Some(Canonicalization::Relaxed),
+        _ => None,
+    }
+}
+
+/// `tag=value` pairs separated by semicolons, with whitespace removed from
+/// base64 values
+fn parse_tags(value: &str) -> HashMap<String, String> {
+    value.split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| {
+            let name = name.trim().to_string();
+            let value = match name.as_str() {
+                "b" | "bh" | "p" => value.chars().filter(|c| !c.is_whitespace()).collect(),
+                _ => value.trim().to_string(),
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    general_purpose::STANDARD.decode(value).ok()
+}
+
+/// Whether `domain` is `parent` or one of its subdomains
+fn in_domain(domain: &str, parent: &str) -> bool {
+    domain == parent || domain.strip_suffix(parent).is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Name servers listed in resolv.conf
+fn parse_resolv_conf(conf: &str) -> Vec<SocketAddr> {
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| {
+            // Drop IPv6 zone identifiers
+            let address = address.trim().split('%').next().unwrap_or_default();
+            address.parse::<IpAddr>().ok()
+        })
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect()
+}
+
+/// Recursive TXT query with an EDNS(0) record allowing large answers
+fn build_query(id: u16, name: &str) -> AsgardResult<Vec<u8>> {
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(AsgardError::validation(format!("Invalid DNS name {}", name)));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    packet.extend_from_slice(&[0, 1]);
+    // OPT pseudo-record: root name, type 41, payload size as class
+    packet.extend_from_slice(&[0, 0, 41]);
+    packet.extend_from_slice(&EDNS_PAYLOAD.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    Ok(packet)
+}
+
+async fn query_udp(server: SocketAddr, query: &[u8]) -> AsgardResult<Vec<u8>> {
+    let local: SocketAddr = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }
+        .parse()
+        .expect("valid socket address");
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+    let mut buffer = vec![0; usize::from(EDNS_PAYLOAD)];
+    let length = socket.recv(&mut buffer).await?;
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+async fn query_tcp(server: SocketAddr, query: &[u8]) -> AsgardResult<Vec<u8>> {
+    let mut stream = TcpStream::connect(server).await?;
+    stream.write_all(&(query.len() as u16).to_be_bytes()).await?;
+    stream.write_all(query).await?;
+    let length = stream.read_u16().await?;
+    let mut buffer = vec![0; usize::from(length)];
+    stream.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+/// Why a DNS answer yielded no records
+#[derive(Debug, PartialEq, Eq)]
+enum DnsAnswer {
+    /// The answer did not fit and should be asked for over TCP
+    Truncated,
+    /// The server failed or the answer is malformed
+    Failed(String),
+}
+
+/// TXT records from a DNS response to the query `id`
+fn parse_response(id: u16, packet: &[u8]) -> Result<Vec<String>, DnsAnswer> {
+    let malformed = || DnsAnswer::Failed("Malformed DNS answer".to_string());
+    let read_u16 = |offset: usize| -> Result<u16, DnsAnswer> {
+        packet.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).ok_or_else(malformed)
+    };
+
+    if packet.len() < 12 || read_u16(0)? != id || packet[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    if packet[2] & 0x02 != 0 {
+        return Err(DnsAnswer::Truncated);
+    }
+    match packet[3] & 0x0f {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        code => return Err(DnsAnswer::Failed(format!("DNS server answered with code {}", code))),
+    }
+
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset).ok_or_else(malformed)? + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(packet, offset).ok_or_else(malformed)?;
+        let record_type = read_u16(offset)?;
+        let length = usize::from(read_u16(offset + 8)?);
+        let data = packet.get(offset + 10..offset + 10 + length).ok_or_else(malformed)?;
+        offset += 10 + length;
+        if record_type != TYPE_TXT {
+            continue;
+        }
+
+        // One or more length-prefixed strings, joined into one record
+        let mut text = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let size = usize::from(data[position]);
+            text.extend_from_slice(data.get(position + 1..position + 1 + size).ok_or_else(malformed)?);
+            position += 1 + size;
+        }
+        records.push(String::from_utf8_lossy(&text).into_owned());
+    }
+    Ok(records)
+}
+
+/// Offset after a possibly compressed name
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(offset)?;
+        match length {
+            0 => return Some(offset + 1),
+            length if length & 0xc0 == 0xc0 => return Some(offset + 2),
+            length => offset += 1 + usize::from(length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELECTOR: &str = "mail";
+    const DOMAIN: &str = "example.com";
+
+    const MESSAGE: &str = "From: Alice <alice@example.com>\r\n\
+        To: Bob <bob@example.org>\r\n\
+        Subject:  Lunch\r\n \tplans\r\n\
+        Date: Mon, 1 Jun 2026 12:00:00 +0000\r\n\
+        Message-ID: <lunch@example.com>\r\n\
+        \r\n\
+        Are you free  at noon?  \r\n\
+        \r\n\
+        \r\n";
+
+    /// Sign `message` with `key`, as a signer would
+    fn sign(message: &str, key: &PKey<openssl::pkey::Private>, algorithm: &str, canon: &str) -> String {
+        let (header_canon, body_canon) = canon.split_once('/').unwrap();
+        let (fields, body) = split_message(message.as_bytes());
+        let body_hash = Sha256::digest(canonicalize_body(&body, canonicalization(body_canon).unwrap()));
+        let unsigned = format!(
+            "DKIM-Signature: v=1; a={}; c={}; d={}; s={};\r\n h=from:to:subject:date:message-id:from;\r\n bh={}; b=\r\n",
+            algorithm, canon, DOMAIN, SELECTOR, general_purpose::STANDARD.encode(body_hash),
+        );
+        let signature = Signature {
+            algorithm: if algorithm == "rsa-sha256" { Algorithm::RsaSha256 } else { Algorithm::Ed25519Sha256 },
+            header_canon: canonicalization(header_canon).unwrap(),
+            body_canon: canonicalization(body_canon).unwrap(),
+            domain: DOMAIN.to_string(),
+            selector: SELECTOR.to_string(),
+            identity: None,
+            headers: ["from", "to", "subject", "date", "message-id", "from"].map(String::from).to_vec(),
+            body_hash: body_hash.to_vec(),
+            signature: Vec::new(),
+            length: None,
+            expires: None,
+        };
+        let field = Field { name: "dkim-signature".to_string(), raw: unsigned.clone() };
+        let data = signed_data(&signature, &field, &fields);
+
+        let signed = if signature.algorithm == Algorithm::RsaSha256 {
+            let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), key).unwrap();
+            signer.update(&data).unwrap();
+            signer.sign_to_vec().unwrap()
+        } else {
+            let mut signer = openssl::sign::Signer::new_without_digest(key).unwrap();
+            signer.sign_oneshot_to_vec(&Sha256::digest(&data)).unwrap()
+        };
+        let encoded = general_purpose::STANDARD.encode(signed);
+        let folded = format!("{}\r\n {}", &encoded[..40], &encoded[40..]);
+        format!("{}{}", unsigned.replace("b=\r\n", &format!("b={}\r\n", folded)), message)
+    }
+
+    fn resolver(record: String) -> Arc<dyn DnsResolver> {
+        let mut resolver = StaticResolver::new();
+        resolver.insert(&format!("{}._domainkey.{}", SELECTOR, DOMAIN), &record);
+        Arc::new(resolver)
+    }
+
+    fn rsa_record(key: &PKey<openssl::pkey::Private>) -> String {
+        format!("v=DKIM1; k=rsa; p={}", general_purpose::STANDARD.encode(key.public_key_to_der().unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_rsa_signatures() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let verifier = DkimVerifier::new(resolver(rsa_record(&key)));
+
+        for canon in ["relaxed/relaxed", "simple/simple", "relaxed/simple", "simple/relaxed"] {
+            let signed = sign(MESSAGE, &key, "rsa-sha256", canon);
+            let results = verifier.verify(signed.as_bytes()).await;
+            assert_eq!(results.len(), 1);
+            assert!(results[0].is_pass(), "{}: {:?}", canon, results[0]);
+            assert_eq!(results[0].domain, DOMAIN);
+
+            // Bare LF line endings verify like CRLF
+            let results = verifier.verify(signed.replace("\r\n", "\n").as_bytes()).await;
+            assert!(results[0].is_pass(), "{} with LF: {:?}", canon, results[0]);
+
+            let tampered = signed.replace("noon", "midnight");
+            let results = verifier.verify(tampered.as_bytes()).await;
+            assert_eq!(results[0].status, DkimStatus::Fail("Body hash does not match".to_string()));
+
+            let tampered = signed.replace("Subject:  Lunch", "Subject:  Dinner");
+            let results = verifier.verify(tampered.as_bytes()).await;
+            assert_eq!(results[0].status, DkimStatus::Fail("Signature does not match".to_string()));
+        }
+
+        // Relaxed canonicalization tolerates whitespace changes
+        let signed = sign(MESSAGE, &key, "rsa-sha256", "relaxed/relaxed");
+        let rewrapped = signed.replace("Subject:  Lunch\r\n \tplans", "subject: Lunch plans")
+            .replace("free  at noon?  ", "free at noon?");
+        assert!(verifier.verify(rewrapped.as_bytes()).await[0].is_pass());
+
+        // An added From header is covered by the extra "from" in h=
+        let spoofed = signed.replace("From: Alice", "From: Mallory <m@evil.example>\r\nFrom: Alice");
+        assert!(!verifier.verify(spoofed.as_bytes()).await[0].is_pass());
+    }
+
+    #[tokio::test]
+    async fn test_ed25519_signature() {
+        let key = PKey::generate_ed25519().unwrap();
+        let record = format!("v=DKIM1; k=ed25519; p={}", general_purpose::STANDARD.encode(key.raw_public_key().unwrap()));
+        let verifier = DkimVerifier::new(resolver(record));
+
+        let signed = sign(MESSAGE, &key, "ed25519-sha256", "relaxed/relaxed");
+        let results = verifier.verify(signed.as_bytes()).await;
+        assert!(results[0].is_pass(), "{:?}", results[0]);
+    }
+
+    #[tokio::test]
+    async fn test_key_errors() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let signed = sign(MESSAGE, &key, "rsa-sha256", "relaxed/relaxed");
+
+        let verifier = DkimVerifier::new(Arc::new(StaticResolver::new()));
+        assert!(matches!(verifier.verify(signed.as_bytes()).await[0].status, DkimStatus::PermError(_)));
+
+        let verifier = DkimVerifier::new(resolver("v=DKIM1; k=rsa; p=".to_string()));
+        assert_eq!(
+            verifier.verify(signed.as_bytes()).await[0].status,
+            DkimStatus::PermError("The key has been revoked".to_string()),
+        );
+
+        let other = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let verifier = DkimVerifier::new(resolver(rsa_record(&other)));
+        assert!(matches!(verifier.verify(signed.as_bytes()).await[0].status, DkimStatus::Fail(_)));
+
+        let weak = PKey::from_rsa(Rsa::generate(512).unwrap()).unwrap();
+        let verifier = DkimVerifier::new(resolver(rsa_record(&weak)));
+        let signed = sign(MESSAGE, &weak, "rsa-sha256", "relaxed/relaxed");
+        assert!(matches!(verifier.verify(signed.as_bytes()).await[0].status, DkimStatus::PermError(_)));
+
+        let sha1 = signed.replace("a=rsa-sha256", "a=rsa-sha1");
+        assert!(matches!(verifier.verify(sha1.as_bytes()).await[0].status, DkimStatus::PermError(_)));
+
+        let unsigned_from = signed.replace("h=from:to:subject:date:message-id:from", "h=to:subject");
+        assert!(matches!(verifier.verify(unsigned_from.as_bytes()).await[0].status, DkimStatus::PermError(_)));
+    }
+
+    #[test]
+    fn test_canonicalization() {
+        // Examples of RFC 6376 section 3.4.6
+        let (fields, body) = split_message(b"A: X\r\nB : Y\t\r\n\tZ  \r\n\r\n C \r\nD \t E\r\n\r\n\r\n");
+        let relaxed: Vec<String> = fields.iter().map(|field| canonicalize_header(&field.raw, Canonicalization::Relaxed)).collect();
+        assert_eq!(relaxed, ["a:X\r\n", "b:Y Z\r\n"]);
+        assert_eq!(canonicalize_header(&fields[1].raw, Canonicalization::Simple), "B : Y\t\r\n\tZ  \r\n");
+        assert_eq!(canonicalize_body(&body, Canonicalization::Relaxed), b" C\r\nD E\r\n");
+        assert_eq!(canonicalize_body(&body, Canonicalization::Simple), b" C \r\nD \t E\r\n");
+
+        // Empty bodies hash to the values given in sections 3.4.3 and 3.4.4
+        let hash = |canon| general_purpose::STANDARD.encode(Sha256::digest(canonicalize_body(b"", canon)));
+        assert_eq!(hash(Canonicalization::Simple), "frcCV1k9oG9oKj3dpUqdJg1PxRT2RSN/XKdLCPjaYaY=");
+        assert_eq!(hash(Canonicalization::Relaxed), "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+
+        assert_eq!(strip_signature("DKIM-Signature: v=1; b=abc\r\n def; bh=xyz\r\n"), "DKIM-Signature: v=1; b=; bh=xyz\r\n");
+        assert_eq!(strip_signature("DKIM-Signature: v=1; bh=xyz;\r\n b=abc\r\n def\r\n"), "DKIM-Signature: v=1; bh=xyz;\r\n b=\r\n");
+    }
+
+    #[test]
+    fn test_dns_packets() {
+        let query = build_query(0x1234, "mail._domainkey.example.com").unwrap();
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert!(build_query(1, "bad..name").is_err());
+
+        // Answer with a compressed name and a record split in two strings
+        let mut answer = query[..query.len() - 11].to_vec();
+        answer[2] = 0x81;
+        answer[3] = 0x80;
+        answer[7] = 1;
+        answer[11] = 0;
+        answer.extend_from_slice(&[0xc0, 12, 0, 16, 0, 1, 0, 0, 1, 0, 0, 13]);
+        answer.extend_from_slice(b"\x07v=DKIM1\x04; p=");
+        assert_eq!(parse_response(0x1234, &answer), Ok(vec!["v=DKIM1; p=".to_string()]));
+        assert!(parse_response(0x4321, &answer).is_err());
+
+        let mut truncated = answer.clone();
+        truncated[2] |= 0x02;
+        assert_eq!(parse_response(0x1234, &truncated), Err(DnsAnswer::Truncated));
+
+        let mut missing = answer.clone();
+        missing[3] = 0x83;
+        assert_eq!(parse_response(0x1234, &missing), Ok(Vec::new()));
+
+        assert_eq!(
+            parse_resolv_conf("# local\nnameserver 192.0.2.1\nnameserver fe80::1%eth0\nsearch example.com\n"),
+            vec!["192.0.2.1:53".parse().unwrap(), "[fe80::1]:53".parse().unwrap()],
+        );
+    }
+}
@@ -1,11 +1,17 @@
 //! Gmail-specific functionality for Asgard Mail
 
+pub mod api;
+pub mod imap_ext;
 pub mod labels;
 pub mod xoauth2;
 
 pub use labels::GmailLabels;
 pub use xoauth2::XOAUTH2;
 
+use crate::account::SyncSettings;
+use crate::search::SearchQuery;
+use time::{Date, Duration, OffsetDateTime};
+
 /// Gmail-specific constants and utilities
 pub mod constants {
     /// Gmail IMAP server
@@ -90,6 +96,98 @@ pub fn is_system_label(label_name: &str) -> bool {
     matches!(label_name, "INBOX" | "SENT" | "DRAFTS" | "TRASH" | "SPAM" | "ALL_MAIL" | "STARRED" | "IMPORTANT")
 }
 
+/// Translate a search query into Gmail search syntax for X-GM-RAW
+///
+/// `raw_query` replaces the text part; the filters are always appended.
+pub fn raw_search_query(query: &SearchQuery) -> String {
+    let mut terms = Vec::new();
+
+    let text = query.query.trim();
+    if let Some(raw) = query.raw_query.as_deref().filter(|r| !r.trim().is_empty()) {
+        terms.push(raw.trim().to_string());
+    } else if !text.is_empty() {
+        // Gmail has no body-only operator, so searching the body means a
+        // plain text search over everything
+        let fields: Vec<&str> = [
+            (query.search_subject, "subject"),
+            (query.search_from, "from"),
+            (query.search_to, "to"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, field)| *field)
+        .collect();
+
+        if query.search_body || fields.is_empty() {
+            terms.push(text.to_string());
+        } else {
+            let scoped: Vec<String> = fields.iter().map(|f| format!("{}:({})", f, text)).collect();
+            if scoped.len() == 1 {
+                terms.push(scoped[0].clone());
+            } else {
+                terms.push(format!("{{{}}}", scoped.join(" ")));
+            }
+        }
+    }
+
+    match query.has_attachments {
+        Some(true) => terms.push("has:attachment".to_string()),
+        Some(false) => terms.push("-has:attachment".to_string()),
+        None => {}
+    }
+    match query.is_read {
+        Some(true) => terms.push("is:read".to_string()),
+        Some(false) => terms.push("is:unread".to_string()),
+        None => {}
+    }
+    match query.is_flagged {
+        Some(true) => terms.push("is:starred".to_string()),
+        Some(false) => terms.push("-is:starred".to_string()),
+        None => {}
+    }
+    if let Some(range) = &query.date_range {
+        // before: is exclusive
+        terms.push(format!("after:{}", search_date(range.start.date())));
+        terms.push(format!("before:{}", search_date(range.end.date() + Duration::days(1))));
+    }
+
+    terms.join(" ")
+}
+
+fn search_date(date: Date) -> String {
+    format!("{}/{:02}/{:02}", date.year(), date.month() as u8, date.day())
+}
+
+/// Check if a search should also be sent to the server
+///
+/// Local results can be incomplete when only recent or a limited number of
+/// messages are synced, and raw Gmail queries can only run on the server.
+pub fn needs_server_search(query: &SearchQuery, settings: &SyncSettings, local_results: usize) -> bool {
+    if !query.server_fallback {
+        return false;
+    }
+    if query.raw_query.as_deref().map_or(false, |r| !r.trim().is_empty()) {
+        return true;
+    }
+    if query.query.trim().is_empty() {
+        return false;
+    }
+    if query.limit.map_or(false, |limit| local_results >= limit) {
+        return false;
+    }
+
+    if let Some(days) = settings.sync_recent_days {
+        let synced_since = OffsetDateTime::now_utc() - Duration::days(days as i64);
+        let reaches_unsynced = query.date_range.as_ref()
+            .map_or(true, |range| range.start < synced_since);
+        if reaches_unsynced {
+            return true;
+        }
+    }
+
+    settings.max_messages.is_some()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,4 +225,55 @@ mod tests {
         assert!(is_system_label("DRAFTS"));
         assert!(!is_system_label("Custom Label"));
     }
+
+    #[test]
+    fn test_raw_search_query() {
+        let query = SearchQuery {
+            query: "invoice".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(raw_search_query(&query), "invoice");
+
+        let query = SearchQuery {
+            query: "alice".to_string(),
+            search_subject: false,
+            search_to: false,
+            search_body: false,
+            is_read: Some(false),
+            has_attachments: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(raw_search_query(&query), "from:(alice) has:attachment is:unread");
+
+        let query = SearchQuery {
+            query: "ignored".to_string(),
+            raw_query: Some("label:work older_than:1y".to_string()),
+            is_flagged: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(raw_search_query(&query), "label:work older_than:1y is:starred");
+    }
+
+    #[test]
+    fn test_needs_server_search() {
+        let settings = SyncSettings::default();
+        let query = SearchQuery {
+            query: "invoice".to_string(),
+            ..Default::default()
+        };
+        assert!(needs_server_search(&query, &settings, 3));
+        assert!(!needs_server_search(&query, &settings, 100));
+
+        let local_only = SearchQuery {
+            server_fallback: false,
+            ..query.clone()
+        };
+        assert!(!needs_server_search(&local_only, &settings, 0));
+
+        let full_sync = SyncSettings {
+            sync_recent_days: None,
+            ..SyncSettings::default()
+        };
+        assert!(!needs_server_search(&query, &full_sync, 0));
+    }
 }
@@ -0,0 +1,625 @@
+//! Gmail REST API client
+//!
+//! Requests go through the [`HttpClient`] trait so the client can be pointed
+//! at a local server in tests.
+
+use crate::error::{AsgardError, AsgardResult};
+use crate::gmail::labels::{GmailLabel, GmailLabelType, GmailLabelVisibility};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Base URL of the Gmail API for the authenticated user
+pub const API_BASE_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me";
+
+/// Upload URL used by messages.send
+pub const UPLOAD_BASE_URL: &str = "https://gmail.googleapis.com/upload/gmail/v1/users/me";
+
+/// HTTP method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+/// HTTP request issued by the API client
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    /// Request method
+    pub method: HttpMethod,
+    /// Full request URL
+    pub url: String,
+    /// Request headers
+    pub headers: Vec<(String, String)>,
+    /// Request body
+    pub body: Option<Vec<u8>>,
+}
+
+/// HTTP response returned to the API client
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+/// Transport used by [`GmailApiClient`]
+#[async_trait::async_trait]
+pub trait HttpClient: Send + Sync {
+    /// Execute a request
+    async fn execute(&self, request: HttpRequest) -> AsgardResult<HttpResponse>;
+}
+
+/// [`HttpClient`] backed by reqwest
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    /// Create a new reqwest client
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for ReqwestClient {
+    async fn execute(&self, request: HttpRequest) -> AsgardResult<HttpResponse> {
+        let mut builder = match request.method {
+            HttpMethod::Get => self.client.get(&request.url),
+            HttpMethod::Post => self.client.post(&request.url),
+        };
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let body = response.bytes().await?.to_vec();
+        Ok(HttpResponse { status, body })
+    }
+}
+
+/// Message reference returned by messages.list and messages.send
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRef {
+    /// Message ID
+    pub id: String,
+    /// Thread ID
+    #[serde(default)]
+    pub thread_id: String,
+    /// Label IDs
+    #[serde(default)]
+    pub label_ids: Vec<String>,
+}
+
+/// Response of users.messages.list
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageList {
+    /// Messages on this page
+    #[serde(default)]
+    pub messages: Vec<MessageRef>,
+    /// Token of the next page
+    pub next_page_token: Option<String>,
+    /// Estimated total number of results
+    #[serde(default)]
+    pub result_size_estimate: u32,
+}
+
+/// Message fetched with `format=raw`
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    /// Message ID
+    pub id: String,
+    /// Thread ID
+    pub thread_id: String,
+    /// Label IDs
+    pub label_ids: Vec<String>,
+    /// History ID of the last change to the message
+    pub history_id: Option<u64>,
+    /// Internal date in milliseconds since the epoch
+    pub internal_date: Option<i64>,
+    /// Estimated size in bytes
+    pub size_estimate: usize,
+    /// RFC 5322 message
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMessageResponse {
+    id: String,
+    #[serde(default)]
+    thread_id: String,
+    #[serde(default)]
+    label_ids: Vec<String>,
+    history_id: Option<String>,
+    internal_date: Option<String>,
+    #[serde(default)]
+    size_estimate: usize,
+    #[serde(default)]
+    raw: String,
+}
+
+/// Label change recorded in the history
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryLabelChange {
+    /// Changed message
+    pub message: MessageRef,
+    /// Labels added or removed
+    #[serde(default)]
+    pub label_ids: Vec<String>,
+}
+
+/// Message added to or deleted from the mailbox
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryMessageChange {
+    /// Changed message
+    pub message: MessageRef,
+}
+
+/// A single history record
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRecord {
+    /// History ID of the record
+    pub id: String,
+    /// Messages added
+    #[serde(default)]
+    pub messages_added: Vec<HistoryMessageChange>,
+    /// Messages deleted
+    #[serde(default)]
+    pub messages_deleted: Vec<HistoryMessageChange>,
+    /// Labels added to messages
+    #[serde(default)]
+    pub labels_added: Vec<HistoryLabelChange>,
+    /// Labels removed from messages
+    #[serde(default)]
+    pub labels_removed: Vec<HistoryLabelChange>,
+}
+
+/// Response of users.history.list
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryList {
+    /// History records on this page
+    #[serde(default)]
+    pub history: Vec<HistoryRecord>,
+    /// Token of the next page
+    pub next_page_token: Option<String>,
+    /// Current history ID of the mailbox
+    pub history_id: Option<String>,
+}
+
+/// Label as returned by users.labels.list and users.labels.get
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiLabel {
+    /// Label ID
+    pub id: String,
+    /// Label name
+    pub name: String,
+    /// `system` or `user`
+    #[serde(rename = "type", default)]
+    pub label_type: String,
+    /// `show`, `hide` or `showIfUnread`
+    pub message_list_visibility: Option<String>,
+    /// `labelShow`, `labelShowIfUnread` or `labelHide`
+    pub label_list_visibility: Option<String>,
+    #[serde(default)]
+    pub messages_total: u32,
+    #[serde(default)]
+    pub messages_unread: u32,
+    #[serde(default)]
+    pub threads_total: u32,
+    #[serde(default)]
+    pub threads_unread: u32,
+}
+
+impl ApiLabel {
+    /// Convert to a [`GmailLabel`]
+    ///
+    /// System labels use the IDs we use for IMAP; user labels are keyed by
+    /// name, which is what X-GM-LABELS reports.
+    pub fn to_gmail_label(&self) -> GmailLabel {
+        let is_system = self.label_type.eq_ignore_ascii_case("system");
+        let hidden = |visibility: &Option<String>| {
+            visibility.as_deref().is_some_and(|v| v.eq_ignore_ascii_case("hide") || v.eq_ignore_ascii_case("labelHide"))
+        };
+
+        GmailLabel {
+            id: if is_system { normalize_label_id(&self.id) } else { self.name.clone() },
+            name: self.name.clone(),
+            label_type: if is_system { GmailLabelType::System } else { GmailLabelType::User },
+            message_list_visibility: if hidden(&self.message_list_visibility) {
+                GmailLabelVisibility::Hide
+            } else {
+                GmailLabelVisibility::Show
+            },
+            label_list_visibility: if hidden(&self.label_list_visibility) {
+                GmailLabelVisibility::Hide
+            } else {
+                GmailLabelVisibility::Show
+            },
+            messages_total: self.messages_total,
+            messages_unread: self.messages_unread,
+            threads_total: self.threads_total,
+            threads_unread: self.threads_unread,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelList {
+    #[serde(default)]
+    labels: Vec<ApiLabel>,
+}
+
+/// Mailbox profile of the authenticated user
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// Email address
+    pub email_address: String,
+    /// Total number of messages
+    #[serde(default)]
+    pub messages_total: u32,
+    /// Current history ID
+    pub history_id: String,
+}
+
+/// Map a Gmail API system label ID to the label name used locally
+pub fn normalize_label_id(id: &str) -> String {
+    match id {
+        "DRAFT" => "DRAFTS".to_string(),
+        _ => id.to_string(),
+    }
+}
+
+/// Map a local system label name back to its Gmail API ID
+pub fn api_label_id(label: &str) -> String {
+    match label {
+        "DRAFTS" => "DRAFT".to_string(),
+        _ => label.to_string(),
+    }
+}
+
+/// Gmail REST API client
+#[derive(Clone)]
+pub struct GmailApiClient {
+    /// HTTP transport
+    http: Arc<dyn HttpClient>,
+    /// API base URL
+    base_url: String,
+    /// Upload base URL
+    upload_url: String,
+    /// OAuth2 access token
+    access_token: String,
+}
+
+impl GmailApiClient {
+    /// Create a client for the Gmail API
+    pub fn new(access_token: String) -> Self {
+        Self::with_http(Arc::new(ReqwestClient::new()), API_BASE_URL, UPLOAD_BASE_URL, access_token)
+    }
+
+    /// Create a client with a custom transport and base URLs
+    pub fn with_http(
+        http: Arc<dyn HttpClient>,
+        base_url: &str,
+        upload_url: &str,
+        access_token: String,
+    ) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            upload_url: upload_url.trim_end_matches('/').to_string(),
+            access_token,
+        }
+    }
+
+    /// Replace the access token after a refresh
+    pub fn set_access_token(&mut self, access_token: String) {
+        self.access_token = access_token;
+    }
+
+    /// Get the mailbox profile (users.getProfile)
+    pub async fn get_profile(&self) -> AsgardResult<Profile> {
+        let body = self.get("/profile", &[]).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// List message IDs (users.messages.list), optionally only those with a label
+    pub async fn list_messages(
+        &self,
+        query: Option<&str>,
+        label_id: Option<&str>,
+        page_token: Option<&str>,
+        max_results: u32,
+    ) -> AsgardResult<MessageList> {
+        let max_results = max_results.to_string();
+        let mut params = vec![
+            ("maxResults", max_results.as_str()),
+            ("includeSpamTrash", "true"),
+        ];
+        if let Some(query) = query {
+            params.push(("q", query));
+        }
+        if let Some(label_id) = label_id {
+            params.push(("labelIds", label_id));
+        }
+        if let Some(page_token) = page_token {
+            params.push(("pageToken", page_token));
+        }
+
+        let body = self.get("/messages", &params).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Fetch a full message (users.messages.get with `format=raw`)
+    pub async fn get_raw_message(&self, id: &str) -> AsgardResult<RawMessage> {
+        let body = self.get(&format!("/messages/{}", id), &[("format", "raw")]).await?;
+        let response: RawMessageResponse = serde_json::from_slice(&body)?;
+
+        Ok(RawMessage {
+            raw: decode_base64url(&response.raw)?,
+            id: response.id,
+            thread_id: response.thread_id,
+            label_ids: response.label_ids,
+            history_id: response.history_id.and_then(|id| id.parse().ok()),
+            internal_date: response.internal_date.and_then(|date| date.parse().ok()),
+            size_estimate: response.size_estimate,
+        })
+    }
+
+    /// List changes since a history ID (users.history.list)
+    ///
+    /// Returns `None` when the history ID is too old and a full sync is needed.
+    pub async fn list_history(&self, start_history_id: u64, page_token: Option<&str>) -> AsgardResult<Option<HistoryList>> {
+        let start = start_history_id.to_string();
+        let mut params = vec![("startHistoryId", start.as_str())];
+        if let Some(page_token) = page_token {
+            params.push(("pageToken", page_token));
+        }
+
+        match self.get("/history", &params).await {
+            Ok(body) => Ok(Some(serde_json::from_slice(&body)?)),
+            Err(AsgardError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List labels (users.labels.list)
+    pub async fn list_labels(&self) -> AsgardResult<Vec<ApiLabel>> {
+        let body = self.get("/labels", &[]).await?;
+        let list: LabelList = serde_json::from_slice(&body)?;
+        Ok(list.labels)
+    }
+
+    /// Get a label with its message and thread counts (users.labels.get)
+    pub async fn get_label(&self, id: &str) -> AsgardResult<ApiLabel> {
+        let body = self.get(&format!("/labels/{}", id), &[]).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Add and remove labels of a message (users.messages.modify)
+    pub async fn modify_message(&self, id: &str, add: &[String], remove: &[String]) -> AsgardResult<MessageRef> {
+        let body = serde_json::json!({
+            "addLabelIds": add,
+            "removeLabelIds": remove,
+        });
+        let url = format!("{}/messages/{}/modify", self.base_url, id);
+        let response = self.post(url, "application/json", serde_json::to_vec(&body)?).await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    /// Send an RFC 5322 message (users.messages.send)
+    pub async fn send_raw(&self, raw: &[u8]) -> AsgardResult<MessageRef> {
+        let url = format!("{}/messages/send?uploadType=media", self.upload_url);
+        let response = self.post(url, "message/rfc822", raw.to_vec()).await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    // Helper methods
+
+    async fn get(&self, path: &str, params: &[(&str, &str)]) -> AsgardResult<Vec<u8>> {
+        let mut url = format!("{}{}", self.base_url, path);
+        if !params.is_empty() {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(params)
+                .finish();
+            url.push('?');
+            url.push_str(&query);
+        }
+
+        self.execute(HttpRequest {
+            method: HttpMethod::Get,
+            url,
+            headers: vec![self.authorization()],
+            body: None,
+        })
+        .await
+    }
+
+    async fn post(&self, url: String, content_type: &str, body: Vec<u8>) -> AsgardResult<Vec<u8>> {
+        self.execute(HttpRequest {
+            method: HttpMethod::Post,
+            url,
+            headers: vec![
+                self.authorization(),
+                ("Content-Type".to_string(), content_type.to_string()),
+            ],
+            body: Some(body),
+        })
+        .await
+    }
+
+    async fn execute(&self, request: HttpRequest) -> AsgardResult<Vec<u8>> {
+        let url = request.url.clone();
+        let response = self.http.execute(request).await?;
+        let message = || format!("{} returned {}: {}", url, response.status, String::from_utf8_lossy(&response.body));
+
+        match response.status {
+            200..=299 => Ok(response.body),
+            401 => Err(AsgardError::auth(message())),
+            403 => Err(AsgardError::Authorization(message())),
+            404 => Err(AsgardError::not_found(message())),
+            429 | 500..=599 => Err(AsgardError::network(message())),
+            _ => Err(AsgardError::generic(message())),
+        }
+    }
+
+    fn authorization(&self) -> (String, String) {
+        ("Authorization".to_string(), format!("Bearer {}", self.access_token))
+    }
+}
+
+/// Decode the base64url `raw` field, with or without padding
+fn decode_base64url(data: &str) -> AsgardResult<Vec<u8>> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(data.trim_end_matches('='))
+        .map_err(|e| AsgardError::validation(format!("Invalid raw message encoding: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Minimal HTTP server answering with canned responses by path prefix
+    async fn mock_server(routes: Vec<(&'static str, u16, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let mut buffer = Vec::new();
+                let mut chunk = [0u8; 4096];
+
+                // Read the head, then as much body as Content-Length announces
+                loop {
+                    let n = socket.read(&mut chunk).await.unwrap_or(0);
+                    if n == 0 {
+                        break;
+                    }
+                    buffer.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buffer).to_string();
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                            .unwrap_or(0);
+                        if buffer.len() >= head_end + 4 + length {
+                            break;
+                        }
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&buffer).to_string();
+                let target = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                log.lock().unwrap().push(request);
+
+                let (status, body) = routes
+                    .iter()
+                    .find(|(prefix, _, _)| target.starts_with(prefix))
+                    .map(|(_, status, body)| (*status, body.clone()))
+                    .unwrap_or((404, "{}".to_string()));
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (address, requests)
+    }
+
+    fn client(address: &str) -> GmailApiClient {
+        GmailApiClient::with_http(Arc::new(ReqwestClient::new()), address, address, "token".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_list_and_get_messages() {
+        let raw = general_purpose::URL_SAFE.encode("Subject: Hello\r\n\r\nBody");
+        let (address, requests) = mock_server(vec![
+            ("/messages?", 200, r#"{"messages":[{"id":"m1","threadId":"t1"}],"resultSizeEstimate":1}"#.to_string()),
+            ("/messages/m1", 200, format!(
+                r#"{{"id":"m1","threadId":"t1","labelIds":["INBOX","UNREAD"],"historyId":"1234","internalDate":"1700000000000","sizeEstimate":42,"raw":"{}"}}"#,
+                raw
+            )),
+        ]).await;
+        let client = client(&address);
+
+        let list = client.list_messages(Some("newer_than:30d"), None, None, 50).await.unwrap();
+        assert_eq!(list.messages.len(), 1);
+        assert!(list.next_page_token.is_none());
+
+        let message = client.get_raw_message(&list.messages[0].id).await.unwrap();
+        assert_eq!(message.raw, b"Subject: Hello\r\n\r\nBody");
+        assert_eq!(message.history_id, Some(1234));
+        assert_eq!(message.label_ids, vec!["INBOX", "UNREAD"]);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("q=newer_than%3A30d"));
+        assert!(requests[0].to_ascii_lowercase().contains("authorization: bearer token"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_history() {
+        let (address, _) = mock_server(vec![
+            ("/history?startHistoryId=1", 404, r#"{"error":{"code":404}}"#.to_string()),
+            ("/history?startHistoryId=500", 200, r#"{"history":[{"id":"501","messagesAdded":[{"message":{"id":"m2","threadId":"t2"}}],"labelsRemoved":[{"message":{"id":"m1"},"labelIds":["UNREAD"]}]}],"historyId":"502"}"#.to_string()),
+        ]).await;
+        let client = client(&address);
+
+        assert!(client.list_history(1, None).await.unwrap().is_none());
+
+        let history = client.list_history(500, None).await.unwrap().unwrap();
+        assert_eq!(history.history_id.as_deref(), Some("502"));
+        assert_eq!(history.history[0].messages_added[0].message.id, "m2");
+        assert_eq!(history.history[0].labels_removed[0].label_ids, vec!["UNREAD"]);
+    }
+
+    #[tokio::test]
+    async fn test_labels_and_send() {
+        let (address, requests) = mock_server(vec![
+            ("/labels/Label_1", 200, r#"{"id":"Label_1","name":"Work","type":"user","messagesTotal":12,"messagesUnread":3}"#.to_string()),
+            ("/labels", 200, r#"{"labels":[{"id":"DRAFT","name":"DRAFT","type":"system"},{"id":"Label_1","name":"Work","type":"user","labelListVisibility":"labelHide"}]}"#.to_string()),
+            ("/messages/send", 200, r#"{"id":"sent1","threadId":"t9","labelIds":["SENT"]}"#.to_string()),
+            ("/messages/m1/modify", 401, r#"{"error":"invalid_token"}"#.to_string()),
+        ]).await;
+        let client = client(&address);
+
+        let labels = client.list_labels().await.unwrap();
+        assert_eq!(labels[0].to_gmail_label().id, "DRAFTS");
+        assert_eq!(labels[1].to_gmail_label().label_list_visibility, GmailLabelVisibility::Hide);
+
+        let work = client.get_label("Label_1").await.unwrap().to_gmail_label();
+        assert_eq!((work.id.as_str(), work.messages_total, work.messages_unread), ("Work", 12, 3));
+
+        let sent = client.send_raw(b"Subject: Hi\r\n\r\nThere").await.unwrap();
+        assert_eq!(sent.id, "sent1");
+        assert!(requests.lock().unwrap().last().unwrap().ends_with("Subject: Hi\r\n\r\nThere"));
+
+        let error = client.modify_message("m1", &["STARRED".to_string()], &[]).await.unwrap_err();
+        assert!(error.is_auth_error());
+    }
+}
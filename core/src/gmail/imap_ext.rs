@@ -0,0 +1,317 @@
+//! Gmail IMAP extensions (X-GM-MSGID, X-GM-THRID, X-GM-LABELS)
+//!
+//! Gmail exposes labels as folders, so the same message shows up in every
+//! folder it is labelled with. These helpers let us fetch the stable message
+//! ID and the label set instead, so a message is stored once.
+
+use crate::gmail::labels::{GmailLabel, GmailLabelType, GmailLabelVisibility, GmailLabels};
+use crate::mailbox::{Mailbox, MailboxFlags, MailboxType};
+use crate::message::Message;
+use crate::sync::imap_list;
+use async_imap::imap_proto::{AttributeValue, MailboxDatum, Response};
+
+/// FETCH items for the Gmail extension attributes
+pub const FETCH_ITEMS: &str = "UID X-GM-MSGID X-GM-THRID X-GM-LABELS";
+
+/// Gmail attributes of a single message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GmailMessageAttributes {
+    /// Message UID in the selected mailbox
+    pub uid: u32,
+    /// Stable Gmail message ID
+    pub message_id: u64,
+    /// Gmail thread ID
+    pub thread_id: u64,
+    /// Normalized labels (system labels use their API IDs, e.g. `INBOX`)
+    pub labels: Vec<String>,
+}
+
+/// Map an X-GM-LABELS value to a label name
+///
+/// System labels such as `\Inbox` map to their API IDs; user labels are
+/// decoded from modified UTF-7.
+pub fn normalize_label(label: &str) -> String {
+    match label.to_ascii_lowercase().as_str() {
+        "\\inbox" | "inbox" => "INBOX".to_string(),
+        "\\sent" => "SENT".to_string(),
+        "\\draft" | "\\drafts" => "DRAFTS".to_string(),
+        "\\trash" => "TRASH".to_string(),
+        "\\spam" | "\\junk" => "SPAM".to_string(),
+        "\\starred" | "\\flagged" => "STARRED".to_string(),
+        "\\important" => "IMPORTANT".to_string(),
+        "\\all" | "\\allmail" => "ALL_MAIL".to_string(),
+        _ => imap_list::decode_modified_utf7(label),
+    }
+}
+
+/// Map a label name back to its X-GM-LABELS form
+pub fn imap_label(label: &str) -> String {
+    match label {
+        "INBOX" => "\\Inbox".to_string(),
+        "SENT" => "\\Sent".to_string(),
+        "DRAFTS" => "\\Draft".to_string(),
+        "TRASH" => "\\Trash".to_string(),
+        "SPAM" => "\\Spam".to_string(),
+        "STARRED" => "\\Starred".to_string(),
+        "IMPORTANT" => "\\Important".to_string(),
+        "ALL_MAIL" => "\\All".to_string(),
+        _ => imap_list::quote(&imap_list::encode_modified_utf7(label)),
+    }
+}
+
+/// Label implied by a Gmail mailbox, if any
+///
+/// Messages are synced from All Mail, Spam and Trash; every other folder is a
+/// view of All Mail filtered by this label.
+pub fn mailbox_label(mailbox: &Mailbox) -> Option<String> {
+    match mailbox.mailbox_type {
+        MailboxType::Inbox => Some("INBOX".to_string()),
+        MailboxType::Sent => Some("SENT".to_string()),
+        MailboxType::Drafts => Some("DRAFTS".to_string()),
+        MailboxType::Trash => Some("TRASH".to_string()),
+        MailboxType::Spam => Some("SPAM".to_string()),
+        MailboxType::Flagged => Some("STARRED".to_string()),
+        MailboxType::All => None,
+        MailboxType::Archive | MailboxType::Custom | MailboxType::Label => {
+            if mailbox.has_flag(MailboxFlags::Important) {
+                Some("IMPORTANT".to_string())
+            } else if mailbox.can_select() {
+                Some(imap_list::decode_modified_utf7(&mailbox.name))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Check if messages should be fetched from this Gmail mailbox
+///
+/// All Mail holds every message except Spam and Trash. When it is hidden
+/// from IMAP every selectable folder is fetched instead; messages are
+/// matched by X-GM-MSGID, so each is still stored once.
+pub fn is_message_source(mailbox: &Mailbox, has_all_mail: bool) -> bool {
+    match mailbox.mailbox_type {
+        MailboxType::All | MailboxType::Spam | MailboxType::Trash => true,
+        _ => !has_all_mail && mailbox.can_select(),
+    }
+}
+
+/// Build the UID STORE commands that add and remove labels
+pub fn store_labels_commands(uid: u32, add: &[String], remove: &[String]) -> Vec<String> {
+    let list = |labels: &[String]| {
+        labels.iter().map(|l| imap_label(l)).collect::<Vec<_>>().join(" ")
+    };
+
+    let mut commands = Vec::new();
+    if !add.is_empty() {
+        commands.push(format!("UID STORE {} +X-GM-LABELS ({})", uid, list(add)));
+    }
+    if !remove.is_empty() {
+        commands.push(format!("UID STORE {} -X-GM-LABELS ({})", uid, list(remove)));
+    }
+    commands
+}
+
+/// Build a UID SEARCH command for a Gmail search query
+///
+/// Quoted strings must be 7-bit, so a non-ASCII query is announced as a
+/// UTF-8 literal and returned separately, to be sent on continuation.
+pub fn raw_search_command(raw: &str) -> (String, Option<String>) {
+    if raw.is_ascii() {
+        (format!("UID SEARCH X-GM-RAW {}", imap_list::quote(raw)), None)
+    } else {
+        (format!("UID SEARCH CHARSET UTF-8 X-GM-RAW {{{}}}", raw.len()), Some(raw.to_string()))
+    }
+}
+
+/// UIDs of an untagged SEARCH response
+pub fn search_uids(response: &Response<'_>) -> Vec<u32> {
+    match response {
+        Response::MailboxData(MailboxDatum::Search(uids)) => uids.clone(),
+        _ => Vec::new(),
+    }
+}
+
+/// Gmail attributes of an untagged FETCH response for [`FETCH_ITEMS`]
+pub fn fetch_attributes(response: &Response<'_>) -> Option<GmailMessageAttributes> {
+    let Response::Fetch(_, values) = response else { return None };
+
+    let mut uid = None;
+    let mut message_id = None;
+    let mut thread_id = None;
+    let mut labels = Vec::new();
+    for value in values {
+        match value {
+            AttributeValue::Uid(value) => uid = Some(*value),
+            AttributeValue::GmailMsgId(value) => message_id = Some(*value),
+            AttributeValue::GmailThrId(value) => thread_id = Some(*value),
+            AttributeValue::GmailLabels(values) => {
+                labels = values.iter().map(|label| normalize_label(label)).collect();
+            }
+            _ => {}
+        }
+    }
+
+    Some(GmailMessageAttributes {
+        uid: uid?,
+        message_id: message_id?,
+        thread_id: thread_id?,
+        labels,
+    })
+}
+
+/// Attach the fetched Gmail attributes to messages, matching them by UID
+///
+/// Messages in Spam and Trash carry no label for their own folder, so the
+/// label of the mailbox they were fetched from is added.
+pub fn apply_attributes(messages: &mut [Message], attributes: &[GmailMessageAttributes], mailbox: &Mailbox) {
+    let folder_label = mailbox_label(mailbox);
+
+    for message in messages.iter_mut() {
+        let Some(uid) = message.uid else { continue };
+        let Some(fetched) = attributes.iter().find(|a| a.uid == uid) else { continue };
+
+        message.gmail_message_id = Some(fetched.message_id);
+        message.conversation_id = Some(fetched.thread_id.to_string());
+        message.set_labels(fetched.labels.clone());
+        if let Some(label) = &folder_label {
+            message.add_label(label.clone());
+        }
+    }
+}
+
+/// Fill the label registry from the synced Gmail mailboxes
+pub fn labels_from_mailboxes(mailboxes: &[Mailbox]) -> GmailLabels {
+    let mut labels = GmailLabels::new();
+
+    for mailbox in mailboxes {
+        let Some(id) = mailbox_label(mailbox) else { continue };
+        let label_type = if mailbox.mailbox_type == MailboxType::Label || mailbox.mailbox_type == MailboxType::Custom {
+            GmailLabelType::User
+        } else {
+            GmailLabelType::System
+        };
+
+        labels.add_label(GmailLabel {
+            name: mailbox.display_name.clone(),
+            id,
+            label_type,
+            message_list_visibility: GmailLabelVisibility::Show,
+            label_list_visibility: GmailLabelVisibility::Show,
+            messages_total: mailbox.stats.total_messages,
+            messages_unread: mailbox.stats.unread_messages,
+            threads_total: 0,
+            threads_unread: 0,
+        });
+    }
+
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{MessageHeaders, MessageImportance};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_fetch_attributes() {
+        let response = "* 1 FETCH (X-GM-THRID 1278455344230334865 X-GM-MSGID 1278455344230334865 UID 4 X-GM-LABELS (\\Inbox \\Sent Important \"Muy Importante\"))\r\n\
+                        * 2 FETCH (UID 5 X-GM-MSGID 1278455344230334866 X-GM-THRID 1278455344230334865 X-GM-LABELS ())\r\n\
+                        A1 OK FETCH (Success)\r\n";
+
+        let fetched: Vec<_> = imap_list::parse_responses(response).iter().filter_map(fetch_attributes).collect();
+        assert_eq!(fetched.len(), 2);
+        assert_eq!(fetched[0].uid, 4);
+        assert_eq!(fetched[0].message_id, 1278455344230334865);
+        assert_eq!(fetched[0].labels, vec!["INBOX", "SENT", "Important", "Muy Importante"]);
+        assert_eq!(fetched[1].thread_id, fetched[0].thread_id);
+        assert!(fetched[1].labels.is_empty());
+    }
+
+    #[test]
+    fn test_store_labels_commands() {
+        let commands = store_labels_commands(
+            42,
+            &["Work/Clients".to_string(), "STARRED".to_string()],
+            &["INBOX".to_string()],
+        );
+        assert_eq!(commands, vec![
+            "UID STORE 42 +X-GM-LABELS (\"Work/Clients\" \\Starred)".to_string(),
+            "UID STORE 42 -X-GM-LABELS (\\Inbox)".to_string(),
+        ]);
+        assert!(store_labels_commands(1, &[], &[]).is_empty());
+        assert_eq!(imap_label(&normalize_label("\\All")), "\\All");
+    }
+
+    #[test]
+    fn test_raw_search() {
+        assert_eq!(
+            raw_search_command("from:alice has:attachment"),
+            ("UID SEARCH X-GM-RAW \"from:alice has:attachment\"".to_string(), None)
+        );
+        assert_eq!(
+            raw_search_command("Grüße"),
+            ("UID SEARCH CHARSET UTF-8 X-GM-RAW {7}".to_string(), Some("Grüße".to_string()))
+        );
+
+        let uids = |response| imap_list::parse_responses(response).iter().flat_map(search_uids).collect::<Vec<_>>();
+        assert_eq!(uids("* SEARCH 12 57 90\r\nA4 OK SEARCH completed (Success)\r\n"), vec![12, 57, 90]);
+        assert!(uids("* SEARCH\r\nA4 OK\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_mailbox_labels() {
+        let account_id = Uuid::new_v4();
+        let all_mail = Mailbox::new(account_id, "[Gmail]/All Mail".to_string(), None, MailboxType::All, None);
+        let work = Mailbox::new(account_id, "Work".to_string(), None, MailboxType::Label, None);
+        let inbox = Mailbox::new_inbox(account_id);
+
+        assert_eq!(mailbox_label(&all_mail), None);
+        assert_eq!(mailbox_label(&work), Some("Work".to_string()));
+        assert!(is_message_source(&all_mail, true));
+        assert!(!is_message_source(&inbox, true));
+        assert!(is_message_source(&inbox, false));
+
+        let labels = labels_from_mailboxes(&[all_mail, work, inbox]);
+        assert_eq!(labels.count(), 2);
+        assert_eq!(labels.get_label("Work").unwrap().label_type, GmailLabelType::User);
+        assert_eq!(labels.get_label("INBOX").unwrap().label_type, GmailLabelType::System);
+    }
+
+    #[test]
+    fn test_apply_attributes() {
+        let account_id = Uuid::new_v4();
+        let trash = Mailbox::new(account_id, "[Gmail]/Trash".to_string(), None, MailboxType::Trash, None);
+        let headers = MessageHeaders {
+            message_id: None,
+            in_reply_to: None,
+            references: None,
+            subject: "Receipt".to_string(),
+            from: vec![],
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            reply_to: vec![],
+            date: None,
+            received_date: None,
+            importance: MessageImportance::Normal,
+            custom: Default::default(),
+        };
+        let mut message = Message::new(account_id, trash.id, headers);
+        message.set_uid(7, 1);
+
+        let fetched = vec![GmailMessageAttributes {
+            uid: 7,
+            message_id: 99,
+            thread_id: 42,
+            labels: vec!["Work".to_string()],
+        }];
+        let mut messages = vec![message];
+        apply_attributes(&mut messages, &fetched, &trash);
+
+        assert_eq!(messages[0].gmail_message_id, Some(99));
+        assert_eq!(messages[0].conversation_id.as_deref(), Some("42"));
+        assert_eq!(messages[0].labels, vec!["Work", "TRASH"]);
+    }
+}
@@ -1,9 +1,12 @@
 //! XOAUTH2 implementation for Gmail
+//!
+//! The SASL mechanism is provider-neutral; Microsoft 365 accounts use it
+//! unchanged against outlook.office365.com and smtp.office365.com.
 
 use crate::error::{AsgardError, AsgardResult};
 use base64::{Engine as _, engine::general_purpose};
 
-/// XOAUTH2 authentication for Gmail IMAP/SMTP
+/// XOAUTH2 authentication for IMAP/SMTP
 pub struct XOAUTH2 {
     /// Email address
     email: String,
@@ -65,6 +68,34 @@ impl XOAUTH2 {
         }
     }
 
+    /// Check whether an authentication failure means the access token was rejected
+    ///
+    /// Gmail sends a base64 JSON challenge with a 400/401 status before
+    /// failing; other servers (Microsoft 365) fail the command outright. Either
+    /// way a token refresh and one retry are worthwhile.
+    pub fn is_token_rejected(response: &str) -> bool {
+        let response = response.trim();
+        if response.starts_with('+') {
+            return match Self::parse_server_response(response) {
+                Ok(XOAUTH2Response::Challenge(challenge)) => {
+                    serde_json::from_str::<serde_json::Value>(&challenge)
+                        .ok()
+                        .and_then(|value| value.get("status").and_then(|s| s.as_str()).map(str::to_string))
+                        .map(|status| status == "400" || status == "401")
+                        .unwrap_or(false)
+                }
+                Ok(XOAUTH2Response::Failure) => true,
+                _ => false,
+            };
+        }
+
+        let upper = response.to_ascii_uppercase();
+        upper.contains("AUTHENTICATIONFAILED")
+            || upper.contains("AUTHENTICATE FAILED")
+            || upper.contains("INVALID CREDENTIALS")
+            || upper.starts_with("535")
+    }
+
     /// Validate XOAUTH2 token
     pub fn validate_token(&self) -> bool {
         !self.access_token.is_empty() && !self.email.is_empty()
@@ -81,6 +112,22 @@ impl XOAUTH2 {
     }
 }
 
+/// SASL exchange for `async_imap::Client::authenticate`
+///
+/// The empty initial challenge gets the credentials; an error challenge
+/// gets an empty response so the server fails the command.
+impl async_imap::Authenticator for XOAUTH2 {
+    type Response = String;
+
+    fn process(&mut self, challenge: &[u8]) -> String {
+        if challenge.is_empty() {
+            format!("user={}\x01auth=Bearer {}\x01\x01", self.email, self.access_token)
+        } else {
+            String::new()
+        }
+    }
+}
+
 /// XOAUTH2 server response types
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum XOAUTH2Response {
@@ -159,6 +206,35 @@ impl XOAUTH2ErrorCode {
     }
 }
 
+/// Result of refreshing an access token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshedToken {
+    /// New access token
+    pub access_token: String,
+    /// New refresh token, when the provider rotates them
+    pub refresh_token: Option<String>,
+    /// Access token lifetime in seconds
+    pub expires_in: Option<u64>,
+}
+
+impl RefreshedToken {
+    /// Parse a token endpoint response body
+    pub fn from_json(body: &serde_json::Value) -> AsgardResult<Self> {
+        let access_token = body.get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| AsgardError::auth("No access token in response"))?;
+
+        Ok(Self {
+            access_token: access_token.to_string(),
+            refresh_token: body.get("refresh_token").and_then(|v| v.as_str()).map(str::to_string),
+            expires_in: body.get("expires_in").and_then(|v| v.as_u64()),
+        })
+    }
+}
+
+/// Google token endpoint, used unless another is set
+pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
 /// XOAUTH2 token refresh helper
 pub struct XOAUTH2TokenRefresh {
     /// Email address
@@ -169,6 +245,8 @@ pub struct XOAUTH2TokenRefresh {
     client_id: String,
     /// Client secret
     client_secret: String,
+    /// Token endpoint
+    token_url: String,
 }
 
 impl XOAUTH2TokenRefresh {
@@ -184,37 +262,54 @@ impl XOAUTH2TokenRefresh {
             refresh_token,
             client_id,
             client_secret,
+            token_url: GOOGLE_TOKEN_URL.to_string(),
         }
     }
 
+    /// Use a different token endpoint, e.g. the Microsoft identity platform
+    pub fn with_token_url(mut self, token_url: impl Into<String>) -> Self {
+        self.token_url = token_url.into();
+        self
+    }
+
     /// Refresh the access token
     pub async fn refresh_token(&self) -> AsgardResult<String> {
+        Ok(self.refresh().await?.access_token)
+    }
+
+    /// Refresh the access token, keeping any rotated refresh token
+    ///
+    /// A revoked or expired refresh token (`invalid_grant`) is reported as
+    /// [`AsgardError::OAuthToken`] so callers can ask the user to sign in again.
+    pub async fn refresh(&self) -> AsgardResult<RefreshedToken> {
         let client = reqwest::Client::new();
         
-        let params = [
+        let mut params = vec![
             ("grant_type", "refresh_token"),
-            ("refresh_token", &self.refresh_token),
-            ("client_id", &self.client_id),
-            ("client_secret", &self.client_secret),
+            ("refresh_token", self.refresh_token.as_str()),
+            ("client_id", self.client_id.as_str()),
         ];
+        // Public clients (Microsoft desktop apps) must not send a secret
+        if !self.client_secret.is_empty() {
+            params.push(("client_secret", self.client_secret.as_str()));
+        }
         
         let response = client
-            .post("https://oauth2.googleapis.com/token")
+            .post(&self.token_url)
             .form(&params)
             .send()
             .await?;
         
         if response.status().is_success() {
             let token_response: serde_json::Value = response.json().await?;
-            
-            if let Some(access_token) = token_response.get("access_token").and_then(|v| v.as_str()) {
-                Ok(access_token.to_string())
-            } else {
-                Err(AsgardError::auth("No access token in response"))
-            }
+            RefreshedToken::from_json(&token_response)
         } else {
             let error_text = response.text().await?;
-            Err(AsgardError::auth(format!("Token refresh failed: {}", error_text)))
+            if XOAUTH2ErrorCode::from_response(&error_text) == Some(XOAUTH2ErrorCode::InvalidGrant) {
+                Err(AsgardError::OAuthToken(format!("Refresh token is no longer valid: {}", error_text)))
+            } else {
+                Err(AsgardError::auth(format!("Token refresh failed: {}", error_text)))
+            }
         }
     }
 
@@ -322,6 +417,53 @@ mod tests {
         assert_eq!(refresh.email(), "test@gmail.com");
     }
 
+    #[test]
+    fn test_xoauth2_token_refresh_endpoint() {
+        let refresh = XOAUTH2TokenRefresh::new(
+            "test@contoso.com".to_string(),
+            "test_refresh_token".to_string(),
+            "test_client_id".to_string(),
+            String::new(),
+        );
+        assert_eq!(refresh.token_url, GOOGLE_TOKEN_URL);
+
+        let refresh = refresh.with_token_url("https://login.microsoftonline.com/common/oauth2/v2.0/token");
+        assert!(refresh.token_url.starts_with("https://login.microsoftonline.com/"));
+    }
+
+    #[test]
+    fn test_token_rejected() {
+        let challenge = general_purpose::STANDARD
+            .encode(r#"{"status":"401","schemes":"bearer","scope":"https://mail.google.com/"}"#);
+        assert!(XOAUTH2::is_token_rejected(&format!("+ {}", challenge)));
+        assert!(XOAUTH2::is_token_rejected("+NO"));
+        assert!(XOAUTH2::is_token_rejected("a1 NO AUTHENTICATE failed."));
+        assert!(XOAUTH2::is_token_rejected("a1 NO [AUTHENTICATIONFAILED] Invalid credentials (Failure)"));
+        assert!(XOAUTH2::is_token_rejected("535 5.7.3 Authentication unsuccessful"));
+
+        assert!(!XOAUTH2::is_token_rejected("+"));
+        assert!(!XOAUTH2::is_token_rejected("+OK"));
+        assert!(!XOAUTH2::is_token_rejected("a1 NO [UNAVAILABLE] Server busy"));
+    }
+
+    #[test]
+    fn test_refreshed_token_from_json() {
+        let body = serde_json::json!({
+            "access_token": "new-access",
+            "refresh_token": "rotated-refresh",
+            "expires_in": 3599,
+            "token_type": "Bearer"
+        });
+        let token = RefreshedToken::from_json(&body).unwrap();
+        assert_eq!(token.access_token, "new-access");
+        assert_eq!(token.refresh_token.as_deref(), Some("rotated-refresh"));
+        assert_eq!(token.expires_in, Some(3599));
+
+        let body = serde_json::json!({"access_token": "new-access"});
+        assert_eq!(RefreshedToken::from_json(&body).unwrap().refresh_token, None);
+        assert!(RefreshedToken::from_json(&serde_json::json!({})).is_err());
+    }
+
     #[test]
     fn test_invalid_xoauth2() {
         let xoauth2 = XOAUTH2::new(
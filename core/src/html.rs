@@ -0,0 +1,286 @@
+//! HTML message bodies
+//!
+//! Helpers for showing `text/html` parts: resolving `cid:` references to
+//! inline resources and a plain-text rendering for builds without a web view.
+
+use crate::message::{Message, MessagePart};
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashMap;
+
+/// Elements whose content is never shown as text
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Elements that start a new line in the plain-text rendering
+const BLOCK_ELEMENTS: [&str; 18] = [
+    "address", "article", "blockquote", "div", "footer", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "ol", "p", "section", "table", "ul",
+];
+
+/// Replace `cid:` URLs with `data:` URLs of the message's inline parts
+///
+/// References to parts that do not exist are left untouched.
+pub fn resolve_cid_urls(html: &str, message: &Message) -> String {
+    let resources = inline_resources(&message.parts);
+    if resources.is_empty() {
+        return html.to_string();
+    }
+
+    let lower = html.to_ascii_lowercase();
+    let mut result = String::with_capacity(html.len());
+    let mut last = 0;
+    for (start, _) in lower.match_indices("cid:") {
+        if start < last || !in_url_position(&html[..start]) {
+            continue;
+        }
+        let value = &html[start + 4..];
+        let end = value.find(|c: char| matches!(c, '"' | '\'' | ')' | '>') || c.is_whitespace())
+            .unwrap_or(value.len());
+        let Some(part) = resources.get(percent_decode(&value[..end]).as_str()) else {
+            continue;
+        };
+
+        result.push_str(&html[last..start]);
+        result.push_str(&data_url(part));
+        last = start + 4 + end;
+    }
+    result.push_str(&html[last..]);
+    result
+}
+
+/// Inline parts keyed by their Content-ID without angle brackets
+fn inline_resources(parts: &[MessagePart]) -> HashMap<String, &MessagePart> {
+    let mut resources = HashMap::new();
+    for part in parts {
+        if let (Some(content_id), Some(_)) = (&part.content_id, &part.content) {
+            let id = content_id.trim().trim_start_matches('<').trim_end_matches('>');
+            resources.insert(id.to_string(), part);
+        }
+        resources.extend(inline_resources(&part.children));
+    }
+    resources
+}
+
+/// Whether a URL starting here is an attribute value or a CSS `url()`
+fn in_url_position(before: &str) -> bool {
+    matches!(before.trim_end().chars().last(), Some('"' | '\'' | '=' | '('))
+}
+
+fn data_url(part: &MessagePart) -> String {
+    format!(
+        "data:{};base64,{}",
+        part.mime_type,
+        general_purpose::STANDARD.encode(part.content.as_deref().unwrap_or_default())
+    )
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Render HTML as readable plain text
+///
+/// Scripts, styles and the head are dropped, block elements and `<br>`
+/// become line breaks and entities are decoded.
+pub fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut text, &rest[..start]);
+        let tag = &rest[start..];
+
+        if let Some(comment) = tag.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|end| &comment[end + 3..]).unwrap_or("");
+            continue;
+        }
+        let Some(end) = tag.find('>') else {
+            rest = "";
+            break;
+        };
+        let (name, closing) = tag_name(&tag[1..end]);
+        rest = &tag[end + 1..];
+
+        if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            rest = rest.to_ascii_lowercase().find(&close)
+                .and_then(|index| rest[index..].find('>').map(|end| &rest[index + end + 1..]))
+                .unwrap_or("");
+            continue;
+        }
+
+        match name.as_str() {
+            "br" => text.push('\n'),
+            "li" if !closing => {
+                start_line(&mut text);
+                text.push_str("• ");
+            }
+            "tr" | "li" => start_line(&mut text),
+            "td" | "th" if !closing => text.push(' '),
+            name if BLOCK_ELEMENTS.contains(&name) => {
+                start_line(&mut text);
+                if matches!(name, "p" | "blockquote" | "table") {
+                    text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    push_text(&mut text, rest);
+
+    let mut result = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 || result.is_empty() {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result.trim_end().to_string()
+}
+
+/// Lowercased element name of a tag body and whether it is a closing tag
+fn tag_name(tag: &str) -> (String, bool) {
+    let (tag, closing) = match tag.strip_prefix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+    (name.to_ascii_lowercase(), closing)
+}
+
+/// Append text content, collapsing whitespace as a browser would
+fn push_text(text: &mut String, content: &str) {
+    let decoded = decode_entities(content);
+    for c in decoded.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !text.ends_with(|last: char| last.is_whitespace()) && !text.is_empty() {
+                text.push(' ');
+            }
+        } else if c == '\u{a0}' {
+            text.push(' ');
+        } else {
+            text.push(c);
+        }
+    }
+}
+
+fn start_line(text: &mut String) {
+    while text.ends_with(' ') {
+        text.pop();
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Decode character references such as `&amp;`, `&#39;` and `&#x2F;`
+///
+/// Unknown named references are kept as written.
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let reference = &rest[start + 1..];
+        let end = reference.find(';').filter(|end| *end <= 10);
+        let value = end.and_then(|end| {
+            let name = &reference[..end];
+            match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => name.strip_prefix("#x").or_else(|| name.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| name.strip_prefix('#').map(str::parse::<u32>))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            }
+        });
+
+        match (value, end) {
+            (Some(c), Some(end)) => {
+                decoded.push(c);
+                rest = &reference[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = reference;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_to_plain_text() {
+        let html = "<html><head><title>Ignored</title><style>p { color: red }</style></head>\
+                    <body><p>Hello&nbsp;<b>Bob</b>,</p><script>alert('x')</script>\
+                    <p>Items:</p><ul><li>one &amp; two</li><li>three</li></ul>\
+                    Line<br>break &lt;tag&gt; &#39;quoted&#x27; &bogus;<!-- hidden --></body></html>";
+
+        assert_eq!(
+            to_plain_text(html),
+            "Hello Bob,\n\nItems:\n\n• one & two\n• three\nLine\nbreak <tag> 'quoted' &bogus;"
+        );
+        assert_eq!(to_plain_text("plain   text\n with  spaces"), "plain text with spaces");
+    }
+
+    #[test]
+    fn test_resolve_cid_urls() {
+        let raw = "From: alice@example.com\r\n\
+                   Content-Type: multipart/related; boundary=\"b\"\r\n\r\n\
+                   --b\r\n\
+                   Content-Type: text/html\r\n\r\n\
+                   <img src=\"cid:logo@example.com\"><p>cid:logo@example.com</p><img src='cid:missing'>\r\n\
+                   --b\r\n\
+                   Content-Type: image/png; name=\"logo.png\"\r\n\
+                   Content-ID: <logo@example.com>\r\n\
+                   Content-Transfer-Encoding: base64\r\n\r\n\
+                   iVBORw==\r\n\
+                   --b--\r\n";
+        let message = Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), raw.as_bytes()).unwrap();
+        assert!(message.attachments.is_empty());
+
+        let html = String::from_utf8_lossy(message.html_content().unwrap()).into_owned();
+        let resolved = resolve_cid_urls(&html, &message);
+        assert!(resolved.contains("<img src=\"data:image/png;base64,iVBORw==\">"));
+        assert!(resolved.contains("<p>cid:logo@example.com</p>"));
+        assert!(resolved.contains("src='cid:missing'"));
+
+        assert_eq!(percent_decode("part%401"), "part@1");
+    }
+}
@@ -0,0 +1,1630 @@
+//! Calendar invitations (iCalendar, RFC 5545) and replies (iTIP, RFC 5546)
+//!
+//! Meeting invites arrive as `text/calendar` parts. [`Calendar::parse`]
+//! reads the scheduling method and the events with their times, organizer,
+//! attendees and recurrence rule, resolving times against the `VTIMEZONE`
+//! definitions of the calendar. [`Calendar::reply`] answers an invitation
+//! with the identifying properties of the event copied verbatim, so the
+//! organizer's calendar can match the reply.
+
+use crate::account::Account;
+use crate::error::{AsgardError, AsgardResult};
+use crate::message::{EmailAddress, Message, MessageHeaders, MessageImportance, MessagePart, MessagePartType};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset, Weekday};
+
+/// Product identifier of calendars we write
+const PRODID: &str = "-//Asgard Mail//Asgard Mail//EN";
+
+/// Longest content line in octets before it is folded
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Recurrence periods scanned at most, against rules that never match
+const MAX_RECURRENCE_PERIODS: usize = 10_000;
+
+/// Event properties copied into a reply, in this order
+const REPLY_PROPERTIES: &[&str] = &["UID", "SEQUENCE", "RECURRENCE-ID", "DTSTART", "DTEND", "DURATION", "SUMMARY", "ORGANIZER"];
+
+/// A content line: `NAME;PARAM=value:value`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// Property name, upper case
+    pub name: String,
+    /// Parameters in order, names upper case and quotes removed
+    pub params: Vec<(String, String)>,
+    /// Value as written, TEXT escapes left in place
+    pub value: String,
+}
+
+impl Property {
+    /// Parse an unfolded content line
+    fn parse(line: &str) -> Option<Self> {
+        let mut in_quotes = false;
+        let colon = line.char_indices().find_map(|(index, c)| {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ':' if !in_quotes => return Some(index),
+                _ => {}
+            }
+            None
+        })?;
+
+        let mut segments = split_unquoted(&line[..colon], ';').into_iter();
+        let name = segments.next()?.trim().to_ascii_uppercase();
+        if name.is_empty() {
+            return None;
+        }
+        let params = segments
+            .filter_map(|segment| {
+                let (key, value) = segment.split_once('=')?;
+                Some((key.trim().to_ascii_uppercase(), value.replace('"', "")))
+            })
+            .collect();
+
+        Some(Self { name, params, value: line[colon + 1..].to_string() })
+    }
+
+    /// Value of the parameter `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Value with TEXT escapes undone
+    pub fn text(&self) -> String {
+        unescape(&self.value)
+    }
+
+    /// The property as a folded, CRLF terminated content line
+    fn to_line(&self) -> String {
+        let mut line = self.name.clone();
+        for (key, value) in &self.params {
+            line.push(';');
+            line.push_str(key);
+            line.push('=');
+            if value.contains([':', ';', ',']) {
+                line.push('"');
+                line.push_str(value);
+                line.push('"');
+            } else {
+                line.push_str(value);
+            }
+        }
+        line.push(':');
+        line.push_str(&self.value);
+        fold(&line)
+    }
+}
+
+/// A `BEGIN:`/`END:` block with its properties and nested blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Component {
+    name: String,
+    properties: Vec<Property>,
+    children: Vec<Component>,
+}
+
+impl Component {
+    /// First property called `name`
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    /// All properties called `name`
+    fn properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> + 'a {
+        self.properties.iter().filter(move |property| property.name == name)
+    }
+
+    /// The component as folded content lines
+    fn to_lines(&self) -> String {
+        let mut lines = format!("BEGIN:{}\r\n", self.name);
+        for property in &self.properties {
+            lines.push_str(&property.to_line());
+        }
+        for child in &self.children {
+            lines.push_str(&child.to_lines());
+        }
+        lines.push_str(&format!("END:{}\r\n", self.name));
+        lines
+    }
+}
+
+/// iTIP scheduling method of a calendar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// Published event, no replies expected
+    Publish,
+    /// Invitation or update from the organizer
+    Request,
+    /// Answer of an attendee
+    Reply,
+    /// Event or occurrence cancelled by the organizer
+    Cancel,
+    /// Any other method
+    Other(String),
+}
+
+impl Method {
+    fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "PUBLISH" => Self::Publish,
+            "REQUEST" => Self::Request,
+            "REPLY" => Self::Reply,
+            "CANCEL" => Self::Cancel,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Method name as written in the calendar
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Publish => "PUBLISH",
+            Self::Request => "REQUEST",
+            Self::Reply => "REPLY",
+            Self::Cancel => "CANCEL",
+            Self::Other(other) => other,
+        }
+    }
+}
+
+/// Participation status of an attendee
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartStat {
+    /// Not answered yet
+    NeedsAction,
+    /// Will attend
+    Accepted,
+    /// Might attend
+    Tentative,
+    /// Will not attend
+    Declined,
+    /// Passed the invitation on
+    Delegated,
+}
+
+impl PartStat {
+    fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "ACCEPTED" => Self::Accepted,
+            "TENTATIVE" => Self::Tentative,
+            "DECLINED" => Self::Declined,
+            "DELEGATED" => Self::Delegated,
+            _ => Self::NeedsAction,
+        }
+    }
+
+    /// Status as written in the `PARTSTAT` parameter
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NeedsAction => "NEEDS-ACTION",
+            Self::Accepted => "ACCEPTED",
+            Self::Tentative => "TENTATIVE",
+            Self::Declined => "DECLINED",
+            Self::Delegated => "DELEGATED",
+        }
+    }
+
+    /// Short label, also the subject prefix of replies
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NeedsAction => "Not answered",
+            Self::Accepted => "Accepted",
+            Self::Tentative => "Tentative",
+            Self::Declined => "Declined",
+            Self::Delegated => "Delegated",
+        }
+    }
+}
+
+/// Organizer or attendee of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Participant {
+    /// Email address, without `mailto:`
+    pub email: String,
+    /// Common name (`CN`)
+    pub name: Option<String>,
+    /// Role, e.g. `REQ-PARTICIPANT` or `CHAIR`
+    pub role: Option<String>,
+    /// Participation status
+    pub status: PartStat,
+    /// Whether the organizer asked for a reply
+    pub rsvp: bool,
+}
+
+impl Participant {
+    fn from_property(property: &Property) -> Self {
+        let value = property.value.trim();
+        let email = if value.len() >= 7 && value[..7].eq_ignore_ascii_case("mailto:") {
+            &value[7..]
+        } else {
+            value
+        };
+        Self {
+            email: email.to_string(),
+            name: property.param("CN").map(str::to_string).filter(|name| !name.is_empty()),
+            role: property.param("ROLE").map(str::to_string),
+            status: property.param("PARTSTAT").map(PartStat::parse).unwrap_or(PartStat::NeedsAction),
+            rsvp: property.param("RSVP").is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE")),
+        }
+    }
+
+    /// Name if known, else the email address
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.email)
+    }
+
+    /// Whether this is the participant with address `email`
+    pub fn is(&self, email: &str) -> bool {
+        self.email.eq_ignore_ascii_case(email)
+    }
+}
+
+/// Start or end of an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTime {
+    /// Whole day
+    Date(Date),
+    /// Instant in UTC or in a time zone defined by the calendar
+    DateTime(OffsetDateTime),
+    /// Wall-clock time without a known zone, read as the viewer's local time
+    Floating(PrimitiveDateTime),
+}
+
+impl EventTime {
+    /// Whether this is a whole day rather than a time
+    pub fn is_all_day(&self) -> bool {
+        matches!(self, Self::Date(_))
+    }
+
+    /// Wall-clock date and time as written, for recurrence expansion
+    fn local(&self) -> PrimitiveDateTime {
+        match *self {
+            Self::Date(date) => PrimitiveDateTime::new(date, Time::MIDNIGHT),
+            Self::DateTime(datetime) => PrimitiveDateTime::new(datetime.date(), datetime.time()),
+            Self::Floating(datetime) => datetime,
+        }
+    }
+
+    fn add(&self, duration: Duration) -> Self {
+        match *self {
+            Self::Date(date) => Self::Date(date + duration),
+            Self::DateTime(datetime) => Self::DateTime(datetime + duration),
+            Self::Floating(datetime) => Self::Floating(datetime + duration),
+        }
+    }
+}
+
+/// How often a recurring event repeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    /// Every n days
+    Daily,
+    /// Every n weeks
+    Weekly,
+    /// Every n months
+    Monthly,
+    /// Every n years
+    Yearly,
+}
+
+/// Recurrence rule (`RRULE`)
+///
+/// `BYSETPOS`, `BYWEEKNO`, `BYYEARDAY` and rules repeating more often than
+/// daily are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    /// Repetition unit
+    pub frequency: Frequency,
+    /// Units between repetitions
+    pub interval: u32,
+    /// Total number of occurrences
+    pub count: Option<u32>,
+    /// Last possible occurrence, compared with wall-clock times
+    pub until: Option<PrimitiveDateTime>,
+    /// Weekdays, with an ordinal within the month (`-1` for the last)
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    /// Days of the month, negative from the end
+    pub by_month_day: Vec<i32>,
+    /// Months, 1 to 12
+    pub by_month: Vec<u8>,
+}
+
+impl Recurrence {
+    /// Parse an `RRULE` value
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut rule = Self {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+        let mut frequency = None;
+
+        for part in value.trim().split(';') {
+            let Some((key, value)) = part.split_once('=') else { continue };
+            match key.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return None,
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|n| *n > 0)?,
+                "COUNT" => rule.count = Some(value.parse().ok()?),
+                "UNTIL" => {
+                    rule.until = Some(match parse_date_time(value) {
+                        Some((datetime, _)) => datetime,
+                        None => PrimitiveDateTime::new(parse_date(value)?, Time::MAX),
+                    })
+                }
+                "BYDAY" => rule.by_day = value.split(',').map(parse_by_day).collect::<Option<_>>()?,
+                "BYMONTHDAY" => rule.by_month_day = value.split(',').map(|day| day.trim().parse().ok()).collect::<Option<_>>()?,
+                "BYMONTH" => rule.by_month = value.split(',').map(|month| month.trim().parse().ok()).collect::<Option<_>>()?,
+                _ => {}
+            }
+        }
+
+        rule.frequency = frequency?;
+        Some(rule)
+    }
+
+    /// Human-readable summary, e.g. "Every 2 weeks on Monday, 10 times"
+    pub fn describe(&self) -> String {
+        let (unit, units) = match self.frequency {
+            Frequency::Daily => ("day", "days"),
+            Frequency::Weekly => ("week", "weeks"),
+            Frequency::Monthly => ("month", "months"),
+            Frequency::Yearly => ("year", "years"),
+        };
+        let mut text = if self.interval == 1 {
+            format!("Every {}", unit)
+        } else {
+            format!("Every {} {}", self.interval, units)
+        };
+
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self.by_day.iter()
+                .map(|&(ordinal, weekday)| match ordinal {
+                    Some(ordinal) => format!("the {} {}", ordinal_name(ordinal), weekday),
+                    None => weekday.to_string(),
+                })
+                .collect();
+            text.push_str(&format!(" on {}", days.join(", ")));
+        }
+        if !self.by_month_day.is_empty() {
+            let days: Vec<String> = self.by_month_day.iter()
+                .map(|&day| if day == -1 { "the last day".to_string() } else { format!("day {}", day) })
+                .collect();
+            text.push_str(&format!(" on {}", days.join(", ")));
+        }
+        if !self.by_month.is_empty() {
+            let months: Vec<String> = self.by_month.iter()
+                .filter_map(|&month| Month::try_from(month).ok())
+                .map(|month| month.to_string())
+                .collect();
+            text.push_str(&format!(" in {}", months.join(", ")));
+        }
+        if let Some(count) = self.count {
+            text.push_str(&format!(", {} times", count));
+        }
+        if let Some(until) = self.until {
+            text.push_str(&format!(", until {}", until.date()));
+        }
+        text
+    }
+
+    /// Occurrences of a series starting at `start`, in order
+    pub fn occurrences(&self, start: PrimitiveDateTime) -> Occurrences<'_> {
+        Occurrences { rule: self, start, period: 0, pending: Vec::new(), emitted: 0 }
+    }
+
+    /// Candidate occurrences in the `period`th period after the start
+    fn expand(&self, start: PrimitiveDateTime, period: usize) -> Vec<PrimitiveDateTime> {
+        let step = period as i64 * self.interval as i64;
+        let first = start.date();
+        let dates: Vec<Date> = match self.frequency {
+            Frequency::Daily => {
+                let day = first + Duration::days(step);
+                let matches = (self.by_day.is_empty() || self.by_day.iter().any(|(_, weekday)| *weekday == day.weekday()))
+                    && (self.by_month_day.is_empty() || self.by_month_day.iter().any(|&n| month_day(day, n)));
+                if matches { vec![day] } else { Vec::new() }
+            }
+            Frequency::Weekly => {
+                let monday = first - Duration::days(first.weekday().number_days_from_monday() as i64) + Duration::weeks(step);
+                if self.by_day.is_empty() {
+                    vec![monday + Duration::days(first.weekday().number_days_from_monday() as i64)]
+                } else {
+                    self.by_day.iter()
+                        .map(|(_, weekday)| monday + Duration::days(weekday.number_days_from_monday() as i64))
+                        .collect()
+                }
+            }
+            Frequency::Monthly => {
+                let (year, month) = add_months(first.year(), first.month(), step);
+                self.month_dates(year, month, first.day())
+            }
+            Frequency::Yearly => {
+                let year = first.year() + step as i32;
+                let months: Vec<Month> = if self.by_month.is_empty() {
+                    vec![first.month()]
+                } else {
+                    self.by_month.iter().filter_map(|&month| Month::try_from(month).ok()).collect()
+                };
+                months.into_iter().flat_map(|month| self.month_dates(year, month, first.day())).collect()
+            }
+        };
+
+        dates.into_iter()
+            .filter(|date| self.by_month.is_empty() || self.by_month.contains(&(date.month() as u8)))
+            .map(|date| PrimitiveDateTime::new(date, start.time()))
+            .collect()
+    }
+
+    /// Dates in a month matching the `BYMONTHDAY` and `BYDAY` parts
+    fn month_dates(&self, year: i32, month: Month, default_day: u8) -> Vec<Date> {
+        let mut dates: Vec<Date> = if !self.by_month_day.is_empty() {
+            let length = days_in_month(year, month) as i32;
+            self.by_month_day.iter()
+                .map(|&day| if day < 0 { length + day + 1 } else { day })
+                .filter(|day| (1..=length).contains(day))
+                .filter_map(|day| Date::from_calendar_date(year, month, day as u8).ok())
+                .collect()
+        } else if !self.by_day.is_empty() {
+            self.by_day.iter()
+                .flat_map(|&(ordinal, weekday)| weekdays_in_month(year, month, weekday, ordinal))
+                .collect()
+        } else {
+            Date::from_calendar_date(year, month, default_day).ok().into_iter().collect()
+        };
+
+        if !self.by_month_day.is_empty() && !self.by_day.is_empty() {
+            dates.retain(|date| self.by_day.iter().any(|(_, weekday)| *weekday == date.weekday()));
+        }
+        dates
+    }
+}
+
+/// Iterator over the occurrences of a [`Recurrence`]
+pub struct Occurrences<'a> {
+    rule: &'a Recurrence,
+    start: PrimitiveDateTime,
+    period: usize,
+    /// Occurrences of the current period, latest first
+    pending: Vec<PrimitiveDateTime>,
+    emitted: u32,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = PrimitiveDateTime;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(next) = self.pending.pop() {
+                if self.rule.count.is_some_and(|count| self.emitted >= count)
+                    || self.rule.until.is_some_and(|until| next > until)
+                {
+                    self.period = MAX_RECURRENCE_PERIODS;
+                    self.pending.clear();
+                    return None;
+                }
+                self.emitted += 1;
+                return Some(next);
+            }
+            if self.period >= MAX_RECURRENCE_PERIODS {
+                return None;
+            }
+
+            let mut candidates = self.rule.expand(self.start, self.period);
+            self.period += 1;
+            candidates.retain(|candidate| *candidate >= self.start);
+            candidates.sort_unstable_by(|a, b| b.cmp(a));
+            candidates.dedup();
+            self.pending = candidates;
+        }
+    }
+}
+
+/// A `STANDARD` or `DAYLIGHT` block of a time zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Observance {
+    /// First onset, in the local time before it
+    onset: PrimitiveDateTime,
+    /// Further onsets (`RDATE`)
+    extra_onsets: Vec<PrimitiveDateTime>,
+    /// Yearly repetition of the onset
+    rule: Option<Recurrence>,
+    offset_from: UtcOffset,
+    offset_to: UtcOffset,
+}
+
+impl Observance {
+    /// Latest onset at or before the wall-clock time `local`
+    fn last_onset(&self, local: PrimitiveDateTime) -> Option<PrimitiveDateTime> {
+        let repeated = self.rule.as_ref()
+            .and_then(|rule| rule.occurrences(self.onset).take_while(|onset| *onset <= local).last());
+        std::iter::once(self.onset)
+            .chain(self.extra_onsets.iter().copied())
+            .chain(repeated)
+            .filter(|onset| *onset <= local)
+            .max()
+    }
+}
+
+/// Time zone defined by a `VTIMEZONE` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    /// Identifier referenced by `TZID` parameters
+    pub tzid: String,
+    observances: Vec<Observance>,
+}
+
+impl TimeZone {
+    fn from_component(component: &Component) -> Option<Self> {
+        let tzid = component.property("TZID")?.value.trim().to_string();
+        let observances = component.children.iter()
+            .filter(|child| child.name == "STANDARD" || child.name == "DAYLIGHT")
+            .filter_map(|child| {
+                Some(Observance {
+                    onset: parse_date_time(&child.property("DTSTART")?.value)?.0,
+                    extra_onsets: child.properties("RDATE")
+                        .flat_map(|rdate| rdate.value.split(','))
+                        .filter_map(|value| parse_date_time(value).map(|(datetime, _)| datetime))
+                        .collect(),
+                    rule: child.property("RRULE").and_then(|rrule| Recurrence::parse(&rrule.value)),
+                    offset_from: parse_offset(&child.property("TZOFFSETFROM")?.value)?,
+                    offset_to: parse_offset(&child.property("TZOFFSETTO")?.value)?,
+                })
+            })
+            .collect();
+        Some(Self { tzid, observances })
+    }
+
+    /// UTC offset in effect at the wall-clock time `local`
+    pub fn offset_at(&self, local: PrimitiveDateTime) -> Option<UtcOffset> {
+        let mut latest: Option<(PrimitiveDateTime, UtcOffset)> = None;
+        for observance in &self.observances {
+            let Some(onset) = observance.last_onset(local) else { continue };
+            match latest {
+                Some((time, _)) if time >= onset => {}
+                _ => latest = Some((onset, observance.offset_to)),
+            }
+        }
+        latest.map(|(_, offset)| offset).or_else(|| {
+            self.observances.iter()
+                .min_by_key(|observance| observance.onset)
+                .map(|observance| observance.offset_from)
+        })
+    }
+}
+
+/// A `VEVENT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Identifier shared by all versions of the event
+    pub uid: String,
+    /// Revision, raised by the organizer on significant changes
+    pub sequence: u32,
+    /// Title
+    pub summary: Option<String>,
+    /// Place or meeting link
+    pub location: Option<String>,
+    /// Notes
+    pub description: Option<String>,
+    /// `TENTATIVE`, `CONFIRMED` or `CANCELLED`
+    pub status: Option<String>,
+    /// Start
+    pub start: Option<EventTime>,
+    /// End, exclusive; computed from `DURATION` when there is no `DTEND`
+    pub end: Option<EventTime>,
+    /// Organizer
+    pub organizer: Option<Participant>,
+    /// Attendees
+    pub attendees: Vec<Participant>,
+    /// Recurrence rule
+    pub recurrence: Option<Recurrence>,
+    /// Occurrences removed from the series (`EXDATE`)
+    pub exceptions: Vec<EventTime>,
+    /// Occurrence of a series this event replaces
+    pub recurrence_id: Option<EventTime>,
+    /// The `VEVENT` as received, for replies and calendar files
+    component: Component,
+}
+
+impl Event {
+    fn from_component(component: &Component, timezones: &[TimeZone]) -> Self {
+        let time = |name: &str| component.property(name).and_then(|property| parse_event_time(property, timezones));
+        let text = |name: &str| component.property(name).map(Property::text).filter(|text| !text.is_empty());
+
+        let start = time("DTSTART");
+        let end = time("DTEND").or_else(|| {
+            let duration = parse_duration(&component.property("DURATION")?.value)?;
+            Some(start?.add(duration))
+        });
+
+        Self {
+            uid: component.property("UID").map(|uid| uid.value.trim().to_string()).unwrap_or_default(),
+            sequence: component.property("SEQUENCE").and_then(|sequence| sequence.value.trim().parse().ok()).unwrap_or(0),
+            summary: text("SUMMARY"),
+            location: text("LOCATION"),
+            description: text("DESCRIPTION"),
+            status: component.property("STATUS").map(|status| status.value.trim().to_ascii_uppercase()),
+            start,
+            end,
+            organizer: component.property("ORGANIZER").map(Participant::from_property),
+            attendees: component.properties("ATTENDEE").map(Participant::from_property).collect(),
+            recurrence: component.property("RRULE").and_then(|rrule| Recurrence::parse(&rrule.value)),
+            exceptions: component.properties("EXDATE")
+                .flat_map(|exdate| {
+                    exdate.value.split(',')
+                        .filter_map(|value| parse_event_time(&Property { value: value.to_string(), ..exdate.clone() }, timezones))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+            recurrence_id: time("RECURRENCE-ID"),
+            component: component.clone(),
+        }
+    }
+
+    /// Summary, or a placeholder for events without one
+    pub fn title(&self) -> &str {
+        self.summary.as_deref().unwrap_or("Untitled event")
+    }
+
+    /// The attendee with address `email`
+    pub fn attendee(&self, email: &str) -> Option<&Participant> {
+        self.attendees.iter().find(|attendee| attendee.is(email))
+    }
+
+    /// Whether the event has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.status.as_deref() == Some("CANCELLED")
+    }
+
+    /// Upcoming starts of a recurring event, without removed occurrences
+    ///
+    /// Returns at most `limit` starts at or after `after`.
+    pub fn next_occurrences(&self, after: PrimitiveDateTime, limit: usize) -> Vec<PrimitiveDateTime> {
+        let (Some(rule), Some(start)) = (&self.recurrence, self.start) else {
+            return Vec::new();
+        };
+        let exceptions: Vec<PrimitiveDateTime> = self.exceptions.iter().map(EventTime::local).collect();
+        rule.occurrences(start.local())
+            .filter(|occurrence| !exceptions.contains(occurrence))
+            .skip_while(|occurrence| *occurrence < after)
+            .take(limit)
+            .collect()
+    }
+
+    /// TZIDs referenced by the copied time properties
+    fn tzids(&self) -> Vec<&str> {
+        self.component.properties.iter()
+            .filter(|property| REPLY_PROPERTIES.contains(&property.name.as_str()))
+            .filter_map(|property| property.param("TZID"))
+            .collect()
+    }
+}
+
+/// How [`Calendar::apply`] changed a calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarChange {
+    /// The event was new
+    Added,
+    /// A stored version of the event was replaced
+    Updated,
+    /// The event or an occurrence was cancelled and removed
+    Removed,
+    /// Nothing to do: the stored version is newer, or a cancelled event
+    /// was never stored
+    Unchanged,
+}
+
+/// A parsed `VCALENDAR`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Calendar {
+    /// iTIP method; calendars without one are plain published data
+    pub method: Option<Method>,
+    /// Events, overridden occurrences of a series following its master
+    pub events: Vec<Event>,
+    /// Time zones defined in the calendar
+    pub timezones: Vec<TimeZone>,
+    /// `VTIMEZONE` blocks as received, for replies and calendar files
+    timezone_components: Vec<Component>,
+    /// Calendar properties other than METHOD, PRODID and VERSION
+    properties: Vec<Property>,
+    /// Components other than events and time zones, e.g. to-dos in a
+    /// calendar file
+    other_components: Vec<Component>,
+}
+
+impl Calendar {
+    /// Parse the text of a `text/calendar` part
+    pub fn parse(text: &str) -> AsgardResult<Self> {
+        let root = parse_components(text)?
+            .into_iter()
+            .find(|component| component.name == "VCALENDAR")
+            .ok_or_else(|| AsgardError::validation("No VCALENDAR in calendar data"))?;
+
+        let timezone_components: Vec<Component> = root.children.iter()
+            .filter(|child| child.name == "VTIMEZONE")
+            .cloned()
+            .collect();
+        let timezones: Vec<TimeZone> = timezone_components.iter().filter_map(TimeZone::from_component).collect();
+        let events = root.children.iter()
+            .filter(|child| child.name == "VEVENT")
+            .map(|child| Event::from_component(child, &timezones))
+            .collect();
+
+        Ok(Self {
+            method: root.property("METHOD").map(|method| Method::parse(&method.value)),
+            events,
+            timezones,
+            timezone_components,
+            properties: root.properties.iter()
+                .filter(|property| !matches!(property.name.as_str(), "METHOD" | "PRODID" | "VERSION"))
+                .cloned()
+                .collect(),
+            other_components: root.children.iter()
+                .filter(|child| child.name != "VEVENT" && child.name != "VTIMEZONE")
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// UIDs of the events, each once
+    pub fn uids(&self) -> Vec<&str> {
+        let mut uids: Vec<&str> = Vec::new();
+        for event in &self.events {
+            if !uids.contains(&event.uid.as_str()) {
+                uids.push(&event.uid);
+            }
+        }
+        uids
+    }
+
+    /// The events with UID `uid`, with the method and time zones
+    pub fn only(&self, uid: &str) -> Calendar {
+        Calendar {
+            method: self.method.clone(),
+            events: self.events.iter().filter(|event| event.uid == uid).cloned().collect(),
+            timezones: self.timezones.clone(),
+            timezone_components: self.timezone_components.clone(),
+            properties: Vec::new(),
+            other_components: Vec::new(),
+        }
+    }
+
+    /// Apply an invitation, update or cancellation to this calendar
+    ///
+    /// Events are matched by UID and RECURRENCE-ID. A stored event is only
+    /// replaced or removed by a version with at least its SEQUENCE, so an
+    /// old invitation opened late cannot undo a newer update. Cancelling
+    /// one occurrence removes its override and excludes it from the
+    /// series. Returns the first change made.
+    pub fn apply(&mut self, incoming: &Calendar) -> AsgardResult<CalendarChange> {
+        let cancel = match &incoming.method {
+            None | Some(Method::Publish) | Some(Method::Request) => false,
+            Some(Method::Cancel) => true,
+            Some(other) => {
+                return Err(AsgardError::invalid_state(format!("A calendar {} cannot be added to a calendar", other.as_str())));
+            }
+        };
+
+        let mut result = CalendarChange::Unchanged;
+        for event in &incoming.events {
+            let change = if cancel { self.cancel(event) } else { self.store(event) };
+            if result == CalendarChange::Unchanged {
+                result = change;
+            }
+        }
+
+        if result != CalendarChange::Unchanged {
+            for component in &incoming.timezone_components {
+                let tzid = component.property("TZID").map(|tzid| tzid.value.trim());
+                let known = self.timezone_components.iter()
+                    .any(|stored| stored.property("TZID").map(|tzid| tzid.value.trim()) == tzid);
+                if !known {
+                    self.timezone_components.push(component.clone());
+                    self.timezones.extend(TimeZone::from_component(component));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Add or replace `event`
+    fn store(&mut self, event: &Event) -> CalendarChange {
+        let stored = self.events.iter()
+            .position(|stored| stored.uid == event.uid && stored.recurrence_id == event.recurrence_id);
+        match stored {
+            Some(index) if self.events[index].sequence > event.sequence => CalendarChange::Unchanged,
+            Some(index) => {
+                self.events[index] = event.clone();
+                CalendarChange::Updated
+            }
+            None => {
+                self.events.push(event.clone());
+                CalendarChange::Added
+            }
+        }
+    }
+
+    /// Remove the event or occurrence cancelled by `event`
+    fn cancel(&mut self, event: &Event) -> CalendarChange {
+        let Some(recurrence_id) = event.component.property("RECURRENCE-ID") else {
+            // The whole series
+            let before = self.events.len();
+            self.events.retain(|stored| stored.uid != event.uid || stored.sequence > event.sequence);
+            return if self.events.len() < before { CalendarChange::Removed } else { CalendarChange::Unchanged };
+        };
+
+        let mut change = CalendarChange::Unchanged;
+        let before = self.events.len();
+        self.events.retain(|stored| {
+            stored.uid != event.uid || stored.recurrence_id != event.recurrence_id || stored.sequence > event.sequence
+        });
+        if self.events.len() < before {
+            change = CalendarChange::Removed;
+        }
+
+        let master = self.events.iter_mut().find(|stored| {
+            stored.uid == event.uid && stored.recurrence_id.is_none() && stored.sequence <= event.sequence
+        });
+        if let (Some(master), Some(occurrence)) = (master, event.recurrence_id) {
+            if !master.exceptions.contains(&occurrence) {
+                master.exceptions.push(occurrence);
+                master.component.properties.push(Property { name: "EXDATE".to_string(), ..recurrence_id.clone() });
+                change = CalendarChange::Removed;
+            }
+        }
+        change
+    }
+
+    /// The calendar as an iCalendar file, without an iTIP method
+    ///
+    /// This is the form calendar files and CalDAV resources take.
+    pub fn to_ics(&self) -> String {
+        let mut text = String::new();
+        text.push_str("BEGIN:VCALENDAR\r\n");
+        text.push_str(&fold(&format!("PRODID:{}", PRODID)));
+        text.push_str("VERSION:2.0\r\n");
+        for property in &self.properties {
+            text.push_str(&property.to_line());
+        }
+        for component in &self.timezone_components {
+            text.push_str(&component.to_lines());
+        }
+        for event in &self.events {
+            text.push_str(&event.component.to_lines());
+        }
+        for component in &self.other_components {
+            text.push_str(&component.to_lines());
+        }
+        text.push_str("END:VCALENDAR\r\n");
+        text
+    }
+
+    /// The event a message is about: the series master if there is one
+    pub fn event(&self) -> Option<&Event> {
+        self.events.iter()
+            .find(|event| event.recurrence_id.is_none())
+            .or_else(|| self.events.first())
+    }
+
+    /// iTIP `REPLY` of the attendee `attendee` to `event`
+    ///
+    /// UID, SEQUENCE, RECURRENCE-ID, times, summary and organizer are copied
+    /// as received, with the time zones they refer to.
+    pub fn reply(&self, event: &Event, attendee: &str, status: PartStat) -> String {
+        let mut text = String::new();
+        text.push_str("BEGIN:VCALENDAR\r\n");
+        text.push_str(&fold(&format!("PRODID:{}", PRODID)));
+        text.push_str("VERSION:2.0\r\n");
+        text.push_str("METHOD:REPLY\r\n");
+
+        let tzids = event.tzids();
+        for component in &self.timezone_components {
+            let referenced = component.property("TZID")
+                .is_some_and(|tzid| tzids.contains(&tzid.value.trim()));
+            if referenced {
+                text.push_str(&component.to_lines());
+            }
+        }
+
+        text.push_str("BEGIN:VEVENT\r\n");
+        for name in REPLY_PROPERTIES {
+            for property in event.component.properties.iter().filter(|property| property.name == *name) {
+                text.push_str(&property.to_line());
+            }
+        }
+        text.push_str(&format!("DTSTAMP:{}\r\n", format_utc(OffsetDateTime::now_utc())));
+
+        let mut params: Vec<(String, String)> = event.component.properties.iter()
+            .filter(|property| property.name == "ATTENDEE")
+            .find(|property| Participant::from_property(property).is(attendee))
+            .map(|property| {
+                property.params.iter()
+                    .filter(|(key, _)| key != "PARTSTAT" && key != "RSVP")
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        params.insert(0, ("PARTSTAT".to_string(), status.as_str().to_string()));
+        let line = Property {
+            name: "ATTENDEE".to_string(),
+            params,
+            value: format!("mailto:{}", attendee),
+        };
+        text.push_str(&line.to_line());
+
+        text.push_str("END:VEVENT\r\n");
+        text.push_str("END:VCALENDAR\r\n");
+        text
+    }
+}
+
+/// Reply message answering the invitation in `invitation` for `account`
+///
+/// The reply goes to the organizer, or to the sender when the event has
+/// none, and carries the iTIP `REPLY` as a `text/calendar` part next to a
+/// short text for clients that do not understand it.
+pub fn reply_message(invitation: &Message, account: &Account, status: PartStat) -> AsgardResult<Message> {
+    let content = invitation.calendar_content()
+        .ok_or_else(|| AsgardError::not_found("Message has no calendar invitation"))?;
+    let calendar = Calendar::parse(&String::from_utf8_lossy(content))?;
+    if calendar.method != Some(Method::Request) {
+        return Err(AsgardError::invalid_state("Only invitations can be answered"));
+    }
+    let event = calendar.event()
+        .ok_or_else(|| AsgardError::validation("Invitation has no event"))?;
+
+    let email = account.email().to_string();
+    let organizer = event.organizer.as_ref()
+        .map(|organizer| EmailAddress { name: organizer.name.clone(), email: organizer.email.clone() })
+        .or_else(|| invitation.headers.reply_to.first().cloned())
+        .or_else(|| invitation.headers.from.first().cloned())
+        .ok_or_else(|| AsgardError::validation("Invitation has no organizer"))?;
+    let name = event.attendee(&email)
+        .and_then(|attendee| attendee.name.clone())
+        .or_else(|| Some(account.config.display_name.clone()).filter(|name| !name.is_empty()));
+
+    let headers = MessageHeaders {
+        message_id: None,
+        in_reply_to: invitation.headers.message_id.clone(),
+        references: invitation.headers.message_id.clone(),
+        subject: format!("{}: {}", status.label(), event.title()),
+        from: vec![EmailAddress { name: name.clone(), email: email.clone() }],
+        to: vec![organizer],
+        cc: Vec::new(),
+        bcc: Vec::new(),
+        reply_to: Vec::new(),
+        date: Some(OffsetDateTime::now_utc()),
+        received_date: None,
+        importance: MessageImportance::Normal,
+        custom: Default::default(),
+    };
+
+    let mut reply = Message::new(invitation.account_id, invitation.mailbox_id, headers);
+    let verb = match status {
+        PartStat::Accepted => "accepted",
+        PartStat::Tentative => "tentatively accepted",
+        PartStat::Declined => "declined",
+        PartStat::Delegated => "delegated",
+        PartStat::NeedsAction => "not yet answered",
+    };
+    let text = format!("{} has {} the invitation to \"{}\".\r\n", name.as_deref().unwrap_or(&email), verb, event.title());
+    let calendar = calendar.reply(event, &email, status);
+    reply.add_part(text_part("1", "text/plain", MessagePartType::Text, text.into_bytes()));
+    reply.add_part(text_part("2", "text/calendar", MessagePartType::Calendar, calendar.into_bytes()));
+    Ok(reply)
+}
+
+fn text_part(id: &str, mime_type: &str, part_type: MessagePartType, content: Vec<u8>) -> MessagePart {
+    MessagePart {
+        id: id.to_string(),
+        part_type,
+        mime_type: mime_type.to_string(),
+        disposition: None,
+        filename: None,
+        size: content.len(),
+        encoding: None,
+        content_id: None,
+        content_location: None,
+        content: Some(content),
+        children: Vec::new(),
+    }
+}
+
+/// Unfold content lines and group them into components
+fn parse_components(text: &str) -> AsgardResult<Vec<Component>> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut stack: Vec<Component> = Vec::new();
+    for line in &lines {
+        let Some(property) = Property::parse(line) else { continue };
+        match property.name.as_str() {
+            "BEGIN" => stack.push(Component {
+                name: property.value.trim().to_ascii_uppercase(),
+                properties: Vec::new(),
+                children: Vec::new(),
+            }),
+            "END" => {
+                let component = stack.pop()
+                    .ok_or_else(|| AsgardError::validation(format!("Unexpected END:{} in calendar data", property.value)))?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(component),
+                    None => roots.push(component),
+                }
+            }
+            _ => {
+                if let Some(current) = stack.last_mut() {
+                    current.properties.push(property);
+                }
+            }
+        }
+    }
+    if let Some(open) = stack.last() {
+        return Err(AsgardError::validation(format!("Unterminated {} in calendar data", open.name)));
+    }
+    Ok(roots)
+}
+
+/// Fold a content line at 75 octets, without splitting characters
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3 + 2);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Split at `separator` outside double quotes
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut begin = 0;
+    for (index, c) in text.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == separator && !in_quotes {
+            parts.push(&text[begin..index]);
+            begin = index + 1;
+        }
+    }
+    parts.push(&text[begin..]);
+    parts
+}
+
+/// Undo TEXT escapes: `\n`, `\,`, `\;` and `\\`
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(other) => text.push(other),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+/// Parse a `DATE` value, `YYYYMMDD`
+fn parse_date(value: &str) -> Option<Date> {
+    let value = value.trim();
+    if value.len() < 8 || !value.is_char_boundary(8) {
+        return None;
+    }
+    let year = value[0..4].parse().ok()?;
+    let month = Month::try_from(value[4..6].parse::<u8>().ok()?).ok()?;
+    let day = value[6..8].parse().ok()?;
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+/// Parse a `DATE-TIME` value, `YYYYMMDDTHHMMSS` with an optional `Z`
+///
+/// Returns the wall-clock time and whether it is UTC.
+fn parse_date_time(value: &str) -> Option<(PrimitiveDateTime, bool)> {
+    let value = value.trim();
+    let (datetime, utc) = match value.strip_suffix(['Z', 'z']) {
+        Some(datetime) => (datetime, true),
+        None => (value, false),
+    };
+    let (date, time) = datetime.split_once(['T', 't'])?;
+    if date.len() != 8 || time.len() != 6 || !time.is_ascii() {
+        return None;
+    }
+    let time = Time::from_hms(time[0..2].parse().ok()?, time[2..4].parse().ok()?, time[4..6].parse::<u8>().ok()?.min(59)).ok()?;
+    Some((PrimitiveDateTime::new(parse_date(date)?, time), utc))
+}
+
+/// Parse a `DTSTART`-like property, resolving `TZID` against `timezones`
+fn parse_event_time(property: &Property, timezones: &[TimeZone]) -> Option<EventTime> {
+    let value = property.value.trim();
+    let is_date = property.param("VALUE").is_some_and(|kind| kind.eq_ignore_ascii_case("DATE"))
+        || (value.len() == 8 && !value.contains(['T', 't']));
+    if is_date {
+        return parse_date(value).map(EventTime::Date);
+    }
+
+    let (datetime, utc) = parse_date_time(value)?;
+    if utc {
+        return Some(EventTime::DateTime(datetime.assume_utc()));
+    }
+    let offset = property.param("TZID")
+        .and_then(|tzid| timezones.iter().find(|timezone| timezone.tzid == tzid.trim()))
+        .and_then(|timezone| timezone.offset_at(datetime));
+    Some(match offset {
+        Some(offset) => EventTime::DateTime(datetime.assume_offset(offset)),
+        None => EventTime::Floating(datetime),
+    })
+}
+
+/// Parse a UTC offset, `+HHMM` or `-HHMMSS`
+fn parse_offset(value: &str) -> Option<UtcOffset> {
+    let value = value.trim();
+    let (sign, digits) = match value.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i8 = digits[0..2].parse().ok()?;
+    let minutes: i8 = digits[2..4].parse().ok()?;
+    let seconds: i8 = digits.get(4..6).map_or(Some(0), |s| s.parse().ok())?;
+    UtcOffset::from_hms(sign * hours, sign * minutes, sign * seconds).ok()
+}
+
+/// Parse a `DURATION` value such as `PT1H30M` or `-P1D`
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix(['P', 'p'])?;
+
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c.to_ascii_uppercase() {
+            'T' => in_time = true,
+            digit if digit.is_ascii_digit() => number.push(digit),
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total += match (unit, in_time) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    number.is_empty().then_some(total * sign)
+}
+
+/// Parse a `BYDAY` entry such as `MO`, `2TU` or `-1SU`
+fn parse_by_day(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let value = value.trim();
+    let split = value.len().checked_sub(2)?;
+    if !value.is_char_boundary(split) {
+        return None;
+    }
+    let (ordinal, day) = value.split_at(split);
+    let weekday = match day.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Monday,
+        "TU" => Weekday::Tuesday,
+        "WE" => Weekday::Wednesday,
+        "TH" => Weekday::Thursday,
+        "FR" => Weekday::Friday,
+        "SA" => Weekday::Saturday,
+        "SU" => Weekday::Sunday,
+        _ => return None,
+    };
+    let ordinal = match ordinal {
+        "" => None,
+        ordinal => Some(ordinal.strip_prefix('+').unwrap_or(ordinal).parse().ok().filter(|n: &i32| *n != 0)?),
+    };
+    Some((ordinal, weekday))
+}
+
+/// Format an instant as a UTC `DATE-TIME`
+fn format_utc(datetime: OffsetDateTime) -> String {
+    let utc = datetime.to_offset(UtcOffset::UTC);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        utc.year(), utc.month() as u8, utc.day(), utc.hour(), utc.minute(), utc.second()
+    )
+}
+
+/// "first", "second", ..., "last", "second to last"
+fn ordinal_name(ordinal: i32) -> String {
+    const NAMES: [&str; 5] = ["first", "second", "third", "fourth", "fifth"];
+    match ordinal {
+        -1 => "last".to_string(),
+        n if n < -1 => format!("{} to last", ordinal_name(-n)),
+        n => NAMES.get(n as usize - 1).map_or_else(|| format!("{}th", n), |name| name.to_string()),
+    }
+}
+
+fn days_in_month(year: i32, month: Month) -> u8 {
+    (28..=31).rev()
+        .find(|&day| Date::from_calendar_date(year, month, day).is_ok())
+        .unwrap_or(28)
+}
+
+fn add_months(year: i32, month: Month, months: i64) -> (i32, Month) {
+    let total = year as i64 * 12 + (month as i64 - 1) + months;
+    let month = Month::try_from((total.rem_euclid(12) + 1) as u8).unwrap_or(Month::January);
+    (total.div_euclid(12) as i32, month)
+}
+
+/// Whether `date` is the `n`th day of its month, negative from the end
+fn month_day(date: Date, n: i32) -> bool {
+    let length = days_in_month(date.year(), date.month()) as i32;
+    let day = if n < 0 { length + n + 1 } else { n };
+    date.day() as i32 == day
+}
+
+/// Every `weekday` of a month, or only the `ordinal`th (negative from the end)
+fn weekdays_in_month(year: i32, month: Month, weekday: Weekday, ordinal: Option<i32>) -> Vec<Date> {
+    let days: Vec<Date> = (1..=days_in_month(year, month))
+        .filter_map(|day| Date::from_calendar_date(year, month, day).ok())
+        .filter(|date| date.weekday() == weekday)
+        .collect();
+    match ordinal {
+        None => days,
+        Some(n) if n > 0 => days.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => days.len().checked_sub(n.unsigned_abs() as usize)
+            .and_then(|index| days.get(index))
+            .copied()
+            .into_iter()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u8, day: u8) -> Date {
+        Date::from_calendar_date(year, Month::try_from(month).unwrap(), day).unwrap()
+    }
+
+    fn at(year: i32, month: u8, date: u8, hour: u8, minute: u8) -> PrimitiveDateTime {
+        day(year, month, date).with_hms(hour, minute, 0).unwrap()
+    }
+
+    fn hours(hours: i8) -> UtcOffset {
+        UtcOffset::from_hms(hours, 0, 0).unwrap()
+    }
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\n\
+        PRODID:-//Google Inc//Google Calendar 70.9054//EN\r\n\
+        VERSION:2.0\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        TZID:Europe/Berlin\r\n\
+        BEGIN:DAYLIGHT\r\n\
+        TZOFFSETFROM:+0100\r\n\
+        TZOFFSETTO:+0200\r\n\
+        TZNAME:CEST\r\n\
+        DTSTART:19700329T020000\r\n\
+        RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\n\
+        END:DAYLIGHT\r\n\
+        BEGIN:STANDARD\r\n\
+        TZOFFSETFROM:+0200\r\n\
+        TZOFFSETTO:+0100\r\n\
+        TZNAME:CET\r\n\
+        DTSTART:19701025T030000\r\n\
+        RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\n\
+        END:STANDARD\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART;TZID=Europe/Berlin:20261020T100000\r\n\
+        DTEND;TZID=Europe/Berlin:20261020T110000\r\n\
+        RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=6\r\n\
+        EXDATE;TZID=Europe/Berlin:20261022T100000\r\n\
+        DTSTAMP:20261018T090000Z\r\n\
+        ORGANIZER;CN=Alice Example:mailto:alice@example.com\r\n\
+        UID:abc123@google.com\r\n\
+        ATTENDEE;CUTYPE=INDIVIDUAL;ROLE=REQ-PARTICIPANT;PARTSTAT=ACCEPTED;CN=Alice \r\n \
+        Example;X-NUM-GUESTS=0:mailto:alice@example.com\r\n\
+        ATTENDEE;CUTYPE=INDIVIDUAL;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=\r\n \
+        TRUE;CN=\"Bob, the Builder\";X-NUM-GUESTS=0:mailto:bob@example.org\r\n\
+        SEQUENCE:2\r\n\
+        STATUS:CONFIRMED\r\n\
+        SUMMARY:Planning\\, Q4\r\n\
+        LOCATION:Room 4\\; 2nd floor\r\n\
+        DESCRIPTION:Agenda:\\n1. Budget\\n2. Hiring\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_invitation() {
+        let calendar = Calendar::parse(INVITE).unwrap();
+        assert_eq!(calendar.method, Some(Method::Request));
+        assert_eq!(calendar.events.len(), 1);
+
+        let event = calendar.event().unwrap();
+        assert_eq!(event.uid, "abc123@google.com");
+        assert_eq!(event.sequence, 2);
+        assert_eq!(event.title(), "Planning, Q4");
+        assert_eq!(event.location.as_deref(), Some("Room 4; 2nd floor"));
+        assert_eq!(event.description.as_deref(), Some("Agenda:\n1. Budget\n2. Hiring"));
+        assert!(!event.is_cancelled());
+
+        // October 20th is still summer time in Berlin
+        assert_eq!(event.start, Some(EventTime::DateTime(at(2026, 10, 20, 10, 0).assume_offset(hours(2)))));
+        assert_eq!(event.end, Some(EventTime::DateTime(at(2026, 10, 20, 9, 0).assume_utc())));
+
+        let organizer = event.organizer.as_ref().unwrap();
+        assert_eq!(organizer.email, "alice@example.com");
+        assert_eq!(organizer.display_name(), "Alice Example");
+
+        assert_eq!(event.attendees.len(), 2);
+        let bob = event.attendee("Bob@Example.org").unwrap();
+        assert_eq!(bob.name.as_deref(), Some("Bob, the Builder"));
+        assert_eq!(bob.status, PartStat::NeedsAction);
+        assert!(bob.rsvp);
+        assert_eq!(event.attendees[0].status, PartStat::Accepted);
+    }
+
+    #[test]
+    fn test_timezone_offsets() {
+        let calendar = Calendar::parse(INVITE).unwrap();
+        let berlin = &calendar.timezones[0];
+        assert_eq!(berlin.offset_at(at(2026, 1, 15, 12, 0)), Some(hours(1)));
+        assert_eq!(berlin.offset_at(at(2026, 3, 29, 1, 59)), Some(hours(1)));
+        assert_eq!(berlin.offset_at(at(2026, 3, 29, 3, 0)), Some(hours(2)));
+        assert_eq!(berlin.offset_at(at(2026, 10, 25, 3, 0)), Some(hours(1)));
+        assert_eq!(berlin.offset_at(at(1960, 6, 1, 12, 0)), Some(hours(1)));
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let calendar = Calendar::parse(INVITE).unwrap();
+        let event = calendar.event().unwrap();
+        let rule = event.recurrence.as_ref().unwrap();
+        assert_eq!(rule.describe(), "Every week on Tuesday, Thursday, 6 times");
+
+        // The 22nd is excluded, six occurrences in total
+        let next = event.next_occurrences(at(2026, 10, 1, 0, 0), 10);
+        assert_eq!(next, vec![
+            at(2026, 10, 20, 10, 0),
+            at(2026, 10, 27, 10, 0),
+            at(2026, 10, 29, 10, 0),
+            at(2026, 11, 3, 10, 0),
+            at(2026, 11, 5, 10, 0),
+        ]);
+
+        let monthly = Recurrence::parse("FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR;UNTIL=20270301").unwrap();
+        assert_eq!(monthly.describe(), "Every 2 months on the last Friday, until 2027-03-01");
+        let dates: Vec<Date> = monthly.occurrences(at(2026, 10, 1, 9, 0)).map(|o| o.date()).collect();
+        assert_eq!(dates, vec![day(2026, 10, 30), day(2026, 12, 25), day(2027, 2, 26)]);
+
+        let month_end = Recurrence::parse("FREQ=MONTHLY;BYMONTHDAY=31;COUNT=3").unwrap();
+        let dates: Vec<Date> = month_end.occurrences(at(2026, 1, 31, 9, 0)).map(|o| o.date()).collect();
+        assert_eq!(dates, vec![day(2026, 1, 31), day(2026, 3, 31), day(2026, 5, 31)]);
+
+        assert!(Recurrence::parse("FREQ=HOURLY").is_none());
+        assert!(Recurrence::parse("INTERVAL=2").is_none());
+    }
+
+    #[test]
+    fn test_all_day_and_floating_times() {
+        let text = "BEGIN:VCALENDAR\nVERSION:2.0\nMETHOD:CANCEL\nBEGIN:VEVENT\n\
+            UID:offsite\nDTSTART;VALUE=DATE:20261102\nDURATION:P2D\nSTATUS:CANCELLED\nEND:VEVENT\n\
+            BEGIN:VEVENT\nUID:call\nDTSTART;TZID=Unknown/Zone:20261103T150000\nDURATION:PT45M\nEND:VEVENT\n\
+            END:VCALENDAR\n";
+        let calendar = Calendar::parse(text).unwrap();
+        assert_eq!(calendar.method, Some(Method::Cancel));
+
+        let offsite = &calendar.events[0];
+        assert!(offsite.is_cancelled());
+        assert_eq!(offsite.start, Some(EventTime::Date(day(2026, 11, 2))));
+        assert_eq!(offsite.end, Some(EventTime::Date(day(2026, 11, 4))));
+        assert!(offsite.start.unwrap().is_all_day());
+
+        let call = &calendar.events[1];
+        assert_eq!(call.start, Some(EventTime::Floating(at(2026, 11, 3, 15, 0))));
+        assert_eq!(call.end, Some(EventTime::Floating(at(2026, 11, 3, 15, 45))));
+        assert_eq!(call.title(), "Untitled event");
+    }
+
+    #[test]
+    fn test_malformed_calendars() {
+        assert!(Calendar::parse("BEGIN:VEVENT\nUID:x\nEND:VEVENT\n").is_err());
+        assert!(Calendar::parse("BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:x\n").is_err());
+        assert!(Calendar::parse("END:VCALENDAR\n").is_err());
+    }
+
+    #[test]
+    fn test_reply() {
+        let calendar = Calendar::parse(INVITE).unwrap();
+        let event = calendar.event().unwrap();
+        let reply = calendar.reply(event, "bob@example.org", PartStat::Tentative);
+
+        assert!(reply.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(reply.ends_with("END:VCALENDAR\r\n"));
+        assert!(reply.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert!(!reply.replace("\r\n", "").contains('\n'));
+
+        let parsed = Calendar::parse(&reply).unwrap();
+        assert_eq!(parsed.method, Some(Method::Reply));
+        assert_eq!(parsed.timezones.len(), 1);
+
+        let answer = parsed.event().unwrap();
+        assert_eq!(answer.uid, event.uid);
+        assert_eq!(answer.sequence, 2);
+        assert_eq!(answer.start, event.start);
+        assert_eq!(answer.summary, event.summary);
+        assert_eq!(answer.organizer, event.organizer);
+        assert_eq!(answer.attendees.len(), 1);
+        let bob = &answer.attendees[0];
+        assert_eq!(bob.email, "bob@example.org");
+        assert_eq!(bob.status, PartStat::Tentative);
+        assert_eq!(bob.name.as_deref(), Some("Bob, the Builder"));
+        assert!(!bob.rsvp);
+        assert!(answer.component.properties.iter().any(|property| property.name == "DTSTAMP"));
+        assert!(answer.recurrence.is_none());
+        assert!(answer.location.is_none());
+    }
+
+    #[test]
+    fn test_reply_message() {
+        let account = Account::new_gmail(
+            "bob@example.org".to_string(),
+            Some("Robert".to_string()),
+            crate::account::GmailOAuthConfig {
+                client_id: "client-id".to_string(),
+                client_secret: "client-secret".to_string(),
+                access_token: None,
+                refresh_token: None,
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        )
+        .unwrap();
+
+        let headers = MessageHeaders {
+            message_id: Some("<invite@google.com>".to_string()),
+            in_reply_to: None,
+            references: None,
+            subject: "Invitation: Planning, Q4".to_string(),
+            from: vec![EmailAddress { name: None, email: "calendar-notification@google.com".to_string() }],
+            to: vec![EmailAddress { name: None, email: "bob@example.org".to_string() }],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: Vec::new(),
+            date: None,
+            received_date: None,
+            importance: MessageImportance::Normal,
+            custom: Default::default(),
+        };
+        let mut invitation = Message::new(account.id, uuid::Uuid::new_v4(), headers);
+        invitation.add_part(text_part("1", "text/plain", MessagePartType::Text, b"You have been invited".to_vec()));
+        assert!(reply_message(&invitation, &account, PartStat::Accepted).is_err());
+
+        invitation.add_part(text_part("2", "text/calendar", MessagePartType::Calendar, INVITE.as_bytes().to_vec()));
+        let reply = reply_message(&invitation, &account, PartStat::Declined).unwrap();
+
+        assert_eq!(reply.headers.subject, "Declined: Planning, Q4");
+        assert_eq!(reply.headers.to[0].email, "alice@example.com");
+        assert_eq!(reply.headers.to[0].name.as_deref(), Some("Alice Example"));
+        assert_eq!(reply.headers.from[0].name.as_deref(), Some("Bob, the Builder"));
+        assert_eq!(reply.headers.in_reply_to.as_deref(), Some("<invite@google.com>"));
+
+        let calendar = Calendar::parse(&String::from_utf8_lossy(reply.calendar_content().unwrap())).unwrap();
+        assert_eq!(calendar.method, Some(Method::Reply));
+        assert_eq!(calendar.event().unwrap().attendees[0].status, PartStat::Declined);
+    }
+
+    #[test]
+    fn test_apply_updates_by_sequence() {
+        let invite = Calendar::parse(INVITE).unwrap();
+        let mut stored = Calendar::default();
+        assert_eq!(stored.apply(&invite).unwrap(), CalendarChange::Added);
+        assert_eq!(stored.timezones.len(), 1);
+
+        // Reading the file back gives the same event, without a method
+        let text = stored.to_ics();
+        assert!(!text.contains("METHOD:"));
+        let reread = Calendar::parse(&text).unwrap();
+        assert_eq!(reread.method, None);
+        assert_eq!(reread.event().unwrap().start, invite.event().unwrap().start);
+        assert_eq!(reread.event().unwrap().title(), "Planning, Q4");
+
+        let moved = INVITE.replace("SEQUENCE:2", "SEQUENCE:3").replace("T100000", "T140000");
+        let mut stored = reread;
+        assert_eq!(stored.apply(&Calendar::parse(&moved).unwrap()).unwrap(), CalendarChange::Updated);
+        assert_eq!(stored.events.len(), 1);
+        assert_eq!(stored.event().unwrap().sequence, 3);
+
+        // The original invitation is older than the stored update
+        assert_eq!(stored.apply(&invite).unwrap(), CalendarChange::Unchanged);
+        assert_eq!(stored.event().unwrap().sequence, 3);
+
+        let reply = Calendar::parse(&invite.reply(invite.event().unwrap(), "bob@example.org", PartStat::Accepted)).unwrap();
+        assert!(stored.apply(&reply).is_err());
+    }
+
+    #[test]
+    fn test_apply_cancellations() {
+        let mut stored = Calendar::parse("BEGIN:VCALENDAR\r\nX-WR-CALNAME:Work\r\nBEGIN:VTODO\r\nUID:todo\r\nEND:VTODO\r\nEND:VCALENDAR\r\n").unwrap();
+        stored.apply(&Calendar::parse(INVITE).unwrap()).unwrap();
+
+        // One occurrence first, then the whole series
+        let occurrence = INVITE
+            .replace("METHOD:REQUEST", "METHOD:CANCEL")
+            .replace("RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=6\r\n", "RECURRENCE-ID;TZID=Europe/Berlin:20261027T100000\r\n");
+        assert_eq!(stored.apply(&Calendar::parse(&occurrence).unwrap()).unwrap(), CalendarChange::Removed);
+        let event = stored.event().unwrap();
+        assert_eq!(event.exceptions.len(), 2);
+        assert!(!event.next_occurrences(at(2026, 10, 1, 0, 0), 10).contains(&at(2026, 10, 27, 10, 0)));
+
+        let text = stored.to_ics();
+        assert!(text.contains("X-WR-CALNAME:Work\r\n"));
+        assert!(text.contains("BEGIN:VTODO\r\n"));
+        assert!(text.contains("EXDATE;TZID=Europe/Berlin:20261027T100000\r\n"));
+
+        let stale = INVITE.replace("METHOD:REQUEST", "METHOD:CANCEL").replace("SEQUENCE:2", "SEQUENCE:1");
+        assert_eq!(stored.apply(&Calendar::parse(&stale).unwrap()).unwrap(), CalendarChange::Unchanged);
+        assert_eq!(stored.events.len(), 1);
+
+        let series = INVITE.replace("METHOD:REQUEST", "METHOD:CANCEL");
+        assert_eq!(stored.apply(&Calendar::parse(&series).unwrap()).unwrap(), CalendarChange::Removed);
+        assert!(stored.events.is_empty());
+        assert_eq!(stored.apply(&Calendar::parse(&series).unwrap()).unwrap(), CalendarChange::Unchanged);
+    }
+
+    #[test]
+    fn test_fold() {
+        let line = format!("DESCRIPTION:{}", "ä".repeat(60));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert_eq!(parse_components(&format!("BEGIN:X\r\n{}END:X\r\n", folded)).unwrap()[0].properties[0].value, "ä".repeat(60));
+    }
+
+    #[test]
+    fn test_durations_and_offsets() {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1DT2H"), Some(Duration::hours(26)));
+        assert_eq!(parse_duration("-P1W"), Some(Duration::weeks(-1)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_offset("-0530"), UtcOffset::from_hms(-5, -30, 0).ok());
+        assert_eq!(parse_offset("0100"), None);
+    }
+}
@@ -6,17 +6,33 @@
 //! - Sync engines (IMAP, SMTP, POP3)
 //! - Search functionality (Tantivy full-text search)
 //! - Gmail-specific features (labels, XOAUTH2)
+//! - OpenPGP and S/MIME signing and encryption, Autocrypt
+//! - Sender authentication (Authentication-Results, ARC, DKIM)
+//! - Calendar invitations (iCalendar parsing, iTIP replies, .ics files and CalDAV)
 
 pub mod account;
+pub mod attachments;
+pub mod authenticity;
+pub mod calendar;
 pub mod error;
 pub mod mailbox;
 pub mod message;
+pub mod html;
+pub mod ical;
+pub mod links;
+pub mod sanitizer;
 pub mod storage;
 pub mod sync;
 pub mod search;
 pub mod gmail;
 pub mod config;
 pub mod crypto;
+pub mod dkim;
+pub mod openpgp;
+pub mod smime;
+pub mod source;
+pub mod autocrypt;
+pub mod secrets;
 pub mod threads;
 pub mod types;
 pub mod threading;
@@ -28,6 +44,15 @@ pub use mailbox::{Mailbox, MailboxType, MailboxFlags};
 pub use message::{Message, MessageFlags, MessagePart, Attachment};
 pub use storage::{Database, Cache};
 pub use config::Config;
+pub use secrets::SecretStore;
+pub use openpgp::{PgpKeyring, PgpStatus};
+pub use smime::{SmimeContext, SmimeStatus};
+pub use autocrypt::{AutocryptAccount, PeerState};
+pub use authenticity::{Authenticity, AuthenticityChecker, Verdict};
+pub use links::LinkChecker;
+pub use sanitizer::HtmlSanitizer;
+pub use source::MessageSource;
+pub use ical::{Calendar, CalendarChange, PartStat};
 pub use types::{MsgMeta, Thread};
 pub use threading::{group_into_threads, normalize_subject};
 
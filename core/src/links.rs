@@ -0,0 +1,420 @@
+//! Link safety checks for message HTML
+//!
+//! Links are unwrapped from known redirect services, then checked for
+//! anchor text naming a different site than the destination, look-alike
+//! internationalized domain names, bare IP addresses and blocked domains.
+
+use crate::config::SecurityConfig;
+use crate::html::{decode_entities, to_plain_text};
+use crate::sanitizer::{covers, parse_attributes, tag_end, DomainLists, DomainPolicy};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use url::{Host, Url};
+
+/// Redirect services: host, path prefix and the query parameters that may
+/// hold the destination
+const REDIRECTORS: &[(&str, &str, &[&str])] = &[
+    ("google.com", "/url", &["q", "url"]),
+    ("safelinks.protection.outlook.com", "/", &["url"]),
+    ("l.facebook.com", "/l.php", &["u"]),
+    ("lm.facebook.com", "/l.php", &["u"]),
+    ("youtube.com", "/redirect", &["q"]),
+    ("linkedin.com", "/redir/redirect", &["url"]),
+    ("slack-redir.net", "/link", &["url"]),
+    ("out.reddit.com", "/", &["url"]),
+    ("steamcommunity.com", "/linkfilter/", &["url", "u"]),
+    ("urldefense.proofpoint.com", "/v2/url", &["u"]),
+];
+
+/// Nested redirects followed at most
+const MAX_REDIRECTS: usize = 5;
+
+/// Cyrillic letters that look like Latin ones
+const CYRILLIC_CONFUSABLES: &str = "аеһіјӏорсѕухԁԛԝ";
+
+/// Greek letters that look like Latin ones
+const GREEK_CONFUSABLES: &str = "αικνορτυχ";
+
+/// Why a link is suspicious
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkWarning {
+    /// The anchor text shows a web address on another site
+    MismatchedText { shown: String, actual: String },
+    /// The domain mixes scripts or is spelled with look-alike letters
+    Homograph { host: String, unicode: String },
+    /// The destination is an IP address rather than a name
+    IpAddress { host: String },
+    /// The destination is on a blocked domain
+    BlockedDomain { host: String },
+}
+
+impl fmt::Display for LinkWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MismatchedText { shown, actual } => {
+                write!(f, "The link text shows {} but the link leads to {}", shown, actual)
+            }
+            Self::Homograph { host, unicode } => {
+                write!(f, "{} ({}) is spelled with look-alike characters", unicode, host)
+            }
+            Self::IpAddress { host } => write!(f, "The link leads to the bare address {}", host),
+            Self::BlockedDomain { host } => write!(f, "{} is on your list of blocked domains", host),
+        }
+    }
+}
+
+/// Result of checking one link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkCheck {
+    /// URL as written in the message
+    pub href: String,
+    /// Destination once known redirectors are unwrapped
+    pub target: String,
+    /// Anchor text
+    pub text: String,
+    /// Reasons to confirm before opening
+    pub warnings: Vec<LinkWarning>,
+}
+
+impl LinkCheck {
+    /// Whether the link should be confirmed before it is opened
+    pub fn is_suspicious(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    /// Whether the link went through a redirect service
+    pub fn is_redirected(&self) -> bool {
+        self.target != self.href
+    }
+}
+
+/// Link checker, configured from [`SecurityConfig`]
+#[derive(Debug, Clone)]
+pub struct LinkChecker {
+    domains: Arc<DomainLists>,
+}
+
+impl LinkChecker {
+    /// Create a checker following `config`
+    pub fn new(config: &SecurityConfig) -> Self {
+        Self { domains: Arc::new(DomainLists::new(&config.trusted_domains, &config.blocked_domains)) }
+    }
+
+    /// Check a link whose anchor text is `text`
+    pub fn check(&self, href: &str, text: &str) -> LinkCheck {
+        let href = href.trim();
+        let target = unwrap_redirects(href);
+        let text = text.trim();
+        let mut warnings = Vec::new();
+
+        if let Some(url) = Url::parse(&target).ok().filter(|url| matches!(url.scheme(), "http" | "https")) {
+            match url.host() {
+                Some(Host::Domain(host)) => {
+                    let host = host.trim_end_matches('.').to_ascii_lowercase();
+                    if let Some(shown) = shown_host(text) {
+                        if !same_site(&shown, &host) {
+                            warnings.push(LinkWarning::MismatchedText { shown, actual: display_host(&host) });
+                        }
+                    }
+                    if let Some(unicode) = homograph(&host) {
+                        warnings.push(LinkWarning::Homograph { host: host.clone(), unicode });
+                    }
+                    if self.domains.policy(&host) == DomainPolicy::Blocked {
+                        warnings.push(LinkWarning::BlockedDomain { host });
+                    }
+                }
+                Some(host) => warnings.push(LinkWarning::IpAddress { host: host.to_string() }),
+                None => {}
+            }
+        }
+
+        LinkCheck { href: href.to_string(), target, text: text.to_string(), warnings }
+    }
+
+    /// Check every link of sanitized HTML, keyed by [`normalize_url`] of
+    /// the link
+    ///
+    /// When one URL appears several times, a suspicious occurrence wins.
+    pub fn check_html(&self, html: &str) -> HashMap<String, LinkCheck> {
+        let mut links: HashMap<String, LinkCheck> = HashMap::new();
+        let mut rest = html;
+
+        while let Some(start) = rest.find("<a") {
+            let tag = &rest[start..];
+            if tag[2..].starts_with(|c: char| c.is_ascii_alphanumeric()) {
+                rest = &tag[2..];
+                continue;
+            }
+            let end = tag_end(tag);
+            let href = parse_attributes(&tag[2..end]).into_iter()
+                .find(|(name, _)| name == "href")
+                .map(|(_, value)| decode_entities(&value));
+            let content = &tag[end..];
+            let close = content.find("</a>").unwrap_or(content.len());
+            rest = &content[close..];
+
+            let Some(href) = href else {
+                continue;
+            };
+            let check = self.check(&href, &to_plain_text(&content[..close]));
+            let key = normalize_url(&href);
+            if links.get(&key).is_none_or(|existing| !existing.is_suspicious()) {
+                links.insert(key, check);
+            }
+        }
+        links
+    }
+}
+
+impl Default for LinkChecker {
+    fn default() -> Self {
+        Self::new(&SecurityConfig::default())
+    }
+}
+
+/// URL in the form a browser reports it, for matching clicked links
+pub fn normalize_url(url: &str) -> String {
+    Url::parse(url.trim()).map(String::from).unwrap_or_else(|_| url.trim().to_string())
+}
+
+/// Destination of a link through known redirect services
+pub fn unwrap_redirects(url: &str) -> String {
+    let mut current = url.trim().to_string();
+    for _ in 0..MAX_REDIRECTS {
+        match Url::parse(&current).ok().and_then(|url| redirect_target(&url)) {
+            Some(target) => current = target,
+            None => break,
+        }
+    }
+    current
+}
+
+/// Destination of a single redirect, if `url` is one
+fn redirect_target(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let path = url.path();
+
+    // Proofpoint v3 embeds the URL in the path: /v3/__https://example.com__;...
+    if covers("urldefense.com", &host) {
+        let embedded = path.strip_prefix("/v3/__")?;
+        let target = &embedded[..embedded.find("__")?];
+        return (!target.contains('*')).then(|| target.to_string()).filter(|target| is_web_url(target));
+    }
+
+    let (_, _, params) = REDIRECTORS.iter()
+        .find(|(domain, prefix, _)| covers(domain, &host) && path.starts_with(prefix))?;
+    let value = url.query_pairs()
+        .find(|(name, _)| params.contains(&name.as_ref()))
+        .map(|(_, value)| value.into_owned())?;
+
+    // Proofpoint v2 escapes the URL with - for % and _ for /
+    let target = if covers("urldefense.proofpoint.com", &host) {
+        let escaped = value.replace('-', "%").replace('_', "/");
+        Url::parse(&format!("http://x/?u={}", escaped)).ok()?
+            .query_pairs()
+            .next()
+            .map(|(_, value)| value.into_owned())?
+    } else {
+        value
+    };
+    is_web_url(&target).then_some(target)
+}
+
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+}
+
+/// Host named by anchor text that reads as a web address
+fn shown_host(text: &str) -> Option<String> {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '(' | ')' | '[' | ']' | '"' | '\'' | ',' | '.'));
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let candidate = if lower.starts_with("http://") || lower.starts_with("https://") {
+        text.to_string()
+    } else {
+        let name = text.split(['/', '?', '#']).next().unwrap_or_default();
+        if !looks_like_domain(name) {
+            return None;
+        }
+        format!("http://{}", text)
+    };
+
+    let host = Url::parse(&candidate).ok()?.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    host.contains('.').then(|| display_host(&host))
+}
+
+/// Whether text such as `example.com` or `www.shop.example` names a domain
+fn looks_like_domain(name: &str) -> bool {
+    let labels: Vec<&str> = name.split('.').collect();
+    labels.len() >= 2
+        && labels.iter().all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-'))
+        && labels.last().is_some_and(|tld| tld.chars().count() >= 2 && tld.chars().all(char::is_alphabetic))
+}
+
+/// Whether two hosts belong to the same site, ignoring `www.` and subdomains
+fn same_site(shown: &str, actual: &str) -> bool {
+    let shown = ascii_host(shown);
+    let shown = shown.strip_prefix("www.").unwrap_or(&shown);
+    let actual = actual.strip_prefix("www.").unwrap_or(actual);
+    covers(shown, actual) || covers(actual, shown)
+}
+
+/// ASCII form of a host that may be written in Unicode
+fn ascii_host(host: &str) -> String {
+    Url::parse(&format!("http://{}/", host)).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| host.to_string())
+}
+
+/// Unicode form of an ASCII host, as users read it
+fn display_host(host: &str) -> String {
+    let (unicode, result) = idna::domain_to_unicode(host);
+    if result.is_ok() { unicode } else { host.to_string() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Other,
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00c0}'..='\u{024f}' | '\u{1e00}'..='\u{1eff}' => Some(Script::Latin),
+        '\u{0400}'..='\u{052f}' => Some(Script::Cyrillic),
+        '\u{0370}'..='\u{03ff}' | '\u{1f00}'..='\u{1fff}' => Some(Script::Greek),
+        c if c.is_alphabetic() => Some(Script::Other),
+        _ => None,
+    }
+}
+
+/// Unicode form of `host` when one of its labels mixes Latin, Cyrillic or
+/// Greek letters, or passes for Latin under a Latin top-level domain
+fn homograph(host: &str) -> Option<String> {
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+    let unicode = display_host(host);
+    let labels: Vec<&str> = unicode.split('.').collect();
+    let latin_tld = labels.last().is_some_and(|tld| tld.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    let suspicious = labels.iter().any(|label| {
+        let scripts: Vec<Script> = label.chars().filter_map(script).collect();
+        let latin = scripts.contains(&Script::Latin);
+        let lookalike = scripts.contains(&Script::Cyrillic) || scripts.contains(&Script::Greek);
+        let mixed = [latin, scripts.contains(&Script::Cyrillic), scripts.contains(&Script::Greek)]
+            .iter()
+            .filter(|present| **present)
+            .count() > 1;
+        let confusable = latin_tld
+            && lookalike
+            && !latin
+            && label.chars()
+                .filter(|c| script(*c).is_some())
+                .all(|c| CYRILLIC_CONFUSABLES.contains(c) || GREEK_CONFUSABLES.contains(c));
+        mixed || confusable
+    });
+    suspicious.then_some(unicode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatched_text() {
+        let checker = LinkChecker::default();
+
+        let check = checker.check("https://evil.example/login", "https://www.paypal.com/signin");
+        assert_eq!(check.warnings, vec![LinkWarning::MismatchedText {
+            shown: "www.paypal.com".to_string(),
+            actual: "evil.example".to_string(),
+        }]);
+        assert!(checker.check("https://evil.example/login", "PayPal.com").is_suspicious());
+
+        assert!(!checker.check("https://www.paypal.com/signin", "paypal.com").is_suspicious());
+        assert!(!checker.check("https://paypal.com/", "https://www.paypal.com").is_suspicious());
+        assert!(!checker.check("https://help.example.com/", "example.com/help").is_suspicious());
+        assert!(!checker.check("https://evil.example/", "Sign in to your account").is_suspicious());
+        assert!(!checker.check("https://evil.example/", "e.g.").is_suspicious());
+        assert!(!checker.check("mailto:bob@example.com", "example.com").is_suspicious());
+    }
+
+    #[test]
+    fn test_homographs() {
+        let checker = LinkChecker::default();
+
+        // "аpple.com" with a Cyrillic а
+        let check = checker.check("https://xn--pple-43d.com/", "Apple");
+        assert_eq!(check.warnings, vec![LinkWarning::Homograph {
+            host: "xn--pple-43d.com".to_string(),
+            unicode: "аpple.com".to_string(),
+        }]);
+        // "аррӏе.com", all Cyrillic
+        assert!(checker.check("https://xn--80ak6aa92e.com/", "").is_suspicious());
+        // Mismatch against the Unicode form the user would see
+        assert!(checker.check("https://xn--pple-43d.com/", "apple.com").warnings.len() == 2);
+
+        assert!(!checker.check("https://xn--mnchen-3ya.de/", "münchen.de").is_suspicious());
+        assert!(!checker.check("https://xn--e1afmkfd.xn--p1ai/", "").is_suspicious());
+    }
+
+    #[test]
+    fn test_redirects() {
+        let checker = LinkChecker::new(&SecurityConfig {
+            blocked_domains: vec!["evil.example".to_string()],
+            ..SecurityConfig::default()
+        });
+
+        let check = checker.check("https://www.google.com/url?q=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc&sa=D", "example.com");
+        assert_eq!(check.target, "https://example.com/a?b=c");
+        assert!(check.is_redirected());
+        assert!(!check.is_suspicious());
+
+        assert_eq!(
+            unwrap_redirects("https://eur01.safelinks.protection.outlook.com/?url=https%3A%2F%2Fl.facebook.com%2Fl.php%3Fu%3Dhttps%253A%252F%252Fexample.org%252F&data=x"),
+            "https://example.org/"
+        );
+        assert_eq!(
+            unwrap_redirects("https://urldefense.proofpoint.com/v2/url?u=https-3A__example.com_path&d=x"),
+            "https://example.com/path"
+        );
+        assert_eq!(
+            unwrap_redirects("https://urldefense.com/v3/__https://example.com/path__;!!abc$"),
+            "https://example.com/path"
+        );
+        assert_eq!(unwrap_redirects("https://www.google.com/url?q=javascript:alert(1)"), "https://www.google.com/url?q=javascript:alert(1)");
+        assert_eq!(unwrap_redirects("https://example.com/url?q=https://evil.example"), "https://example.com/url?q=https://evil.example");
+
+        let check = checker.check("https://www.google.com/url?q=https://login.evil.example/", "Your bank");
+        assert_eq!(check.warnings, vec![LinkWarning::BlockedDomain { host: "login.evil.example".to_string() }]);
+        assert_eq!(
+            checker.check("http://192.168.1.7/login", "").warnings,
+            vec![LinkWarning::IpAddress { host: "192.168.1.7".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_check_html() {
+        let checker = LinkChecker::default();
+        let html = "<p>Visit <a href=\"https://example.com\" rel=\"noopener noreferrer\">example.com</a> or \
+                    <a href=\"https://evil.example/?a=1&amp;b=2\" rel=\"noopener noreferrer\"><b>https://bank.example</b></a>\
+                    <abbr title=\"x\">X</abbr><a rel=\"noopener noreferrer\">no link</a>\
+                    <a href=\"https://example.com/\">https://other.example</a></p>";
+
+        let links = checker.check_html(html);
+        assert_eq!(links.len(), 2);
+
+        let evil = &links[&normalize_url("https://evil.example/?a=1&b=2")];
+        assert_eq!(evil.text, "https://bank.example");
+        assert!(evil.is_suspicious());
+        // The suspicious occurrence of a repeated link is kept
+        assert!(links["https://example.com/"].is_suspicious());
+    }
+}
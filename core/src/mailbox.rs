@@ -27,6 +27,10 @@ pub enum MailboxType {
     Custom,
     /// Gmail label
     Label,
+    /// Virtual mailbox holding every message (`\All`)
+    All,
+    /// Virtual mailbox holding flagged messages (`\Flagged`)
+    Flagged,
 }
 
 impl std::fmt::Display for MailboxType {
@@ -40,10 +44,28 @@ impl std::fmt::Display for MailboxType {
             MailboxType::Archive => write!(f, "Archive"),
             MailboxType::Custom => write!(f, "Custom"),
             MailboxType::Label => write!(f, "Label"),
+            MailboxType::All => write!(f, "All"),
+            MailboxType::Flagged => write!(f, "Flagged"),
         }
     }
 }
 
+impl MailboxType {
+    /// Determine the mailbox type from RFC 6154 special-use flags
+    pub fn from_special_use(flags: &[MailboxFlags]) -> Option<Self> {
+        flags.iter().find_map(|flag| match flag {
+            MailboxFlags::Sent => Some(MailboxType::Sent),
+            MailboxFlags::Drafts => Some(MailboxType::Drafts),
+            MailboxFlags::Trash => Some(MailboxType::Trash),
+            MailboxFlags::Junk => Some(MailboxType::Spam),
+            MailboxFlags::Archive => Some(MailboxType::Archive),
+            MailboxFlags::All => Some(MailboxType::All),
+            MailboxFlags::Flagged => Some(MailboxType::Flagged),
+            _ => None,
+        })
+    }
+}
+
 /// Mailbox flags
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -76,6 +98,14 @@ pub enum MailboxFlags {
     Sent,
     /// Trash flag (trash mailbox)
     Trash,
+    /// Important flag (important messages)
+    Important,
+    /// Subscribed flag (LIST-EXTENDED)
+    Subscribed,
+    /// Non-existent flag (hierarchy placeholder that cannot hold messages)
+    NonExistent,
+    /// Remote flag (mailbox lives on another server)
+    Remote,
 }
 
 impl std::fmt::Display for MailboxFlags {
@@ -95,6 +125,37 @@ impl std::fmt::Display for MailboxFlags {
             MailboxFlags::Junk => write!(f, "Junk"),
             MailboxFlags::Sent => write!(f, "Sent"),
             MailboxFlags::Trash => write!(f, "Trash"),
+            MailboxFlags::Important => write!(f, "Important"),
+            MailboxFlags::Subscribed => write!(f, "Subscribed"),
+            MailboxFlags::NonExistent => write!(f, "NonExistent"),
+            MailboxFlags::Remote => write!(f, "Remote"),
+        }
+    }
+}
+
+impl MailboxFlags {
+    /// Parse an IMAP LIST attribute such as `\HasChildren` or `\Sent`
+    pub fn from_imap_attribute(attribute: &str) -> Option<Self> {
+        let name = attribute.trim().trim_start_matches('\\').to_ascii_lowercase();
+        match name.as_str() {
+            "noselect" => Some(MailboxFlags::NoSelect),
+            "noinferiors" => Some(MailboxFlags::NoInferiors),
+            "marked" => Some(MailboxFlags::Marked),
+            "unmarked" => Some(MailboxFlags::Unmarked),
+            "haschildren" => Some(MailboxFlags::HasChildren),
+            "hasnochildren" => Some(MailboxFlags::HasNoChildren),
+            "all" | "allmail" => Some(MailboxFlags::All),
+            "archive" => Some(MailboxFlags::Archive),
+            "drafts" => Some(MailboxFlags::Drafts),
+            "flagged" | "starred" => Some(MailboxFlags::Flagged),
+            "junk" | "spam" => Some(MailboxFlags::Junk),
+            "sent" => Some(MailboxFlags::Sent),
+            "trash" => Some(MailboxFlags::Trash),
+            "important" => Some(MailboxFlags::Important),
+            "subscribed" => Some(MailboxFlags::Subscribed),
+            "nonexistent" => Some(MailboxFlags::NonExistent),
+            "remote" => Some(MailboxFlags::Remote),
+            _ => None,
         }
     }
 }
@@ -108,6 +169,9 @@ pub struct MailboxAttributes {
     pub permissions: Vec<String>,
     /// Mailbox capabilities
     pub capabilities: Vec<String>,
+    /// Hierarchy delimiter reported by the server
+    #[serde(default)]
+    pub delimiter: Option<char>,
 }
 
 /// Mailbox statistics
@@ -180,6 +244,7 @@ impl Mailbox {
                 flags: vec![],
                 permissions: vec![],
                 capabilities: vec![],
+                delimiter: None,
             },
             stats: MailboxStats::default(),
             settings: HashMap::new(),
@@ -336,12 +401,15 @@ impl Mailbox {
                 | MailboxType::Trash
                 | MailboxType::Spam
                 | MailboxType::Archive
+                | MailboxType::All
+                | MailboxType::Flagged
         )
     }
 
     /// Check if this mailbox can be selected
     pub fn can_select(&self) -> bool {
         !self.attributes.flags.contains(&MailboxFlags::NoSelect)
+            && !self.attributes.flags.contains(&MailboxFlags::NonExistent)
     }
 
     /// Check if this mailbox has children
@@ -438,6 +506,29 @@ impl Mailbox {
         descendants
     }
 
+    /// Build the full name of a child mailbox
+    pub fn child_name(&self, leaf_name: &str) -> AsgardResult<String> {
+        if self.has_flag(MailboxFlags::NoInferiors) {
+            return Err(AsgardError::validation(format!("Mailbox {} cannot have children", self.display_name)));
+        }
+        let delimiter = self.attributes.delimiter
+            .ok_or_else(|| AsgardError::validation("Mailbox hierarchy is flat"))?;
+        validate_leaf_name(leaf_name, Some(delimiter))?;
+
+        Ok(format!("{}{}{}", self.name, delimiter, leaf_name))
+    }
+
+    /// Build the full name of this mailbox with its last segment replaced
+    pub fn renamed_name(&self, leaf_name: &str) -> AsgardResult<String> {
+        let delimiter = self.attributes.delimiter;
+        validate_leaf_name(leaf_name, delimiter)?;
+
+        match delimiter.and_then(|d| self.name.rfind(d)) {
+            Some(index) => Ok(format!("{}{}", &self.name[..index + 1], leaf_name)),
+            None => Ok(leaf_name.to_string()),
+        }
+    }
+
     /// Validate the mailbox
     pub fn validate(&self) -> AsgardResult<()> {
         if self.name.is_empty() {
@@ -452,6 +543,29 @@ impl Mailbox {
     }
 }
 
+/// Validate a single mailbox name segment
+pub fn validate_leaf_name(leaf_name: &str, delimiter: Option<char>) -> AsgardResult<()> {
+    if leaf_name.trim().is_empty() {
+        return Err(AsgardError::validation("Mailbox name cannot be empty"));
+    }
+
+    if leaf_name.eq_ignore_ascii_case("INBOX") {
+        return Err(AsgardError::validation("INBOX is a reserved mailbox name"));
+    }
+
+    if let Some(delimiter) = delimiter {
+        if leaf_name.contains(delimiter) {
+            return Err(AsgardError::validation(format!("Mailbox name cannot contain '{}'", delimiter)));
+        }
+    }
+
+    if leaf_name.chars().any(|c| c.is_control() || c == '*' || c == '%') {
+        return Err(AsgardError::validation("Mailbox name contains invalid characters"));
+    }
+
+    Ok(())
+}
+
 /// Mailbox hierarchy builder
 pub struct MailboxHierarchy {
     mailboxes: Vec<Mailbox>,
@@ -475,6 +589,72 @@ impl MailboxHierarchy {
         &self.mailboxes
     }
 
+    /// Get a mailbox by ID
+    pub fn get_mailbox(&self, mailbox_id: Uuid) -> Option<&Mailbox> {
+        self.mailboxes.iter().find(|m| m.id == mailbox_id)
+    }
+
+    /// Rename a mailbox and rewrite the names of all its descendants
+    ///
+    /// Returns every mailbox that changed so callers can persist them.
+    pub fn rename_mailbox(
+        &mut self,
+        mailbox_id: Uuid,
+        new_name: String,
+        display_name: String,
+    ) -> AsgardResult<Vec<Mailbox>> {
+        let mailbox = self.get_mailbox(mailbox_id)
+            .ok_or_else(|| AsgardError::not_found(format!("Mailbox not found: {}", mailbox_id)))?;
+        let old_name = mailbox.name.clone();
+        let delimiter = mailbox.attributes.delimiter;
+        let descendant_ids: Vec<Uuid> = mailbox.descendants(&self.mailboxes)
+            .iter()
+            .map(|m| m.id)
+            .collect();
+
+        let mut changed = Vec::new();
+        for mailbox in self.mailboxes.iter_mut() {
+            if mailbox.id == mailbox_id {
+                mailbox.name = new_name.clone();
+                mailbox.display_name = display_name.clone();
+            } else if descendant_ids.contains(&mailbox.id) {
+                let Some(delimiter) = delimiter else { continue };
+                let old_prefix = format!("{}{}", old_name, delimiter);
+                if let Some(rest) = mailbox.name.strip_prefix(&old_prefix) {
+                    mailbox.name = format!("{}{}{}", new_name, delimiter, rest);
+                } else {
+                    continue;
+                }
+            } else {
+                continue;
+            }
+            mailbox.updated_at = OffsetDateTime::now_utc();
+            changed.push(mailbox.clone());
+        }
+
+        Ok(changed)
+    }
+
+    /// Remove a mailbox and all its descendants
+    ///
+    /// The removed mailboxes are returned deepest first, which is the order
+    /// servers expect when deleting a hierarchy.
+    pub fn remove_mailbox(&mut self, mailbox_id: Uuid) -> Vec<Mailbox> {
+        let Some(mailbox) = self.get_mailbox(mailbox_id) else {
+            return Vec::new();
+        };
+
+        let mut removed: Vec<Mailbox> = mailbox.descendants(&self.mailboxes)
+            .into_iter()
+            .cloned()
+            .collect();
+        removed.push(mailbox.clone());
+        removed.sort_by_key(|m| std::cmp::Reverse(m.hierarchy_level(&self.mailboxes)));
+
+        self.mailboxes.retain(|m| !removed.iter().any(|r| r.id == m.id));
+        removed
+    }
+
     /// Get root mailboxes (no parent)
     pub fn root_mailboxes(&self) -> Vec<&Mailbox> {
         self.mailboxes
@@ -651,4 +831,61 @@ mod tests {
         mailbox.remove_flag(MailboxFlags::HasChildren);
         assert!(!mailbox.has_flag(MailboxFlags::HasChildren));
     }
+
+    #[test]
+    fn test_rename_updates_descendants() {
+        let account_id = Uuid::new_v4();
+        let mut hierarchy = MailboxHierarchy::new();
+
+        let mut projects = Mailbox::new(account_id, "Projects".to_string(), None, MailboxType::Custom, None);
+        projects.attributes.delimiter = Some('/');
+        let mut asgard = Mailbox::new(account_id, "Projects/Asgard".to_string(), Some("Asgard".to_string()), MailboxType::Custom, Some(projects.id));
+        asgard.attributes.delimiter = Some('/');
+        let mut design = Mailbox::new(account_id, "Projects/Asgard/Design".to_string(), Some("Design".to_string()), MailboxType::Custom, Some(asgard.id));
+        design.attributes.delimiter = Some('/');
+        let design_id = design.id;
+        let projects_id = projects.id;
+
+        hierarchy.add_mailbox(projects);
+        hierarchy.add_mailbox(asgard);
+        hierarchy.add_mailbox(design);
+
+        let new_name = hierarchy.get_mailbox(projects_id).unwrap().renamed_name("Work").unwrap();
+        assert_eq!(new_name, "Work");
+
+        let changed = hierarchy.rename_mailbox(projects_id, new_name, "Work".to_string()).unwrap();
+        assert_eq!(changed.len(), 3);
+        assert_eq!(hierarchy.get_mailbox(design_id).unwrap().name, "Work/Asgard/Design");
+        assert_eq!(hierarchy.get_mailbox(design_id).unwrap().display_name, "Design");
+
+        let removed = hierarchy.remove_mailbox(projects_id);
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed[0].id, design_id);
+        assert!(hierarchy.mailboxes().is_empty());
+    }
+
+    #[test]
+    fn test_child_name_validation() {
+        let account_id = Uuid::new_v4();
+        let mut parent = Mailbox::new(account_id, "INBOX".to_string(), None, MailboxType::Inbox, None);
+        parent.attributes.delimiter = Some('.');
+
+        assert_eq!(parent.child_name("Receipts").unwrap(), "INBOX.Receipts");
+        assert!(parent.child_name("a.b").is_err());
+        assert!(parent.child_name("  ").is_err());
+
+        parent.add_flag(MailboxFlags::NoInferiors);
+        assert!(parent.child_name("Receipts").is_err());
+    }
+
+    #[test]
+    fn test_special_use_flags() {
+        assert_eq!(MailboxFlags::from_imap_attribute("\\Sent"), Some(MailboxFlags::Sent));
+        assert_eq!(MailboxFlags::from_imap_attribute("\\HasNoChildren"), Some(MailboxFlags::HasNoChildren));
+        assert_eq!(MailboxFlags::from_imap_attribute("\\X-Unknown"), None);
+
+        let flags = vec![MailboxFlags::HasNoChildren, MailboxFlags::Junk];
+        assert_eq!(MailboxType::from_special_use(&flags), Some(MailboxType::Spam));
+        assert_eq!(MailboxType::from_special_use(&[MailboxFlags::HasChildren]), None);
+    }
 }
@@ -72,10 +72,24 @@ pub enum MessagePartType {
     Attachment,
     /// Embedded image
     EmbeddedImage,
+    /// Calendar data, e.g. a meeting invitation
+    Calendar,
     /// Other content
     Other,
 }
 
+impl MessagePartType {
+    /// Type of a body part with the given lowercase MIME type
+    pub fn for_mime_type(mime_type: &str) -> Self {
+        match mime_type {
+            "text/plain" => Self::Text,
+            "text/html" => Self::Html,
+            "text/calendar" => Self::Calendar,
+            _ => Self::Other,
+        }
+    }
+}
+
 /// Message part
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessagePart {
@@ -128,6 +142,30 @@ pub struct Attachment {
     pub created_at: OffsetDateTime,
 }
 
+impl Attachment {
+    /// Whether `content` matches the recorded hash
+    ///
+    /// Attachments recorded without a hash accept any content.
+    pub fn matches_hash(&self, content: &[u8]) -> bool {
+        self.content_hash.is_empty() || crate::crypto::DataIntegrity::sha256(content) == self.content_hash
+    }
+
+    /// Whether the attachment is an image that can be previewed
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+
+    /// Whether the attachment is a PDF document
+    pub fn is_pdf(&self) -> bool {
+        self.mime_type == "application/pdf" || self.filename.to_ascii_lowercase().ends_with(".pdf")
+    }
+
+    /// Whether the attachment is iCalendar data, e.g. an `invite.ics`
+    pub fn is_calendar(&self) -> bool {
+        self.mime_type == "text/calendar" || self.mime_type == "application/ics"
+    }
+}
+
 /// Email address
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EmailAddress {
@@ -207,6 +245,9 @@ pub struct Message {
     pub thread_id: Option<Uuid>,
     /// Conversation ID (for Gmail conversations)
     pub conversation_id: Option<String>,
+    /// Stable Gmail message ID (X-GM-MSGID)
+    #[serde(default)]
+    pub gmail_message_id: Option<u64>,
     /// Message size in bytes
     pub size: usize,
     /// Raw message content
@@ -240,6 +281,7 @@ impl Message {
             attachments: vec![],
             thread_id: None,
             conversation_id: None,
+            gmail_message_id: None,
             size: 0,
             raw_content: None,
             created_at: OffsetDateTime::now_utc(),
@@ -248,6 +290,103 @@ impl Message {
         }
     }
 
+    /// Parse an RFC 5322 message
+    ///
+    /// Text and HTML bodies become parts, everything with a filename or an
+    /// attachment disposition becomes an attachment.
+    pub fn from_rfc822(account_id: Uuid, mailbox_id: Uuid, raw: &[u8]) -> AsgardResult<Self> {
+        use mailparse::MailHeaderMap;
+
+        let parsed = mailparse::parse_mail(raw)?;
+        let header = |name: &str| parsed.headers.get_first_value(name);
+        let addresses = |name: &str| {
+            header(name).map(|value| parse_address_list(&value)).unwrap_or_default()
+        };
+        let date = header("Date")
+            .and_then(|value| mailparse::dateparse(&value).ok())
+            .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok());
+        let importance = match header("Importance").or_else(|| header("X-Priority")).as_deref().map(str::trim) {
+            Some(value) if value.eq_ignore_ascii_case("high") || value.starts_with('1') || value.starts_with('2') => MessageImportance::High,
+            Some(value) if value.eq_ignore_ascii_case("low") || value.starts_with('4') || value.starts_with('5') => MessageImportance::Low,
+            _ => MessageImportance::Normal,
+        };
+
+        let headers = MessageHeaders {
+            message_id: header("Message-ID").map(|v| v.trim().to_string()),
+            in_reply_to: header("In-Reply-To").map(|v| v.trim().to_string()),
+            references: header("References").map(|v| v.trim().to_string()),
+            subject: header("Subject").unwrap_or_default(),
+            from: addresses("From"),
+            to: addresses("To"),
+            cc: addresses("Cc"),
+            bcc: addresses("Bcc"),
+            reply_to: addresses("Reply-To"),
+            date,
+            received_date: None,
+            importance,
+            custom: crate::authenticity::AUTHENTICATION_HEADERS.iter()
+                .filter_map(|name| header(*name).map(|value| (name.to_string(), value)))
+                .collect(),
+        };
+
+        let mut message = Self::new(account_id, mailbox_id, headers);
+        message.size = raw.len();
+        message.raw_content = Some(raw.to_vec());
+
+        let mut leaves = Vec::new();
+        collect_leaf_parts(&parsed, "", &mut leaves);
+        for (id, part) in leaves {
+            let disposition = part.get_content_disposition();
+            let filename = disposition.params.get("filename")
+                .or_else(|| part.ctype.params.get("name"))
+                .cloned();
+            let content = part.get_body_raw()?;
+            let mime_type = part.ctype.mimetype.to_ascii_lowercase();
+            let content_id = part.headers.get_first_value("Content-ID");
+            let is_attachment_disposition = disposition.disposition == mailparse::DispositionType::Attachment;
+            let inline_resource = is_inline_resource(&mime_type, is_attachment_disposition, content_id.is_some());
+
+            if is_attachment_part(&mime_type, is_attachment_disposition, filename.is_some(), content_id.is_some()) {
+                message.attachments.push(Attachment {
+                    id: Uuid::new_v4(),
+                    message_id: message.id,
+                    part_id: id,
+                    filename: filename.unwrap_or_else(|| "attachment".to_string()),
+                    mime_type,
+                    size: content.len(),
+                    content_hash: crate::crypto::DataIntegrity::sha256(&content),
+                    file_path: None,
+                    content: Some(content),
+                    created_at: OffsetDateTime::now_utc(),
+                });
+                continue;
+            }
+
+            let part_type = MessagePartType::for_mime_type(&mime_type);
+            // Store text decoded to UTF-8 so later readers need no charset
+            let content = match part_type {
+                MessagePartType::Text | MessagePartType::Html | MessagePartType::Calendar => part.get_body()?.into_bytes(),
+                _ => content,
+            };
+
+            message.parts.push(MessagePart {
+                id,
+                part_type,
+                mime_type,
+                disposition: inline_resource.then(|| "inline".to_string()),
+                filename,
+                size: content.len(),
+                encoding: part.headers.get_first_value("Content-Transfer-Encoding"),
+                content_id,
+                content_location: part.headers.get_first_value("Content-Location"),
+                content: Some(content),
+                children: vec![],
+            });
+        }
+
+        Ok(message)
+    }
+
     /// Get the message ID
     pub fn id(&self) -> Uuid {
         self.id
@@ -393,6 +532,28 @@ impl Message {
         None
     }
 
+    /// Get the calendar data of a meeting invitation or reply
+    ///
+    /// Looks for a `text/calendar` body part first, then for an attached
+    /// `.ics` file.
+    pub fn calendar_content(&self) -> Option<&[u8]> {
+        fn find(parts: &[MessagePart]) -> Option<&[u8]> {
+            parts.iter().find_map(|part| {
+                if part.part_type == MessagePartType::Calendar {
+                    part.content.as_deref()
+                } else {
+                    find(&part.children)
+                }
+            })
+        }
+
+        find(&self.parts).or_else(|| {
+            self.attachments.iter()
+                .find(|a| a.is_calendar())
+                .and_then(|a| a.content.as_deref())
+        })
+    }
+
     /// Add a flag to the message
     pub fn add_flag(&mut self, flag: MessageFlags) {
         if !self.flags.contains(&flag) {
@@ -507,6 +668,66 @@ impl Message {
         self.updated_at = OffsetDateTime::now_utc();
     }
 
+    /// Replace parts and attachments with those of a MIME entity, e.g. the
+    /// decrypted content of an encrypted message
+    pub fn replace_body(&mut self, entity: &[u8]) -> AsgardResult<()> {
+        let body = Self::from_rfc822(self.account_id, self.mailbox_id, entity)?;
+        self.parts = body.parts;
+        self.attachments = body.attachments;
+        for attachment in &mut self.attachments {
+            attachment.message_id = self.id;
+        }
+        Ok(())
+    }
+
+    /// IDs of text, HTML and calendar parts whose content has not been
+    /// downloaded
+    pub fn missing_body_parts(&self) -> Vec<String> {
+        fn collect(parts: &[MessagePart], missing: &mut Vec<String>) {
+            for part in parts {
+                if part.content.is_none() && matches!(part.part_type, MessagePartType::Text | MessagePartType::Html | MessagePartType::Calendar) {
+                    missing.push(part.id.clone());
+                }
+                collect(&part.children, missing);
+            }
+        }
+
+        let mut missing = Vec::new();
+        collect(&self.parts, &mut missing);
+        missing
+    }
+
+    /// Fill in downloaded content of the body part or attachment `part_id`
+    ///
+    /// Attachments recorded without a hash get one from the content.
+    /// Returns false when the message has no such part.
+    pub fn set_part_content(&mut self, part_id: &str, content: Vec<u8>) -> bool {
+        fn find<'a>(parts: &'a mut [MessagePart], part_id: &str) -> Option<&'a mut MessagePart> {
+            for part in parts {
+                if part.id == part_id {
+                    return Some(part);
+                }
+                if let Some(child) = find(&mut part.children, part_id) {
+                    return Some(child);
+                }
+            }
+            None
+        }
+
+        if let Some(part) = find(&mut self.parts, part_id) {
+            part.content = Some(content);
+        } else if let Some(attachment) = self.attachments.iter_mut().find(|a| a.part_id == part_id) {
+            if attachment.content_hash.is_empty() {
+                attachment.content_hash = crate::crypto::DataIntegrity::sha256(&content);
+            }
+            attachment.content = Some(content);
+        } else {
+            return false;
+        }
+        self.updated_at = OffsetDateTime::now_utc();
+        true
+    }
+
     /// Validate the message
     pub fn validate(&self) -> AsgardResult<()> {
         if self.headers.subject.is_empty() {
@@ -612,6 +833,157 @@ impl MessageThread {
     }
 }
 
+/// Parse an address header into email addresses, flattening groups
+fn parse_address_list(value: &str) -> Vec<EmailAddress> {
+    let Ok(list) = mailparse::addrparse(value) else {
+        return vec![];
+    };
+
+    let single = |info: &mailparse::SingleInfo| EmailAddress {
+        name: info.display_name.clone(),
+        email: info.addr.clone(),
+    };
+    list.iter()
+        .flat_map(|addr| match addr {
+            mailparse::MailAddr::Single(info) => vec![single(info)],
+            mailparse::MailAddr::Group(group) => group.addrs.iter().map(single).collect(),
+        })
+        .collect()
+}
+
+/// Collect the leaf parts of a MIME tree with their IMAP part numbers
+fn collect_leaf_parts<'a>(part: &'a mailparse::ParsedMail<'a>, id: &str, leaves: &mut Vec<(String, &'a mailparse::ParsedMail<'a>)>) {
+    if part.subparts.is_empty() {
+        let id = if id.is_empty() { "1" } else { id };
+        leaves.push((id.to_string(), part));
+        return;
+    }
+
+    for (index, child) in part.subparts.iter().enumerate() {
+        let child_id = if id.is_empty() {
+            (index + 1).to_string()
+        } else {
+            format!("{}.{}", id, index + 1)
+        };
+        collect_leaf_parts(child, &child_id, leaves);
+    }
+}
+
+/// Decoded content of the leaf part numbered `part_id` in a raw message
+pub(crate) fn extract_part(raw: &[u8], part_id: &str) -> AsgardResult<Option<Vec<u8>>> {
+    let parsed = mailparse::parse_mail(raw)?;
+    let mut leaves = Vec::new();
+    collect_leaf_parts(&parsed, "", &mut leaves);
+
+    match leaves.into_iter().find(|(id, _)| id == part_id) {
+        Some((_, part)) => Ok(Some(part.get_body_raw()?)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a part is an inline resource referenced through a cid: URL,
+/// which belongs to the body rather than the attachments
+pub(crate) fn is_inline_resource(mime_type: &str, attachment_disposition: bool, has_content_id: bool) -> bool {
+    has_content_id && !attachment_disposition && !mime_type.starts_with("text/")
+}
+
+/// Whether a leaf part is listed as an attachment
+pub(crate) fn is_attachment_part(mime_type: &str, attachment_disposition: bool, has_filename: bool, has_content_id: bool) -> bool {
+    !is_inline_resource(mime_type, attachment_disposition, has_content_id)
+        && (attachment_disposition || has_filename)
+}
+
+/// Decoded content of a single MIME entity, its headers followed by its body
+///
+/// Text is converted to UTF-8 like the parts of [`Message::from_rfc822`].
+pub(crate) fn decode_entity(entity: &[u8]) -> AsgardResult<Vec<u8>> {
+    let parsed = mailparse::parse_mail(entity)?;
+    let mut leaf = &parsed;
+    while let Some(child) = leaf.subparts.first() {
+        leaf = child;
+    }
+
+    if leaf.ctype.mimetype.to_ascii_lowercase().starts_with("text/") {
+        Ok(leaf.get_body()?.into_bytes())
+    } else {
+        Ok(leaf.get_body_raw()?)
+    }
+}
+
+/// Find the first entity of `mimetype` in a parsed MIME tree
+pub(crate) fn find_entity<'a>(part: &'a mailparse::ParsedMail<'a>, mimetype: &str) -> Option<&'a mailparse::ParsedMail<'a>> {
+    if part.ctype.mimetype.eq_ignore_ascii_case(mimetype) {
+        return Some(part);
+    }
+    part.subparts.iter().find_map(|child| find_entity(child, mimetype))
+}
+
+/// Body of a MIME entity, after the blank line ending its headers
+pub(crate) fn entity_body(raw: &[u8]) -> &[u8] {
+    for (index, window) in raw.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return &raw[index + 2..];
+        }
+        if window == b"\n\r" && raw.get(index + 2) == Some(&b'\n') {
+            return &raw[index + 3..];
+        }
+    }
+    &[]
+}
+
+/// Split a multipart body into its parts, byte for byte (RFC 2046 section 5.1.1)
+///
+/// The line break before each delimiter belongs to the delimiter, so it is
+/// not part of the preceding part.
+pub(crate) fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..].iter().position(|&b| b == b'\n')
+            .map(|pos| line_start + pos + 1)
+            .unwrap_or(body.len());
+        let line = &body[line_start..line_end];
+
+        if line.starts_with(delimiter) {
+            if let Some(part_start) = start {
+                let mut part_end = line_start;
+                if part_end > part_start && body[part_end - 1] == b'\n' {
+                    part_end -= 1;
+                    if part_end > part_start && body[part_end - 1] == b'\r' {
+                        part_end -= 1;
+                    }
+                }
+                parts.push(&body[part_start..part_end]);
+            }
+            if line[delimiter.len()..].starts_with(b"--") {
+                break;
+            }
+            start = Some(line_end);
+        }
+        line_start = line_end;
+    }
+
+    parts
+}
+
+/// Convert line endings to CRLF, as signed MIME content is hashed that way
+pub(crate) fn canonicalize(data: &[u8]) -> Vec<u8> {
+    let mut canonical = Vec::with_capacity(data.len() + data.len() / 40);
+    let mut previous = 0u8;
+    for &byte in data {
+        if byte == b'\n' && previous != b'\r' {
+            canonical.push(b'\r');
+        }
+        canonical.push(byte);
+        previous = byte;
+    }
+    canonical
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -726,4 +1098,33 @@ mod tests {
         let message = Message::new(account_id, mailbox_id, headers);
         assert!(message.validate().is_err());
     }
+
+    #[test]
+    fn test_extract_attachment_part() {
+        let raw = b"From: alice@example.com\r\n\
+            Subject: Scan\r\n\
+            Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+            \r\n\
+            --b1\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            See attached\r\n\
+            --b1\r\n\
+            Content-Type: application/pdf; name=\"scan.pdf\"\r\n\
+            Content-Disposition: attachment; filename=\"scan.pdf\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            JVBERi0xLjQ=\r\n\
+            --b1--\r\n";
+        let message = Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), raw).unwrap();
+        let attachment = &message.attachments[0];
+        assert!(attachment.is_pdf());
+        assert!(!attachment.is_image());
+
+        let content = extract_part(raw, &attachment.part_id).unwrap().unwrap();
+        assert_eq!(content, b"%PDF-1.4");
+        assert!(attachment.matches_hash(&content));
+        assert!(!attachment.matches_hash(b"%PDF-1.5"));
+        assert!(extract_part(raw, "7").unwrap().is_none());
+    }
 }
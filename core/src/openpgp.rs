@@ -0,0 +1,877 @@
+//! OpenPGP signing, encryption, decryption and verification
+//!
+//! Supports PGP/MIME (RFC 3156) and inline PGP using the pure-Rust `pgp`
+//! crate. Keys come from a local keyring directory of armored `.asc` files,
+//! and trust decisions are stored next to them in `trust.json`.
+
+use crate::error::{AsgardError, AsgardResult};
+use crate::message::{canonicalize, entity_body, find_entity, split_multipart, Message, MessagePart, MessagePartType};
+use lettre::message::{header, MultiPart, SinglePart};
+use mailparse::MailHeaderMap;
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::crypto::sym::SymmetricKeyAlgorithm;
+use pgp::types::{KeyId, KeyTrait, SecretKeyTrait};
+use pgp::{Deserializable, Message as PgpMessage, SignedPublicKey, SignedPublicSubKey, SignedSecretKey, StandaloneSignature};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Keyring directory inside the data directory
+pub const KEYRING_DIR: &str = "openpgp";
+
+/// File holding trust decisions, keyed by fingerprint
+pub const TRUST_FILENAME: &str = "trust.json";
+
+/// MIME type of a detached PGP/MIME signature
+pub const PGP_SIGNATURE_TYPE: &str = "application/pgp-signature";
+
+/// MIME type of the PGP/MIME version part
+pub const PGP_ENCRYPTED_TYPE: &str = "application/pgp-encrypted";
+
+/// Hash algorithm announced in `micalg`
+const MICALG: &str = "pgp-sha256";
+
+const SIGNED_MARKER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const MESSAGE_MARKER: &str = "-----BEGIN PGP MESSAGE-----";
+const MESSAGE_END_MARKER: &str = "-----END PGP MESSAGE-----";
+const SIGNATURE_MARKER: &str = "-----BEGIN PGP SIGNATURE-----";
+const SIGNATURE_END_MARKER: &str = "-----END PGP SIGNATURE-----";
+
+/// How much a key is trusted to belong to its user IDs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    /// Explicitly distrusted
+    Never,
+    /// No decision made
+    #[default]
+    Unknown,
+    /// Partly verified
+    Marginal,
+    /// Verified
+    Full,
+    /// One of our own keys
+    Ultimate,
+}
+
+/// How a message is protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgpKind {
+    /// Not protected
+    None,
+    /// `multipart/signed` with an OpenPGP signature
+    MimeSigned,
+    /// `multipart/encrypted` with OpenPGP data
+    MimeEncrypted,
+    /// Cleartext-signed text body
+    InlineSigned,
+    /// Armored OpenPGP message in the text body
+    InlineEncrypted,
+}
+
+/// Result of checking a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// No signature present
+    Unsigned,
+    /// Signature verified with a key from the keyring
+    Valid {
+        /// Primary user ID of the signing key
+        signer: String,
+        /// Fingerprint of the signing key (hex)
+        fingerprint: String,
+        /// Trust in the signing key
+        trust: TrustLevel,
+    },
+    /// The signing key is not in the keyring
+    UnknownKey {
+        /// Issuer key ID (hex)
+        key_id: String,
+    },
+    /// The signature does not match the content
+    Invalid(String),
+}
+
+/// OpenPGP state of a message, for display next to its headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgpStatus {
+    /// How the message is protected
+    pub kind: PgpKind,
+    /// Whether the message was encrypted
+    pub encrypted: bool,
+    /// Whether decryption succeeded
+    pub decrypted: bool,
+    /// Signature check result
+    pub signature: SignatureStatus,
+    /// Why processing failed, if it did
+    pub error: Option<String>,
+    /// `Autocrypt-Gossip` header values found inside the encrypted data
+    pub gossip: Vec<String>,
+}
+
+impl PgpStatus {
+    fn new(kind: PgpKind) -> Self {
+        Self {
+            kind,
+            encrypted: matches!(kind, PgpKind::MimeEncrypted | PgpKind::InlineEncrypted),
+            decrypted: false,
+            signature: SignatureStatus::Unsigned,
+            error: None,
+            gossip: Vec::new(),
+        }
+    }
+
+    /// Whether the message carries any OpenPGP protection
+    pub fn is_protected(&self) -> bool {
+        self.kind != PgpKind::None
+    }
+
+    /// Whether the message has a valid signature from a trusted key
+    pub fn is_trusted(&self) -> bool {
+        matches!(self.signature, SignatureStatus::Valid { trust, .. } if trust >= TrustLevel::Full)
+    }
+
+    /// Short description for the message header
+    pub fn summary(&self) -> String {
+        let signature = match &self.signature {
+            SignatureStatus::Unsigned => None,
+            SignatureStatus::Valid { signer, trust: TrustLevel::Never, .. } => Some(format!("Signed by distrusted key of {}", signer)),
+            SignatureStatus::Valid { signer, trust, .. } if *trust >= TrustLevel::Full => Some(format!("Signed by {}", signer)),
+            SignatureStatus::Valid { signer, .. } => Some(format!("Signed by {} (key not verified)", signer)),
+            SignatureStatus::UnknownKey { key_id } => Some(format!("Signed with unknown key {}", key_id)),
+            SignatureStatus::Invalid(_) => Some("Bad signature".to_string()),
+        };
+
+        match (self.encrypted, self.decrypted, signature) {
+            (true, false, _) => "Encrypted, cannot decrypt".to_string(),
+            (true, true, Some(signature)) => format!("Encrypted · {}", signature),
+            (true, true, None) => "Encrypted".to_string(),
+            (false, _, Some(signature)) => signature,
+            (false, _, None) => "Not signed".to_string(),
+        }
+    }
+}
+
+/// Sign and encrypt toggles of the compose window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComposeOptions {
+    /// Sign with the sender's key
+    pub sign: bool,
+    /// Encrypt to all recipients and the sender
+    pub encrypt: bool,
+    /// Use S/MIME instead of OpenPGP
+    pub smime: bool,
+}
+
+impl ComposeOptions {
+    /// Whether the message needs OpenPGP processing at all
+    pub fn is_enabled(&self) -> bool {
+        self.sign || self.encrypt
+    }
+}
+
+/// Public key with the data needed for lookups
+#[derive(Clone)]
+struct PublicEntry {
+    key: SignedPublicKey,
+    fingerprint: String,
+    user_id: String,
+    emails: Vec<String>,
+}
+
+/// Secret key with the data needed for lookups
+#[derive(Clone)]
+struct SecretEntry {
+    key: SignedSecretKey,
+    fingerprint: String,
+    emails: Vec<String>,
+}
+
+/// Local OpenPGP keyring
+///
+/// Secret keys must not be passphrase-protected; they are expected to be
+/// kept safe by the encrypted data directory.
+#[derive(Clone, Default)]
+pub struct PgpKeyring {
+    /// Directory keys are imported into (`None` for in-memory keyrings)
+    dir: Option<PathBuf>,
+    public_keys: Vec<PublicEntry>,
+    secret_keys: Vec<SecretEntry>,
+    trust: HashMap<String, TrustLevel>,
+}
+
+impl PgpKeyring {
+    /// Create an empty in-memory keyring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default keyring directory
+    pub fn default_dir() -> AsgardResult<PathBuf> {
+        Ok(crate::get_data_dir()?.join(KEYRING_DIR))
+    }
+
+    /// Load all `.asc` files and trust decisions from `dir`
+    ///
+    /// Files that cannot be parsed are skipped with a warning.
+    pub fn open(dir: impl AsRef<Path>) -> AsgardResult<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut keyring = Self {
+            dir: Some(dir.to_path_buf()),
+            ..Self::default()
+        };
+
+        let trust_path = dir.join(TRUST_FILENAME);
+        if trust_path.exists() {
+            keyring.trust = serde_json::from_slice(&std::fs::read(&trust_path)?)?;
+        }
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("asc") {
+                continue;
+            }
+            let armored = std::fs::read_to_string(&path)?;
+            if let Err(e) = keyring.add_armored(&armored) {
+                warn!("Skipping OpenPGP key {}: {}", path.display(), e);
+            }
+        }
+
+        Ok(keyring)
+    }
+
+    /// Import an armored public or secret key and save it in the keyring directory
+    ///
+    /// Returns the fingerprint of the imported key.
+    pub fn import(&mut self, armored: &str) -> AsgardResult<String> {
+        let fingerprint = self.add_armored(armored)?;
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.asc", fingerprint));
+            if armored.contains("PRIVATE KEY BLOCK") {
+                crate::crypto::write_private(&path, armored.as_bytes())?;
+            } else {
+                std::fs::write(&path, armored)?;
+            }
+        }
+        Ok(fingerprint)
+    }
+
+    fn add_armored(&mut self, armored: &str) -> AsgardResult<String> {
+        if armored.contains("PRIVATE KEY BLOCK") {
+            let (key, _) = SignedSecretKey::from_string(armored).map_err(pgp_error)?;
+            self.add_secret_key(key)
+        } else {
+            let (key, _) = SignedPublicKey::from_string(armored).map_err(pgp_error)?;
+            self.add_public_key(key)
+        }
+    }
+
+    /// Add a public key, replacing any key with the same fingerprint
+    pub fn add_public_key(&mut self, key: SignedPublicKey) -> AsgardResult<String> {
+        key.verify().map_err(pgp_error)?;
+
+        let fingerprint = hex::encode_upper(key.fingerprint());
+        let user_ids: Vec<String> = key.details.users.iter().map(|user| user_id(user)).collect();
+        let entry = PublicEntry {
+            fingerprint: fingerprint.clone(),
+            user_id: user_ids.first().cloned().unwrap_or_else(|| fingerprint.clone()),
+            emails: user_ids.iter().filter_map(|id| user_id_email(id)).collect(),
+            key,
+        };
+
+        self.public_keys.retain(|existing| existing.fingerprint != fingerprint);
+        self.public_keys.push(entry);
+        Ok(fingerprint)
+    }
+
+    /// Add one of our own secret keys together with its public half
+    pub fn add_secret_key(&mut self, key: SignedSecretKey) -> AsgardResult<String> {
+        key.verify().map_err(pgp_error)?;
+
+        let public_key = key.public_key().sign(&key, String::new).map_err(pgp_error)?;
+        let fingerprint = self.add_public_key(public_key)?;
+        let entry = SecretEntry {
+            fingerprint: fingerprint.clone(),
+            emails: key.details.users.iter().filter_map(|user| user_id_email(&user_id(user))).collect(),
+            key,
+        };
+
+        self.secret_keys.retain(|existing| existing.fingerprint != fingerprint);
+        self.secret_keys.push(entry);
+        Ok(fingerprint)
+    }
+
+    /// Record how much a key is trusted
+    pub fn set_trust(&mut self, fingerprint: &str, level: TrustLevel) -> AsgardResult<()> {
+        self.trust.insert(fingerprint.to_ascii_uppercase(), level);
+        if let Some(dir) = &self.dir {
+            std::fs::write(dir.join(TRUST_FILENAME), serde_json::to_vec_pretty(&self.trust)?)?;
+        }
+        Ok(())
+    }
+
+    /// Trust in a key; our own keys are trusted ultimately
+    pub fn trust(&self, fingerprint: &str) -> TrustLevel {
+        if self.secret_keys.iter().any(|entry| entry.fingerprint == fingerprint) {
+            return TrustLevel::Ultimate;
+        }
+        self.trust.get(fingerprint).copied().unwrap_or_default()
+    }
+
+    /// Whether there is a public key for `email`
+    pub fn has_public_key(&self, email: &str) -> bool {
+        self.encryption_key_for(email).is_some()
+    }
+
+    /// Whether we can sign as `email`
+    pub fn has_secret_key(&self, email: &str) -> bool {
+        self.secret_entry_for(email).is_some()
+    }
+
+    fn secret_entry_for(&self, email: &str) -> Option<&SecretEntry> {
+        let email = email.to_ascii_lowercase();
+        self.secret_keys.iter().find(|entry| entry.emails.contains(&email))
+    }
+
+    /// Encryption subkey of the first usable public key for `email`
+    fn encryption_key_for(&self, email: &str) -> Option<&SignedPublicSubKey> {
+        let email = email.to_ascii_lowercase();
+        self.public_keys.iter()
+            .filter(|entry| entry.emails.contains(&email) && self.trust(&entry.fingerprint) != TrustLevel::Never)
+            .find_map(|entry| entry.key.public_subkeys.iter().find(|subkey| subkey.is_encryption_key()))
+    }
+
+    fn public_entry_for_id(&self, key_id: &KeyId) -> Option<&PublicEntry> {
+        self.public_keys.iter().find(|entry| {
+            entry.key.key_id() == *key_id
+                || entry.key.public_subkeys.iter().any(|subkey| subkey.key_id() == *key_id)
+        })
+    }
+
+    fn valid_signature(&self, entry: &PublicEntry) -> SignatureStatus {
+        SignatureStatus::Valid {
+            signer: entry.user_id.clone(),
+            fingerprint: entry.fingerprint.clone(),
+            trust: self.trust(&entry.fingerprint),
+        }
+    }
+
+    fn unverified_signature(&self, issuer: Option<&KeyId>) -> SignatureStatus {
+        match issuer {
+            Some(key_id) if self.public_entry_for_id(key_id).is_some() => {
+                SignatureStatus::Invalid("The signature does not match the message".to_string())
+            }
+            Some(key_id) => SignatureStatus::UnknownKey { key_id: hex::encode_upper(key_id) },
+            None => SignatureStatus::Invalid("The signature names no issuer".to_string()),
+        }
+    }
+
+    // Reading
+
+    /// Verify and decrypt `message` in place
+    ///
+    /// Decrypted parts replace the encrypted ones and detached signatures
+    /// are dropped from the attachments; `raw_content` is left untouched.
+    pub fn process(&self, message: &mut Message) -> PgpStatus {
+        let kind = detect(message);
+        let mut status = PgpStatus::new(kind);
+
+        let result = match kind {
+            PgpKind::None => Ok(()),
+            PgpKind::MimeSigned => self.process_mime_signed(message, &mut status),
+            PgpKind::MimeEncrypted => self.process_mime_encrypted(message, &mut status),
+            PgpKind::InlineSigned => self.process_inline_signed(message, &mut status),
+            PgpKind::InlineEncrypted => self.process_inline_encrypted(message, &mut status),
+        };
+        if let Err(e) = result {
+            status.error = Some(e.to_string());
+        }
+
+        status
+    }
+
+    fn process_mime_signed(&self, message: &mut Message, status: &mut PgpStatus) -> AsgardResult<()> {
+        let raw = message.raw_content.as_deref()
+            .ok_or_else(|| AsgardError::invalid_state("Message content is not available"))?;
+        status.signature = self.verify_mime_signed(raw)?;
+        message.attachments.retain(|attachment| attachment.mime_type != PGP_SIGNATURE_TYPE);
+        Ok(())
+    }
+
+    fn process_mime_encrypted(&self, message: &mut Message, status: &mut PgpStatus) -> AsgardResult<()> {
+        let raw = message.raw_content.as_deref()
+            .ok_or_else(|| AsgardError::invalid_state("Message content is not available"))?;
+        let parsed = mailparse::parse_mail(raw)?;
+        let encrypted = find_entity(&parsed, "multipart/encrypted")
+            .ok_or_else(|| AsgardError::validation("No multipart/encrypted part found"))?;
+        let payload = encrypted.subparts.get(1)
+            .ok_or_else(|| AsgardError::validation("Encrypted message has no OpenPGP data"))?;
+
+        let (entity, signature) = self.decrypt(&payload.get_body()?)?;
+        status.decrypted = true;
+        status.signature = signature;
+
+        // Sign-then-encrypt (RFC 3156 section 6.1) nests a multipart/signed
+        let inner = mailparse::parse_mail(&entity)?;
+        status.gossip = inner.headers.get_all_values(crate::autocrypt::GOSSIP_HEADER);
+        if find_entity(&inner, "multipart/signed").is_some() {
+            status.signature = self.verify_mime_signed(&entity)?;
+        }
+
+        message.replace_body(&entity)?;
+        message.attachments.retain(|attachment| attachment.mime_type != PGP_SIGNATURE_TYPE);
+        Ok(())
+    }
+
+    fn process_inline_signed(&self, message: &mut Message, status: &mut PgpStatus) -> AsgardResult<()> {
+        let part = text_part_mut(message)
+            .ok_or_else(|| AsgardError::invalid_state("Message has no text body"))?;
+        let text = String::from_utf8_lossy(part.content.as_deref().unwrap_or_default()).into_owned();
+        let start = text.find(SIGNED_MARKER)
+            .ok_or_else(|| AsgardError::validation("No signed block found"))?;
+
+        let (signed_text, signature) = parse_cleartext(&text[start..])?;
+        status.signature = self.verify_detached(&cleartext_data(&signed_text), &signature);
+
+        set_part_text(part, &signed_text);
+        Ok(())
+    }
+
+    fn process_inline_encrypted(&self, message: &mut Message, status: &mut PgpStatus) -> AsgardResult<()> {
+        let part = text_part_mut(message)
+            .ok_or_else(|| AsgardError::invalid_state("Message has no text body"))?;
+        let text = String::from_utf8_lossy(part.content.as_deref().unwrap_or_default()).into_owned();
+        let start = text.find(MESSAGE_MARKER)
+            .ok_or_else(|| AsgardError::validation("No encrypted block found"))?;
+        let end = text[start..].find(MESSAGE_END_MARKER)
+            .map(|end| start + end + MESSAGE_END_MARKER.len())
+            .ok_or_else(|| AsgardError::validation("Encrypted block is truncated"))?;
+
+        let (plaintext, signature) = self.decrypt(&text[start..end])?;
+        status.decrypted = true;
+        status.signature = signature;
+
+        set_part_text(part, &String::from_utf8_lossy(&plaintext));
+        Ok(())
+    }
+
+    /// Verify the first `multipart/signed` entity in `raw`
+    fn verify_mime_signed(&self, raw: &[u8]) -> AsgardResult<SignatureStatus> {
+        let parsed = mailparse::parse_mail(raw)?;
+        let signed = find_entity(&parsed, "multipart/signed")
+            .ok_or_else(|| AsgardError::validation("No multipart/signed part found"))?;
+        let boundary = signed.ctype.params.get("boundary")
+            .ok_or_else(|| AsgardError::validation("multipart/signed has no boundary"))?;
+
+        let parts = split_multipart(entity_body(signed.raw_bytes), boundary);
+        let (Some(content), Some(signature_part)) = (parts.first(), parts.get(1)) else {
+            return Err(AsgardError::validation("multipart/signed needs two parts"));
+        };
+        let signature = mailparse::parse_mail(signature_part)?.get_body()?;
+
+        Ok(self.verify_detached(&canonicalize(content), &signature))
+    }
+
+    /// Check a detached armored signature over `data`
+    fn verify_detached(&self, data: &[u8], armored: &str) -> SignatureStatus {
+        let signature = match StandaloneSignature::from_string(armored) {
+            Ok((signature, _)) => signature,
+            Err(e) => return SignatureStatus::Invalid(format!("Unreadable signature: {}", e)),
+        };
+
+        self.public_keys.iter()
+            .find(|entry| signature.verify(&entry.key, data).is_ok()
+                || entry.key.public_subkeys.iter().any(|subkey| signature.verify(subkey, data).is_ok()))
+            .map(|entry| self.valid_signature(entry))
+            .unwrap_or_else(|| self.unverified_signature(signature.signature.issuer()))
+    }
+
+    /// Decrypt an armored OpenPGP message, checking an embedded signature
+    fn decrypt(&self, armored: &str) -> AsgardResult<(Vec<u8>, SignatureStatus)> {
+        let keys: Vec<&SignedSecretKey> = self.secret_keys.iter().map(|entry| &entry.key).collect();
+        if keys.is_empty() {
+            return Err(AsgardError::crypto("No secret key available to decrypt with"));
+        }
+
+        let (encrypted, _) = PgpMessage::from_string(armored).map_err(pgp_error)?;
+        let (mut decrypter, _) = encrypted.decrypt(String::new, &keys)
+            .map_err(|e| AsgardError::crypto(format!("Cannot decrypt message: {}", e)))?;
+        let decrypted = decrypter.next()
+            .ok_or_else(|| AsgardError::crypto("Encrypted message has no content"))?
+            .and_then(PgpMessage::decompress)
+            .map_err(|e| AsgardError::crypto(format!("Cannot decrypt message: {}", e)))?;
+
+        let signature = match &decrypted {
+            PgpMessage::Signed { signature, .. } => self.public_keys.iter()
+                .find(|entry| decrypted.verify(&entry.key).is_ok()
+                    || entry.key.public_subkeys.iter().any(|subkey| decrypted.verify(subkey).is_ok()))
+                .map(|entry| self.valid_signature(entry))
+                .unwrap_or_else(|| self.unverified_signature(signature.issuer())),
+            _ => SignatureStatus::Unsigned,
+        };
+        let content = decrypted.get_content().map_err(pgp_error)?
+            .ok_or_else(|| AsgardError::crypto("Encrypted message has no content"))?;
+
+        Ok((content, signature))
+    }
+
+    // Writing
+
+    /// Wrap `part` in a `multipart/signed` entity signed by `signer`
+    pub fn sign_part(&self, signer: &str, part: SinglePart) -> AsgardResult<MultiPart> {
+        let signature = self.signature_part(signer, &part.formatted())?;
+        Ok(MultiPart::signed(PGP_SIGNATURE_TYPE.to_string(), MICALG.to_string())
+            .singlepart(part)
+            .singlepart(signature))
+    }
+
+    /// Like [`PgpKeyring::sign_part`] for a multipart entity
+    pub fn sign_multipart(&self, signer: &str, part: MultiPart) -> AsgardResult<MultiPart> {
+        let signature = self.signature_part(signer, &part.formatted())?;
+        Ok(MultiPart::signed(PGP_SIGNATURE_TYPE.to_string(), MICALG.to_string())
+            .multipart(part)
+            .singlepart(signature))
+    }
+
+    /// Detached `signature.asc` part over a formatted MIME entity
+    fn signature_part(&self, signer: &str, entity: &[u8]) -> AsgardResult<SinglePart> {
+        // The CRLF before the next boundary belongs to the delimiter
+        let entity = entity.strip_suffix(b"\r\n").unwrap_or(entity);
+        let signed = self.sign_literal(signer, PgpMessage::new_literal_bytes("", &canonicalize(entity)))?;
+        let signature = signed.into_signature().to_armored_string(None).map_err(pgp_error)?;
+
+        Ok(SinglePart::builder()
+            .header(content_type(&format!("{}; name=\"signature.asc\"", PGP_SIGNATURE_TYPE))?)
+            .header(header::ContentDisposition::attachment("signature.asc"))
+            .body(signature))
+    }
+
+    /// Wrap `part` in a `multipart/encrypted` entity for `recipients`
+    ///
+    /// With `signer` set, the signature goes inside the encrypted data
+    /// (RFC 3156 section 6.2). Fails if any recipient has no usable key.
+    pub fn encrypt_part(&self, recipients: &[&str], signer: Option<&str>, part: SinglePart) -> AsgardResult<MultiPart> {
+        self.encrypt_entity(recipients, signer, &part.formatted())
+    }
+
+    /// Like [`PgpKeyring::encrypt_part`] for an already formatted MIME entity
+    pub fn encrypt_entity(&self, recipients: &[&str], signer: Option<&str>, entity: &[u8]) -> AsgardResult<MultiPart> {
+        let mut keys = Vec::with_capacity(recipients.len());
+        let mut missing = Vec::new();
+        for recipient in recipients {
+            match self.encryption_key_for(recipient) {
+                Some(key) => keys.push(key),
+                None => missing.push(*recipient),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(AsgardError::not_found(format!("No OpenPGP key for {}", missing.join(", "))));
+        }
+
+        let mut literal = PgpMessage::new_literal_bytes("", entity);
+        if let Some(signer) = signer {
+            literal = self.sign_literal(signer, literal)?;
+        }
+        let encrypted = literal
+            .encrypt_to_keys(&mut rand::thread_rng(), SymmetricKeyAlgorithm::AES256, &keys)
+            .map_err(pgp_error)?;
+        let armored = encrypted.to_armored_string(None).map_err(pgp_error)?;
+
+        Ok(MultiPart::encrypted(PGP_ENCRYPTED_TYPE.to_string())
+            .singlepart(SinglePart::builder()
+                .header(content_type(PGP_ENCRYPTED_TYPE)?)
+                .body(String::from("Version: 1\r\n")))
+            .singlepart(SinglePart::builder()
+                .header(content_type("application/octet-stream; name=\"encrypted.asc\"")?)
+                .header(header::ContentDisposition::inline_with_name("encrypted.asc"))
+                .body(armored)))
+    }
+
+    fn sign_literal(&self, signer: &str, literal: PgpMessage) -> AsgardResult<PgpMessage> {
+        let entry = self.secret_entry_for(signer)
+            .ok_or_else(|| AsgardError::not_found(format!("No OpenPGP secret key for {}", signer)))?;
+
+        let signed = if entry.key.is_signing_key() {
+            literal.sign(&entry.key, String::new, HashAlgorithm::SHA2_256)
+        } else {
+            let subkey = entry.key.secret_subkeys.iter()
+                .find(|subkey| subkey.is_signing_key())
+                .ok_or_else(|| AsgardError::crypto(format!("Key {} cannot sign", entry.fingerprint)))?;
+            literal.sign(subkey, String::new, HashAlgorithm::SHA2_256)
+        };
+        signed.map_err(pgp_error)
+    }
+}
+
+/// Detect OpenPGP protection from the message parts
+pub fn detect(message: &Message) -> PgpKind {
+    let mut mime_types = Vec::new();
+    collect_mime_types(&message.parts, &mut mime_types);
+    mime_types.extend(message.attachments.iter().map(|attachment| attachment.mime_type.as_str()));
+
+    if mime_types.contains(&PGP_ENCRYPTED_TYPE) {
+        return PgpKind::MimeEncrypted;
+    }
+    if mime_types.contains(&PGP_SIGNATURE_TYPE) {
+        return PgpKind::MimeSigned;
+    }
+
+    let text = message.text_content().map(String::from_utf8_lossy).unwrap_or_default();
+    if text.contains(SIGNED_MARKER) {
+        PgpKind::InlineSigned
+    } else if text.contains(MESSAGE_MARKER) {
+        PgpKind::InlineEncrypted
+    } else {
+        PgpKind::None
+    }
+}
+
+fn collect_mime_types<'a>(parts: &'a [MessagePart], mime_types: &mut Vec<&'a str>) {
+    for part in parts {
+        mime_types.push(part.mime_type.as_str());
+        collect_mime_types(&part.children, mime_types);
+    }
+}
+
+fn text_part_mut(message: &mut Message) -> Option<&mut MessagePart> {
+    message.parts.iter_mut().find(|part| part.part_type == MessagePartType::Text)
+}
+
+fn set_part_text(part: &mut MessagePart, text: &str) {
+    part.size = text.len();
+    part.content = Some(text.as_bytes().to_vec());
+}
+
+/// Split a cleartext-signed block (RFC 4880 section 7) into the signed
+/// text, dash-escaping removed, and the armored signature
+fn parse_cleartext(block: &str) -> AsgardResult<(String, String)> {
+    let mut lines = block.lines().skip(1);
+    // Armor headers such as "Hash: SHA256" end at the first empty line
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut text = Vec::new();
+    let mut signature = None;
+    for line in lines.by_ref() {
+        if line.trim_end() == SIGNATURE_MARKER {
+            signature = Some(vec![line.trim_end()]);
+            break;
+        }
+        text.push(line.strip_prefix("- ").unwrap_or(line));
+    }
+    let mut signature = signature.ok_or_else(|| AsgardError::validation("Signed block has no signature"))?;
+    for line in lines {
+        signature.push(line.trim_end());
+        if line.trim_end() == SIGNATURE_END_MARKER {
+            return Ok((text.join("\n"), signature.join("\n")));
+        }
+    }
+    Err(AsgardError::validation("Signature block is truncated"))
+}
+
+/// The bytes a cleartext signature covers: lines without trailing
+/// whitespace, joined with CRLF and no final line break
+fn cleartext_data(text: &str) -> Vec<u8> {
+    text.lines()
+        .map(|line| line.trim_end_matches([' ', '\t', '\r']))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        .into_bytes()
+}
+
+/// A user ID as text; invalid UTF-8 is replaced
+pub(crate) fn user_id(user: &pgp::types::SignedUser) -> String {
+    String::from_utf8_lossy(user.id.id()).into_owned()
+}
+
+/// Email address of a user ID such as `Alice <alice@example.com>`
+pub(crate) fn user_id_email(user_id: &str) -> Option<String> {
+    let email = match (user_id.rfind('<'), user_id.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &user_id[start + 1..end],
+        _ => user_id.trim(),
+    };
+    email.contains('@').then(|| email.to_ascii_lowercase())
+}
+
+fn content_type(value: &str) -> AsgardResult<header::ContentType> {
+    header::ContentType::parse(value)
+        .map_err(|e| AsgardError::validation(format!("Invalid content type {}: {}", value, e)))
+}
+
+fn pgp_error(e: pgp::errors::Error) -> AsgardError {
+    AsgardError::crypto(format!("OpenPGP: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lettre::Message as LettreMessage;
+    use pgp::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+    use uuid::Uuid;
+
+    fn generate_key(user_id: &str) -> SignedSecretKey {
+        let params = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id(user_id.to_string())
+            .subkey(SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .build()
+                .unwrap())
+            .build()
+            .unwrap();
+        params.generate().unwrap().sign(String::new).unwrap()
+    }
+
+    fn public_half(key: &SignedSecretKey) -> SignedPublicKey {
+        key.public_key().sign(key, String::new).unwrap()
+    }
+
+    fn send(multipart: MultiPart) -> Message {
+        let email = LettreMessage::builder()
+            .from("Alice <alice@example.com>".parse().unwrap())
+            .to("Bob <bob@example.com>".parse().unwrap())
+            .subject("Quarterly keys")
+            .multipart(multipart)
+            .unwrap();
+        Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), &email.formatted()).unwrap()
+    }
+
+    #[test]
+    fn test_split_multipart_and_canonicalize() {
+        let body = b"preamble\r\n--b\r\nContent-Type: text/plain\r\n\r\nhello\r\n--b\r\nsig\r\n--b--\r\n";
+        let parts = split_multipart(body, "b");
+        assert_eq!(parts, vec![&b"Content-Type: text/plain\r\n\r\nhello"[..], &b"sig"[..]]);
+
+        assert_eq!(canonicalize(b"a\nb\r\nc"), b"a\r\nb\r\nc");
+        assert_eq!(entity_body(b"Content-Type: text/plain\r\n\r\nbody"), b"body");
+        assert_eq!(user_id_email("Alice <Alice@Example.com>").as_deref(), Some("alice@example.com"));
+    }
+
+    #[test]
+    fn test_mime_sign_and_verify() {
+        let alice = generate_key("Alice <alice@example.com>");
+        let mut sender = PgpKeyring::new();
+        sender.add_secret_key(alice.clone()).unwrap();
+
+        let signed = sender.sign_part("alice@example.com", SinglePart::plain("Meet at noon.\nBring the keys.".to_string())).unwrap();
+        let message = send(signed);
+        assert_eq!(detect(&message), PgpKind::MimeSigned);
+
+        // Bob has Alice's key but has not verified it yet
+        let mut receiver = PgpKeyring::new();
+        let fingerprint = receiver.add_public_key(public_half(&alice)).unwrap();
+        let status = receiver.process(&mut message.clone());
+        assert!(matches!(&status.signature, SignatureStatus::Valid { trust: TrustLevel::Unknown, .. }));
+        assert!(!status.is_trusted());
+
+        receiver.set_trust(&fingerprint, TrustLevel::Full).unwrap();
+        let mut processed = message.clone();
+        let status = receiver.process(&mut processed);
+        assert!(status.is_trusted());
+        assert!(processed.attachments.is_empty());
+
+        // Without the key the signer is unknown
+        let status = PgpKeyring::new().process(&mut message.clone());
+        assert!(matches!(status.signature, SignatureStatus::UnknownKey { .. }));
+    }
+
+    #[test]
+    fn test_tampered_signature_is_invalid() {
+        let alice = generate_key("Alice <alice@example.com>");
+        let mut keyring = PgpKeyring::new();
+        keyring.add_secret_key(alice).unwrap();
+
+        let message = send(keyring.sign_part("alice@example.com", SinglePart::plain("Pay 100 EUR".to_string())).unwrap());
+        let raw = String::from_utf8(message.raw_content.clone().unwrap()).unwrap();
+        let tampered = raw.replace("Pay 100 EUR", "Pay 900 EUR");
+        let mut tampered = Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), tampered.as_bytes()).unwrap();
+
+        let status = keyring.process(&mut tampered);
+        assert!(matches!(status.signature, SignatureStatus::Invalid(_)));
+    }
+
+    #[test]
+    fn test_inline_signed() {
+        let alice = generate_key("Alice <alice@example.com>");
+        let mut keyring = PgpKeyring::new();
+        keyring.add_secret_key(alice).unwrap();
+
+        let text = "Meet at noon.  \n-- \nAlice";
+        let signature = keyring.sign_literal("alice@example.com", PgpMessage::new_literal_bytes("", &cleartext_data(text)))
+            .unwrap()
+            .into_signature()
+            .to_armored_string(None)
+            .unwrap();
+        let block = format!("{}\nHash: SHA256\n\nMeet at noon.  \n- -- \nAlice\n{}", SIGNED_MARKER, signature);
+        let message = send(MultiPart::mixed().singlepart(SinglePart::plain(block.clone())));
+        assert_eq!(detect(&message), PgpKind::InlineSigned);
+
+        let mut processed = message.clone();
+        let status = keyring.process(&mut processed);
+        assert!(matches!(status.signature, SignatureStatus::Valid { trust: TrustLevel::Ultimate, .. }), "{:?}", status);
+        assert_eq!(String::from_utf8_lossy(processed.text_content().unwrap()), text);
+
+        let tampered = block.replace("noon", "midnight");
+        let mut tampered = send(MultiPart::mixed().singlepart(SinglePart::plain(tampered)));
+        let status = keyring.process(&mut tampered);
+        assert!(matches!(status.signature, SignatureStatus::Invalid(_)));
+    }
+
+    #[test]
+    fn test_mime_encrypt_and_decrypt() {
+        let alice = generate_key("Alice <alice@example.com>");
+        let bob = generate_key("Bob <bob@example.com>");
+
+        let mut sender = PgpKeyring::new();
+        sender.add_secret_key(alice.clone()).unwrap();
+        sender.add_public_key(public_half(&bob)).unwrap();
+
+        let part = SinglePart::plain("The launch code is 0000.".to_string());
+        assert!(sender.encrypt_part(&["carol@example.com"], None, part.clone()).is_err());
+
+        let encrypted = sender.encrypt_part(&["bob@example.com", "alice@example.com"], Some("alice@example.com"), part).unwrap();
+        let message = send(encrypted);
+        assert_eq!(detect(&message), PgpKind::MimeEncrypted);
+        assert!(!String::from_utf8_lossy(message.raw_content.as_deref().unwrap()).contains("launch code"));
+
+        let mut receiver = PgpKeyring::new();
+        receiver.add_secret_key(bob).unwrap();
+        receiver.add_public_key(public_half(&alice)).unwrap();
+
+        let mut processed = message.clone();
+        let status = receiver.process(&mut processed);
+        assert!(status.decrypted, "{:?}", status.error);
+        assert!(matches!(status.signature, SignatureStatus::Valid { .. }));
+        let text = String::from_utf8_lossy(processed.text_content().unwrap()).into_owned();
+        assert!(text.contains("The launch code is 0000."));
+
+        let status = PgpKeyring::new().process(&mut message.clone());
+        assert!(!status.decrypted);
+        assert_eq!(status.summary(), "Encrypted, cannot decrypt");
+    }
+
+    #[test]
+    fn test_keyring_directory_and_trust() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice = generate_key("Alice <alice@example.com>");
+        let armored = public_half(&alice).to_armored_string(None).unwrap();
+
+        let mut keyring = PgpKeyring::open(dir.path()).unwrap();
+        let fingerprint = keyring.import(&armored).unwrap();
+        keyring.set_trust(&fingerprint, TrustLevel::Marginal).unwrap();
+
+        let reopened = PgpKeyring::open(dir.path()).unwrap();
+        assert!(reopened.has_public_key("alice@example.com"));
+        assert!(!reopened.has_secret_key("alice@example.com"));
+        assert_eq!(reopened.trust(&fingerprint), TrustLevel::Marginal);
+    }
+}
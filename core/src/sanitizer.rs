@@ -0,0 +1,900 @@
+//! HTML sanitizing for message display
+//!
+//! Message HTML is reduced to an allow-list of tags and attributes before it
+//! reaches the web view. Scripts, forms, event handlers and `<base>` never
+//! survive, inline and embedded CSS is filtered declaration by declaration,
+//! and URLs pointing at blocked domains are dropped.
+//!
+//! Unless the user chose to load it, remote content is replaced with
+//! placeholders, and tracking pixels are removed whether or not it is.
+
+use crate::config::SecurityConfig;
+use crate::html::decode_entities;
+use ammonia::{Builder, UrlRelative};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Elements kept in sanitized HTML
+const TAGS: &[&str] = &[
+    "a", "abbr", "acronym", "address", "article", "aside", "b", "bdi", "bdo", "big", "blockquote",
+    "br", "caption", "center", "cite", "code", "col", "colgroup", "dd", "del", "details", "dfn",
+    "div", "dl", "dt", "em", "figcaption", "figure", "font", "footer", "h1", "h2", "h3", "h4",
+    "h5", "h6", "header", "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q",
+    "rp", "rt", "ruby", "s", "samp", "section", "small", "span", "strike", "strong", "sub",
+    "summary", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "time", "tr", "tt", "u",
+    "ul", "var", "wbr",
+];
+
+/// Elements removed together with their content
+///
+/// Everything else outside `TAGS` is unwrapped, keeping its text.
+const REMOVED_WITH_CONTENT: &[&str] = &[
+    "applet", "frame", "frameset", "iframe", "math", "noembed", "noframes", "object", "script",
+    "select", "style", "svg", "template", "textarea", "title",
+];
+
+/// Attributes allowed on every kept element
+const GENERIC_ATTRIBUTES: &[&str] = &[
+    "align", "bgcolor", "border", "class", "color", "dir", "height", "lang", "style", "title",
+    "valign", "width",
+];
+
+/// Attributes allowed on specific elements
+const TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "name"]),
+    ("blockquote", &["cite"]),
+    ("col", &["span"]),
+    ("colgroup", &["span"]),
+    ("del", &["cite", "datetime"]),
+    ("font", &["face", "size"]),
+    ("img", &["alt", "src", "hspace", "vspace"]),
+    ("ins", &["cite", "datetime"]),
+    ("li", &["value"]),
+    ("ol", &["reversed", "start", "type"]),
+    ("q", &["cite"]),
+    ("table", &["background", "cellpadding", "cellspacing", "frame", "rules", "summary"]),
+    ("td", &["abbr", "background", "colspan", "headers", "nowrap", "rowspan", "scope"]),
+    ("th", &["abbr", "background", "colspan", "headers", "nowrap", "rowspan", "scope"]),
+    ("time", &["datetime"]),
+    ("ul", &["type"]),
+];
+
+/// URL schemes allowed in attributes, narrowed further per attribute
+const URL_SCHEMES: &[&str] = &["cid", "data", "http", "https", "mailto"];
+
+/// Image types allowed as `data:` URLs
+const DATA_IMAGE_TYPES: &[&str] = &["image/bmp", "image/gif", "image/jpeg", "image/jpg", "image/png", "image/webp"];
+
+/// CSS properties kept by the strict policy
+const CSS_PROPERTIES: &[&str] = &[
+    "background", "border", "border-collapse", "border-spacing", "box-sizing", "caption-side",
+    "clear", "color", "direction", "display", "empty-cells", "float", "font", "height",
+    "letter-spacing", "line-height", "list-style", "margin", "max-height", "max-width",
+    "min-height", "min-width", "opacity", "overflow", "overflow-wrap", "padding", "table-layout",
+    "vertical-align", "visibility", "white-space", "width", "word-break", "word-spacing",
+    "word-wrap",
+];
+
+/// Families of CSS properties kept by the strict policy
+const CSS_PROPERTY_PREFIXES: &[&str] = &["background-", "border-", "font-", "list-style-", "margin-", "padding-", "text-"];
+
+/// CSS properties that run code in some engine, dropped even when unsafe HTML is allowed
+const CSS_FORBIDDEN_PROPERTIES: &[&str] = &["-moz-binding", "-ms-behavior", "behavior"];
+
+/// Fragments that make a CSS value unsafe wherever they appear
+const CSS_FORBIDDEN_VALUES: &[&str] = &[
+    "expression", "javascript:", "vbscript:", "-moz-binding", "behavior", "@import", "image-set(",
+    "src(",
+];
+
+/// Domains of mail tracking services, with the service name
+const KNOWN_TRACKERS: &[(&str, &str)] = &[
+    ("bananatag.com", "Bananatag"),
+    ("customeriomail.com", "Customer.io"),
+    ("doubleclick.net", "DoubleClick"),
+    ("emltrk.com", "Litmus"),
+    ("exacttarget.com", "Salesforce Marketing Cloud"),
+    ("google-analytics.com", "Google Analytics"),
+    ("list-manage.com", "Mailchimp"),
+    ("mailtrack.io", "Mailtrack"),
+    ("mandrillapp.com", "Mandrill"),
+    ("mixmax.com", "Mixmax"),
+    ("sendgrid.net", "SendGrid"),
+    ("sparkpostmail.com", "SparkPost"),
+    ("trk.klaviyomail.com", "Klaviyo"),
+    ("yesware.com", "Yesware"),
+];
+
+/// Transparent GIF shown in place of a blocked remote image
+const BLOCKED_IMAGE: &str = "data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+
+/// Class added to images whose remote source was blocked
+pub const BLOCKED_IMAGE_CLASS: &str = "remote-blocked";
+
+/// A tracking pixel removed from a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tracker {
+    /// URL the pixel would have loaded
+    pub url: String,
+    /// Tracking service, when the domain is a known one
+    pub service: Option<String>,
+}
+
+/// Sanitized HTML and what was taken out of it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanitizedHtml {
+    /// HTML fragment safe to display
+    pub html: String,
+    /// Remote images and style sheet URLs replaced by placeholders
+    pub blocked_remote: Vec<String>,
+    /// Tracking pixels removed
+    pub trackers: Vec<Tracker>,
+}
+
+/// How links and resources on a domain are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainPolicy {
+    /// Listed in `trusted_domains`
+    Trusted,
+    /// Listed in `blocked_domains`; URLs are removed
+    Blocked,
+    /// Not listed
+    Unlisted,
+}
+
+/// Trusted and blocked domains, normalized to lowercase ASCII
+#[derive(Debug, Default)]
+pub(crate) struct DomainLists {
+    trusted: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl DomainLists {
+    pub(crate) fn new(trusted: &[String], blocked: &[String]) -> Self {
+        Self {
+            trusted: trusted.iter().filter_map(|domain| normalize_domain(domain)).collect(),
+            blocked: blocked.iter().filter_map(|domain| normalize_domain(domain)).collect(),
+        }
+    }
+
+    /// Policy for a host; a domain covers all of its subdomains
+    pub(crate) fn policy(&self, host: &str) -> DomainPolicy {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.blocked.iter().any(|domain| covers(domain, &host)) {
+            DomainPolicy::Blocked
+        } else if self.trusted.iter().any(|domain| covers(domain, &host)) {
+            DomainPolicy::Trusted
+        } else {
+            DomainPolicy::Unlisted
+        }
+    }
+
+    /// Whether `url` may stay in `attribute`
+    ///
+    /// Links may be web or mail addresses, resources web, `cid:` or
+    /// `data:` image URLs. Anything on a blocked domain is dropped.
+    fn allows_url(&self, attribute: &str, url: &str) -> bool {
+        let Ok(parsed) = Url::parse(url.trim()) else {
+            return false;
+        };
+        let resource = matches!(attribute, "src" | "background");
+        match parsed.scheme() {
+            "http" | "https" => parsed.host_str().is_some_and(|host| self.policy(host) != DomainPolicy::Blocked),
+            "mailto" => attribute == "href",
+            "cid" => resource,
+            "data" => resource && is_data_image(parsed.path()),
+            _ => false,
+        }
+    }
+}
+
+/// Sanitizer for message HTML, configured from [`SecurityConfig`]
+///
+/// With `sanitize_html` off, HTML is passed through unchanged; the web view
+/// still runs no scripts and loads no remote content unless allowed.
+/// `allow_unsafe_html` keeps CSS properties outside the strict allow-list,
+/// such as positioning.
+#[derive(Debug, Clone)]
+pub struct HtmlSanitizer {
+    enabled: bool,
+    strict_css: bool,
+    block_remote: bool,
+    strip_trackers: bool,
+    domains: Arc<DomainLists>,
+}
+
+/// State of one sanitizing pass, shared with the attribute filter
+#[derive(Debug)]
+struct Pass {
+    strict_css: bool,
+    block_remote: bool,
+    domains: Arc<DomainLists>,
+    /// Remote URLs replaced so far
+    blocked: Mutex<Vec<String>>,
+}
+
+impl Pass {
+    /// Whether a resource URL is replaced by a placeholder, recording it if so
+    ///
+    /// Resources on trusted domains always load.
+    fn blocks(&self, url: &str) -> bool {
+        let Some(host) = remote_host(url) else {
+            return false;
+        };
+        if !self.block_remote || self.domains.policy(&host) == DomainPolicy::Trusted {
+            return false;
+        }
+        let mut blocked = self.blocked.lock().unwrap_or_else(|e| e.into_inner());
+        if !blocked.iter().any(|blocked| blocked == url) {
+            blocked.push(url.to_string());
+        }
+        true
+    }
+}
+
+impl HtmlSanitizer {
+    /// Create a sanitizer following `config`
+    pub fn new(config: &SecurityConfig) -> Self {
+        Self {
+            enabled: config.sanitize_html,
+            strict_css: !config.allow_unsafe_html,
+            block_remote: config.block_remote_images,
+            strip_trackers: config.strip_tracking_pixels,
+            domains: Arc::new(DomainLists::new(&config.trusted_domains, &config.blocked_domains)),
+        }
+    }
+
+    /// Trusted domains, whose images load without asking
+    pub fn trusted_domains(&self) -> &[String] {
+        &self.domains.trusted
+    }
+
+    /// Policy for the domain of `url`
+    ///
+    /// URLs without a host, such as `mailto:` or `cid:`, are unlisted.
+    pub fn domain_policy(&self, url: &str) -> DomainPolicy {
+        Url::parse(url.trim()).ok()
+            .and_then(|url| url.host_str().map(|host| self.domains.policy(host)))
+            .unwrap_or(DomainPolicy::Unlisted)
+    }
+
+    /// Sanitize message HTML for display
+    ///
+    /// The result is a fragment: `<html>`, `<head>` and `<body>` are
+    /// dropped, and the filtered rules of all `<style>` elements are
+    /// gathered into a single leading `<style>`. Remote images and style
+    /// sheet URLs are kept only with `load_remote` or when blocking is off.
+    pub fn sanitize(&self, html: &str, load_remote: bool) -> SanitizedHtml {
+        if !self.enabled {
+            return SanitizedHtml { html: html.to_string(), ..SanitizedHtml::default() };
+        }
+
+        let pass = Arc::new(Pass {
+            strict_css: self.strict_css,
+            block_remote: self.block_remote && !load_remote,
+            domains: self.domains.clone(),
+            blocked: Mutex::new(Vec::new()),
+        });
+        let stylesheet = style_blocks(html)
+            .map(|css| sanitize_stylesheet(css, &pass))
+            .filter(|css| !css.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = Self::builder(pass.clone()).clean(html).to_string();
+
+        let mut trackers = Vec::new();
+        let body = filter_images(&body, &pass, self.strip_trackers, &mut trackers);
+        let html = if stylesheet.is_empty() {
+            body
+        } else {
+            format!("<style>{}</style>{}", stylesheet, body)
+        };
+
+        let blocked_remote = std::mem::take(&mut *pass.blocked.lock().unwrap_or_else(|e| e.into_inner()));
+        SanitizedHtml { html, blocked_remote, trackers }
+    }
+
+    fn builder(pass: Arc<Pass>) -> Builder<'static> {
+        let tag_attributes: HashMap<&str, HashSet<&str>> = TAG_ATTRIBUTES.iter()
+            .map(|(tag, attributes)| (*tag, attributes.iter().copied().collect()))
+            .collect();
+
+        let mut builder = Builder::empty();
+        builder
+            .tags(TAGS.iter().copied().collect())
+            .clean_content_tags(REMOVED_WITH_CONTENT.iter().copied().collect())
+            .generic_attributes(GENERIC_ATTRIBUTES.iter().copied().collect())
+            .tag_attributes(tag_attributes)
+            .url_schemes(URL_SCHEMES.iter().copied().collect())
+            .url_relative(UrlRelative::Deny)
+            .link_rel(Some("noopener noreferrer"))
+            .strip_comments(true)
+            .attribute_filter(move |_, attribute, value| match attribute {
+                "style" => {
+                    let css = sanitize_declarations(value, &pass);
+                    (!css.is_empty()).then_some(Cow::Owned(css))
+                }
+                // Images are checked for tracking afterwards, in `filter_images`
+                "background" => {
+                    (pass.domains.allows_url(attribute, value) && !pass.blocks(value)).then_some(Cow::Borrowed(value))
+                }
+                "href" | "src" | "cite" => {
+                    pass.domains.allows_url(attribute, value).then_some(Cow::Borrowed(value))
+                }
+                _ => Some(Cow::Borrowed(value)),
+            });
+        builder
+    }
+}
+
+impl Default for HtmlSanitizer {
+    fn default() -> Self {
+        Self::new(&SecurityConfig::default())
+    }
+}
+
+/// Whether `domain` is `host` or one of its parent domains
+pub(crate) fn covers(domain: &str, host: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Lowercase host of an `http` or `https` URL
+fn remote_host(url: &str) -> Option<String> {
+    let url = Url::parse(url.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    url.host_str().map(|host| host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// Lowercase ASCII host of a domain list entry such as `example.com`,
+/// `*.example.com` or `https://example.com/`
+fn normalize_domain(entry: &str) -> Option<String> {
+    let entry = entry.trim().trim_start_matches("*.").trim_start_matches('.');
+    if entry.is_empty() {
+        return None;
+    }
+    let url = if entry.contains("://") {
+        Url::parse(entry)
+    } else {
+        Url::parse(&format!("http://{}/", entry))
+    };
+    url.ok()?.host_str().map(|host| host.trim_end_matches('.').to_string())
+}
+
+/// Whether the path of a `data:` URL holds one of `DATA_IMAGE_TYPES`
+fn is_data_image(path: &str) -> bool {
+    let media_type = path.split([';', ',']).next().unwrap_or_default().trim().to_ascii_lowercase();
+    DATA_IMAGE_TYPES.contains(&media_type.as_str())
+}
+
+/// Contents of the `<style>` elements in `html`
+///
+/// This is a textual scan, so it may also pick up text that only looks
+/// like a style element; everything it returns is filtered as CSS and the
+/// elements themselves are removed by the sanitizer.
+fn style_blocks(html: &str) -> impl Iterator<Item = &str> {
+    let lower = html.to_ascii_lowercase();
+    let mut blocks = Vec::new();
+    let mut position = 0;
+
+    while let Some(start) = find_tag(&lower, "<style", position) {
+        let Some(open_end) = lower[start..].find('>').map(|end| start + end + 1) else {
+            break;
+        };
+        let close = find_tag(&lower, "</style", open_end).unwrap_or(html.len());
+        blocks.push(&html[open_end..close]);
+        position = close;
+        if close == html.len() {
+            break;
+        }
+    }
+    blocks.into_iter()
+}
+
+/// Start of the next tag named like `prefix`, ignoring longer names
+fn find_tag(lower: &str, prefix: &str, from: usize) -> Option<usize> {
+    let mut position = from;
+    while let Some(index) = lower[position..].find(prefix) {
+        let start = position + index;
+        let next = lower[start + prefix.len()..].chars().next();
+        if next.is_none_or(|c| c.is_ascii_whitespace() || c == '>' || c == '/') {
+            return Some(start);
+        }
+        position = start + prefix.len();
+    }
+    None
+}
+
+/// Filter a style sheet, keeping plain rules and `@media` blocks
+fn sanitize_stylesheet(css: &str, pass: &Pass) -> String {
+    let css = strip_css_comments(css);
+    let mut rules = Vec::new();
+    let mut rest = css.as_str();
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let Some(open) = rest.find(['{', ';']) else {
+            break;
+        };
+        let prelude = rest[..open].trim();
+        if rest[open..].starts_with(';') {
+            // Statement at-rules like @import and @charset, or stray text
+            rest = &rest[open + 1..];
+            continue;
+        }
+
+        let close = matching_brace(rest, open);
+        let block = &rest[open + 1..close];
+        rest = rest.get(close + 1..).unwrap_or("");
+
+        if !safe_prelude(prelude) {
+            continue;
+        }
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            if at_rule.to_ascii_lowercase().starts_with("media") {
+                let inner = sanitize_stylesheet(block, pass);
+                if !inner.is_empty() {
+                    rules.push(format!("{} {{\n{}\n}}", prelude, inner));
+                }
+            }
+            continue;
+        }
+        let declarations = sanitize_declarations(block, pass);
+        if !declarations.is_empty() {
+            rules.push(format!("{} {{ {} }}", prelude, declarations));
+        }
+    }
+    rules.join("\n")
+}
+
+/// Index of the brace closing the block opened at `open`, or the end of `css`
+fn matching_brace(css: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (index, c) in css[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + index;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Whether a selector or at-rule prelude can be copied as written
+fn safe_prelude(prelude: &str) -> bool {
+    let lower = prelude.to_ascii_lowercase();
+    !prelude.is_empty()
+        && !prelude.contains(['<', '>', '\\', '{', '}', '"', '\''])
+        && !lower.contains("url(")
+        && !lower.contains("expression")
+}
+
+/// Filter a declaration list, as found in `style` attributes and rules
+fn sanitize_declarations(css: &str, pass: &Pass) -> String {
+    let css = strip_css_comments(css);
+    split_declarations(&css)
+        .into_iter()
+        .filter_map(|declaration| {
+            let (name, value) = declaration.split_once(':')?;
+            let name = name.trim().to_ascii_lowercase();
+            if !allowed_property(&name, pass.strict_css) {
+                return None;
+            }
+            sanitize_value(value.trim(), pass).map(|value| format!("{}: {}", name, value))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Split on semicolons outside of parentheses and quotes
+fn split_declarations(css: &str) -> Vec<&str> {
+    let mut declarations = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (index, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, ';') if depth == 0 => {
+                declarations.push(&css[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    declarations.push(&css[start..]);
+    declarations.into_iter().filter(|declaration| !declaration.trim().is_empty()).collect()
+}
+
+fn allowed_property(name: &str, strict: bool) -> bool {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+        return false;
+    }
+    if CSS_FORBIDDEN_PROPERTIES.contains(&name) {
+        return false;
+    }
+    !strict
+        || CSS_PROPERTIES.contains(&name)
+        || CSS_PROPERTY_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// A property value free of script, loading only allowed URLs
+///
+/// Blocked remote `url()`s become `none`.
+fn sanitize_value(value: &str, pass: &Pass) -> Option<String> {
+    let lower = value.to_ascii_lowercase();
+    if value.is_empty()
+        || value.contains(['\\', '<', '>', '{', '}'])
+        || CSS_FORBIDDEN_VALUES.iter().any(|fragment| lower.contains(fragment))
+    {
+        return None;
+    }
+
+    let mut sanitized = String::with_capacity(value.len());
+    let mut last = 0;
+    let mut position = 0;
+    while let Some(start) = lower[position..].find("url(").map(|start| position + start) {
+        let end = start + 4 + lower[start + 4..].find(')')?;
+        let url = value[start + 4..end].trim().trim_matches(['"', '\'']);
+        if !pass.domains.allows_url("src", url) {
+            return None;
+        }
+        if pass.blocks(url) {
+            sanitized.push_str(&value[last..start]);
+            sanitized.push_str("none");
+            last = end + 1;
+        }
+        position = end + 1;
+    }
+    sanitized.push_str(&value[last..]);
+    Some(sanitized)
+}
+
+/// Drop tracking pixels and block remote sources of `<img>` elements
+///
+/// Runs on the sanitizer's own output, where every raw `<` starts a tag
+/// and attribute values are double quoted.
+fn filter_images(html: &str, pass: &Pass, strip_trackers: bool, trackers: &mut Vec<Tracker>) -> String {
+    let mut filtered = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        filtered.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let end = tag_end(tag);
+        let (tag, after) = tag.split_at(end);
+        rest = after;
+
+        let Some(attributes) = tag.strip_prefix("<img").filter(|rest| !rest.starts_with(|c: char| c.is_ascii_alphanumeric())) else {
+            filtered.push_str(tag);
+            continue;
+        };
+        let mut attributes = parse_attributes(attributes);
+        let src = attributes.iter()
+            .find(|(name, _)| name == "src")
+            .map(|(_, value)| decode_entities(value));
+
+        if let Some(src) = src.filter(|src| remote_host(src).is_some()) {
+            if strip_trackers {
+                if let Some(tracker) = tracking_pixel(&src, &attributes) {
+                    if !trackers.contains(&tracker) {
+                        trackers.push(tracker);
+                    }
+                    continue;
+                }
+            }
+            if pass.blocks(&src) {
+                block_image(&mut attributes);
+            }
+        }
+
+        filtered.push_str("<img");
+        for (name, value) in &attributes {
+            filtered.push_str(&format!(" {}=\"{}\"", name, value));
+        }
+        filtered.push('>');
+    }
+    filtered.push_str(rest);
+    filtered
+}
+
+/// Length of the tag at the start of `html`, honoring quoted values
+pub(crate) fn tag_end(html: &str) -> usize {
+    let mut quoted = false;
+    for (index, c) in html.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '>' if !quoted => return index + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Attribute names and still-escaped values of a serialized tag
+pub(crate) fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_end_matches('>').trim_end_matches('/');
+
+    loop {
+        rest = rest.trim_start();
+        let name_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = rest[..name_end].to_string();
+        rest = &rest[name_end..];
+
+        let value = match rest.strip_prefix("=\"") {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                rest = quoted.get(end + 1..).unwrap_or("");
+                quoted[..end].to_string()
+            }
+            None => String::new(),
+        };
+        attributes.push((name, value));
+    }
+    attributes
+}
+
+/// A tracker when `src` is on a known tracking domain, or the image is
+/// tiny or hidden
+fn tracking_pixel(src: &str, attributes: &[(String, String)]) -> Option<Tracker> {
+    let host = remote_host(src)?;
+    let service = KNOWN_TRACKERS.iter()
+        .find(|(domain, _)| covers(domain, &host))
+        .map(|(_, service)| service.to_string());
+
+    let style = attributes.iter()
+        .find(|(name, _)| name == "style")
+        .map(|(_, style)| decode_entities(style).to_ascii_lowercase())
+        .unwrap_or_default();
+    let declarations: Vec<(&str, &str)> = split_declarations(&style)
+        .into_iter()
+        .filter_map(|declaration| declaration.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+
+    let dimension = |property: &str| {
+        attributes.iter()
+            .find(|(name, _)| name == property)
+            .map(|(_, value)| value.as_str())
+            .or_else(|| declarations.iter().find(|(name, _)| *name == property).map(|(_, value)| *value))
+    };
+    let tiny = ["width", "height"].iter()
+        .filter_map(|property| dimension(property))
+        .filter_map(|value| value.trim().trim_end_matches("px").trim().parse::<f32>().ok())
+        .any(|size| size <= 1.0);
+    let hidden = declarations.iter().any(|declaration| {
+        matches!(*declaration, ("display", "none") | ("visibility", "hidden") | ("opacity", "0"))
+    });
+
+    (service.is_some() || tiny || hidden).then(|| Tracker { url: src.to_string(), service })
+}
+
+/// Show the placeholder instead of a remote image
+fn block_image(attributes: &mut Vec<(String, String)>) {
+    for (name, value) in attributes.iter_mut() {
+        if name == "src" {
+            *value = BLOCKED_IMAGE.to_string();
+        }
+    }
+    match attributes.iter_mut().find(|(name, _)| name == "class") {
+        Some((_, class)) => {
+            class.push(' ');
+            class.push_str(BLOCKED_IMAGE_CLASS);
+        }
+        None => attributes.push(("class".to_string(), BLOCKED_IMAGE_CLASS.to_string())),
+    }
+}
+
+fn strip_css_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        stripped.push(' ');
+        rest = rest[start + 2..].find("*/").map(|end| &rest[start + 2 + end + 2..]).unwrap_or("");
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hostile inputs; no output may contain any of `FORBIDDEN_OUTPUT`
+    const HOSTILE: &[&str] = &[
+        "<script>alert(1)</script>",
+        "<SCRIPT SRC=http://evil.example/x.js></SCRIPT>",
+        "<scr<script>ipt>alert(1)</scr</script>ipt>",
+        "<img src=x onerror=alert(1)>",
+        "<img src=\"javascript:alert(1)\">",
+        "<img src=\"jav&#x09;ascript:alert(1)\">",
+        "<img src=\" &#14;  javascript:alert(1)\">",
+        "<body onload=alert(1)>",
+        "<svg onload=alert(1)><script>alert(1)</script></svg>",
+        "<svg><style><img src=x onerror=alert(1)></style></svg>",
+        "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)>",
+        "<a href=\"javascript:alert(1)\">x</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">x</a>",
+        "<a href=\"vbscript:msgbox(1)\">x</a>",
+        "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">x</a>",
+        "<iframe src=\"http://evil.example\"></iframe>",
+        "<iframe srcdoc=\"<script>alert(1)</script>\"></iframe>",
+        "<object data=\"http://evil.example/x.swf\"></object><embed src=\"http://evil.example/x.swf\">",
+        "<base href=\"http://evil.example/\"><a href=\"/login\">x</a>",
+        "<form action=\"http://evil.example/\"><input name=password><button formaction=\"javascript:alert(1)\">go</button></form>",
+        "<meta http-equiv=\"refresh\" content=\"0;url=http://evil.example/\">",
+        "<link rel=stylesheet href=\"http://evil.example/x.css\">",
+        "<div style=\"width: expression(alert(1))\">x</div>",
+        "<div style=\"background: url(javascript:alert(1))\">x</div>",
+        "<div style=\"background:url('java\\73 cript:alert(1)')\">x</div>",
+        "<div style=\"-moz-binding: url(http://evil.example/x.xml#xss)\">x</div>",
+        "<div style=\"behavior: url(x.htc)\">x</div>",
+        "<div style=\"wi/**/dth: expre/**/ssion(alert(1))\">x</div>",
+        "<style>@import url(http://evil.example/x.css); body { background: url(javascript:alert(1)) }</style>",
+        "<style>p { color: red }</style><style>a { -moz-binding: url(x) }</style>",
+        "<style></stylex>body { width: expression(alert(1)) }</style>",
+        "<style>p { color: red }</style><img src=x onerror=alert(1)>",
+        "<style>div { background: red </style><script>alert(1)</script>",
+        "<!--<img src=x onerror=alert(1)>-->",
+        "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\"></noscript>",
+        "<template><script>alert(1)</script></template>",
+        "<textarea></textarea><script>alert(1)</script>",
+        "<xmp><script>alert(1)</script></xmp>",
+        "<p style=\"position: fixed; top: 0; left: 0\">fake dialog</p>",
+    ];
+
+    const FORBIDDEN_OUTPUT: &[&str] = &[
+        "<script", "<iframe", "<object", "<embed", "<base", "<form", "<input", "<button", "<meta",
+        "<link", "<svg", "<math", "<textarea", "onerror", "onload", "javascript:", "vbscript:",
+        "data:text", "srcdoc", "formaction", "expression", "-moz-binding", "behavior", "@import",
+        "position",
+    ];
+
+    #[test]
+    fn test_hostile_corpus() {
+        let sanitizer = HtmlSanitizer::default();
+        for (input, load_remote) in HOSTILE.iter().flat_map(|input| [(input, false), (input, true)]) {
+            let output = sanitizer.sanitize(input, load_remote).html.to_ascii_lowercase();
+            for forbidden in FORBIDDEN_OUTPUT {
+                assert!(!output.contains(forbidden), "{:?} survived in {:?} from {:?}", forbidden, output, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_keeps_formatting() {
+        let sanitizer = HtmlSanitizer::default();
+        let html = "<html><head><title>Newsletter</title>\
+                    <style>/* theme */ p.lead { color: #333; font-size: 16px; position: absolute }\
+                    @media (max-width: 600px) { td { padding: 4px } }\
+                    @font-face { font-family: x; src: url(http://fonts.example/x.woff) }</style></head>\
+                    <body><table width=\"600\" cellpadding=\"0\"><tr><td style=\"color: red; font-weight: bold\">\
+                    <p class=\"lead\" id=\"top\">Hi <b>there</b></p>\
+                    <a href=\"https://example.com/a?b=c\" target=\"_blank\">link</a>\
+                    <img src=\"cid:logo@example.com\" alt=\"Logo\"><img src=\"data:image/png;base64,iVBORw==\">\
+                    </td></tr></table></body></html>";
+
+        let output = sanitizer.sanitize(html, false).html;
+        assert!(output.starts_with("<style>p.lead { color: #333; font-size: 16px }\n@media (max-width: 600px) {\ntd { padding: 4px }\n}</style>"));
+        assert!(!output.contains("Newsletter"));
+        assert!(!output.contains("font-face"));
+        assert!(output.contains("<table width=\"600\" cellpadding=\"0\">"));
+        assert!(output.contains("<td style=\"color: red; font-weight: bold\">"));
+        assert!(output.contains("<p class=\"lead\">Hi <b>there</b></p>"));
+        assert!(output.contains("<a href=\"https://example.com/a?b=c\" rel=\"noopener noreferrer\">link</a>"));
+        assert!(output.contains("<img src=\"cid:logo@example.com\" alt=\"Logo\">"));
+        assert!(output.contains("<img src=\"data:image/png;base64,iVBORw==\">"));
+    }
+
+    #[test]
+    fn test_domain_policy() {
+        let config = SecurityConfig {
+            trusted_domains: vec!["Example.com".to_string(), "*.cdn.example.net".to_string()],
+            blocked_domains: vec!["tracker.example.com".to_string(), "https://evil.example/".to_string()],
+            ..SecurityConfig::default()
+        };
+        let sanitizer = HtmlSanitizer::new(&config);
+
+        assert_eq!(sanitizer.domain_policy("https://example.com/x"), DomainPolicy::Trusted);
+        assert_eq!(sanitizer.domain_policy("https://images.EXAMPLE.com/x"), DomainPolicy::Trusted);
+        assert_eq!(sanitizer.domain_policy("https://a.cdn.example.net/x"), DomainPolicy::Trusted);
+        assert_eq!(sanitizer.domain_policy("https://notexample.com/x"), DomainPolicy::Unlisted);
+        assert_eq!(sanitizer.domain_policy("https://px.tracker.example.com/x"), DomainPolicy::Blocked);
+        assert_eq!(sanitizer.domain_policy("http://evil.example./x"), DomainPolicy::Blocked);
+        assert_eq!(sanitizer.domain_policy("mailto:bob@evil.example"), DomainPolicy::Unlisted);
+
+        let output = sanitizer.sanitize(
+            "<a href=\"https://evil.example/login\">a</a><img src=\"https://px.tracker.example.com/o.gif\">\
+             <div style=\"background: url(https://evil.example/bg.png); color: red\">b</div>\
+             <img src=\"https://example.com/logo.png\">",
+            false,
+        ).html;
+        assert!(!output.contains("evil.example"));
+        assert!(!output.contains("tracker"));
+        assert!(output.contains("<a rel=\"noopener noreferrer\">a</a>"));
+        assert!(output.contains("<div style=\"color: red\">b</div>"));
+        assert!(output.contains("<img src=\"https://example.com/logo.png\">"));
+    }
+
+    #[test]
+    fn test_config_switches() {
+        let html = "<p style=\"position: absolute; color: red\">x</p><script>alert(1)</script>";
+
+        let relaxed = HtmlSanitizer::new(&SecurityConfig { allow_unsafe_html: true, ..SecurityConfig::default() });
+        assert_eq!(relaxed.sanitize(html, false).html, "<p style=\"position: absolute; color: red\">x</p>");
+
+        let disabled = HtmlSanitizer::new(&SecurityConfig { sanitize_html: false, ..SecurityConfig::default() });
+        assert_eq!(disabled.sanitize(html, false).html, html);
+    }
+
+    #[test]
+    fn test_remote_content() {
+        let sanitizer = HtmlSanitizer::default();
+        let html = "<style>td { background: #fff url('https://cdn.example.com/bg.png') no-repeat }</style>\
+                    <table background=\"https://cdn.example.com/table.png\"><tr><td>\
+                    <img src=\"https://cdn.example.com/hero.png?a=1&amp;b=2\" alt=\"Hero\" class=\"wide\">\
+                    <img src=\"cid:logo@example.com\" width=\"1\" height=\"1\">\
+                    <img src=\"https://cdn.example.com/open?id=42\" width=\"1\" height=\"1\" alt=\"\">\
+                    <img src=\"https://mc.us1.list-manage.com/track/open.php?u=1\">\
+                    <img src=\"https://cdn.example.com/p.gif\" style=\"display: none\">\
+                    </td></tr></table>";
+
+        let blocked = sanitizer.sanitize(html, false);
+        assert_eq!(blocked.blocked_remote, vec![
+            "https://cdn.example.com/bg.png",
+            "https://cdn.example.com/table.png",
+            "https://cdn.example.com/hero.png?a=1&b=2",
+        ]);
+        assert_eq!(blocked.trackers, vec![
+            Tracker { url: "https://cdn.example.com/open?id=42".to_string(), service: None },
+            Tracker { url: "https://mc.us1.list-manage.com/track/open.php?u=1".to_string(), service: Some("Mailchimp".to_string()) },
+            Tracker { url: "https://cdn.example.com/p.gif".to_string(), service: None },
+        ]);
+        assert!(blocked.html.starts_with("<style>td { background: #fff none no-repeat }</style><table><tbody><tr><td>"));
+        assert!(blocked.html.contains(&format!("<img src=\"{}\" alt=\"Hero\" class=\"wide remote-blocked\">", BLOCKED_IMAGE)));
+        assert!(blocked.html.contains("<img src=\"cid:logo@example.com\" width=\"1\" height=\"1\">"));
+        assert!(!blocked.html.contains("open?id"));
+        assert!(!blocked.html.contains("list-manage"));
+        assert!(!blocked.html.contains("p.gif"));
+
+        let loaded = sanitizer.sanitize(html, true);
+        assert!(loaded.blocked_remote.is_empty());
+        assert_eq!(loaded.trackers, blocked.trackers);
+        assert!(loaded.html.contains("url('https://cdn.example.com/bg.png')"));
+        assert!(loaded.html.contains("<table background=\"https://cdn.example.com/table.png\">"));
+        assert!(loaded.html.contains("<img src=\"https://cdn.example.com/hero.png?a=1&amp;b=2\" alt=\"Hero\" class=\"wide\">"));
+        assert!(!loaded.html.contains("list-manage"));
+
+        let tracking_allowed = HtmlSanitizer::new(&SecurityConfig {
+            block_remote_images: false,
+            strip_tracking_pixels: false,
+            ..SecurityConfig::default()
+        });
+        let output = tracking_allowed.sanitize(html, false);
+        assert!(output.blocked_remote.is_empty());
+        assert!(output.trackers.is_empty());
+        assert!(output.html.contains("list-manage"));
+    }
+}
@@ -35,6 +35,10 @@ pub struct SearchQuery {
     pub is_read: Option<bool>,
     /// Is flagged filter
     pub is_flagged: Option<bool>,
+    /// Ask the server when local results may be incomplete (Gmail only)
+    pub server_fallback: bool,
+    /// Query in Gmail search syntax, sent to the server as X-GM-RAW
+    pub raw_query: Option<String>,
 }
 
 /// Date range for search filtering
@@ -90,6 +94,54 @@ impl Default for SearchQuery {
             has_attachments: None,
             is_read: None,
             is_flagged: None,
+            server_fallback: true,
+            raw_query: None,
         }
     }
 }
+
+/// Merge server results into local ones
+///
+/// Local results keep their order; server results not found locally are
+/// appended, up to the query limit.
+pub fn merge_results(local: Vec<SearchResult>, remote: Vec<SearchResult>, limit: Option<usize>) -> Vec<SearchResult> {
+    let mut merged = local;
+    for result in remote {
+        if !merged.iter().any(|r| r.message_id == result.message_id) {
+            merged.push(result);
+        }
+    }
+
+    if let Some(limit) = limit {
+        merged.truncate(limit);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn result(message_id: Uuid) -> SearchResult {
+        SearchResult {
+            message_id,
+            account_id: Uuid::nil(),
+            mailbox_id: Uuid::nil(),
+            score: 1.0,
+            snippets: vec![],
+        }
+    }
+
+    #[test]
+    fn test_merge_results() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let merged = merge_results(vec![result(a), result(b)], vec![result(b), result(c)], None);
+        let ids: Vec<Uuid> = merged.iter().map(|r| r.message_id).collect();
+        assert_eq!(ids, vec![a, b, c]);
+
+        let merged = merge_results(vec![result(a)], vec![result(b), result(c)], Some(2));
+        assert_eq!(merged.len(), 2);
+    }
+}
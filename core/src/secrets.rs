@@ -0,0 +1,448 @@
+//! Secret storage for account credentials
+//!
+//! Passwords, OAuth2 tokens and client secrets live in the freedesktop
+//! Secret Service (via the `keyring` crate). SQLite and the config only keep
+//! opaque references such as `secret:<account id>/refresh_token`. When no
+//! keyring daemon is running, secrets go to an encrypted [`SecureStorage`]
+//! file in the data directory instead.
+
+use crate::account::{AccountConfig, CalendarTarget};
+use crate::crypto::{write_private, EncryptionKey, SecureStorage};
+use crate::error::{AsgardError, AsgardResult};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Service name used for keyring entries
+pub const SECRET_SERVICE: &str = "asgard-mail";
+
+/// Prefix marking a stored value as a secret reference
+pub const SECRET_REF_PREFIX: &str = "secret:";
+
+/// Fallback file holding encrypted secrets
+pub const SECRETS_FILENAME: &str = "secrets.enc";
+
+/// Key for the fallback file
+pub const SECRETS_KEY_FILENAME: &str = "secrets.key";
+
+/// Reference name of the OAuth2 client secret
+pub const CLIENT_SECRET: &str = "client_secret";
+
+/// Reference name of the OAuth2 access token
+pub const ACCESS_TOKEN: &str = "access_token";
+
+/// Reference name of the OAuth2 refresh token
+pub const REFRESH_TOKEN: &str = "refresh_token";
+
+/// Reference name of an account's S/MIME identity (PEM key and certificates)
+pub const SMIME_IDENTITY: &str = "smime_identity";
+
+/// Reference name of an account's Autocrypt key (armored secret key)
+pub const AUTOCRYPT_KEY: &str = "autocrypt_key";
+
+/// Reference name of the password of an account's CalDAV calendar
+pub const CALDAV_PASSWORD: &str = "caldav_password";
+
+/// Reference name of the key encrypting stored messages and cache files
+pub const STORAGE_KEY: &str = "storage_key";
+
+/// A place secrets can be written to
+pub trait SecretBackend: Send + Sync {
+    /// Human-readable backend name
+    fn name(&self) -> &str;
+
+    /// Store a secret under `key`, replacing any previous value
+    fn set(&self, key: &str, secret: &str) -> AsgardResult<()>;
+
+    /// Read the secret stored under `key`
+    fn get(&self, key: &str) -> AsgardResult<Option<String>>;
+
+    /// Remove the secret stored under `key`; missing keys are not an error
+    fn delete(&self, key: &str) -> AsgardResult<()>;
+}
+
+/// Secrets in the freedesktop Secret Service
+pub struct KeyringBackend {
+    service: String,
+}
+
+impl KeyringBackend {
+    /// Create a backend storing entries under `service`
+    pub fn new(service: impl Into<String>) -> Self {
+        Self { service: service.into() }
+    }
+
+    /// Check whether a keyring daemon answers
+    pub fn is_available(&self) -> bool {
+        match keyring::Entry::new(&self.service, "availability-probe").and_then(|entry| entry.get_password()) {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                warn!("Keyring unavailable: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl SecretBackend for KeyringBackend {
+    fn name(&self) -> &str {
+        "Secret Service"
+    }
+
+    fn set(&self, key: &str, secret: &str) -> AsgardResult<()> {
+        keyring::Entry::new(&self.service, key)?.set_password(secret)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> AsgardResult<Option<String>> {
+        match keyring::Entry::new(&self.service, key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> AsgardResult<()> {
+        match keyring::Entry::new(&self.service, key)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Secrets in an encrypted file, for sessions without a keyring daemon
+///
+/// The key sits next to the file with owner-only permissions, so this
+/// protects against secrets leaking through the database or config, not
+/// against someone who can read the user's files.
+pub struct EncryptedFileBackend {
+    path: PathBuf,
+    storage: Mutex<SecureStorage>,
+}
+
+impl EncryptedFileBackend {
+    /// Open (or create) the encrypted secrets file in `dir`
+    pub fn open(dir: &Path) -> AsgardResult<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let key_path = dir.join(SECRETS_KEY_FILENAME);
+        let key = if key_path.exists() {
+            EncryptionKey::from_bytes(&std::fs::read(&key_path)?)?
+        } else {
+            let key = EncryptionKey::new();
+            write_private(&key_path, key.as_bytes())?;
+            key
+        };
+
+        let path = dir.join(SECRETS_FILENAME);
+        let storage = SecureStorage::load(&path, key)?;
+
+        Ok(Self {
+            path,
+            storage: Mutex::new(storage),
+        })
+    }
+
+    fn lock(&self) -> AsgardResult<std::sync::MutexGuard<'_, SecureStorage>> {
+        self.storage
+            .lock()
+            .map_err(|_| AsgardError::invalid_state("Secret storage lock poisoned"))
+    }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+    fn name(&self) -> &str {
+        "encrypted file"
+    }
+
+    fn set(&self, key: &str, secret: &str) -> AsgardResult<()> {
+        let mut storage = self.lock()?;
+        storage.store(key, secret.as_bytes())?;
+        storage.save(&self.path)
+    }
+
+    fn get(&self, key: &str) -> AsgardResult<Option<String>> {
+        let storage = self.lock()?;
+        match storage.retrieve(key)? {
+            Some(bytes) => String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|_| AsgardError::crypto("Stored secret is not valid UTF-8")),
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&self, key: &str) -> AsgardResult<()> {
+        let mut storage = self.lock()?;
+        if storage.remove(key).is_some() {
+            storage.save(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores credentials and hands out opaque references to them
+pub struct SecretStore {
+    backend: Box<dyn SecretBackend>,
+}
+
+impl SecretStore {
+    /// Use the Secret Service, or an encrypted file in `data_dir` if no keyring daemon runs
+    pub fn open(data_dir: &Path) -> AsgardResult<Self> {
+        let keyring = KeyringBackend::new(SECRET_SERVICE);
+        if keyring.is_available() {
+            return Ok(Self::with_backend(Box::new(keyring)));
+        }
+
+        info!("No keyring daemon running, storing secrets in {}", data_dir.join(SECRETS_FILENAME).display());
+        Ok(Self::with_backend(Box::new(EncryptedFileBackend::open(data_dir)?)))
+    }
+
+    /// Use a specific backend
+    pub fn with_backend(backend: Box<dyn SecretBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// Name of the backend in use
+    pub fn backend_name(&self) -> &str {
+        self.backend.name()
+    }
+
+    /// Reference for one of an account's secrets
+    pub fn reference(account_id: Uuid, name: &str) -> String {
+        format!("{}{}/{}", SECRET_REF_PREFIX, account_id, name)
+    }
+
+    /// Check whether a stored value is a reference rather than a secret
+    pub fn is_reference(value: &str) -> bool {
+        value.starts_with(SECRET_REF_PREFIX)
+    }
+
+    /// Store a secret under a reference
+    pub fn store(&self, reference: &str, secret: &str) -> AsgardResult<()> {
+        self.backend.set(Self::key(reference)?, secret)
+    }
+
+    /// Look up the secret behind a reference
+    pub fn resolve(&self, reference: &str) -> AsgardResult<Option<String>> {
+        self.backend.get(Self::key(reference)?)
+    }
+
+    /// Remove the secret behind a reference
+    pub fn delete(&self, reference: &str) -> AsgardResult<()> {
+        self.backend.delete(Self::key(reference)?)
+    }
+
+    /// Get the key for encryption at rest, creating it on first use
+    ///
+    /// Losing this key (e.g. by resetting the keyring) makes encrypted
+    /// messages and cache files unreadable; they have to be synced again.
+    pub fn storage_key(&self) -> AsgardResult<EncryptionKey> {
+        let reference = format!("{}{}", SECRET_REF_PREFIX, STORAGE_KEY);
+        if let Some(encoded) = self.resolve(&reference)? {
+            let bytes = hex::decode(encoded)
+                .map_err(|_| AsgardError::crypto("Invalid storage key"))?;
+            return EncryptionKey::from_bytes(&bytes);
+        }
+
+        let key = EncryptionKey::new();
+        self.store(&reference, &hex::encode(key.as_bytes()))?;
+        Ok(key)
+    }
+
+    /// Move the secrets of an account config into the store
+    ///
+    /// Returns the config to persist, with every secret replaced by its
+    /// reference. Empty values (e.g. the client secret of a public client)
+    /// are left alone.
+    pub fn seal_config(&self, account_id: Uuid, config: &AccountConfig) -> AsgardResult<AccountConfig> {
+        let mut config = config.clone();
+
+        if let Some(oauth) = config.gmail_oauth.as_mut() {
+            if !oauth.client_secret.is_empty() {
+                oauth.client_secret = self.seal(account_id, CLIENT_SECRET, &oauth.client_secret)?;
+            }
+            if let Some(token) = oauth.access_token.as_mut() {
+                *token = self.seal(account_id, ACCESS_TOKEN, token)?;
+            }
+            if let Some(token) = oauth.refresh_token.as_mut() {
+                *token = self.seal(account_id, REFRESH_TOKEN, token)?;
+            }
+        }
+        if let Some(CalendarTarget::CalDav { password: Some(password), .. }) = config.calendar.as_mut() {
+            *password = self.seal(account_id, CALDAV_PASSWORD, password)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Replace the references in a loaded account config with the secrets
+    ///
+    /// A secret missing from the store (e.g. after the keyring was reset)
+    /// leaves the field empty, so the account asks for sign-in again.
+    pub fn unseal_config(&self, config: &mut AccountConfig) -> AsgardResult<()> {
+        if let Some(oauth) = config.gmail_oauth.as_mut() {
+            if Self::is_reference(&oauth.client_secret) {
+                oauth.client_secret = self.unseal(&oauth.client_secret)?.unwrap_or_default();
+            }
+            if let Some(token) = oauth.access_token.take() {
+                oauth.access_token = if Self::is_reference(&token) { self.unseal(&token)? } else { Some(token) };
+            }
+            if let Some(token) = oauth.refresh_token.take() {
+                oauth.refresh_token = if Self::is_reference(&token) { self.unseal(&token)? } else { Some(token) };
+            }
+        }
+        if let Some(CalendarTarget::CalDav { password, .. }) = config.calendar.as_mut() {
+            if let Some(value) = password.take() {
+                *password = if Self::is_reference(&value) { self.unseal(&value)? } else { Some(value) };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check whether an account config still holds secrets inline
+    pub fn has_inline_secrets(config: &AccountConfig) -> bool {
+        let oauth = config.gmail_oauth.as_ref().is_some_and(|oauth| {
+            (!oauth.client_secret.is_empty() && !Self::is_reference(&oauth.client_secret))
+                || oauth.access_token.as_deref().is_some_and(|t| !Self::is_reference(t))
+                || oauth.refresh_token.as_deref().is_some_and(|t| !Self::is_reference(t))
+        });
+        let caldav = matches!(
+            &config.calendar,
+            Some(CalendarTarget::CalDav { password: Some(password), .. }) if !Self::is_reference(password)
+        );
+        oauth || caldav
+    }
+
+    /// Remove every secret belonging to an account
+    pub fn delete_account(&self, account_id: Uuid) -> AsgardResult<()> {
+        for name in [CLIENT_SECRET, ACCESS_TOKEN, REFRESH_TOKEN, SMIME_IDENTITY, AUTOCRYPT_KEY, CALDAV_PASSWORD] {
+            self.delete(&Self::reference(account_id, name))?;
+        }
+        Ok(())
+    }
+
+    fn seal(&self, account_id: Uuid, name: &str, value: &str) -> AsgardResult<String> {
+        if Self::is_reference(value) {
+            return Ok(value.to_string());
+        }
+
+        let reference = Self::reference(account_id, name);
+        self.store(&reference, value)?;
+        Ok(reference)
+    }
+
+    fn unseal(&self, reference: &str) -> AsgardResult<Option<String>> {
+        let secret = self.resolve(reference)?;
+        if secret.is_none() {
+            warn!("Secret {} is missing from the {}", reference, self.backend_name());
+        }
+        Ok(secret)
+    }
+
+    fn key(reference: &str) -> AsgardResult<&str> {
+        reference
+            .strip_prefix(SECRET_REF_PREFIX)
+            .ok_or_else(|| AsgardError::validation("Not a secret reference"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Account, GmailOAuthConfig};
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir) -> SecretStore {
+        SecretStore::with_backend(Box::new(EncryptedFileBackend::open(dir.path()).unwrap()))
+    }
+
+    fn account() -> Account {
+        Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            GmailOAuthConfig {
+                client_id: "client-id".to_string(),
+                client_secret: "client-secret".to_string(),
+                access_token: Some("access-token".to_string()),
+                refresh_token: Some("refresh-token".to_string()),
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_seal_and_unseal_account() {
+        let temp_dir = TempDir::new().unwrap();
+        let secrets = store(&temp_dir);
+        let mut account = account();
+        account.config.calendar = Some(CalendarTarget::CalDav {
+            url: "https://dav.example.com/calendars/test/".to_string(),
+            username: Some("test".to_string()),
+            password: Some("caldav-password".to_string()),
+        });
+
+        let sealed = secrets.seal_config(account.id, &account.config).unwrap();
+        let serialized = serde_json::to_string(&sealed).unwrap();
+        assert!(!serialized.contains("client-secret"));
+        assert!(!serialized.contains("caldav-password"));
+        assert!(!serialized.contains("access-token"));
+        assert!(!serialized.contains("refresh-token"));
+        assert!(!SecretStore::has_inline_secrets(&sealed));
+        assert!(SecretStore::has_inline_secrets(&account.config));
+
+        let oauth = sealed.gmail_oauth.as_ref().unwrap();
+        assert_eq!(oauth.refresh_token, Some(SecretStore::reference(account.id, REFRESH_TOKEN)));
+
+        let mut loaded = account.clone();
+        loaded.config = sealed;
+        secrets.unseal_config(&mut loaded.config).unwrap();
+        let oauth = loaded.config.gmail_oauth.unwrap();
+        assert_eq!(oauth.client_secret, "client-secret");
+        assert_eq!(oauth.access_token.as_deref(), Some("access-token"));
+        assert_eq!(oauth.refresh_token.as_deref(), Some("refresh-token"));
+        assert_eq!(loaded.config.calendar, account.config.calendar);
+    }
+
+    #[test]
+    fn test_encrypted_file_survives_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let account = account();
+        let sealed = store(&temp_dir).seal_config(account.id, &account.config).unwrap();
+
+        let contents = std::fs::read_to_string(temp_dir.path().join(SECRETS_FILENAME)).unwrap();
+        assert!(!contents.contains("refresh-token"));
+
+        let reopened = store(&temp_dir);
+        let reference = sealed.gmail_oauth.unwrap().refresh_token.unwrap();
+        assert_eq!(reopened.resolve(&reference).unwrap().as_deref(), Some("refresh-token"));
+    }
+
+    #[test]
+    fn test_storage_key_is_stable() {
+        let temp_dir = TempDir::new().unwrap();
+        let key = store(&temp_dir).storage_key().unwrap();
+        let again = store(&temp_dir).storage_key().unwrap();
+        assert_eq!(key.as_bytes(), again.as_bytes());
+    }
+
+    #[test]
+    fn test_missing_secret_clears_token() {
+        let temp_dir = TempDir::new().unwrap();
+        let secrets = store(&temp_dir);
+        let account = account();
+
+        let mut loaded = account.clone();
+        loaded.config = secrets.seal_config(account.id, &account.config).unwrap();
+        secrets.delete_account(account.id).unwrap();
+
+        secrets.unseal_config(&mut loaded.config).unwrap();
+        let oauth = loaded.config.gmail_oauth.unwrap();
+        assert!(oauth.client_secret.is_empty());
+        assert_eq!(oauth.access_token, None);
+        assert_eq!(oauth.refresh_token, None);
+    }
+}
@@ -0,0 +1,743 @@
+//! S/MIME signing, encryption, decryption and verification
+//!
+//! Handles `multipart/signed` with `application/pkcs7-signature` and
+//! `application/pkcs7-mime` (signed-data and enveloped-data) per RFC 8551,
+//! using OpenSSL. Certificate chains are validated against the configured
+//! trust store; the account's own identity is imported from PKCS#12 into the
+//! [`SecretStore`], and certificates of correspondents are collected from
+//! their verified signatures so we can encrypt to them.
+
+use crate::config::SecurityConfig;
+use crate::error::{AsgardError, AsgardResult};
+use crate::message::{canonicalize, entity_body, find_entity, split_multipart, Message, MessagePart};
+use crate::secrets::{SecretStore, SMIME_IDENTITY};
+use lettre::message::{header, MultiPart, SinglePart};
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::pkey::{PKey, Private};
+use openssl::stack::Stack;
+use openssl::symm::Cipher;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::{X509Ref, X509};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Directory of collected certificates inside the data directory
+pub const SMIME_DIR: &str = "smime";
+
+/// Detached signature MIME types (the `x-` variant is still common)
+const SIGNATURE_TYPES: &[&str] = &["application/pkcs7-signature", "application/x-pkcs7-signature"];
+
+/// Opaque signed or enveloped data MIME types
+const MIME_TYPES: &[&str] = &["application/pkcs7-mime", "application/x-pkcs7-mime"];
+
+/// Digest announced in `micalg`
+const MICALG: &str = "sha-256";
+
+/// How a message is protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmimeKind {
+    /// Not protected
+    None,
+    /// `multipart/signed` with a detached signature
+    Signed,
+    /// `application/pkcs7-mime` with signed-data
+    OpaqueSigned,
+    /// `application/pkcs7-mime` with enveloped-data
+    Enveloped,
+}
+
+/// Result of checking a signature and its certificate chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmimeSignature {
+    /// No signature present
+    Unsigned,
+    /// Valid signature from a certificate chaining to the trust store
+    Valid {
+        /// Subject of the signing certificate
+        signer: String,
+        /// Whether the certificate names the sender's address
+        email_matches: bool,
+    },
+    /// The signature is intact but the certificate is not trusted
+    Untrusted {
+        /// Subject of the signing certificate
+        signer: String,
+        /// Why chain validation failed
+        reason: String,
+    },
+    /// The signature does not match the content
+    Invalid(String),
+}
+
+/// S/MIME state of a message, for display next to its headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmimeStatus {
+    /// How the message is protected
+    pub kind: SmimeKind,
+    /// Whether the message was encrypted
+    pub encrypted: bool,
+    /// Whether decryption succeeded
+    pub decrypted: bool,
+    /// Signature check result
+    pub signature: SmimeSignature,
+    /// Why processing failed, if it did
+    pub error: Option<String>,
+}
+
+impl SmimeStatus {
+    fn new(kind: SmimeKind) -> Self {
+        Self {
+            kind,
+            encrypted: kind == SmimeKind::Enveloped,
+            decrypted: false,
+            signature: SmimeSignature::Unsigned,
+            error: None,
+        }
+    }
+
+    /// Whether the message carries any S/MIME protection
+    pub fn is_protected(&self) -> bool {
+        self.kind != SmimeKind::None
+    }
+
+    /// Whether the message is signed by a trusted certificate of the sender
+    pub fn is_trusted(&self) -> bool {
+        matches!(self.signature, SmimeSignature::Valid { email_matches: true, .. })
+    }
+
+    /// Short description for the message header
+    pub fn summary(&self) -> String {
+        let signature = match &self.signature {
+            SmimeSignature::Unsigned => None,
+            SmimeSignature::Valid { signer, email_matches: true } => Some(format!("Signed by {}", signer)),
+            SmimeSignature::Valid { signer, email_matches: false } => Some(format!("Signed by {} (not the sender's address)", signer)),
+            SmimeSignature::Untrusted { signer, .. } => Some(format!("Signed by {} (untrusted certificate)", signer)),
+            SmimeSignature::Invalid(_) => Some("Bad signature".to_string()),
+        };
+
+        match (self.encrypted, self.decrypted, signature) {
+            (true, false, _) => "Encrypted, cannot decrypt".to_string(),
+            (true, true, Some(signature)) => format!("Encrypted · {}", signature),
+            (true, true, None) => "Encrypted".to_string(),
+            (false, _, Some(signature)) => signature,
+            (false, _, None) => "Not signed".to_string(),
+        }
+    }
+}
+
+/// Private key and certificate chain of one of our accounts
+pub struct SmimeIdentity {
+    key: PKey<Private>,
+    certificate: X509,
+    chain: Vec<X509>,
+}
+
+impl SmimeIdentity {
+    /// Read an identity from a PKCS#12 (`.p12` / `.pfx`) file
+    pub fn from_pkcs12(der: &[u8], password: &str) -> AsgardResult<Self> {
+        let parsed = Pkcs12::from_der(der)
+            .and_then(|pkcs12| pkcs12.parse2(password))
+            .map_err(|e| AsgardError::crypto(format!("Cannot read PKCS#12 identity: {}", e)))?;
+
+        Ok(Self {
+            key: parsed.pkey.ok_or_else(|| AsgardError::crypto("PKCS#12 file contains no private key"))?,
+            certificate: parsed.cert.ok_or_else(|| AsgardError::crypto("PKCS#12 file contains no certificate"))?,
+            chain: parsed.ca.map(|ca| ca.into_iter().collect()).unwrap_or_default(),
+        })
+    }
+
+    /// Read an identity from PEM: the private key, then the certificate and its chain
+    pub fn from_pem(pem: &str) -> AsgardResult<Self> {
+        let key = PKey::private_key_from_pem(pem.as_bytes()).map_err(ssl_error)?;
+        let mut certificates = X509::stack_from_pem(pem.as_bytes()).map_err(ssl_error)?.into_iter();
+
+        Ok(Self {
+            key,
+            certificate: certificates.next().ok_or_else(|| AsgardError::crypto("Identity contains no certificate"))?,
+            chain: certificates.collect(),
+        })
+    }
+
+    /// Serialize as PEM, in the layout read by [`Self::from_pem`]
+    pub fn to_pem(&self) -> AsgardResult<String> {
+        let mut pem = self.key.private_key_to_pem_pkcs8().map_err(ssl_error)?;
+        for certificate in std::iter::once(&self.certificate).chain(&self.chain) {
+            pem.extend(certificate.to_pem().map_err(ssl_error)?);
+        }
+        String::from_utf8(pem).map_err(|_| AsgardError::crypto("Invalid PEM data"))
+    }
+
+    /// Import a PKCS#12 identity for an account into the secret store
+    pub fn import(secrets: &SecretStore, account_id: Uuid, der: &[u8], password: &str) -> AsgardResult<Self> {
+        let identity = Self::from_pkcs12(der, password)?;
+        secrets.store(&SecretStore::reference(account_id, SMIME_IDENTITY), &identity.to_pem()?)?;
+        Ok(identity)
+    }
+
+    /// Load the identity of an account from the secret store
+    pub fn load(secrets: &SecretStore, account_id: Uuid) -> AsgardResult<Option<Self>> {
+        secrets.resolve(&SecretStore::reference(account_id, SMIME_IDENTITY))?
+            .map(|pem| Self::from_pem(&pem))
+            .transpose()
+    }
+
+    /// Email addresses the certificate is issued for
+    pub fn emails(&self) -> Vec<String> {
+        certificate_emails(&self.certificate)
+    }
+
+    /// The signing certificate
+    pub fn certificate(&self) -> &X509 {
+        &self.certificate
+    }
+}
+
+/// Build the trust store for chain validation
+///
+/// Uses the PEM file or directory of PEM files at `path`, or the system
+/// roots when no path is configured.
+pub fn trust_store(path: Option<&Path>) -> AsgardResult<X509Store> {
+    let mut builder = X509StoreBuilder::new().map_err(ssl_error)?;
+
+    match path {
+        None => builder.set_default_paths().map_err(ssl_error)?,
+        Some(path) => {
+            let files = if path.is_dir() {
+                std::fs::read_dir(path)?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                vec![path.to_path_buf()]
+            };
+            for file in files.iter().filter(|file| file.is_file()) {
+                let certificates = match X509::stack_from_pem(&std::fs::read(file)?) {
+                    Ok(certificates) => certificates,
+                    Err(e) => {
+                        warn!("Skipping trust store file {}: {}", file.display(), e);
+                        continue;
+                    }
+                };
+                for certificate in certificates {
+                    builder.add_cert(certificate).map_err(ssl_error)?;
+                }
+            }
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// S/MIME processing for one account
+pub struct SmimeContext {
+    /// Roots for chain validation
+    trust: X509Store,
+    /// Our own key and certificate
+    identity: Option<SmimeIdentity>,
+    /// Certificates of correspondents, by lowercase email address
+    certificates: RwLock<HashMap<String, X509>>,
+    /// Directory certificates are saved to (`None` keeps them in memory)
+    dir: Option<PathBuf>,
+}
+
+impl SmimeContext {
+    /// Create a context with an in-memory certificate cache
+    pub fn new(trust: X509Store, identity: Option<SmimeIdentity>) -> Self {
+        Self {
+            trust,
+            identity,
+            certificates: RwLock::new(HashMap::new()),
+            dir: None,
+        }
+    }
+
+    /// Create a context from the security settings, loading collected
+    /// certificates from `dir`
+    pub fn open(config: &SecurityConfig, identity: Option<SmimeIdentity>, dir: impl AsRef<Path>) -> AsgardResult<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut certificates = HashMap::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("pem") {
+                continue;
+            }
+            match X509::from_pem(&std::fs::read(&path)?) {
+                Ok(certificate) => {
+                    for email in certificate_emails(&certificate) {
+                        certificates.insert(email, certificate.clone());
+                    }
+                }
+                Err(e) => warn!("Skipping certificate {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self {
+            trust: trust_store(config.smime_trust_store.as_deref())?,
+            identity,
+            certificates: RwLock::new(certificates),
+            dir: Some(dir.to_path_buf()),
+        })
+    }
+
+    /// Default directory for collected certificates
+    pub fn default_dir() -> AsgardResult<PathBuf> {
+        Ok(crate::get_data_dir()?.join(SMIME_DIR))
+    }
+
+    /// Our identity, if one was imported
+    pub fn identity(&self) -> Option<&SmimeIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Remember a correspondent's certificate for encrypting to them
+    pub fn add_certificate(&self, certificate: X509) -> AsgardResult<()> {
+        let emails = certificate_emails(&certificate);
+        if emails.is_empty() {
+            return Err(AsgardError::validation("Certificate names no email address"));
+        }
+
+        if let Some(dir) = &self.dir {
+            std::fs::write(dir.join(format!("{}.pem", emails[0])), certificate.to_pem().map_err(ssl_error)?)?;
+        }
+        let mut certificates = self.certificates.write().unwrap();
+        for email in emails {
+            certificates.insert(email, certificate.clone());
+        }
+        Ok(())
+    }
+
+    /// Certificate to encrypt to `email`
+    pub fn certificate_for(&self, email: &str) -> Option<X509> {
+        let email = email.to_ascii_lowercase();
+        if let Some(identity) = &self.identity {
+            if identity.emails().contains(&email) {
+                return Some(identity.certificate.clone());
+            }
+        }
+        self.certificates.read().unwrap().get(&email).cloned()
+    }
+
+    // Reading
+
+    /// Verify and decrypt `message` in place
+    ///
+    /// Decrypted or unwrapped parts replace the S/MIME ones and detached
+    /// signatures are dropped from the attachments; `raw_content` is left
+    /// untouched. Certificates of trusted signers are remembered.
+    pub fn process(&self, message: &mut Message) -> SmimeStatus {
+        let Some(raw) = message.raw_content.clone() else {
+            return SmimeStatus::new(SmimeKind::None);
+        };
+
+        let mut status = SmimeStatus::new(detect(message, &raw));
+        if !status.is_protected() {
+            return status;
+        }
+        if let Err(e) = self.process_entity(message, &raw, &mut status) {
+            status.error = Some(e.to_string());
+        }
+        status
+    }
+
+    fn process_entity(&self, message: &mut Message, raw: &[u8], status: &mut SmimeStatus) -> AsgardResult<()> {
+        let sender = message.sender_email().map(str::to_string);
+        let parsed = mailparse::parse_mail(raw)?;
+
+        if let Some(signed) = find_entity(&parsed, "multipart/signed").filter(|signed| {
+            signed.ctype.params.get("protocol").is_some_and(|protocol| SIGNATURE_TYPES.contains(&protocol.to_ascii_lowercase().as_str()))
+        }) {
+            let boundary = signed.ctype.params.get("boundary")
+                .ok_or_else(|| AsgardError::validation("multipart/signed has no boundary"))?;
+            let parts = split_multipart(entity_body(signed.raw_bytes), boundary);
+            let (Some(content), Some(signature)) = (parts.first(), parts.get(1)) else {
+                return Err(AsgardError::validation("multipart/signed needs two parts"));
+            };
+
+            let pkcs7 = Pkcs7::from_der(&mailparse::parse_mail(signature)?.get_body_raw()?).map_err(ssl_error)?;
+            let (signature, _) = self.verify(&pkcs7, Some(&canonicalize(content)), sender.as_deref());
+            status.signature = signature;
+            message.attachments.retain(|attachment| !SIGNATURE_TYPES.contains(&attachment.mime_type.as_str()));
+            return Ok(());
+        }
+
+        let Some(opaque) = MIME_TYPES.iter().find_map(|mime_type| find_entity(&parsed, mime_type)) else {
+            return Ok(());
+        };
+        let pkcs7 = Pkcs7::from_der(&opaque.get_body_raw()?).map_err(ssl_error)?;
+        let smime_type = opaque.ctype.params.get("smime-type").map(|value| value.to_ascii_lowercase());
+
+        let entity = if smime_type.as_deref() == Some("signed-data") {
+            let (signature, entity) = self.verify(&pkcs7, None, sender.as_deref());
+            status.signature = signature;
+            entity
+        } else {
+            let identity = self.identity.as_ref()
+                .ok_or_else(|| AsgardError::crypto("No S/MIME identity to decrypt with"))?;
+            let entity = pkcs7.decrypt(&identity.key, &identity.certificate, Pkcs7Flags::empty())
+                .map_err(|e| AsgardError::crypto(format!("Cannot decrypt message: {}", e)))?;
+            status.decrypted = true;
+            entity
+        };
+
+        message.replace_body(&entity)?;
+
+        // Signed-then-enveloped messages carry the signature inside
+        let inner = mailparse::parse_mail(&entity)?;
+        let inner_type = inner.ctype.mimetype.to_ascii_lowercase();
+        if inner_type == "multipart/signed" || MIME_TYPES.contains(&inner_type.as_str()) {
+            self.process_entity(message, &entity, status)?;
+        }
+        Ok(())
+    }
+
+    /// Check a signature and its certificate chain
+    ///
+    /// Returns the status and, for opaque signatures, the signed content.
+    fn verify(&self, pkcs7: &Pkcs7, content: Option<&[u8]>, sender: Option<&str>) -> (SmimeSignature, Vec<u8>) {
+        let Ok(no_certificates) = Stack::new() else {
+            return (SmimeSignature::Invalid("Out of memory".to_string()), vec![]);
+        };
+        let signer = pkcs7.signers(&no_certificates, Pkcs7Flags::empty()).ok()
+            .and_then(|signers| signers.iter().next().map(|certificate| certificate.to_owned()));
+        let signer_name = signer.as_deref().map(certificate_subject).unwrap_or_else(|| "unknown signer".to_string());
+
+        let mut signed_content = Vec::new();
+        let chain_error = match pkcs7.verify(&no_certificates, &self.trust, content, Some(&mut signed_content), Pkcs7Flags::BINARY) {
+            Ok(()) => {
+                let email_matches = match (&signer, sender) {
+                    (Some(certificate), Some(sender)) => certificate_emails(certificate).contains(&sender.to_ascii_lowercase()),
+                    _ => false,
+                };
+                if let Some(certificate) = signer.filter(|_| email_matches) {
+                    if let Err(e) = self.add_certificate(certificate) {
+                        warn!("Cannot remember S/MIME certificate: {}", e);
+                    }
+                }
+                return (SmimeSignature::Valid { signer: signer_name, email_matches }, signed_content);
+            }
+            Err(e) => e,
+        };
+
+        // Tell an untrusted certificate apart from a broken signature
+        signed_content.clear();
+        match pkcs7.verify(&no_certificates, &self.trust, content, Some(&mut signed_content), Pkcs7Flags::BINARY | Pkcs7Flags::NOVERIFY) {
+            Ok(()) => (SmimeSignature::Untrusted { signer: signer_name, reason: chain_error.to_string() }, signed_content),
+            Err(e) => (SmimeSignature::Invalid(e.to_string()), vec![]),
+        }
+    }
+
+    // Writing
+
+    /// Wrap `part` in a `multipart/signed` entity signed with our identity
+    pub fn sign_part(&self, part: SinglePart) -> AsgardResult<MultiPart> {
+        let signature = self.signature_part(&part.formatted())?;
+        Ok(MultiPart::signed(SIGNATURE_TYPES[0].to_string(), MICALG.to_string())
+            .singlepart(part)
+            .singlepart(signature))
+    }
+
+    /// Like [`SmimeContext::sign_part`] for a multipart entity
+    pub fn sign_multipart(&self, part: MultiPart) -> AsgardResult<MultiPart> {
+        let signature = self.signature_part(&part.formatted())?;
+        Ok(MultiPart::signed(SIGNATURE_TYPES[0].to_string(), MICALG.to_string())
+            .multipart(part)
+            .singlepart(signature))
+    }
+
+    /// Encrypt `part` as `application/pkcs7-mime` enveloped-data for `recipients`
+    ///
+    /// With `sign` set, the part is signed first (`multipart/signed` inside
+    /// the envelope). Fails if any recipient has no known certificate.
+    pub fn encrypt_part(&self, recipients: &[&str], sign: bool, part: SinglePart) -> AsgardResult<SinglePart> {
+        let certificates = self.recipient_certificates(recipients)?;
+        let entity = if sign {
+            self.sign_part(part)?.formatted()
+        } else {
+            part.formatted()
+        };
+        envelope(&certificates, &entity)
+    }
+
+    /// Like [`SmimeContext::encrypt_part`] for a multipart entity
+    pub fn encrypt_multipart(&self, recipients: &[&str], sign: bool, part: MultiPart) -> AsgardResult<SinglePart> {
+        let certificates = self.recipient_certificates(recipients)?;
+        let entity = if sign {
+            self.sign_multipart(part)?.formatted()
+        } else {
+            part.formatted()
+        };
+        envelope(&certificates, &entity)
+    }
+
+    /// Detached `smime.p7s` part over a formatted MIME entity
+    fn signature_part(&self, entity: &[u8]) -> AsgardResult<SinglePart> {
+        let identity = self.identity.as_ref()
+            .ok_or_else(|| AsgardError::not_found("No S/MIME identity to sign with"))?;
+
+        // The CRLF before the next boundary belongs to the delimiter
+        let entity = entity.strip_suffix(b"\r\n").unwrap_or(entity);
+        let signature = sign(identity, &canonicalize(entity), Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY)?;
+
+        Ok(SinglePart::builder()
+            .header(content_type(&format!("{}; name=\"smime.p7s\"", SIGNATURE_TYPES[0]))?)
+            .header(header::ContentDisposition::attachment("smime.p7s"))
+            .body(signature))
+    }
+
+    /// Certificates of all `recipients`, failing if any is unknown
+    fn recipient_certificates(&self, recipients: &[&str]) -> AsgardResult<Stack<X509>> {
+        let mut certificates = Stack::new().map_err(ssl_error)?;
+        let mut missing = Vec::new();
+        for recipient in recipients {
+            match self.certificate_for(recipient) {
+                Some(certificate) => certificates.push(certificate).map_err(ssl_error)?,
+                None => missing.push(*recipient),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(AsgardError::not_found(format!("No S/MIME certificate for {}", missing.join(", "))));
+        }
+        Ok(certificates)
+    }
+}
+
+/// `application/pkcs7-mime` enveloped-data part holding `entity`
+fn envelope(certificates: &Stack<X509>, entity: &[u8]) -> AsgardResult<SinglePart> {
+    let envelope = Pkcs7::encrypt(certificates, entity, Cipher::aes_256_cbc(), Pkcs7Flags::BINARY)
+        .and_then(|pkcs7| pkcs7.to_der())
+        .map_err(ssl_error)?;
+
+    Ok(SinglePart::builder()
+        .header(content_type(&format!("{}; smime-type=enveloped-data; name=\"smime.p7m\"", MIME_TYPES[0]))?)
+        .header(header::ContentDisposition::attachment("smime.p7m"))
+        .body(envelope))
+}
+
+/// Detect S/MIME protection from the message parts, using the raw
+/// message to tell signed-data from enveloped-data
+pub fn detect(message: &Message, raw: &[u8]) -> SmimeKind {
+    let mut mime_types = Vec::new();
+    collect_mime_types(&message.parts, &mut mime_types);
+    mime_types.extend(message.attachments.iter().map(|attachment| attachment.mime_type.as_str()));
+
+    if mime_types.iter().any(|mime_type| SIGNATURE_TYPES.contains(mime_type)) {
+        return SmimeKind::Signed;
+    }
+    if !mime_types.iter().any(|mime_type| MIME_TYPES.contains(mime_type)) {
+        return SmimeKind::None;
+    }
+
+    let smime_type = mailparse::parse_mail(raw).ok().and_then(|parsed| {
+        MIME_TYPES.iter()
+            .find_map(|mime_type| find_entity(&parsed, mime_type))
+            .and_then(|entity| entity.ctype.params.get("smime-type").cloned())
+    });
+    match smime_type.as_deref() {
+        Some(value) if value.eq_ignore_ascii_case("signed-data") => SmimeKind::OpaqueSigned,
+        _ => SmimeKind::Enveloped,
+    }
+}
+
+fn collect_mime_types<'a>(parts: &'a [MessagePart], mime_types: &mut Vec<&'a str>) {
+    for part in parts {
+        mime_types.push(part.mime_type.as_str());
+        collect_mime_types(&part.children, mime_types);
+    }
+}
+
+fn sign(identity: &SmimeIdentity, data: &[u8], flags: Pkcs7Flags) -> AsgardResult<Vec<u8>> {
+    let mut chain = Stack::new().map_err(ssl_error)?;
+    for certificate in &identity.chain {
+        chain.push(certificate.clone()).map_err(ssl_error)?;
+    }
+    Pkcs7::sign(&identity.certificate, &identity.key, &chain, data, flags)
+        .and_then(|pkcs7| pkcs7.to_der())
+        .map_err(ssl_error)
+}
+
+/// Lowercase email addresses in the subject alternative names and subject
+fn certificate_emails(certificate: &X509Ref) -> Vec<String> {
+    let mut emails: Vec<String> = certificate.subject_alt_names()
+        .map(|names| names.iter().filter_map(|name| name.email().map(str::to_ascii_lowercase)).collect())
+        .unwrap_or_default();
+    for entry in certificate.subject_name().entries_by_nid(Nid::PKCS9_EMAILADDRESS) {
+        if let Ok(email) = std::str::from_utf8(entry.data().as_slice()) {
+            let email = email.to_ascii_lowercase();
+            if !emails.contains(&email) {
+                emails.push(email);
+            }
+        }
+    }
+    emails
+}
+
+/// Display name of a certificate: common name, else the first email
+fn certificate_subject(certificate: &X509Ref) -> String {
+    certificate.subject_name().entries_by_nid(Nid::COMMONNAME).next()
+        .and_then(|entry| std::str::from_utf8(entry.data().as_slice()).ok().map(str::to_string))
+        .or_else(|| certificate_emails(certificate).into_iter().next())
+        .unwrap_or_else(|| "unnamed certificate".to_string())
+}
+
+fn content_type(value: &str) -> AsgardResult<header::ContentType> {
+    header::ContentType::parse(value)
+        .map_err(|e| AsgardError::validation(format!("Invalid content type {}: {}", value, e)))
+}
+
+fn ssl_error(e: openssl::error::ErrorStack) -> AsgardError {
+    AsgardError::crypto(format!("S/MIME: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lettre::Message as LettreMessage;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::X509NameBuilder;
+
+    fn certificate(name: &str, email: Option<&str>, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(rand::random::<u16>() as u32).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(issuer.map(|(ca, _)| ca.subject_name()).unwrap_or(&subject)).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(30).unwrap()).unwrap();
+        match email {
+            Some(email) => {
+                let san = SubjectAlternativeName::new().email(email).build(&builder.x509v3_context(issuer.map(|(ca, _)| &**ca), None)).unwrap();
+                builder.append_extension(san).unwrap();
+            }
+            None => builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap(),
+        }
+        builder.sign(issuer.map(|(_, key)| key).unwrap_or(&key), MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    fn trust(ca: &X509) -> X509Store {
+        let mut builder = X509StoreBuilder::new().unwrap();
+        builder.add_cert(ca.clone()).unwrap();
+        builder.build()
+    }
+
+    fn identity(ca: &(X509, PKey<Private>), name: &str, email: &str) -> SmimeIdentity {
+        let (certificate, key) = certificate(name, Some(email), Some((&ca.0, &ca.1)));
+        SmimeIdentity { key, certificate, chain: vec![ca.0.clone()] }
+    }
+
+    fn send(from: &str, body: SinglePart, signed: Option<MultiPart>) -> Message {
+        let builder = LettreMessage::builder()
+            .from(from.parse().unwrap())
+            .to("Bob <bob@example.com>".parse().unwrap())
+            .subject("Budget");
+        let email = match signed {
+            Some(multipart) => builder.multipart(multipart).unwrap(),
+            None => builder.singlepart(body).unwrap(),
+        };
+        Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), &email.formatted()).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify_chain() {
+        let ca = certificate("Corp Root CA", None, None);
+        let alice = SmimeContext::new(trust(&ca.0), Some(identity(&ca, "Alice", "alice@example.com")));
+
+        let signed = alice.sign_part(SinglePart::plain("Approved.\nShip it.".to_string())).unwrap();
+        let message = send("Alice <alice@example.com>", SinglePart::plain(String::new()), Some(signed));
+        assert_eq!(detect(&message, message.raw_content.as_deref().unwrap()), SmimeKind::Signed);
+
+        // Bob trusts the corporate root and learns Alice's certificate
+        let bob = SmimeContext::new(trust(&ca.0), None);
+        let mut processed = message.clone();
+        let status = bob.process(&mut processed);
+        assert!(status.is_trusted(), "{:?}", status);
+        assert!(processed.attachments.is_empty());
+        assert!(bob.certificate_for("alice@example.com").is_some());
+
+        // Someone trusting another root sees an intact but untrusted signature
+        let other_ca = certificate("Other Root CA", None, None);
+        let status = SmimeContext::new(trust(&other_ca.0), None).process(&mut message.clone());
+        assert!(matches!(status.signature, SmimeSignature::Untrusted { .. }));
+
+        // A forged sender does not match the certificate
+        let raw = String::from_utf8(message.raw_content.clone().unwrap()).unwrap();
+        let forged = raw.replace("alice@example.com", "ceo@example.com");
+        let mut forged = Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), forged.as_bytes()).unwrap();
+        let status = SmimeContext::new(trust(&ca.0), None).process(&mut forged);
+        assert!(matches!(status.signature, SmimeSignature::Valid { email_matches: false, .. }));
+
+        // Tampered content breaks the signature
+        let tampered = raw.replace("Ship it.", "Scrap it.");
+        let mut tampered = Message::from_rfc822(Uuid::new_v4(), Uuid::new_v4(), tampered.as_bytes()).unwrap();
+        let status = SmimeContext::new(trust(&ca.0), None).process(&mut tampered);
+        assert!(matches!(status.signature, SmimeSignature::Invalid(_)));
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let ca = certificate("Corp Root CA", None, None);
+        let alice_identity = identity(&ca, "Alice", "alice@example.com");
+        let bob_identity = identity(&ca, "Bob", "bob@example.com");
+        let bob_certificate = bob_identity.certificate.clone();
+
+        let alice = SmimeContext::new(trust(&ca.0), Some(alice_identity));
+        let part = SinglePart::plain("Salary bands attached.".to_string());
+        assert!(alice.encrypt_part(&["bob@example.com"], false, part.clone()).is_err());
+
+        alice.add_certificate(bob_certificate).unwrap();
+        let envelope = alice.encrypt_part(&["bob@example.com", "alice@example.com"], true, part).unwrap();
+        let message = send("Alice <alice@example.com>", envelope, None);
+        let raw = message.raw_content.clone().unwrap();
+        assert_eq!(detect(&message, &raw), SmimeKind::Enveloped);
+        assert!(!String::from_utf8_lossy(&raw).contains("Salary"));
+
+        let bob = SmimeContext::new(trust(&ca.0), Some(bob_identity));
+        let mut processed = message.clone();
+        let status = bob.process(&mut processed);
+        assert!(status.decrypted, "{:?}", status.error);
+        assert!(status.is_trusted());
+        let text = String::from_utf8_lossy(processed.text_content().unwrap()).into_owned();
+        assert!(text.contains("Salary bands attached."));
+
+        let status = SmimeContext::new(trust(&ca.0), None).process(&mut message.clone());
+        assert_eq!(status.summary(), "Encrypted, cannot decrypt");
+    }
+
+    #[test]
+    fn test_identity_round_trip() {
+        let ca = certificate("Corp Root CA", None, None);
+        let original = identity(&ca, "Alice", "Alice@Example.com");
+
+        let pkcs12 = Pkcs12::builder()
+            .name("Alice")
+            .pkey(&original.key)
+            .cert(&original.certificate)
+            .build2("hunter2")
+            .unwrap()
+            .to_der()
+            .unwrap();
+        assert!(SmimeIdentity::from_pkcs12(&pkcs12, "wrong").is_err());
+
+        let identity = SmimeIdentity::from_pkcs12(&pkcs12, "hunter2").unwrap();
+        assert_eq!(identity.emails(), vec!["alice@example.com".to_string()]);
+
+        let restored = SmimeIdentity::from_pem(&identity.to_pem().unwrap()).unwrap();
+        assert_eq!(restored.certificate.to_der().unwrap(), original.certificate.to_der().unwrap());
+    }
+}
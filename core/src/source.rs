@@ -0,0 +1,219 @@
+//! Original message source for the "Show original" view
+//!
+//! Keeps the message exactly as the server sent it and offers the pieces the
+//! viewer needs: header fields as folded on the wire, the undecoded body and
+//! a decoded rendering with transfer encodings and charsets undone.
+
+use crate::error::AsgardResult;
+use crate::message::entity_body;
+
+/// Longest subject prefix used for a saved file name
+const MAX_FILENAME_CHARS: usize = 80;
+
+/// A header field as it appears in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    /// Field name, empty for a malformed line without a colon
+    pub name: String,
+    /// Everything after the colon, folding kept and line breaks as `\n`
+    pub value: String,
+}
+
+/// Raw RFC 822 source of a message
+#[derive(Debug, Clone)]
+pub struct MessageSource {
+    raw: Vec<u8>,
+}
+
+impl MessageSource {
+    /// Wrap the raw bytes of a message
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    /// The bytes as received, for saving as .eml
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Size of the source in bytes
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Whether the source is empty
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Header fields of the top-level entity, in order
+    pub fn headers(&self) -> Vec<HeaderField> {
+        let text = String::from_utf8_lossy(&self.raw);
+        let mut fields: Vec<HeaderField> = Vec::new();
+
+        for line in text.lines() {
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with([' ', '\t']) {
+                if let Some(field) = fields.last_mut() {
+                    field.value.push('\n');
+                    field.value.push_str(line);
+                    continue;
+                }
+            }
+            let field = match line.split_once(':') {
+                Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => HeaderField {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                },
+                _ => HeaderField { name: String::new(), value: line.to_string() },
+            };
+            fields.push(field);
+        }
+
+        fields
+    }
+
+    /// Body exactly as transferred, with encodings left in place
+    pub fn body(&self) -> String {
+        String::from_utf8_lossy(entity_body(&self.raw)).into_owned()
+    }
+
+    /// Body with transfer encodings and charsets decoded
+    ///
+    /// Each part of a multipart message is introduced by its number and
+    /// content type; parts that are not text are summarized by size.
+    pub fn decoded_body(&self) -> AsgardResult<String> {
+        let parsed = mailparse::parse_mail(&self.raw)?;
+        let mut output = String::new();
+        render_part(&parsed, "", &mut output)?;
+        Ok(output)
+    }
+}
+
+/// Suggested file name for saving a message as .eml
+pub fn eml_filename(subject: &str) -> String {
+    let name: String = subject
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let name: String = name.trim_matches(['.', '_', ' ']).chars().take(MAX_FILENAME_CHARS).collect();
+
+    if name.is_empty() {
+        "message.eml".to_string()
+    } else {
+        format!("{}.eml", name.trim_end())
+    }
+}
+
+/// Append the decoded content of a MIME entity and its children
+fn render_part(part: &mailparse::ParsedMail<'_>, id: &str, output: &mut String) -> AsgardResult<()> {
+    if !part.subparts.is_empty() {
+        for (index, child) in part.subparts.iter().enumerate() {
+            let child_id = if id.is_empty() {
+                (index + 1).to_string()
+            } else {
+                format!("{}.{}", id, index + 1)
+            };
+            if child.subparts.is_empty() {
+                if !output.is_empty() && !output.ends_with("\n\n") {
+                    output.push_str(if output.ends_with('\n') { "\n" } else { "\n\n" });
+                }
+                output.push_str(&format!("[{}] {}\n", child_id, describe(child)));
+            }
+            render_part(child, &child_id, output)?;
+        }
+        return Ok(());
+    }
+
+    let mimetype = part.ctype.mimetype.to_ascii_lowercase();
+    if mimetype.starts_with("text/") || mimetype == "message/rfc822" {
+        output.push_str(&part.get_body()?);
+    } else {
+        output.push_str(&format!("({} bytes not shown)\n", part.get_body_raw()?.len()));
+    }
+    Ok(())
+}
+
+/// Content type of a part, with its file name when it has one
+fn describe(part: &mailparse::ParsedMail<'_>) -> String {
+    let disposition = part.get_content_disposition();
+    let filename = disposition.params.get("filename")
+        .or_else(|| part.ctype.params.get("name"));
+    match filename {
+        Some(filename) => format!("{} \"{}\"", part.ctype.mimetype, filename),
+        None => part.ctype.mimetype.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTIPART: &str = "From: Alice <alice@example.com>\r\n\
+        Received: from mx.example.com\r\n\tby mail.example.org; Mon, 2 Feb 2026 10:00:00 +0000\r\n\
+        Subject: Report\r\n\
+        MIME-Version: 1.0\r\n\
+        Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+        \r\n\
+        --b1\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Gr=C3=BC=C3=9Fe\r\n\
+        --b1\r\n\
+        Content-Type: application/pdf; name=\"report.pdf\"\r\n\
+        Content-Disposition: attachment; filename=\"report.pdf\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        JVBERi0xLjQ=\r\n\
+        --b1--\r\n";
+
+    #[test]
+    fn test_headers_keep_folding() {
+        let source = MessageSource::new(MULTIPART.as_bytes().to_vec());
+        let headers = source.headers();
+
+        assert_eq!(headers.len(), 5);
+        assert_eq!(headers[0], HeaderField { name: "From".to_string(), value: " Alice <alice@example.com>".to_string() });
+        assert_eq!(headers[1].name, "Received");
+        assert_eq!(headers[1].value, " from mx.example.com\n\tby mail.example.org; Mon, 2 Feb 2026 10:00:00 +0000");
+        assert_eq!(headers[4].name, "Content-Type");
+    }
+
+    #[test]
+    fn test_body_views() {
+        let source = MessageSource::new(MULTIPART.as_bytes().to_vec());
+
+        let body = source.body();
+        assert!(body.starts_with("--b1\r\n"));
+        assert!(body.contains("Gr=C3=BC=C3=9Fe"));
+
+        let decoded = source.decoded_body().unwrap();
+        assert!(decoded.contains("[1] text/plain\n"));
+        assert!(decoded.contains("Grüße"));
+        assert!(decoded.contains("[2] application/pdf \"report.pdf\"\n"));
+        assert!(decoded.contains("(8 bytes not shown)"));
+        assert!(!decoded.contains("JVBERi0xLjQ="));
+    }
+
+    #[test]
+    fn test_single_part_decoding() {
+        let raw = b"Subject: Hi\r\nContent-Transfer-Encoding: base64\r\n\r\nSGVsbG8gdGhlcmU=\r\n";
+        let source = MessageSource::new(raw.to_vec());
+        assert_eq!(source.decoded_body().unwrap(), "Hello there");
+    }
+
+    #[test]
+    fn test_eml_filename() {
+        assert_eq!(eml_filename("Re: Invoice 12/2026"), "Re_ Invoice 12_2026.eml");
+        assert_eq!(eml_filename("  multiple   spaces\there "), "multiple spaces_here.eml");
+        assert_eq!(eml_filename(""), "message.eml");
+        assert_eq!(eml_filename("..."), "message.eml");
+        assert_eq!(eml_filename(&"x".repeat(200)).len(), MAX_FILENAME_CHARS + 4);
+    }
+}
@@ -1,5 +1,6 @@
 //! Cache layer for Asgard Mail
 
+use crate::crypto::StorageCipher;
 use crate::error::{AsgardError, AsgardResult};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -46,6 +47,9 @@ pub struct CacheStats {
 }
 
 /// File-based cache for storing message content and attachments
+///
+/// Cloning is cheap and the clones share entries and statistics.
+#[derive(Clone)]
 pub struct Cache {
     /// Cache directory
     cache_dir: PathBuf,
@@ -55,6 +59,8 @@ pub struct Cache {
     entries: Arc<RwLock<HashMap<String, CacheEntry>>>,
     /// Cache statistics
     stats: Arc<RwLock<CacheStats>>,
+    /// Encryption of cached files, if set up
+    cipher: Option<Arc<StorageCipher>>,
 }
 
 impl Cache {
@@ -73,6 +79,7 @@ impl Cache {
             max_size: 500 * 1024 * 1024, // 500MB default
             entries: Arc::new(RwLock::new(HashMap::new())),
             stats: Arc::new(RwLock::new(CacheStats::default())),
+            cipher: None,
         };
         
         // Load existing cache entries
@@ -104,12 +111,20 @@ impl Cache {
         self.max_size = max_size;
     }
 
+    /// Encrypt cached files with `cipher` while it is enabled
+    pub fn set_cipher(&mut self, cipher: Arc<StorageCipher>) {
+        self.cipher = Some(cipher);
+    }
+
     /// Get cache statistics
     pub async fn get_stats(&self) -> CacheStats {
         self.stats.read().await.clone()
     }
 
     /// Store data in the cache
+    ///
+    /// The content hash is taken over the plain data, so deduplication and
+    /// [`Cache::retrieve_by_hash`] work the same with encryption enabled.
     pub async fn store(&self, key: &str, data: &[u8], content_type: &str, ttl: Option<time::Duration>) -> AsgardResult<()> {
         let content_hash = self.calculate_hash(data);
         
@@ -117,6 +132,9 @@ impl Cache {
         if let Some(existing_entry) = self.find_by_hash(&content_hash).await? {
             // Create a hard link to the existing file
             let new_path = self.get_file_path(key);
+            if let Some(parent) = new_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
             std::fs::hard_link(&existing_entry.file_path, &new_path)?;
             
             // Update metadata
@@ -137,7 +155,10 @@ impl Cache {
         
         // Store new content
         let file_path = self.get_file_path(key);
-        std::fs::write(&file_path, data)?;
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file_path, self.encode(data)?)?;
         
         let entry = CacheEntry {
             key: key.to_string(),
@@ -152,9 +173,6 @@ impl Cache {
         
         self.entries.write().await.insert(key.to_string(), entry);
         
-        // Update statistics
-        self.update_stats_hit().await;
-        
         // Check if we need to enforce size limit
         self.enforce_size_limit().await?;
         
@@ -184,7 +202,7 @@ impl Cache {
             match std::fs::read(&entry.file_path) {
                 Ok(data) => {
                     self.update_stats_hit().await;
-                    Ok(Some(data))
+                    Ok(Some(self.decode(&data)?))
                 }
                 Err(_) => {
                     // File doesn't exist, remove entry
@@ -199,6 +217,53 @@ impl Cache {
         }
     }
 
+    /// Retrieve data by the hash of its content
+    pub async fn retrieve_by_hash(&self, content_hash: &str) -> AsgardResult<Option<Vec<u8>>> {
+        match self.find_by_hash(content_hash).await? {
+            Some(entry) => self.retrieve(&entry.key).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Re-encode every cached file to match the encryption setting
+    ///
+    /// Walks the cache directory rather than the entries, so files left by
+    /// earlier sessions are converted too. Returns the number of files
+    /// rewritten.
+    pub async fn reencode(&self) -> AsgardResult<usize> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher.clone(),
+            None => return Ok(0),
+        };
+
+        let mut pending = vec![self.cache_dir.clone()];
+        let mut converted = 0;
+        while let Some(dir) = pending.pop() {
+            for dir_entry in std::fs::read_dir(&dir)? {
+                let path = dir_entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let data = std::fs::read(&path)?;
+                if !cipher.needs_reencode(&data) {
+                    continue;
+                }
+
+                // Write next to the file and rename, so a crash never leaves half a file
+                let tmp_path = path.with_extension("reencode");
+                std::fs::write(&tmp_path, cipher.seal(&cipher.open(&data)?)?)?;
+                std::fs::rename(&tmp_path, &path)?;
+                converted += 1;
+
+                tokio::task::yield_now().await;
+            }
+        }
+
+        Ok(converted)
+    }
+
     /// Remove data from the cache
     pub async fn remove(&self, key: &str) -> AsgardResult<bool> {
         let mut entries = self.entries.write().await;
@@ -340,6 +405,20 @@ impl Cache {
         }
     }
 
+    fn encode(&self, data: &[u8]) -> AsgardResult<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher.seal(data),
+            None => Ok(data.to_vec()),
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> AsgardResult<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => cipher.open(data),
+            None => Ok(data.to_vec()),
+        }
+    }
+
     fn calculate_hash(&self, data: &[u8]) -> String {
         let mut hasher = Sha256::new();
         hasher.update(data);
@@ -399,7 +478,7 @@ mod tests {
     async fn test_cache_creation() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
     }
 
@@ -407,7 +486,7 @@ mod tests {
     async fn test_cache_store_retrieve() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let data = b"Hello, World!";
@@ -428,7 +507,7 @@ mod tests {
     async fn test_cache_expiration() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let data = b"Hello, World!";
@@ -452,7 +531,7 @@ mod tests {
     async fn test_cache_deduplication() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let data = b"Hello, World!";
@@ -475,7 +554,7 @@ mod tests {
     async fn test_message_cache() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let message_id = Uuid::new_v4();
@@ -493,7 +572,7 @@ mod tests {
     async fn test_attachment_cache() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let attachment_id = Uuid::new_v4();
@@ -511,7 +590,7 @@ mod tests {
     async fn test_cache_stats() {
         let temp_dir = TempDir::new().unwrap();
         let cache_dir = temp_dir.path().to_path_buf();
-        let cache = Cache::new(cache_dir).await.unwrap();
+        let mut cache = Cache::new(cache_dir).await.unwrap();
         cache.initialize().await.unwrap();
 
         let data = b"Hello, World!";
@@ -527,4 +606,40 @@ mod tests {
         assert_eq!(stats.misses, 1);
         assert_eq!(stats.hit_ratio, 0.5);
     }
+
+    #[tokio::test]
+    async fn test_encrypted_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut cache = Cache::new(temp_dir.path().to_path_buf()).await.unwrap();
+        let cipher = Arc::new(StorageCipher::new(crate::crypto::EncryptionKey::new(), false));
+        cache.set_cipher(cipher.clone());
+
+        let message_id = Uuid::new_v4();
+        cache.store_message(message_id, b"Plain message body", "message/rfc822").await.unwrap();
+
+        // Turn encryption on and migrate what is already on disk
+        cipher.set_enabled(true);
+        assert_eq!(cache.reencode().await.unwrap(), 1);
+        assert_eq!(cache.reencode().await.unwrap(), 0);
+
+        let attachment_id = Uuid::new_v4();
+        cache.store_attachment(attachment_id, b"Secret attachment", "application/pdf").await.unwrap();
+
+        for key in [format!("message:{}", message_id), format!("attachment:{}", attachment_id)] {
+            let on_disk = std::fs::read(&cache.get_entry(&key).await.unwrap().file_path).unwrap();
+            assert!(StorageCipher::is_sealed(&on_disk));
+        }
+
+        assert_eq!(cache.retrieve_message(message_id).await.unwrap().unwrap(), b"Plain message body");
+
+        // Lookup by content hash still uses the plain content
+        let hash = crate::crypto::DataIntegrity::sha256(b"Secret attachment");
+        assert_eq!(cache.get_entry(&format!("attachment:{}", attachment_id)).await.unwrap().content_hash, hash);
+        assert_eq!(cache.retrieve_by_hash(&hash).await.unwrap().unwrap(), b"Secret attachment");
+
+        // And back to plaintext
+        cipher.set_enabled(false);
+        assert_eq!(cache.reencode().await.unwrap(), 2);
+        assert_eq!(cache.retrieve_attachment(attachment_id).await.unwrap().unwrap(), b"Secret attachment");
+    }
 }
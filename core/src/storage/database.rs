@@ -1,11 +1,16 @@
 //! Database layer for Asgard Mail
 
 use crate::error::{AsgardError, AsgardResult};
-use crate::account::{Account, AccountStats};
-use crate::mailbox::{Mailbox, MailboxStats};
-use crate::message::{Message, MessageFlags, Attachment, MessagePart};
+use crate::account::{Account, AccountConfig, AccountStats};
+use crate::mailbox::{Mailbox, MailboxStats, MailboxType};
+use crate::message::{Message, MessageFlags, Attachment, MessagePart, EmailAddress, MessageHeaders};
+use crate::crypto::{DataIntegrity, StorageCipher};
+use crate::autocrypt::PeerState;
+use crate::sanitizer::Tracker;
+use crate::secrets::SecretStore;
 use rusqlite::{Connection, Result as SqliteResult, Row, params};
 use serde_json;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -13,13 +18,46 @@ use time::OffsetDateTime;
 use uuid::Uuid;
 
 /// Database connection wrapper
+///
+/// Cloning is cheap and the clones share the connection.
+#[derive(Clone)]
 pub struct Database {
     connection: Arc<Mutex<Connection>>,
+    /// Where account credentials are kept; rows only hold references
+    secrets: Arc<SecretStore>,
+    /// Encryption of message bodies, parts and attachments
+    cipher: Arc<StorageCipher>,
 }
 
+/// Rows re-encoded per lock of the connection during an encryption migration
+const REENCODE_BATCH_SIZE: usize = 50;
+
+/// Columns holding message content: (table, id column, content column)
+const CONTENT_COLUMNS: [(&str, &str, &str); 3] = [
+    ("messages", "id", "raw_content"),
+    ("message_parts", "id", "content"),
+    ("attachments", "id", "content"),
+];
+
 impl Database {
     /// Create a new database connection
+    ///
+    /// Credentials go to the Secret Service, or to an encrypted file next
+    /// to the database when no keyring daemon is running.
     pub async fn new(database_path: PathBuf) -> AsgardResult<Self> {
+        let data_dir = database_path
+            .parent()
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_default();
+        let secrets = SecretStore::open(&data_dir)?;
+        Self::with_secret_store(database_path, Arc::new(secrets)).await
+    }
+
+    /// Create a new database connection using a specific secret store
+    ///
+    /// The key for encryption at rest comes from the same store; encryption
+    /// starts disabled, see [`Database::set_encryption`].
+    pub async fn with_secret_store(database_path: PathBuf, secrets: Arc<SecretStore>) -> AsgardResult<Self> {
         // Ensure parent directory exists
         if let Some(parent) = database_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -27,14 +65,23 @@ impl Database {
 
         let connection = Connection::open(database_path)?;
         
-        // Enable WAL mode for better concurrency
-        connection.execute("PRAGMA journal_mode=WAL", [])?;
-        connection.execute("PRAGMA synchronous=NORMAL", [])?;
-        connection.execute("PRAGMA cache_size=10000", [])?;
-        connection.execute("PRAGMA temp_store=MEMORY", [])?;
+        // Enable WAL mode for better concurrency; journal_mode answers with
+        // a row, which execute() rejects. SQLite only enforces the ON DELETE
+        // CASCADE of the schema with foreign_keys on.
+        connection.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             PRAGMA foreign_keys=ON;
+             PRAGMA synchronous=NORMAL;
+             PRAGMA cache_size=10000;
+             PRAGMA temp_store=MEMORY;",
+        )?;
         
+        let cipher = Arc::new(StorageCipher::new(secrets.storage_key()?, false));
+
         Ok(Self {
             connection: Arc::new(Mutex::new(connection)),
+            secrets,
+            cipher,
         })
     }
 
@@ -44,9 +91,114 @@ impl Database {
         let mut migration_manager = crate::storage::migrations::MigrationManager::new(connection);
         
         migration_manager.run_migrations().await?;
+        self.migrate_inline_secrets().await?;
         Ok(())
     }
 
+    /// Get the secret store holding account credentials
+    pub fn secrets(&self) -> &Arc<SecretStore> {
+        &self.secrets
+    }
+
+    /// Get the cipher used for message content
+    pub fn cipher(&self) -> &Arc<StorageCipher> {
+        &self.cipher
+    }
+
+    /// Turn encryption of message content on or off
+    ///
+    /// Only affects content written from now on; existing rows are
+    /// converted by [`Database::reencode_content`].
+    pub fn set_encryption(&self, enabled: bool) {
+        self.cipher.set_enabled(enabled);
+    }
+
+    /// Re-encode stored message content to match the encryption setting
+    ///
+    /// Works in small batches and releases the connection in between, so it
+    /// can run in the background while the app keeps using the database.
+    /// Returns the number of values converted.
+    pub async fn reencode_content(&self) -> AsgardResult<usize> {
+        let mut converted = 0;
+
+        for (table, id_column, column) in CONTENT_COLUMNS {
+            let select = format!(
+                "SELECT {id}, {col} FROM {table} WHERE {col} IS NOT NULL AND (substr({col}, 1, ?1) = ?2) = ?3 LIMIT ?4",
+                id = id_column,
+                col = column,
+                table = table,
+            );
+            let update = format!("UPDATE {} SET {} = ? WHERE {} = ?", table, column, id_column);
+
+            loop {
+                let conn = self.connection.lock().await;
+                // Rows still in the encoding the current setting replaces
+                let stale_sealed = !self.cipher.is_enabled();
+
+                let rows: Vec<(String, Vec<u8>)> = {
+                    let mut stmt = conn.prepare(&select)?;
+                    let rows = stmt.query_map(
+                        params![crate::crypto::AT_REST_MAGIC.len(), crate::crypto::AT_REST_MAGIC, stale_sealed, REENCODE_BATCH_SIZE],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )?;
+                    rows.collect::<SqliteResult<_>>()?
+                };
+                if rows.is_empty() {
+                    break;
+                }
+
+                for (id, content) in rows {
+                    let content = self.cipher.seal(&self.cipher.open(&content)?)?;
+                    conn.execute(&update, params![content, id])?;
+                    converted += 1;
+                }
+
+                drop(conn);
+                tokio::task::yield_now().await;
+            }
+        }
+
+        Ok(converted)
+    }
+
+    /// Move credentials stored inline by older versions into the secret store
+    ///
+    /// Returns the number of accounts migrated. Rows are rewritten only after
+    /// their secrets were stored, so an interrupted run is simply repeated.
+    pub async fn migrate_inline_secrets(&self) -> AsgardResult<usize> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let rows: Vec<(String, String)> = {
+            let mut stmt = conn.prepare("SELECT id, config FROM accounts")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<SqliteResult<_>>()?
+        };
+
+        let mut migrated = 0;
+        for (id, config) in rows {
+            let config: AccountConfig = serde_json::from_str(&config)?;
+            if !SecretStore::has_inline_secrets(&config) {
+                continue;
+            }
+
+            let account_id = Uuid::parse_str(&id)
+                .map_err(|_| AsgardError::validation("Invalid account ID"))?;
+            let sealed = self.secrets.seal_config(account_id, &config)?;
+            conn.execute(
+                "UPDATE accounts SET config = ? WHERE id = ?",
+                params![serde_json::to_string(&sealed)?, id],
+            )?;
+            migrated += 1;
+        }
+
+        if migrated > 0 {
+            tracing::info!("Moved credentials of {} account(s) to the {}", migrated, self.secrets.backend_name());
+        }
+
+        Ok(migrated)
+    }
+
     /// Close the database connection
     pub async fn close(self) -> AsgardResult<()> {
         // Connection will be dropped automatically
@@ -57,6 +209,7 @@ impl Database {
 
     /// Create a new account
     pub async fn create_account(&self, account: &Account) -> AsgardResult<()> {
+        let config = self.secrets.seal_config(account.id, &account.config)?;
         let connection = self.connection.clone();
         let conn = connection.lock().await;
         
@@ -68,7 +221,7 @@ impl Database {
                 serde_json::to_string(&account.config.account_type)?,
                 account.config.display_name,
                 account.config.email,
-                serde_json::to_string(&account.config)?,
+                serde_json::to_string(&config)?,
                 serde_json::to_string(&account.status)?,
                 account.last_sync.map(|dt| dt.unix_timestamp()),
                 account.last_error,
@@ -96,7 +249,10 @@ impl Database {
         });
         
         match account_result {
-            Ok(account) => Ok(Some(account)),
+            Ok(mut account) => {
+                self.secrets.unseal_config(&mut account.config)?;
+                Ok(Some(account))
+            }
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(e) => Err(e.into()),
         }
@@ -118,7 +274,9 @@ impl Database {
         
         let mut accounts = Vec::new();
         for account_result in account_iter {
-            accounts.push(account_result?);
+            let mut account = account_result?;
+            self.secrets.unseal_config(&mut account.config)?;
+            accounts.push(account);
         }
         
         Ok(accounts)
@@ -126,6 +284,7 @@ impl Database {
 
     /// Update an account
     pub async fn update_account(&self, account: &Account) -> AsgardResult<()> {
+        let config = self.secrets.seal_config(account.id, &account.config)?;
         let connection = self.connection.clone();
         let conn = connection.lock().await;
         
@@ -136,7 +295,7 @@ impl Database {
                 serde_json::to_string(&account.config.account_type)?,
                 account.config.display_name,
                 account.config.email,
-                serde_json::to_string(&account.config)?,
+                serde_json::to_string(&config)?,
                 serde_json::to_string(&account.status)?,
                 account.last_sync.map(|dt| dt.unix_timestamp()),
                 account.last_error,
@@ -155,6 +314,7 @@ impl Database {
         let conn = connection.lock().await;
         
         let _changes = conn.execute("DELETE FROM accounts WHERE id = ?", [account_id.to_string()])?;
+        self.secrets.delete_account(account_id)?;
         Ok(())
     }
 
@@ -255,6 +415,109 @@ impl Database {
         Ok(())
     }
 
+    /// Update several mailboxes in a single transaction
+    pub async fn update_mailboxes(&self, mailboxes: &[Mailbox]) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let tx = conn.transaction()?;
+
+        for mailbox in mailboxes {
+            tx.execute(
+                "UPDATE mailboxes SET name = ?, display_name = ?, parent_id = ?, attributes = ?, updated_at = ?
+                 WHERE id = ?",
+                params![
+                    mailbox.name,
+                    mailbox.display_name,
+                    mailbox.parent_id.map(|id| id.to_string()),
+                    serde_json::to_string(&mailbox.attributes)?,
+                    mailbox.updated_at.unix_timestamp(),
+                    mailbox.id.to_string(),
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Store the mailboxes listed by a sync of `account_id`
+    ///
+    /// Mailboxes already stored under the same name keep their ID and local
+    /// settings, so `mailboxes` is rewritten to those IDs, parent links
+    /// included. New mailboxes are inserted.
+    pub async fn store_synced_mailboxes(&self, account_id: Uuid, mailboxes: &mut [Mailbox]) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let tx = conn.transaction()?;
+
+        let stored: HashMap<String, Uuid> = {
+            let mut stmt = tx.prepare("SELECT name, id FROM mailboxes WHERE account_id = ?")?;
+            let rows = stmt.query_map([account_id.to_string()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut stored = HashMap::new();
+            for row in rows {
+                let (name, id) = row?;
+                if let Ok(id) = Uuid::parse_str(&id) {
+                    stored.entry(name).or_insert(id);
+                }
+            }
+            stored
+        };
+
+        let mut ids = HashMap::new();
+        for mailbox in mailboxes.iter_mut() {
+            if let Some(id) = stored.get(&mailbox.name) {
+                ids.insert(mailbox.id, *id);
+                mailbox.id = *id;
+            }
+        }
+
+        for mailbox in mailboxes.iter_mut() {
+            if let Some(parent_id) = mailbox.parent_id.and_then(|id| ids.get(&id)) {
+                mailbox.parent_id = Some(*parent_id);
+            }
+
+            if stored.contains_key(&mailbox.name) {
+                tx.execute(
+                    "UPDATE mailboxes SET display_name = ?, mailbox_type = ?, parent_id = ?, attributes = ?, stats = ?, updated_at = ?
+                     WHERE id = ?",
+                    params![
+                        mailbox.display_name,
+                        serde_json::to_string(&mailbox.mailbox_type)?,
+                        mailbox.parent_id.map(|id| id.to_string()),
+                        serde_json::to_string(&mailbox.attributes)?,
+                        serde_json::to_string(&mailbox.stats)?,
+                        mailbox.updated_at.unix_timestamp(),
+                        mailbox.id.to_string(),
+                    ],
+                )?;
+            } else {
+                tx.execute(
+                    "INSERT INTO mailboxes (id, account_id, name, display_name, mailbox_type, parent_id, attributes, stats, settings, created_at, updated_at, last_sync)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![
+                        mailbox.id.to_string(),
+                        mailbox.account_id.to_string(),
+                        mailbox.name,
+                        mailbox.display_name,
+                        serde_json::to_string(&mailbox.mailbox_type)?,
+                        mailbox.parent_id.map(|id| id.to_string()),
+                        serde_json::to_string(&mailbox.attributes)?,
+                        serde_json::to_string(&mailbox.stats)?,
+                        serde_json::to_string(&mailbox.settings)?,
+                        mailbox.created_at.unix_timestamp(),
+                        mailbox.updated_at.unix_timestamp(),
+                        mailbox.last_sync.map(|dt| dt.unix_timestamp()),
+                    ],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Delete a mailbox
     pub async fn delete_mailbox(&self, mailbox_id: Uuid) -> AsgardResult<()> {
         let connection = self.connection.clone();
@@ -264,6 +527,77 @@ impl Database {
         Ok(())
     }
 
+    /// Delete mailboxes together with their messages in one transaction
+    ///
+    /// Returns the IDs of the deleted messages.
+    pub async fn delete_mailboxes(&self, mailbox_ids: &[Uuid]) -> AsgardResult<Vec<Uuid>> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let tx = conn.transaction()?;
+
+        let mut message_ids: Vec<String> = Vec::new();
+        for mailbox_id in mailbox_ids {
+            let mut stmt = tx.prepare("SELECT id FROM messages WHERE mailbox_id = ?")?;
+            let rows = stmt.query_map([mailbox_id.to_string()], |row| row.get(0))?;
+            message_ids.extend(rows.collect::<SqliteResult<Vec<String>>>()?);
+        }
+        for mailbox_id in mailbox_ids {
+            tx.execute("DELETE FROM messages WHERE mailbox_id = ?", [mailbox_id.to_string()])?;
+            tx.execute("DELETE FROM mailboxes WHERE id = ?", [mailbox_id.to_string()])?;
+        }
+
+        tx.commit()?;
+        Ok(message_ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
+    /// Get the total size of stored messages per mailbox
+    pub async fn get_mailbox_sizes(&self, account_id: Uuid) -> AsgardResult<HashMap<Uuid, u64>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT mailbox_id, COALESCE(SUM(size), 0) FROM messages WHERE account_id = ? GROUP BY mailbox_id"
+        )?;
+
+        let size_iter = stmt.query_map([account_id.to_string()], |row| {
+            let mailbox_id: String = row.get(0)?;
+            let size: i64 = row.get(1)?;
+            Ok((mailbox_id, size))
+        })?;
+
+        let mut sizes = HashMap::new();
+        for size_result in size_iter {
+            let (mailbox_id, size) = size_result?;
+            if let Ok(mailbox_id) = Uuid::parse_str(&mailbox_id) {
+                sizes.insert(mailbox_id, size.max(0) as u64);
+            }
+        }
+
+        Ok(sizes)
+    }
+
+    /// Get the largest messages of an account
+    pub async fn get_largest_messages(&self, account_id: Uuid, limit: usize) -> AsgardResult<Vec<Message>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync, gmail_msgid
+             FROM messages WHERE account_id = ? ORDER BY size DESC LIMIT ?"
+        )?;
+
+        let message_iter = stmt.query_map(params![account_id.to_string(), limit], |row| {
+            self.row_to_message(row)
+        })?;
+
+        let mut messages = Vec::new();
+        for message_result in message_iter {
+            messages.push(message_result?);
+        }
+
+        Ok(messages)
+    }
+
     // Message operations
 
     /// Create a new message
@@ -275,8 +609,8 @@ impl Database {
         
         // Insert message
         tx.execute(
-            "INSERT INTO messages (id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO messages (id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync, gmail_msgid)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 message.id.to_string(),
                 message.account_id.to_string(),
@@ -288,10 +622,11 @@ impl Database {
                 message.size,
                 message.thread_id.map(|id| id.to_string()),
                 message.conversation_id,
-                message.raw_content,
+                self.seal_blob(&message.raw_content)?,
                 message.created_at.unix_timestamp(),
                 message.updated_at.unix_timestamp(),
                 message.last_sync.map(|dt| dt.unix_timestamp()),
+                message.gmail_message_id.map(|id| id as i64),
             ],
         )?;
         
@@ -330,7 +665,7 @@ impl Database {
                     attachment.size,
                     attachment.content_hash,
                     attachment.file_path,
-                    attachment.content,
+                    self.seal_blob(&attachment.content)?,
                     attachment.created_at.unix_timestamp(),
                 ],
             )?;
@@ -349,7 +684,7 @@ impl Database {
         let offset = offset.unwrap_or(0);
         
         let mut stmt = conn.prepare(
-            "SELECT id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync
+            "SELECT id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync, gmail_msgid
              FROM messages WHERE mailbox_id = ? ORDER BY created_at DESC LIMIT ? OFFSET ?"
         )?;
         
@@ -385,7 +720,7 @@ impl Database {
         let conn = connection.lock().await;
         
         let mut stmt = conn.prepare(
-            "SELECT id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync
+            "SELECT id, account_id, mailbox_id, uid, uid_validity, sequence_number, headers, size, thread_id, conversation_id, raw_content, created_at, updated_at, last_sync, gmail_msgid
              FROM messages WHERE id = ?"
         )?;
         
@@ -423,9 +758,10 @@ impl Database {
         
         // Update message
         tx.execute(
-            "UPDATE messages SET uid = ?, uid_validity = ?, sequence_number = ?, headers = ?, size = ?, thread_id = ?, conversation_id = ?, raw_content = ?, updated_at = ?, last_sync = ?
+            "UPDATE messages SET mailbox_id = ?, uid = ?, uid_validity = ?, sequence_number = ?, headers = ?, size = ?, thread_id = ?, conversation_id = ?, raw_content = ?, updated_at = ?, last_sync = ?, gmail_msgid = ?
              WHERE id = ?",
             params![
+                message.mailbox_id.to_string(),
                 message.uid,
                 message.uid_validity,
                 message.sequence_number,
@@ -433,9 +769,10 @@ impl Database {
                 message.size,
                 message.thread_id.map(|id| id.to_string()),
                 message.conversation_id,
-                message.raw_content,
+                self.seal_blob(&message.raw_content)?,
                 message.updated_at.unix_timestamp(),
                 message.last_sync.map(|dt| dt.unix_timestamp()),
+                message.gmail_message_id.map(|id| id as i64),
                 message.id.to_string(),
             ],
         )?;
@@ -471,6 +808,273 @@ impl Database {
         Ok(())
     }
 
+    /// Delete the messages of a mailbox stored under another UIDVALIDITY
+    ///
+    /// Their UIDs no longer name the same messages on the server, which
+    /// lists them again under the new UIDVALIDITY. Returns the deleted IDs.
+    pub async fn delete_stale_messages(&self, mailbox_id: Uuid, uid_validity: u32) -> AsgardResult<Vec<Uuid>> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let tx = conn.transaction()?;
+
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM messages WHERE mailbox_id = ? AND uid IS NOT NULL AND uid_validity IS NOT ?"
+            )?;
+            let rows = stmt.query_map(params![mailbox_id.to_string(), uid_validity], |row| row.get(0))?;
+            rows.collect::<SqliteResult<_>>()?
+        };
+        for id in &ids {
+            tx.execute("DELETE FROM messages WHERE id = ?", [id])?;
+        }
+
+        tx.commit()?;
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
+    /// Delete the messages of an account with the given X-GM-MSGIDs
+    ///
+    /// Returns the deleted IDs.
+    pub async fn delete_gmail_messages(&self, account_id: Uuid, gmail_ids: &[u64]) -> AsgardResult<Vec<Uuid>> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let tx = conn.transaction()?;
+
+        let mut ids: Vec<String> = Vec::new();
+        {
+            let mut stmt = tx.prepare("SELECT id FROM messages WHERE account_id = ? AND gmail_msgid = ?")?;
+            for gmail_id in gmail_ids {
+                let rows = stmt.query_map(params![account_id.to_string(), *gmail_id as i64], |row| row.get(0))?;
+                ids.extend(rows.collect::<SqliteResult<Vec<String>>>()?);
+            }
+        }
+        for id in &ids {
+            tx.execute("DELETE FROM messages WHERE id = ?", [id])?;
+        }
+
+        tx.commit()?;
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
+    /// Find the stored copy of a synced message
+    ///
+    /// Gmail messages are matched by their X-GM-MSGID so a message is stored
+    /// once however many labels it has; other messages by mailbox and UID.
+    pub async fn find_existing_message(&self, message: &Message) -> AsgardResult<Option<Uuid>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let result = if let Some(gmail_id) = message.gmail_message_id {
+            conn.query_row(
+                "SELECT id FROM messages WHERE account_id = ? AND gmail_msgid = ?",
+                params![message.account_id.to_string(), gmail_id as i64],
+                |row| row.get::<_, String>(0),
+            )
+        } else if let Some(uid) = message.uid {
+            conn.query_row(
+                "SELECT id FROM messages WHERE mailbox_id = ? AND uid = ? AND uid_validity IS ?",
+                params![message.mailbox_id.to_string(), uid, message.uid_validity],
+                |row| row.get::<_, String>(0),
+            )
+        } else {
+            conn.query_row(
+                "SELECT id FROM messages WHERE id = ?",
+                [message.id.to_string()],
+                |row| row.get::<_, String>(0),
+            )
+        };
+
+        match result {
+            Ok(id) => Ok(Uuid::parse_str(&id).ok()),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Get messages of an account carrying a label
+    pub async fn get_messages_with_label(&self, account_id: Uuid, label: &str, limit: Option<usize>, offset: Option<usize>) -> AsgardResult<Vec<Message>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let limit = limit.unwrap_or(100);
+        let offset = offset.unwrap_or(0);
+
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.account_id, m.mailbox_id, m.uid, m.uid_validity, m.sequence_number, m.headers, m.size, m.thread_id, m.conversation_id, m.raw_content, m.created_at, m.updated_at, m.last_sync, m.gmail_msgid
+             FROM messages m JOIN message_labels l ON l.message_id = m.id
+             WHERE m.account_id = ? AND l.label = ? ORDER BY m.created_at DESC LIMIT ? OFFSET ?"
+        )?;
+
+        let message_iter = stmt.query_map(params![account_id.to_string(), label, limit, offset], |row| {
+            self.row_to_message(row)
+        })?;
+
+        let mut messages = Vec::new();
+        for message_result in message_iter {
+            let mut message = message_result?;
+            message.flags = self.get_message_flags(&conn, &message.id)?;
+            message.labels = self.get_message_labels(&conn, &message.id)?;
+            message.parts = self.get_message_parts(&conn, &message.id, None)?;
+            message.attachments = self.get_message_attachments(&conn, &message.id)?;
+            messages.push(message);
+        }
+
+        Ok(messages)
+    }
+
+    /// Replace the labels of a message
+    pub async fn set_message_labels(&self, message_id: Uuid, labels: &[String]) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM message_labels WHERE message_id = ?", [message_id.to_string()])?;
+        for label in labels {
+            tx.execute(
+                "INSERT OR IGNORE INTO message_labels (message_id, label) VALUES (?, ?)",
+                params![message_id.to_string(), label],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Store content downloaded for body parts and attachments of a message
+    ///
+    /// Attachments recorded without a content hash get one.
+    pub async fn update_part_contents(&self, message_id: Uuid, contents: &[(String, Vec<u8>)]) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+
+        let tx = conn.transaction()?;
+        for (part_id, content) in contents {
+            let sealed = self.seal_blob(&Some(content.clone()))?;
+            tx.execute(
+                "UPDATE message_parts SET content = ? WHERE message_id = ? AND part_id = ?",
+                params![sealed, message_id.to_string(), part_id],
+            )?;
+            tx.execute(
+                "UPDATE attachments SET content = ?, content_hash = CASE WHEN content_hash = '' THEN ? ELSE content_hash END
+                 WHERE message_id = ? AND part_id = ?",
+                params![sealed, DataIntegrity::sha256(content), message_id.to_string(), part_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the Autocrypt state of a correspondent
+    pub async fn get_autocrypt_peer(&self, account_id: Uuid, email: &str) -> AsgardResult<Option<PeerState>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let result = conn.query_row(
+            "SELECT email, last_seen, autocrypt_timestamp, public_key, prefer_encrypt, gossip_timestamp, gossip_key
+             FROM autocrypt_peers WHERE account_id = ? AND email = ?",
+            params![account_id.to_string(), email.to_ascii_lowercase()],
+            |row| self.row_to_autocrypt_peer(row),
+        );
+
+        match result {
+            Ok(peer) => Ok(Some(peer)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Insert or replace the Autocrypt state of a correspondent
+    pub async fn save_autocrypt_peer(&self, account_id: Uuid, peer: &PeerState) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO autocrypt_peers (account_id, email, last_seen, autocrypt_timestamp, public_key, prefer_encrypt, gossip_timestamp, gossip_key)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                account_id.to_string(),
+                peer.email.to_ascii_lowercase(),
+                peer.last_seen.unix_timestamp(),
+                peer.autocrypt_timestamp.map(|dt| dt.unix_timestamp()),
+                peer.public_key,
+                serde_json::to_string(&peer.prefer_encrypt)?,
+                peer.gossip_timestamp.map(|dt| dt.unix_timestamp()),
+                peer.gossip_key,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record tracking pixels removed from a message
+    ///
+    /// Trackers already recorded keep the time they were first blocked.
+    pub async fn save_blocked_trackers(&self, message_id: Uuid, trackers: &[Tracker]) -> AsgardResult<()> {
+        let connection = self.connection.clone();
+        let mut conn = connection.lock().await;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let tx = conn.transaction()?;
+        for tracker in trackers {
+            tx.execute(
+                "INSERT OR IGNORE INTO blocked_trackers (message_id, url, service, blocked_at) VALUES (?, ?, ?, ?)",
+                params![message_id.to_string(), tracker.url, tracker.service, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Tracking pixels removed from a message, in the order they were recorded
+    pub async fn get_blocked_trackers(&self, message_id: Uuid) -> AsgardResult<Vec<Tracker>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT url, service FROM blocked_trackers WHERE message_id = ? ORDER BY blocked_at, rowid",
+        )?;
+        let tracker_iter = stmt.query_map([message_id.to_string()], |row| {
+            Ok(Tracker { url: row.get(0)?, service: row.get(1)? })
+        })?;
+
+        let mut trackers = Vec::new();
+        for tracker in tracker_iter {
+            trackers.push(tracker?);
+        }
+        Ok(trackers)
+    }
+
+    /// Recipients of sent messages, one per address, newest name first
+    ///
+    /// These are the people the user writes to, whose names impersonators
+    /// borrow.
+    pub async fn get_known_contacts(&self) -> AsgardResult<Vec<EmailAddress>> {
+        let connection = self.connection.clone();
+        let conn = connection.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT m.headers FROM messages m JOIN mailboxes b ON m.mailbox_id = b.id
+             WHERE b.mailbox_type = ? ORDER BY m.created_at DESC, m.rowid DESC",
+        )?;
+        let headers_iter = stmt.query_map([serde_json::to_string(&MailboxType::Sent)?], |row| row.get::<_, String>(0))?;
+
+        let mut contacts: Vec<EmailAddress> = Vec::new();
+        for headers in headers_iter {
+            let headers: MessageHeaders = serde_json::from_str(&headers?)?;
+            for address in headers.to.into_iter().chain(headers.cc).chain(headers.bcc) {
+                let name = address.name.filter(|name| !name.trim().is_empty());
+                match contacts.iter_mut().find(|contact| contact.email.eq_ignore_ascii_case(&address.email)) {
+                    Some(contact) => {
+                        if contact.name.is_none() {
+                            contact.name = name;
+                        }
+                    }
+                    None => contacts.push(EmailAddress { name, email: address.email }),
+                }
+            }
+        }
+        Ok(contacts)
+    }
+
     // Helper methods
 
     fn row_to_account(&self, row: &Row) -> SqliteResult<Account> {
@@ -539,10 +1143,11 @@ impl Database {
         let size: usize = row.get(7)?;
         let thread_id: Option<String> = row.get(8)?;
         let conversation_id: Option<String> = row.get(9)?;
-        let raw_content: Option<Vec<u8>> = row.get(10)?;
+        let raw_content = self.open_blob(row.get(10)?, 10)?;
         let created_at: i64 = row.get(11)?;
         let updated_at: i64 = row.get(12)?;
         let last_sync: Option<i64> = row.get(13)?;
+        let gmail_msgid: Option<i64> = row.get(14)?;
 
         Ok(Message {
             id: Uuid::parse_str(&id).map_err(|_| rusqlite::Error::InvalidColumnType(0, "UUID".to_string(), rusqlite::types::Type::Text))?,
@@ -558,6 +1163,7 @@ impl Database {
             attachments: vec![], // Will be loaded separately
             thread_id: thread_id.map(|id| Uuid::parse_str(&id).unwrap()),
             conversation_id,
+            gmail_message_id: gmail_msgid.map(|id| id as u64),
             size,
             raw_content,
             created_at: OffsetDateTime::from_unix_timestamp(created_at).unwrap_or_else(|_| OffsetDateTime::now_utc()),
@@ -566,6 +1172,21 @@ impl Database {
         })
     }
 
+    fn row_to_autocrypt_peer(&self, row: &Row) -> SqliteResult<PeerState> {
+        let timestamp = |ts: i64| OffsetDateTime::from_unix_timestamp(ts).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+        let prefer_encrypt: String = row.get(4)?;
+
+        Ok(PeerState {
+            email: row.get(0)?,
+            last_seen: timestamp(row.get(1)?),
+            autocrypt_timestamp: row.get::<_, Option<i64>>(2)?.map(timestamp),
+            public_key: row.get(3)?,
+            prefer_encrypt: serde_json::from_str(&prefer_encrypt).unwrap_or_default(),
+            gossip_timestamp: row.get::<_, Option<i64>>(5)?.map(timestamp),
+            gossip_key: row.get(6)?,
+        })
+    }
+
     fn get_message_flags(&self, conn: &Connection, message_id: &Uuid) -> SqliteResult<Vec<MessageFlags>> {
         let mut stmt = conn.prepare("SELECT flag FROM message_flags WHERE message_id = ?")?;
         let flag_iter = stmt.query_map([message_id.to_string()], |row| {
@@ -599,7 +1220,7 @@ impl Database {
     fn get_message_parts(&self, conn: &Connection, message_id: &Uuid, parent_id: Option<&str>) -> SqliteResult<Vec<MessagePart>> {
         let mut stmt = conn.prepare("SELECT id, part_id, part_type, mime_type, disposition, filename, size, encoding, content_id, content_location, content FROM message_parts WHERE message_id = ? AND parent_id IS ?")?;
         let part_iter = stmt.query_map(params![message_id.to_string(), parent_id], |row| {
-            let id: String = row.get(0)?;
+            let row_id: String = row.get(0)?;
             let part_id: String = row.get(1)?;
             let part_type: String = row.get(2)?;
             let mime_type: String = row.get(3)?;
@@ -609,10 +1230,10 @@ impl Database {
             let encoding: Option<String> = row.get(7)?;
             let content_id: Option<String> = row.get(8)?;
             let content_location: Option<String> = row.get(9)?;
-            let content: Option<Vec<u8>> = row.get(10)?;
+            let content = self.open_blob(row.get(10)?, 10)?;
 
-            Ok(MessagePart {
-                id,
+            Ok((row_id, MessagePart {
+                id: part_id,
                 part_type: serde_json::from_str(&part_type).unwrap_or(crate::message::MessagePartType::Other),
                 mime_type,
                 disposition,
@@ -623,15 +1244,15 @@ impl Database {
                 content_location,
                 content,
                 children: vec![], // Will be loaded recursively
-            })
+            }))
         })?;
         
         let mut parts = Vec::new();
         for part_result in part_iter {
-            let mut part = part_result?;
+            let (row_id, mut part) = part_result?;
             
             // Load child parts recursively
-            part.children = self.get_message_parts(conn, message_id, Some(&part.id))?;
+            part.children = self.get_message_parts(conn, message_id, Some(&row_id))?;
             
             parts.push(part);
         }
@@ -649,7 +1270,7 @@ impl Database {
             let size: usize = row.get(4)?;
             let content_hash: String = row.get(5)?;
             let file_path: Option<String> = row.get(6)?;
-            let content: Option<Vec<u8>> = row.get(7)?;
+            let content = self.open_blob(row.get(7)?, 7)?;
             let created_at: i64 = row.get(8)?;
 
             Ok(Attachment {
@@ -674,12 +1295,29 @@ impl Database {
         Ok(attachments)
     }
 
+    fn seal_blob(&self, content: &Option<Vec<u8>>) -> SqliteResult<Option<Vec<u8>>> {
+        content
+            .as_deref()
+            .map(|content| self.cipher.seal(content))
+            .transpose()
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.to_string().into()))
+    }
+
+    fn open_blob(&self, content: Option<Vec<u8>>, column: usize) -> SqliteResult<Option<Vec<u8>>> {
+        content
+            .map(|content| self.cipher.open(&content))
+            .transpose()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Blob, e.to_string().into()))
+    }
+
     fn insert_message_part(&self, tx: &rusqlite::Transaction, message_id: &Uuid, part: &MessagePart, parent_id: Option<&str>) -> SqliteResult<()> {
+        // Part IDs are section numbers, unique only within their message
+        let row_id = format!("{}:{}", message_id, part.id);
         tx.execute(
             "INSERT INTO message_parts (id, message_id, part_id, part_type, mime_type, disposition, filename, size, encoding, content_id, content_location, content, parent_id)
              VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
-                part.id,
+                row_id,
                 message_id.to_string(),
                 part.id,
                 serde_json::to_string(&part.part_type).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
@@ -690,14 +1328,14 @@ impl Database {
                 part.encoding,
                 part.content_id,
                 part.content_location,
-                part.content,
+                self.seal_blob(&part.content)?,
                 parent_id,
             ],
         )?;
         
         // Insert child parts recursively
         for child_part in &part.children {
-            self.insert_message_part(tx, message_id, child_part, Some(&part.id))?;
+            self.insert_message_part(tx, message_id, child_part, Some(&row_id))?;
         }
         
         Ok(())
@@ -714,7 +1352,7 @@ mod tests {
     async fn test_database_creation() {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
-        let database = Database::new(db_path).await.unwrap();
+        let mut database = Database::new(db_path).await.unwrap();
         database.initialize().await.unwrap();
     }
 
@@ -722,7 +1360,7 @@ mod tests {
     async fn test_account_operations() {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
-        let database = Database::new(db_path).await.unwrap();
+        let mut database = Database::new(db_path).await.unwrap();
         database.initialize().await.unwrap();
 
         let oauth_config = GmailOAuthConfig {
@@ -762,4 +1400,279 @@ mod tests {
         let deleted_account = database.get_account(account.id).await.unwrap();
         assert!(deleted_account.is_none());
     }
+
+    /// An account with an inbox, for rows that reference them
+    async fn create_account(database: &Database) -> (Account, Mailbox) {
+        let account = Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            GmailOAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: String::new(),
+                access_token: None,
+                refresh_token: None,
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        ).unwrap();
+        database.create_account(&account).await.unwrap();
+        let inbox = Mailbox::new_inbox(account.id);
+        database.create_mailbox(&inbox).await.unwrap();
+        (account, inbox)
+    }
+
+    #[tokio::test]
+    async fn test_inline_secrets_are_migrated() {
+        let temp_dir = TempDir::new().unwrap();
+        let secrets = SecretStore::with_backend(Box::new(
+            crate::secrets::EncryptedFileBackend::open(temp_dir.path()).unwrap(),
+        ));
+        let mut database = Database::with_secret_store(temp_dir.path().join("test.db"), Arc::new(secrets))
+            .await
+            .unwrap();
+        database.initialize().await.unwrap();
+
+        let account = Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            GmailOAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: "test-client-secret".to_string(),
+                access_token: Some("test-access-token".to_string()),
+                refresh_token: Some("test-refresh-token".to_string()),
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        ).unwrap();
+
+        // Write the row the way older versions did, with the secrets inline
+        {
+            let conn = database.connection.lock().await;
+            conn.execute(
+                "INSERT INTO accounts (id, account_type, display_name, email, config, status, last_sync, last_error, stats, created_at, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?, NULL, NULL, ?, ?, ?)",
+                params![
+                    account.id.to_string(),
+                    serde_json::to_string(&account.config.account_type).unwrap(),
+                    account.config.display_name,
+                    account.config.email,
+                    serde_json::to_string(&account.config).unwrap(),
+                    serde_json::to_string(&account.status).unwrap(),
+                    serde_json::to_string(&account.stats).unwrap(),
+                    account.created_at.unix_timestamp(),
+                    account.updated_at.unix_timestamp(),
+                ],
+            ).unwrap();
+        }
+
+        assert_eq!(database.migrate_inline_secrets().await.unwrap(), 1);
+        assert_eq!(database.migrate_inline_secrets().await.unwrap(), 0);
+
+        let stored: String = {
+            let conn = database.connection.lock().await;
+            conn.query_row("SELECT config FROM accounts WHERE id = ?", [account.id.to_string()], |row| row.get(0))
+                .unwrap()
+        };
+        assert!(!stored.contains("test-client-secret"));
+        assert!(!stored.contains("test-access-token"));
+        assert!(!stored.contains("test-refresh-token"));
+
+        let loaded = database.get_account(account.id).await.unwrap().unwrap();
+        let oauth = loaded.config.gmail_oauth.unwrap();
+        assert_eq!(oauth.client_secret, "test-client-secret");
+        assert_eq!(oauth.refresh_token.as_deref(), Some("test-refresh-token"));
+
+        database.delete_account(account.id).await.unwrap();
+        assert_eq!(database.secrets().resolve(&SecretStore::reference(account.id, crate::secrets::REFRESH_TOKEN)).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_message_content_encryption_migration() {
+        let temp_dir = TempDir::new().unwrap();
+        let secrets = SecretStore::with_backend(Box::new(
+            crate::secrets::EncryptedFileBackend::open(temp_dir.path()).unwrap(),
+        ));
+        let mut database = Database::with_secret_store(temp_dir.path().join("test.db"), Arc::new(secrets))
+            .await
+            .unwrap();
+        database.initialize().await.unwrap();
+
+        let (account, inbox) = create_account(&database).await;
+
+        let raw = b"From: a@example.com\r\nTo: b@example.com\r\nSubject: Quarterly numbers\r\n\r\nConfidential body\r\n";
+        let message = Message::from_rfc822(account.id, inbox.id, raw).unwrap();
+        database.create_message(&message).await.unwrap();
+
+        let stored_raw = |database: &Database| {
+            let database = database.clone();
+            let id = message.id.to_string();
+            async move {
+                let conn = database.connection.lock().await;
+                conn.query_row("SELECT raw_content FROM messages WHERE id = ?", [id], |row| row.get::<_, Vec<u8>>(0))
+                    .unwrap()
+            }
+        };
+
+        assert_eq!(stored_raw(&database).await, raw);
+
+        // The raw message and its text part
+        database.set_encryption(true);
+        assert_eq!(database.reencode_content().await.unwrap(), 2);
+        assert_eq!(database.reencode_content().await.unwrap(), 0);
+
+        let sealed = stored_raw(&database).await;
+        assert!(StorageCipher::is_sealed(&sealed));
+        assert!(!sealed.windows(12).any(|w| w == b"Confidential"));
+
+        let loaded = database.get_message(message.id).await.unwrap().unwrap();
+        assert_eq!(loaded.raw_content.as_deref(), Some(&raw[..]));
+
+        database.set_encryption(false);
+        assert_eq!(database.reencode_content().await.unwrap(), 2);
+        assert_eq!(stored_raw(&database).await, raw);
+    }
+
+    #[tokio::test]
+    async fn test_autocrypt_peer_round_trip() {
+        use crate::autocrypt::PreferEncrypt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::new(temp_dir.path().join("test.db")).await.unwrap();
+        database.initialize().await.unwrap();
+        let (account, _) = create_account(&database).await;
+        let account_id = account.id;
+
+        assert!(database.get_autocrypt_peer(account_id, "bob@example.com").await.unwrap().is_none());
+
+        let mut peer = PeerState::new("Bob@Example.com");
+        peer.last_seen = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        peer.autocrypt_timestamp = Some(peer.last_seen);
+        peer.public_key = Some(vec![1, 2, 3]);
+        peer.prefer_encrypt = PreferEncrypt::Mutual;
+        database.save_autocrypt_peer(account_id, &peer).await.unwrap();
+
+        let stored = database.get_autocrypt_peer(account_id, "BOB@example.com").await.unwrap().unwrap();
+        assert_eq!(stored, peer);
+        assert!(database.get_autocrypt_peer(Uuid::new_v4(), "bob@example.com").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_trackers() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::new(temp_dir.path().join("test.db")).await.unwrap();
+        database.initialize().await.unwrap();
+        let (account, inbox) = create_account(&database).await;
+        let message = Message::from_rfc822(account.id, inbox.id, b"From: news@example.com\r\n\r\nHi\r\n").unwrap();
+        database.create_message(&message).await.unwrap();
+        let message_id = message.id;
+
+        let pixel = Tracker { url: "https://t.example.com/open?id=1".to_string(), service: None };
+        let mailchimp = Tracker {
+            url: "https://x.list-manage.com/track/open.php".to_string(),
+            service: Some("Mailchimp".to_string()),
+        };
+        database.save_blocked_trackers(message_id, &[pixel.clone(), mailchimp.clone()]).await.unwrap();
+        database.save_blocked_trackers(message_id, &[pixel.clone()]).await.unwrap();
+
+        assert_eq!(database.get_blocked_trackers(message_id).await.unwrap(), vec![pixel, mailchimp]);
+        assert!(database.get_blocked_trackers(Uuid::new_v4()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_part_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::new(temp_dir.path().join("test.db")).await.unwrap();
+        database.initialize().await.unwrap();
+        let (account, inbox) = create_account(&database).await;
+
+        let raw = b"From: a@example.com\r\nSubject: Scan\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n\
+            --b\r\nContent-Type: text/plain\r\n\r\nSee attached\r\n\
+            --b\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment; filename=scan.pdf\r\n\r\n%PDF\r\n\
+            --b--\r\n";
+        let mut message = Message::from_rfc822(account.id, inbox.id, raw).unwrap();
+        // As left by a sync that fetched only the structure
+        message.raw_content = None;
+        message.parts[0].content = None;
+        message.attachments[0].content = None;
+        message.attachments[0].content_hash = String::new();
+        database.create_message(&message).await.unwrap();
+
+        let loaded = database.get_message(message.id).await.unwrap().unwrap();
+        assert_eq!(loaded.missing_body_parts(), ["1"]);
+
+        let contents = [("1".to_string(), b"See attached".to_vec()), ("2".to_string(), b"%PDF".to_vec())];
+        database.update_part_contents(message.id, &contents).await.unwrap();
+
+        let loaded = database.get_message(message.id).await.unwrap().unwrap();
+        assert!(loaded.missing_body_parts().is_empty());
+        assert_eq!(loaded.parts[0].content.as_deref(), Some(&b"See attached"[..]));
+        assert_eq!(loaded.attachments[0].content.as_deref(), Some(&b"%PDF"[..]));
+        assert_eq!(loaded.attachments[0].content_hash, DataIntegrity::sha256(b"%PDF"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_mailboxes_removes_children_and_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::new(temp_dir.path().join("test.db")).await.unwrap();
+        database.initialize().await.unwrap();
+        let (account, inbox) = create_account(&database).await;
+
+        let mut stored = Vec::new();
+        for (name, top) in [("Work", "Projects"), ("Home", "Bills")] {
+            let parent = Mailbox::new(account.id, top.to_string(), None, MailboxType::Custom, None);
+            let child = Mailbox::new(account.id, format!("{}/{}", top, name), None, MailboxType::Custom, Some(parent.id));
+            for mailbox in [&parent, &child] {
+                database.create_mailbox(mailbox).await.unwrap();
+                let message = Message::from_rfc822(account.id, mailbox.id, b"Subject: Hi\r\n\r\nHi\r\n").unwrap();
+                database.create_message(&message).await.unwrap();
+            }
+            stored.push((parent, child));
+        }
+        let kept = Message::from_rfc822(account.id, inbox.id, b"Subject: Kept\r\n\r\nHi\r\n").unwrap();
+        database.create_message(&kept).await.unwrap();
+
+        // The schema cascades a parent's deletion to its children and messages
+        let (parent, child) = &stored[0];
+        database.delete_mailbox(parent.id).await.unwrap();
+        assert!(database.get_mailbox(child.id).await.unwrap().is_none());
+        assert!(database.get_messages(child.id, None, None).await.unwrap().is_empty());
+
+        let (parent, child) = &stored[1];
+        let deleted = database.delete_mailboxes(&[child.id, parent.id]).await.unwrap();
+        assert_eq!(deleted.len(), 2);
+        for id in [parent.id, child.id] {
+            assert!(database.get_mailbox(id).await.unwrap().is_none());
+            assert!(database.get_messages(id, None, None).await.unwrap().is_empty());
+        }
+
+        let mailboxes = database.get_mailboxes(account.id).await.unwrap();
+        assert_eq!(mailboxes.iter().map(|m| m.id).collect::<Vec<_>>(), [inbox.id]);
+        assert!(database.get_message(kept.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_known_contacts() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::new(temp_dir.path().join("test.db")).await.unwrap();
+        database.initialize().await.unwrap();
+        let (account, inbox) = create_account(&database).await;
+        let sent = Mailbox::new_sent(account.id, "Sent".to_string());
+        database.create_mailbox(&sent).await.unwrap();
+
+        let messages: [(&Mailbox, &[u8]); 3] = [
+            (&sent, b"From: me@example.com\r\nTo: jane@example.com\r\nCc: Bob <bob@example.com>\r\n\r\nHi\r\n"),
+            (&sent, b"From: me@example.com\r\nTo: Jane Doe <JANE@example.com>\r\n\r\nHi\r\n"),
+            (&inbox, b"From: Mallory <m@example.com>\r\nTo: Me <me@example.com>\r\n\r\nHi\r\n"),
+        ];
+        for (mailbox, raw) in messages {
+            let message = Message::from_rfc822(account.id, mailbox.id, raw).unwrap();
+            database.create_message(&message).await.unwrap();
+        }
+
+        let contacts = database.get_known_contacts().await.unwrap();
+        assert_eq!(contacts.len(), 2);
+        let jane = contacts.iter().find(|contact| contact.email.eq_ignore_ascii_case("jane@example.com")).unwrap();
+        assert_eq!(jane.name.as_deref(), Some("Jane Doe"));
+        assert!(contacts.iter().any(|contact| contact.email == "bob@example.com" && contact.name.as_deref() == Some("Bob")));
+    }
 }
@@ -81,6 +81,9 @@ impl MigrationManager {
             Box::new(CreateSearchIndexTable),
             Box::new(CreateCacheTable),
             Box::new(AddIndexes),
+            Box::new(AddGmailMessageIds),
+            Box::new(CreateAutocryptPeersTable),
+            Box::new(CreateBlockedTrackersTable),
         ]
     }
 }
@@ -428,41 +431,111 @@ impl Migration for AddIndexes {
     }
 }
 
+/// Migration: Add Gmail message IDs so labelled messages are stored once
+struct AddGmailMessageIds;
+
+impl Migration for AddGmailMessageIds {
+    fn name(&self) -> &str {
+        "add_gmail_message_ids"
+    }
+
+    fn apply(&self, connection: &mut Connection) -> SqliteResult<()> {
+        connection.execute("ALTER TABLE messages ADD COLUMN gmail_msgid INTEGER", [])?;
+        connection.execute(
+            "CREATE INDEX IF NOT EXISTS idx_messages_gmail_msgid ON messages (account_id, gmail_msgid)",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+/// Migration: Create Autocrypt peer state table
+struct CreateAutocryptPeersTable;
+
+impl Migration for CreateAutocryptPeersTable {
+    fn name(&self) -> &str {
+        "create_autocrypt_peers_table"
+    }
+
+    fn apply(&self, connection: &mut Connection) -> SqliteResult<()> {
+        connection.execute(
+            "CREATE TABLE autocrypt_peers (
+                account_id TEXT NOT NULL,
+                email TEXT NOT NULL,
+                last_seen INTEGER NOT NULL,
+                autocrypt_timestamp INTEGER,
+                public_key BLOB,
+                prefer_encrypt TEXT NOT NULL,
+                gossip_timestamp INTEGER,
+                gossip_key BLOB,
+                PRIMARY KEY (account_id, email),
+                FOREIGN KEY (account_id) REFERENCES accounts (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+/// Migration: Create table of tracking pixels removed from messages
+struct CreateBlockedTrackersTable;
+
+impl Migration for CreateBlockedTrackersTable {
+    fn name(&self) -> &str {
+        "create_blocked_trackers_table"
+    }
+
+    fn apply(&self, connection: &mut Connection) -> SqliteResult<()> {
+        connection.execute(
+            "CREATE TABLE blocked_trackers (
+                message_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                service TEXT,
+                blocked_at INTEGER NOT NULL,
+                PRIMARY KEY (message_id, url),
+                FOREIGN KEY (message_id) REFERENCES messages (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
 
-    #[test]
-    fn test_migration_manager() {
+    #[tokio::test]
+    async fn test_migration_manager() {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
-        let connection = Connection::open(db_path).unwrap();
+        let connection = Arc::new(Mutex::new(Connection::open(db_path).unwrap()));
         let mut migration_manager = MigrationManager::new(connection);
         
         // Run migrations
-        migration_manager.run_migrations().unwrap();
+        migration_manager.run_migrations().await.unwrap();
         
         // Check that migrations table was created
-        let mut stmt = migration_manager.connection.prepare("SELECT COUNT(*) FROM migrations").unwrap();
-        let count: i64 = stmt.query_row([], |row| row.get(0)).unwrap();
+        let conn = migration_manager.connection.lock().await;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM migrations", [], |row| row.get(0)).unwrap();
         assert!(count > 0);
     }
 
-    #[test]
-    fn test_migration_idempotency() {
+    #[tokio::test]
+    async fn test_migration_idempotency() {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
-        let connection = Connection::open(db_path).unwrap();
+        let connection = Arc::new(Mutex::new(Connection::open(db_path).unwrap()));
         let mut migration_manager = MigrationManager::new(connection);
         
         // Run migrations twice
-        migration_manager.run_migrations().unwrap();
-        migration_manager.run_migrations().unwrap();
+        migration_manager.run_migrations().await.unwrap();
+        migration_manager.run_migrations().await.unwrap();
         
         // Should not fail on second run
-        let mut stmt = migration_manager.connection.prepare("SELECT COUNT(*) FROM migrations").unwrap();
-        let count: i64 = stmt.query_row([], |row| row.get(0)).unwrap();
+        let conn = migration_manager.connection.lock().await;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM migrations", [], |row| row.get(0)).unwrap();
         assert!(count > 0);
     }
 }
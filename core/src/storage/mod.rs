@@ -3,10 +3,12 @@
 pub mod database;
 pub mod cache;
 pub mod migrations;
+pub mod usage;
 
 pub use database::Database;
 pub use cache::Cache;
 pub use migrations::MigrationManager;
+pub use usage::{Quota, StorageReport};
 
 /// Storage manager that coordinates database and cache operations
 pub struct StorageManager {
@@ -18,7 +20,8 @@ impl StorageManager {
     /// Create a new storage manager
     pub async fn new(database_path: std::path::PathBuf, cache_dir: std::path::PathBuf) -> crate::error::AsgardResult<Self> {
         let database = Database::new(database_path).await?;
-        let cache = Cache::new(cache_dir).await?;
+        let mut cache = Cache::new(cache_dir).await?;
+        cache.set_cipher(database.cipher().clone());
         
         Ok(Self { database, cache })
     }
@@ -43,6 +46,29 @@ impl StorageManager {
         &mut self.cache
     }
 
+    /// Turn encryption at rest on or off for message content and the cache
+    ///
+    /// New data follows the setting right away; existing data is converted
+    /// by a background task, whose handle is returned with the number of
+    /// values and files it re-encoded.
+    pub fn set_encryption(&self, enabled: bool) -> tokio::task::JoinHandle<crate::error::AsgardResult<usize>> {
+        self.database.set_encryption(enabled);
+
+        let database = self.database.clone();
+        let cache = self.cache.clone();
+        tokio::spawn(async move {
+            let converted = database.reencode_content().await? + cache.reencode().await?;
+            if converted > 0 {
+                tracing::info!(
+                    "Re-encoded {} stored item(s) with encryption {}",
+                    converted,
+                    if enabled { "enabled" } else { "disabled" }
+                );
+            }
+            Ok(converted)
+        })
+    }
+
     /// Initialize storage (run migrations, etc.)
     pub async fn initialize(&mut self) -> crate::error::AsgardResult<()> {
         self.database.initialize().await?;
@@ -0,0 +1,169 @@
+//! Storage usage and quota reporting
+
+use crate::mailbox::Mailbox;
+use crate::message::Message;
+use uuid::Uuid;
+
+/// Usage ratio at which the user is warned about their quota
+pub const QUOTA_WARNING_THRESHOLD: f64 = 0.9;
+
+/// A single quota resource (RFC 9208)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaResource {
+    /// Resource name, e.g. `STORAGE` or `MESSAGE`
+    pub name: String,
+    /// Current usage
+    pub usage: u64,
+    /// Usage limit
+    pub limit: u64,
+}
+
+/// Quota reported by the server for a quota root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    /// Quota root name
+    pub root: String,
+    /// Resources limited by this root
+    pub resources: Vec<QuotaResource>,
+}
+
+impl Quota {
+    /// Get a resource by name
+    pub fn resource(&self, name: &str) -> Option<&QuotaResource> {
+        self.resources.iter().find(|r| r.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Storage usage and limit in bytes (STORAGE is counted in KiB)
+    pub fn storage_bytes(&self) -> Option<(u64, u64)> {
+        self.resource("STORAGE")
+            .map(|r| (r.usage.saturating_mul(1024), r.limit.saturating_mul(1024)))
+    }
+}
+
+/// Storage usage of an account
+#[derive(Debug, Clone)]
+pub struct StorageReport {
+    /// Account ID
+    pub account_id: Uuid,
+    /// Quota reported by the server, if any
+    pub quota: Option<Quota>,
+    /// Bytes used (quota usage, or the sum of mailbox sizes)
+    pub used_bytes: u64,
+    /// Bytes available in total, if the server has a quota
+    pub limit_bytes: Option<u64>,
+    /// Mailboxes ordered by size, largest first
+    pub mailboxes: Vec<Mailbox>,
+    /// Largest messages, largest first
+    pub largest_messages: Vec<Message>,
+}
+
+impl StorageReport {
+    /// Build a report from the quota and mailbox sizes
+    pub fn new(
+        account_id: Uuid,
+        quota: Option<Quota>,
+        mut mailboxes: Vec<Mailbox>,
+        largest_messages: Vec<Message>,
+    ) -> Self {
+        mailboxes.sort_by(|a, b| b.stats.size.cmp(&a.stats.size));
+
+        let local_total: u64 = mailboxes.iter().map(|m| m.stats.size).sum();
+        let (used_bytes, limit_bytes) = match quota.as_ref().and_then(|q| q.storage_bytes()) {
+            Some((used, limit)) => (used, Some(limit)),
+            None => (local_total, None),
+        };
+
+        Self {
+            account_id,
+            quota,
+            used_bytes,
+            limit_bytes,
+            mailboxes,
+            largest_messages,
+        }
+    }
+
+    /// Fraction of the quota in use (0.0 - 1.0)
+    pub fn usage_ratio(&self) -> Option<f64> {
+        match self.limit_bytes {
+            Some(limit) if limit > 0 => Some(self.used_bytes as f64 / limit as f64),
+            _ => None,
+        }
+    }
+
+    /// Check if usage is above the warning threshold
+    pub fn is_near_quota(&self) -> bool {
+        self.usage_ratio()
+            .map(|ratio| ratio >= QUOTA_WARNING_THRESHOLD)
+            .unwrap_or(false)
+    }
+}
+
+/// Format a byte count for display
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mailbox::MailboxType;
+
+    fn quota(usage: u64, limit: u64) -> Quota {
+        Quota {
+            root: "".to_string(),
+            resources: vec![QuotaResource {
+                name: "STORAGE".to_string(),
+                usage,
+                limit,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_quota_warning() {
+        let account_id = Uuid::new_v4();
+
+        let report = StorageReport::new(account_id, Some(quota(950, 1000)), vec![], vec![]);
+        assert_eq!(report.used_bytes, 950 * 1024);
+        assert!(report.is_near_quota());
+
+        let report = StorageReport::new(account_id, Some(quota(100, 1000)), vec![], vec![]);
+        assert!(!report.is_near_quota());
+    }
+
+    #[test]
+    fn test_report_without_quota() {
+        let account_id = Uuid::new_v4();
+        let mut small = Mailbox::new(account_id, "Small".to_string(), None, MailboxType::Custom, None);
+        small.stats.size = 10;
+        let mut large = Mailbox::new(account_id, "Large".to_string(), None, MailboxType::Custom, None);
+        large.stats.size = 1000;
+
+        let report = StorageReport::new(account_id, None, vec![small, large], vec![]);
+        assert_eq!(report.used_bytes, 1010);
+        assert_eq!(report.mailboxes[0].name, "Large");
+        assert!(report.usage_ratio().is_none());
+        assert!(!report.is_near_quota());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(15 * 1024 * 1024 * 1024), "15.0 GB");
+    }
+}
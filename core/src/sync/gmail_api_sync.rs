@@ -0,0 +1,702 @@
+//! Gmail REST API sync engine
+//!
+//! An alternative to IMAP for Gmail accounts. Messages are fetched in raw
+//! form, stored once with their labels like the X-GM-LABELS path, and kept
+//! up to date incrementally through history.list.
+
+use crate::account::Account;
+use crate::error::{AsgardError, AsgardResult};
+use crate::gmail::api::{self, GmailApiClient, HttpClient, RawMessage, ReqwestClient};
+use crate::gmail::{self, constants, GmailLabels};
+use crate::mailbox::{Mailbox, MailboxType};
+use crate::message::{Message, MessageFlags};
+use crate::search::SearchQuery;
+use crate::sync::imap_list::{self, ListEntry};
+use crate::sync::{SyncEngine, SyncResult, SyncStatus};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Account setting that selects this engine for a Gmail account
+pub const GMAIL_API_SETTING: &str = "gmail_api";
+
+/// Account setting holding the history ID the next sync starts from
+pub const HISTORY_ID_SETTING: &str = "gmail_history_id";
+
+/// Page size for messages.list
+const PAGE_SIZE: u32 = 100;
+
+/// Messages fetched on the first sync when `max_messages` is not set
+const DEFAULT_INITIAL_MESSAGES: usize = 1000;
+
+/// Gmail REST API sync engine
+pub struct GmailApiSync {
+    /// Account being synced
+    account: Account,
+    /// HTTP transport for the API client
+    http: Arc<dyn HttpClient>,
+    /// API base URL
+    base_url: String,
+    /// Upload base URL
+    upload_url: String,
+    /// API client, set while connected
+    client: Option<GmailApiClient>,
+    /// Sync status
+    status: SyncStatus,
+    /// Last sync result
+    last_sync_result: Option<SyncResult>,
+    /// Labels with their message counts
+    labels: GmailLabels,
+    /// API label ID to local label name (user labels are keyed by name)
+    label_names: HashMap<String, String>,
+    /// History ID the next incremental sync starts from
+    history_id: Option<u64>,
+    /// Gmail message IDs deleted since the last sync
+    deleted_message_ids: Vec<u64>,
+    /// Whether the current sync started without a history ID
+    full_sync: bool,
+    /// API message IDs fetched during the current full sync
+    fetched_ids: HashSet<String>,
+    /// Local mailbox IDs of All Mail, Spam and Trash
+    all_mail_id: Option<Uuid>,
+    spam_id: Option<Uuid>,
+    trash_id: Option<Uuid>,
+}
+
+impl GmailApiSync {
+    /// Create a new Gmail API sync engine
+    pub fn new(account: Account) -> Self {
+        Self::with_http(account, Arc::new(ReqwestClient::new()), api::API_BASE_URL, api::UPLOAD_BASE_URL)
+    }
+
+    /// Create an engine with a custom transport and base URLs
+    pub fn with_http(account: Account, http: Arc<dyn HttpClient>, base_url: &str, upload_url: &str) -> Self {
+        let history_id = account.config.settings.get(HISTORY_ID_SETTING).and_then(|v| v.as_u64());
+        Self {
+            account,
+            http,
+            base_url: base_url.to_string(),
+            upload_url: upload_url.to_string(),
+            client: None,
+            status: SyncStatus::Idle,
+            last_sync_result: None,
+            labels: GmailLabels::new(),
+            label_names: HashMap::new(),
+            history_id,
+            deleted_message_ids: Vec::new(),
+            full_sync: false,
+            fetched_ids: HashSet::new(),
+            all_mail_id: None,
+            spam_id: None,
+            trash_id: None,
+        }
+    }
+
+    /// Get the account ID
+    pub fn account_id(&self) -> Uuid {
+        self.account.id
+    }
+
+    /// Connect to the Gmail API
+    pub async fn connect(&mut self) -> AsgardResult<()> {
+        let access_token = self.account.gmail_oauth_config()
+            .ok_or_else(|| AsgardError::auth("OAuth configuration not found"))?
+            .access_token
+            .clone()
+            .ok_or_else(|| AsgardError::auth("No access token available"))?;
+
+        let client = GmailApiClient::with_http(self.http.clone(), &self.base_url, &self.upload_url, access_token);
+        let profile = client.get_profile().await?;
+        info!("Connected to Gmail API for {}", profile.email_address);
+
+        self.client = Some(client);
+        Ok(())
+    }
+
+    /// Disconnect from the Gmail API
+    pub async fn disconnect(&mut self) -> AsgardResult<()> {
+        self.client = None;
+        Ok(())
+    }
+
+    /// Use an updated account, passing a refreshed access token to the live client
+    pub fn update_credentials(&mut self, account: &Account) {
+        self.account = account.clone();
+        let access_token = account.oauth_config().and_then(|c| c.access_token.clone());
+        if let (Some(client), Some(access_token)) = (self.client.as_mut(), access_token) {
+            client.set_access_token(access_token);
+        }
+    }
+
+    /// Sync labels and build mailboxes from them
+    ///
+    /// Mailbox names follow the IMAP folder names so switching between the
+    /// API and IMAP keeps the same tree.
+    pub async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+        let client = self.client()?.clone();
+
+        let mut labels = GmailLabels::new();
+        let mut label_names = HashMap::new();
+        let mut entries = vec![ListEntry {
+            name: constants::ALL_MAIL.to_string(),
+            delimiter: Some('/'),
+            attributes: vec!["\\All".to_string()],
+        }];
+        let mut stats = HashMap::new();
+
+        for summary in client.list_labels().await? {
+            let Some((name, attributes)) = label_entry(&summary) else { continue };
+
+            // labels.list has no counts; labels.get does
+            let label = match client.get_label(&summary.id).await {
+                Ok(label) => label,
+                Err(e) => {
+                    warn!("Failed to get Gmail label {}: {}", summary.id, e);
+                    summary.clone()
+                }
+            };
+
+            let gmail_label = label.to_gmail_label();
+            label_names.insert(label.id.clone(), gmail_label.id.clone());
+            stats.insert(name.clone(), (label.messages_total, label.messages_unread));
+            labels.add_label(gmail_label);
+            entries.push(ListEntry {
+                name: imap_list::encode_modified_utf7(&name),
+                delimiter: Some('/'),
+                attributes,
+            });
+        }
+
+        let mut mailboxes = imap_list::build_mailboxes(self.account.id, &entries, None, true);
+        for mailbox in &mut mailboxes {
+            let name = imap_list::decode_modified_utf7(&mailbox.name);
+            if let Some((total, unread)) = stats.get(&name) {
+                mailbox.stats.total_messages = *total;
+                mailbox.stats.unread_messages = *unread;
+            }
+        }
+        self.use_mailboxes(&mailboxes);
+
+        self.labels = labels;
+        self.label_names = label_names;
+        self.full_sync = self.history_id.is_none();
+        self.fetched_ids.clear();
+        info!("Synced {} Gmail labels for account: {}", self.labels.count(), self.account.email());
+        Ok(mailboxes)
+    }
+
+    /// Remember where All Mail, Spam and Trash messages are stored
+    pub fn use_mailboxes(&mut self, mailboxes: &[Mailbox]) {
+        for mailbox in mailboxes {
+            match mailbox.mailbox_type {
+                MailboxType::All => self.all_mail_id = Some(mailbox.id),
+                MailboxType::Spam => self.spam_id = Some(mailbox.id),
+                MailboxType::Trash => self.trash_id = Some(mailbox.id),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sync messages
+    ///
+    /// Messages are stored once, in All Mail, Spam or Trash; the other
+    /// mailboxes list them by label. The first sync lists recent messages of
+    /// All Mail and of every label, so older messages of a label are not
+    /// missed; later syncs replay the history through All Mail.
+    pub async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+        if mailbox.mailbox_type != MailboxType::All {
+            return self.sync_label_messages(mailbox).await;
+        }
+        let client = self.client()?.clone();
+        let profile_history = client.get_profile().await?.history_id.parse::<u64>().ok();
+
+        let ids = match self.history_id {
+            Some(start) => match self.changed_message_ids(&client, start).await? {
+                Some(ids) => ids,
+                None => {
+                    warn!("Gmail history {} expired, running a full sync", start);
+                    self.recent_message_ids(&client, None).await?
+                }
+            },
+            None => self.recent_message_ids(&client, None).await?,
+        };
+
+        let messages = self.fetch_messages(&client, ids, mailbox).await?;
+        if profile_history.is_some() {
+            self.history_id = profile_history;
+        }
+        info!("Synced {} messages through the Gmail API", messages.len());
+        Ok(messages)
+    }
+
+    /// List the recent messages of a label mailbox during a full sync
+    async fn sync_label_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+        if !self.full_sync {
+            return Ok(Vec::new());
+        }
+        let Some(label) = gmail::imap_ext::mailbox_label(mailbox) else {
+            return Ok(Vec::new());
+        };
+        let label_id = self.api_label_ids(&[label])?.remove(0);
+
+        let client = self.client()?.clone();
+        let ids = self.recent_message_ids(&client, Some(&label_id)).await?;
+        let messages = self.fetch_messages(&client, ids, mailbox).await?;
+        info!("Synced {} messages of label {} through the Gmail API", messages.len(), label_id);
+        Ok(messages)
+    }
+
+    /// Fetch messages by API ID, skipping ones this full sync already has
+    async fn fetch_messages(&mut self, client: &GmailApiClient, ids: Vec<String>, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+        let mut messages = Vec::new();
+        for id in ids {
+            if self.full_sync && !self.fetched_ids.insert(id.clone()) {
+                continue;
+            }
+            match client.get_raw_message(&id).await {
+                Ok(raw) => messages.push(self.message_from_raw(raw, mailbox)?),
+                // Deleted between listing and fetching
+                Err(AsgardError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Add and remove labels of a message (messages.modify)
+    pub async fn store_labels(&mut self, _mailbox: &Mailbox, message: &Message, add: &[String], remove: &[String]) -> AsgardResult<()> {
+        let gmail_id = message.gmail_message_id
+            .ok_or_else(|| AsgardError::invalid_state("Message has no Gmail ID"))?;
+        let add = self.api_label_ids(add)?;
+        let remove = self.api_label_ids(remove)?;
+
+        self.client()?.modify_message(&format!("{:x}", gmail_id), &add, &remove).await?;
+        Ok(())
+    }
+
+    /// Fetch the complete message (messages.get with format=raw)
+    pub async fn fetch_raw_message(&mut self, _mailbox: &Mailbox, message: &Message) -> AsgardResult<Vec<u8>> {
+        let gmail_id = message.gmail_message_id
+            .ok_or_else(|| AsgardError::invalid_state("Message has no Gmail ID"))?;
+        Ok(self.client()?.get_raw_message(&format!("{:x}", gmail_id)).await?.raw)
+    }
+
+    /// Search with Gmail query syntax (messages.list with `q`)
+    pub async fn search_server(&mut self, mailbox: &Mailbox, query: &SearchQuery) -> AsgardResult<Vec<Message>> {
+        let client = self.client()?.clone();
+        let raw_query = gmail::raw_search_query(query);
+        let limit = query.limit.unwrap_or(100);
+
+        let list = client.list_messages(Some(&raw_query), None, None, limit.min(500) as u32).await?;
+        let mut messages = Vec::new();
+        for reference in list.messages.into_iter().take(limit) {
+            let raw = client.get_raw_message(&reference.id).await?;
+            messages.push(self.message_from_raw(raw, mailbox)?);
+        }
+        Ok(messages)
+    }
+
+    /// Send a message (messages.send), returning its Gmail ID
+    pub async fn send_message(&self, message: &Message) -> AsgardResult<String> {
+        let email = crate::sync::smtp_send::build_email(message)?;
+        self.send_raw(&email.formatted()).await
+    }
+
+    /// Send an RFC 5322 message (messages.send), returning its Gmail ID
+    pub async fn send_raw(&self, raw: &[u8]) -> AsgardResult<String> {
+        let sent = self.client()?.send_raw(raw).await?;
+        info!("Sent message {} through the Gmail API", sent.id);
+        Ok(sent.id)
+    }
+
+    /// Get the labels found during the last mailbox sync
+    pub fn labels(&self) -> &GmailLabels {
+        &self.labels
+    }
+
+    /// Get the history ID the next sync starts from
+    pub fn history_id(&self) -> Option<u64> {
+        self.history_id
+    }
+
+    /// Get the account settings to store once a sync completed
+    pub fn sync_settings(&self) -> HashMap<String, serde_json::Value> {
+        self.history_id
+            .map(|id| (HISTORY_ID_SETTING.to_string(), id.into()))
+            .into_iter()
+            .collect()
+    }
+
+    /// Take the Gmail IDs of messages deleted since the last call
+    pub fn take_deleted_message_ids(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.deleted_message_ids)
+    }
+
+    /// Get sync status
+    pub fn status(&self) -> SyncStatus {
+        self.status
+    }
+
+    /// Get last sync result
+    pub fn last_sync_result(&self) -> Option<&SyncResult> {
+        self.last_sync_result.as_ref()
+    }
+
+    // Helper methods
+
+    fn client(&self) -> AsgardResult<&GmailApiClient> {
+        self.client.as_ref()
+            .ok_or_else(|| AsgardError::invalid_state("Not connected to the Gmail API"))
+    }
+
+    async fn recent_message_ids(&self, client: &GmailApiClient, label_id: Option<&str>) -> AsgardResult<Vec<String>> {
+        let settings = &self.account.config.sync_settings;
+        let query = settings.sync_recent_days.map(|days| format!("newer_than:{}d", days));
+        let max = settings.max_messages.unwrap_or(DEFAULT_INITIAL_MESSAGES);
+
+        let mut ids = Vec::new();
+        let mut page_token = None;
+        loop {
+            let list = client.list_messages(query.as_deref(), label_id, page_token.as_deref(), PAGE_SIZE).await?;
+            ids.extend(list.messages.into_iter().map(|m| m.id));
+            page_token = list.next_page_token;
+            if page_token.is_none() || ids.len() >= max {
+                break;
+            }
+        }
+
+        ids.truncate(max);
+        Ok(ids)
+    }
+
+    /// IDs of messages added or relabelled since `start`, or `None` if the
+    /// history is no longer available
+    async fn changed_message_ids(&mut self, client: &GmailApiClient, start: u64) -> AsgardResult<Option<Vec<String>>> {
+        let mut ids: Vec<String> = Vec::new();
+        let mut page_token = None;
+
+        loop {
+            let Some(history) = client.list_history(start, page_token.as_deref()).await? else {
+                return Ok(None);
+            };
+
+            for record in history.history {
+                let changed = record.messages_added.iter().map(|c| &c.message)
+                    .chain(record.labels_added.iter().map(|c| &c.message))
+                    .chain(record.labels_removed.iter().map(|c| &c.message));
+                for message in changed {
+                    if !ids.contains(&message.id) {
+                        ids.push(message.id.clone());
+                    }
+                }
+                for deleted in record.messages_deleted {
+                    ids.retain(|id| *id != deleted.message.id);
+                    if let Ok(gmail_id) = u64::from_str_radix(&deleted.message.id, 16) {
+                        self.deleted_message_ids.push(gmail_id);
+                    }
+                }
+            }
+
+            page_token = history.next_page_token;
+            if page_token.is_none() {
+                return Ok(Some(ids));
+            }
+        }
+    }
+
+    /// Build a message stored in All Mail, Spam or Trash; `mailbox` is used
+    /// when those are unknown
+    fn message_from_raw(&self, raw: RawMessage, mailbox: &Mailbox) -> AsgardResult<Message> {
+        let all_mail_id = self.all_mail_id.unwrap_or(mailbox.id);
+        let mailbox_id = if raw.label_ids.iter().any(|l| l == "SPAM") {
+            self.spam_id.unwrap_or(all_mail_id)
+        } else if raw.label_ids.iter().any(|l| l == "TRASH") {
+            self.trash_id.unwrap_or(all_mail_id)
+        } else {
+            all_mail_id
+        };
+
+        let mut message = Message::from_rfc822(self.account.id, mailbox_id, &raw.raw)?;
+        // API IDs are the hexadecimal form of X-GM-MSGID and X-GM-THRID
+        message.gmail_message_id = u64::from_str_radix(&raw.id, 16).ok();
+        message.conversation_id = u64::from_str_radix(&raw.thread_id, 16)
+            .map(|id| id.to_string())
+            .ok()
+            .or(Some(raw.thread_id.clone()));
+        message.headers.received_date = raw.internal_date
+            .and_then(|ms| OffsetDateTime::from_unix_timestamp(ms / 1000).ok());
+        message.last_sync = Some(OffsetDateTime::now_utc());
+
+        let (labels, flags) = self.local_labels(&raw.label_ids);
+        message.set_labels(labels);
+        message.set_flags(flags);
+        Ok(message)
+    }
+
+    /// Split API label IDs into local labels and message flags
+    fn local_labels(&self, label_ids: &[String]) -> (Vec<String>, Vec<MessageFlags>) {
+        let mut labels = Vec::new();
+        let mut flags = vec![MessageFlags::Seen];
+
+        for id in label_ids {
+            match id.as_str() {
+                "UNREAD" => flags.retain(|f| *f != MessageFlags::Seen),
+                "STARRED" => {
+                    flags.push(MessageFlags::Flagged);
+                    labels.push(id.clone());
+                }
+                "DRAFT" => {
+                    flags.push(MessageFlags::Draft);
+                    labels.push(api::normalize_label_id(id));
+                }
+                _ if id.starts_with("CATEGORY_") || id == "CHAT" => {}
+                _ => labels.push(self.label_names.get(id).cloned().unwrap_or_else(|| api::normalize_label_id(id))),
+            }
+        }
+
+        (labels, flags)
+    }
+
+    fn api_label_ids(&self, labels: &[String]) -> AsgardResult<Vec<String>> {
+        labels.iter()
+            .map(|label| {
+                if gmail::is_system_label(label) {
+                    return Ok(api::api_label_id(label));
+                }
+                self.label_names.iter()
+                    .find(|(_, name)| *name == label)
+                    .map(|(id, _)| id.clone())
+                    .ok_or_else(|| AsgardError::not_found(format!("Gmail label not found: {}", label)))
+            })
+            .collect()
+    }
+}
+
+/// Folder name and special-use attributes of a label, or `None` for labels
+/// that are not shown as mailboxes
+fn label_entry(label: &api::ApiLabel) -> Option<(String, Vec<String>)> {
+    let attribute = |name: &str| vec![name.to_string()];
+    match label.id.as_str() {
+        "INBOX" => Some((constants::INBOX.to_string(), vec![])),
+        "SENT" => Some((constants::SENT.to_string(), attribute("\\Sent"))),
+        "DRAFT" => Some((constants::DRAFTS.to_string(), attribute("\\Drafts"))),
+        "TRASH" => Some((constants::TRASH.to_string(), attribute("\\Trash"))),
+        "SPAM" => Some((constants::SPAM.to_string(), attribute("\\Junk"))),
+        "STARRED" => Some((constants::STARRED.to_string(), attribute("\\Flagged"))),
+        "IMPORTANT" => Some((constants::IMPORTANT.to_string(), attribute("\\Important"))),
+        _ if label.label_type.eq_ignore_ascii_case("system") => None,
+        _ => Some((label.name.clone(), vec![])),
+    }
+}
+
+#[async_trait::async_trait]
+impl SyncEngine for GmailApiSync {
+    fn account_id(&self) -> Uuid {
+        self.account.id
+    }
+
+    fn status(&self) -> SyncStatus {
+        self.status
+    }
+
+    fn last_sync_result(&self) -> Option<&SyncResult> {
+        self.last_sync_result.as_ref()
+    }
+
+    async fn connect(&mut self) -> AsgardResult<()> {
+        GmailApiSync::connect(self).await
+    }
+
+    async fn disconnect(&mut self) -> AsgardResult<()> {
+        GmailApiSync::disconnect(self).await
+    }
+
+    fn update_credentials(&mut self, account: &Account) {
+        GmailApiSync::update_credentials(self, account)
+    }
+
+    async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+        GmailApiSync::sync_mailboxes(self).await
+    }
+
+    fn use_mailboxes(&mut self, mailboxes: &[Mailbox]) {
+        GmailApiSync::use_mailboxes(self, mailboxes)
+    }
+
+    fn sync_settings(&self) -> HashMap<String, serde_json::Value> {
+        GmailApiSync::sync_settings(self)
+    }
+
+    fn take_deleted_message_ids(&mut self) -> Vec<u64> {
+        GmailApiSync::take_deleted_message_ids(self)
+    }
+
+    async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+        GmailApiSync::sync_mailbox_messages(self, mailbox).await
+    }
+
+    async fn store_labels(&mut self, mailbox: &Mailbox, message: &Message, add: &[String], remove: &[String]) -> AsgardResult<()> {
+        GmailApiSync::store_labels(self, mailbox, message, add, remove).await
+    }
+
+    async fn search_server(&mut self, mailbox: &Mailbox, query: &SearchQuery) -> AsgardResult<Vec<Message>> {
+        GmailApiSync::search_server(self, mailbox, query).await
+    }
+
+    async fn fetch_raw_message(&mut self, mailbox: &Mailbox, message: &Message) -> AsgardResult<Vec<u8>> {
+        GmailApiSync::fetch_raw_message(self, mailbox, message).await
+    }
+
+    async fn send_message(&mut self, message: &Message) -> AsgardResult<()> {
+        GmailApiSync::send_message(self, message).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::GmailOAuthConfig;
+    use crate::gmail::api::{HttpRequest, HttpResponse};
+    use std::sync::Mutex;
+
+    /// Transport answering from a route table, recording request URLs
+    struct MockHttp {
+        routes: Vec<(String, String)>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HttpClient for MockHttp {
+        async fn execute(&self, request: HttpRequest) -> AsgardResult<HttpResponse> {
+            let path = request.url.trim_start_matches("http://gmail.test").to_string();
+            self.requests.lock().unwrap().push(path.clone());
+            Ok(self.routes.iter()
+                .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+                .map(|(_, body)| HttpResponse { status: 200, body: body.clone().into_bytes() })
+                .unwrap_or(HttpResponse { status: 404, body: vec![] }))
+        }
+    }
+
+    fn account() -> Account {
+        Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            GmailOAuthConfig {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                access_token: Some("token".to_string()),
+                refresh_token: None,
+                token_expires_at: None,
+                scopes: vec![],
+            },
+        ).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sync_labels_and_messages() {
+        use base64::Engine as _;
+        let raw = base64::engine::general_purpose::URL_SAFE
+            .encode("From: Alice <alice@example.com>\r\nSubject: Plans\r\n\r\nSee you");
+
+        let http = Arc::new(MockHttp {
+            routes: vec![
+                ("/profile".to_string(), r#"{"emailAddress":"test@gmail.com","historyId":"900"}"#.to_string()),
+                ("/labels/INBOX".to_string(), r#"{"id":"INBOX","name":"INBOX","type":"system","messagesTotal":5,"messagesUnread":2}"#.to_string()),
+                ("/labels/Label_7".to_string(), r#"{"id":"Label_7","name":"Work/Clients","type":"user","messagesTotal":1}"#.to_string()),
+                ("/labels".to_string(), r#"{"labels":[{"id":"INBOX","name":"INBOX","type":"system"},{"id":"UNREAD","name":"UNREAD","type":"system"},{"id":"Label_7","name":"Work/Clients","type":"user"}]}"#.to_string()),
+                ("/messages?".to_string(), r#"{"messages":[{"id":"11a","threadId":"11a"}]}"#.to_string()),
+                ("/messages/11a".to_string(), format!(r#"{{"id":"11a","threadId":"11a","labelIds":["INBOX","UNREAD","Label_7","CATEGORY_PERSONAL"],"historyId":"880","raw":"{}"}}"#, raw)),
+            ],
+            requests: Mutex::new(Vec::new()),
+        });
+
+        let mut engine = GmailApiSync::with_http(account(), http.clone(), "http://gmail.test", "http://gmail.test");
+        engine.connect().await.unwrap();
+
+        let mailboxes = engine.sync_mailboxes().await.unwrap();
+        let inbox = mailboxes.iter().find(|m| m.mailbox_type == MailboxType::Inbox).unwrap();
+        assert_eq!((inbox.stats.total_messages, inbox.stats.unread_messages), (5, 2));
+        let clients = mailboxes.iter().find(|m| m.name == "Work/Clients").unwrap();
+        assert!(clients.parent_id.is_some());
+        assert_eq!(engine.labels().get_label("Work/Clients").unwrap().messages_total, 1);
+
+        let all_mail = mailboxes.iter().find(|m| m.mailbox_type == MailboxType::All).unwrap().clone();
+
+        // The first sync lists each label too, fetching what All Mail missed
+        let messages = engine.sync_mailbox_messages(clients).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].mailbox_id, all_mail.id);
+        assert!(http.requests.lock().unwrap().iter().any(|r| r.contains("labelIds=Label_7")));
+
+        let messages = engine.sync_mailbox_messages(&all_mail).await.unwrap();
+        assert!(messages.is_empty());
+        assert_eq!(engine.history_id(), Some(900));
+
+        // The mock has no history, so the next sync finds it expired and
+        // lists All Mail again
+        engine.sync_mailboxes().await.unwrap();
+        assert!(engine.sync_mailbox_messages(clients).await.unwrap().is_empty());
+        let messages = engine.sync_mailbox_messages(&all_mail).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].subject(), "Plans");
+        assert_eq!(messages[0].gmail_message_id, Some(0x11a));
+        assert_eq!(messages[0].labels, vec!["INBOX", "Work/Clients"]);
+        assert!(!messages[0].flags.contains(&MessageFlags::Seen));
+        assert_eq!(engine.history_id(), Some(900));
+
+        assert_eq!(engine.api_label_ids(&["Work/Clients".to_string(), "DRAFTS".to_string()]).unwrap(), vec!["Label_7", "DRAFT"]);
+
+        // An engine for the account with the stored settings continues from there
+        let mut account = account();
+        account.config.settings.extend(engine.sync_settings());
+        let engine = GmailApiSync::with_http(account, http, "http://gmail.test", "http://gmail.test");
+        assert_eq!(engine.history_id(), Some(900));
+    }
+
+    #[tokio::test]
+    async fn test_send_message() {
+        let http = Arc::new(MockHttp {
+            routes: vec![
+                ("/profile".to_string(), r#"{"emailAddress":"test@gmail.com","historyId":"900"}"#.to_string()),
+                ("/messages/send".to_string(), r#"{"id":"12c","threadId":"12c"}"#.to_string()),
+            ],
+            requests: Mutex::new(Vec::new()),
+        });
+
+        let mut engine = GmailApiSync::with_http(account(), http.clone(), "http://gmail.test", "http://gmail.test");
+        engine.connect().await.unwrap();
+        let raw = b"From: test@gmail.com\r\nTo: bob@example.com\r\nSubject: Lunch\r\n\r\nNoon?";
+        let message = Message::from_rfc822(engine.account_id(), Uuid::new_v4(), raw).unwrap();
+
+        SyncEngine::send_message(&mut engine, &message).await.unwrap();
+        assert!(http.requests.lock().unwrap().iter().any(|r| r.starts_with("/messages/send")));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_sync_reports_deleted_messages() {
+        let http = Arc::new(MockHttp {
+            routes: vec![
+                ("/profile".to_string(), r#"{"emailAddress":"test@gmail.com","historyId":"950"}"#.to_string()),
+                ("/history".to_string(), r#"{"history":[{"id":"940","messagesDeleted":[{"message":{"id":"11b"}}]}],"historyId":"950"}"#.to_string()),
+                ("/labels".to_string(), r#"{"labels":[]}"#.to_string()),
+            ],
+            requests: Mutex::new(Vec::new()),
+        });
+
+        let mut account = account();
+        account.config.settings.insert(HISTORY_ID_SETTING.to_string(), 900.into());
+        let mut engine = GmailApiSync::with_http(account, http.clone(), "http://gmail.test", "http://gmail.test");
+        engine.connect().await.unwrap();
+
+        let mailboxes = engine.sync_mailboxes().await.unwrap();
+        let all_mail = mailboxes.iter().find(|m| m.mailbox_type == MailboxType::All).unwrap();
+        assert!(engine.sync_mailbox_messages(all_mail).await.unwrap().is_empty());
+        assert!(http.requests.lock().unwrap().iter().any(|r| r.contains("startHistoryId=900")));
+
+        assert_eq!(engine.take_deleted_message_ids(), vec![0x11b]);
+        assert!(engine.take_deleted_message_ids().is_empty());
+        assert_eq!(engine.history_id(), Some(950));
+    }
+}
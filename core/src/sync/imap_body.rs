@@ -0,0 +1,309 @@
+//! IMAP BODYSTRUCTURE parsing and partial message download
+//!
+//! Sync fetches headers, envelope and BODYSTRUCTURE only. The structure
+//! becomes message parts and attachments without content; text bodies are
+//! fetched section by section when a message is opened or prefetched, and
+//! attachments when they are needed.
+
+use crate::account::SyncSettings;
+use crate::message::{is_attachment_part, is_inline_resource, Attachment, Message, MessagePart, MessagePartType};
+use async_imap::imap_proto::{AttributeValue, BodyParams, BodyStructure, ContentEncoding, Response};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// A leaf part described by BODYSTRUCTURE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodyPart {
+    /// IMAP part number, e.g. `1.2`
+    pub part_id: String,
+    /// Lowercase MIME type
+    pub mime_type: String,
+    /// Transfer encoding
+    pub encoding: Option<String>,
+    /// Size of the encoded content in bytes
+    pub size: usize,
+    /// File name from the disposition or the `name` parameter
+    pub filename: Option<String>,
+    /// Whether the disposition is `attachment`
+    pub attachment_disposition: bool,
+    /// Content-ID
+    pub content_id: Option<String>,
+}
+
+/// Size and structure of one message from a FETCH response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageStructure {
+    /// Message UID
+    pub uid: u32,
+    /// RFC822.SIZE
+    pub size: Option<usize>,
+    /// Leaf parts in order
+    pub parts: Vec<BodyPart>,
+}
+
+/// Header and body sections to fetch for one part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartSections {
+    /// Section holding the part's MIME headers
+    pub header: String,
+    /// Section holding the part's content
+    pub body: String,
+}
+
+impl PartSections {
+    /// FETCH items for both sections, without setting \Seen
+    pub fn fetch_items(&self) -> String {
+        format!("BODY.PEEK[{}] BODY.PEEK[{}]", self.header, self.body)
+    }
+}
+
+/// Command fetching size and structure of the messages in `uid_set`
+pub fn structure_command(uid_set: &str) -> String {
+    format!("UID FETCH {} (UID RFC822.SIZE BODYSTRUCTURE)", uid_set)
+}
+
+/// Size and structure of a message from an untagged FETCH response of
+/// [`structure_command`]
+pub fn message_structure(response: &Response<'_>) -> Option<MessageStructure> {
+    let Response::Fetch(_, values) = response else { return None };
+
+    let mut uid = None;
+    let mut size = None;
+    let mut parts = Vec::new();
+    for value in values {
+        match value {
+            AttributeValue::Uid(value) => uid = Some(*value),
+            AttributeValue::Rfc822Size(value) => size = Some(*value as usize),
+            AttributeValue::BodyStructure(body) => collect_parts(body, "", &mut parts),
+            _ => {}
+        }
+    }
+
+    Some(MessageStructure { uid: uid?, size, parts })
+}
+
+/// Add the parts and attachments of `structure` to `message`, without content
+pub fn apply_structure(message: &mut Message, structure: &MessageStructure) {
+    if let Some(size) = structure.size {
+        message.size = size;
+    }
+
+    for part in &structure.parts {
+        let has_content_id = part.content_id.is_some();
+        if is_attachment_part(&part.mime_type, part.attachment_disposition, part.filename.is_some(), has_content_id) {
+            message.attachments.push(Attachment {
+                id: Uuid::new_v4(),
+                message_id: message.id,
+                part_id: part.part_id.clone(),
+                filename: part.filename.clone().unwrap_or_else(|| "attachment".to_string()),
+                mime_type: part.mime_type.clone(),
+                size: part.size,
+                content_hash: String::new(),
+                file_path: None,
+                content: None,
+                created_at: OffsetDateTime::now_utc(),
+            });
+            continue;
+        }
+
+        let inline = is_inline_resource(&part.mime_type, part.attachment_disposition, has_content_id);
+        message.parts.push(MessagePart {
+            id: part.part_id.clone(),
+            part_type: MessagePartType::for_mime_type(&part.mime_type),
+            mime_type: part.mime_type.clone(),
+            disposition: inline.then(|| "inline".to_string()),
+            filename: part.filename.clone(),
+            size: part.size,
+            encoding: part.encoding.clone(),
+            content_id: part.content_id.clone(),
+            content_location: None,
+            content: None,
+            children: vec![],
+        });
+    }
+}
+
+/// Parts of `message` to download during sync
+///
+/// Text bodies are always included; attachments only when `settings`
+/// allows syncing them and they are within the size limit.
+pub fn prefetch_parts(message: &Message, settings: &SyncSettings) -> Vec<String> {
+    let mut parts = message.missing_body_parts();
+    if settings.sync_attachments {
+        parts.extend(message.attachments.iter()
+            .filter(|a| a.content.is_none() && a.size <= settings.max_attachment_size)
+            .map(|a| a.part_id.clone()));
+    }
+    parts
+}
+
+/// Sections holding `part_id` of `message`
+///
+/// A message that is not multipart has its only part in the message body,
+/// described by the message header.
+pub fn part_sections(message: &Message, part_id: &str) -> PartSections {
+    let leaves = message.parts.len() + message.attachments.len();
+    if leaves <= 1 && part_id == "1" {
+        PartSections { header: "HEADER".to_string(), body: "TEXT".to_string() }
+    } else {
+        PartSections { header: format!("{}.MIME", part_id), body: part_id.to_string() }
+    }
+}
+
+/// Collect leaf parts with their part numbers (RFC 3501 section 7.4.2)
+fn collect_parts(body: &BodyStructure<'_>, id: &str, parts: &mut Vec<BodyPart>) {
+    let (common, single) = match body {
+        BodyStructure::Multipart { bodies, .. } => {
+            for (index, child) in bodies.iter().enumerate() {
+                let child_id = if id.is_empty() {
+                    (index + 1).to_string()
+                } else {
+                    format!("{}.{}", id, index + 1)
+                };
+                collect_parts(child, &child_id, parts);
+            }
+            return;
+        }
+        BodyStructure::Basic { common, other, .. }
+        | BodyStructure::Text { common, other, .. }
+        | BodyStructure::Message { common, other, .. } => (common, other),
+    };
+
+    let disposition = common.disposition.as_ref();
+    let filename = disposition.and_then(|d| parameter(&d.params, "filename"))
+        .or_else(|| parameter(&common.ty.params, "name"));
+
+    parts.push(BodyPart {
+        part_id: if id.is_empty() { "1".to_string() } else { id.to_string() },
+        mime_type: format!("{}/{}", common.ty.ty, common.ty.subtype).to_ascii_lowercase(),
+        encoding: Some(encoding_name(&single.transfer_encoding)),
+        size: single.octets as usize,
+        filename,
+        attachment_disposition: disposition.is_some_and(|d| d.ty.eq_ignore_ascii_case("attachment")),
+        content_id: single.id.as_ref().map(|id| id.to_string()),
+    });
+}
+
+/// Value of `name` in a parameter list such as `("charset" "utf-8")`
+fn parameter(params: &BodyParams<'_>, name: &str) -> Option<String> {
+    params.as_ref()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.to_string())
+}
+
+fn encoding_name(encoding: &ContentEncoding<'_>) -> String {
+    match encoding {
+        ContentEncoding::SevenBit => "7BIT".to_string(),
+        ContentEncoding::EightBit => "8BIT".to_string(),
+        ContentEncoding::Binary => "BINARY".to_string(),
+        ContentEncoding::Base64 => "BASE64".to_string(),
+        ContentEncoding::QuotedPrintable => "QUOTED-PRINTABLE".to_string(),
+        ContentEncoding::Other(name) => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::imap_list::parse_responses;
+    use std::collections::HashMap;
+
+    fn parse_structure_response(response: &str) -> Vec<MessageStructure> {
+        parse_responses(response).iter().filter_map(message_structure).collect()
+    }
+
+    fn message() -> Message {
+        let headers = crate::message::MessageHeaders {
+            message_id: None,
+            in_reply_to: None,
+            references: None,
+            subject: "Report".to_string(),
+            from: vec![],
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            reply_to: vec![],
+            date: None,
+            received_date: None,
+            importance: crate::message::MessageImportance::Normal,
+            custom: HashMap::new(),
+        };
+        Message::new(Uuid::new_v4(), Uuid::new_v4(), headers)
+    }
+
+    const MIXED: &str = "* 12 FETCH (UID 4827 RFC822.SIZE 120534 BODYSTRUCTURE (\
+        ((\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"QUOTED-PRINTABLE\" 1152 23 NIL NIL NIL)\
+        (\"TEXT\" \"HTML\" (\"CHARSET\" \"utf-8\") NIL NIL \"QUOTED-PRINTABLE\" 4210 80 NIL NIL NIL) \"ALTERNATIVE\" (\"BOUNDARY\" \"alt\") NIL NIL)\
+        (\"IMAGE\" \"PNG\" (\"NAME\" \"logo.png\") \"<logo@example.com>\" NIL \"BASE64\" 5230 NIL (\"INLINE\" NIL) NIL)\
+        (\"APPLICATION\" \"PDF\" (\"NAME\" \"report.pdf\") NIL NIL \"BASE64\" 108000 NIL (\"ATTACHMENT\" (\"FILENAME\" {10}\r\nreport.pdf)) NIL) \
+        \"MIXED\" (\"BOUNDARY\" \"mix\") NIL NIL))\r\n\
+        * 13 FETCH (UID 4828 RFC822.SIZE 342 BODYSTRUCTURE (\"TEXT\" \"PLAIN\" (\"CHARSET\" \"us-ascii\") NIL NIL \"7BIT\" 12 1 NIL NIL NIL))\r\n\
+        A3 OK UID FETCH completed\r\n";
+
+    #[test]
+    fn test_parse_structure_response() {
+        let structures = parse_structure_response(MIXED);
+        assert_eq!(structures.len(), 2);
+
+        let mixed = &structures[0];
+        assert_eq!(mixed.uid, 4827);
+        assert_eq!(mixed.size, Some(120534));
+        let ids: Vec<&str> = mixed.parts.iter().map(|p| p.part_id.as_str()).collect();
+        assert_eq!(ids, ["1.1", "1.2", "2", "3"]);
+        assert_eq!(mixed.parts[1].mime_type, "text/html");
+        assert_eq!(mixed.parts[1].encoding.as_deref(), Some("QUOTED-PRINTABLE"));
+        assert_eq!(mixed.parts[2].content_id.as_deref(), Some("<logo@example.com>"));
+        assert_eq!(mixed.parts[3].filename.as_deref(), Some("report.pdf"));
+        assert!(mixed.parts[3].attachment_disposition);
+        assert_eq!(mixed.parts[3].size, 108000);
+
+        let plain = &structures[1];
+        assert_eq!(plain.uid, 4828);
+        assert_eq!(plain.parts.len(), 1);
+        assert_eq!(plain.parts[0].part_id, "1");
+        assert_eq!(plain.parts[0].mime_type, "text/plain");
+    }
+
+    #[test]
+    fn test_apply_structure_and_prefetch() {
+        let structures = parse_structure_response(MIXED);
+        let mut message = message();
+        apply_structure(&mut message, &structures[0]);
+
+        assert_eq!(message.size, 120534);
+        assert_eq!(message.parts.len(), 3);
+        assert_eq!(message.parts[2].disposition.as_deref(), Some("inline"));
+        assert_eq!(message.attachments.len(), 1);
+        assert_eq!(message.attachments[0].filename, "report.pdf");
+        assert!(message.attachments[0].content.is_none());
+        assert_eq!(message.missing_body_parts(), ["1.1", "1.2"]);
+
+        let settings = SyncSettings::default();
+        assert_eq!(prefetch_parts(&message, &settings), ["1.1", "1.2", "3"]);
+        let small = SyncSettings { max_attachment_size: 100_000, ..SyncSettings::default() };
+        assert_eq!(prefetch_parts(&message, &small), ["1.1", "1.2"]);
+        let lazy = SyncSettings { sync_attachments: false, ..SyncSettings::default() };
+        assert_eq!(prefetch_parts(&message, &lazy), ["1.1", "1.2"]);
+
+        assert!(message.set_part_content("1.1", b"Hello".to_vec()));
+        assert!(message.set_part_content("3", b"%PDF".to_vec()));
+        assert!(!message.set_part_content("9", vec![]));
+        assert_eq!(message.missing_body_parts(), ["1.2"]);
+        assert!(message.attachments[0].matches_hash(b"%PDF"));
+    }
+
+    #[test]
+    fn test_part_sections() {
+        let structures = parse_structure_response(MIXED);
+
+        let mut mixed = message();
+        apply_structure(&mut mixed, &structures[0]);
+        let sections = part_sections(&mixed, "1.2");
+        assert_eq!(sections.fetch_items(), "BODY.PEEK[1.2.MIME] BODY.PEEK[1.2]");
+
+        let mut plain = message();
+        apply_structure(&mut plain, &structures[1]);
+        assert_eq!(part_sections(&plain, "1").fetch_items(), "BODY.PEEK[HEADER] BODY.PEEK[TEXT]");
+    }
+}
@@ -0,0 +1,701 @@
+//! IMAP mailbox discovery (LIST, SPECIAL-USE, NAMESPACE)
+//!
+//! Turns LIST responses into a mailbox hierarchy with types taken from
+//! RFC 6154 special-use attributes.
+
+use crate::mailbox::{Mailbox, MailboxFlags, MailboxType};
+use async_imap::imap_proto::{MailboxDatum, NameAttribute, Response};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A single untagged LIST response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListEntry {
+    /// Raw mailbox name as sent by the server (modified UTF-7)
+    pub name: String,
+    /// Hierarchy delimiter (None for flat namespaces)
+    pub delimiter: Option<char>,
+    /// Name attributes, e.g. `\HasChildren` or `\Sent`
+    pub attributes: Vec<String>,
+}
+
+impl ListEntry {
+    /// Known mailbox flags from the LIST attributes
+    pub fn flags(&self) -> Vec<MailboxFlags> {
+        let mut flags = Vec::new();
+        for attribute in &self.attributes {
+            if let Some(flag) = MailboxFlags::from_imap_attribute(attribute) {
+                if !flags.contains(&flag) {
+                    flags.push(flag);
+                }
+            }
+        }
+        flags
+    }
+}
+
+/// A single namespace (prefix and delimiter)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    /// Namespace prefix, e.g. `""` or `"INBOX."`
+    pub prefix: String,
+    /// Hierarchy delimiter
+    pub delimiter: Option<char>,
+}
+
+/// Namespaces reported by the NAMESPACE command (RFC 2342)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespaces {
+    /// Personal namespaces
+    pub personal: Vec<Namespace>,
+    /// Other users' namespaces
+    pub other_users: Vec<Namespace>,
+    /// Shared namespaces
+    pub shared: Vec<Namespace>,
+}
+
+impl Namespaces {
+    /// Get the primary personal namespace
+    pub fn personal_prefix(&self) -> Option<&Namespace> {
+        self.personal.first()
+    }
+}
+
+/// Build the LIST command for the server capabilities
+pub fn list_command(capabilities: &[String]) -> String {
+    let has = |name: &str| capabilities.iter().any(|c| c.eq_ignore_ascii_case(name));
+
+    let mut return_options = Vec::new();
+    if has("SPECIAL-USE") {
+        return_options.push("SPECIAL-USE");
+    }
+    if has("LIST-EXTENDED") {
+        return_options.push("CHILDREN");
+        return_options.push("SUBSCRIBED");
+    }
+
+    if has("LIST-EXTENDED") {
+        format!("LIST \"\" \"*\" RETURN ({})", return_options.join(" "))
+    } else {
+        "LIST \"\" \"*\"".to_string()
+    }
+}
+
+/// Quote a string for use as an IMAP command argument
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Entry of an untagged LIST response
+pub fn list_entry(response: &Response<'_>) -> Option<ListEntry> {
+    match response {
+        Response::MailboxData(MailboxDatum::List { name_attributes, delimiter, name }) => Some(ListEntry {
+            name: name.to_string(),
+            delimiter: delimiter.as_ref().and_then(|d| d.chars().next()),
+            attributes: name_attributes.iter().filter_map(name_attribute).collect(),
+        }),
+        _ => None,
+    }
+}
+
+fn name_attribute(attribute: &NameAttribute<'_>) -> Option<String> {
+    let name = match attribute {
+        NameAttribute::NoInferiors => "\\Noinferiors",
+        NameAttribute::NoSelect => "\\Noselect",
+        NameAttribute::Marked => "\\Marked",
+        NameAttribute::Unmarked => "\\Unmarked",
+        NameAttribute::All => "\\All",
+        NameAttribute::Archive => "\\Archive",
+        NameAttribute::Drafts => "\\Drafts",
+        NameAttribute::Flagged => "\\Flagged",
+        NameAttribute::Junk => "\\Junk",
+        NameAttribute::Sent => "\\Sent",
+        NameAttribute::Trash => "\\Trash",
+        NameAttribute::Extension(name) => name,
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Namespaces of an untagged NAMESPACE response (RFC 2342)
+///
+/// imap-proto has no grammar for NAMESPACE, so the session reads this line
+/// off the raw stream. Namespace response extensions are skipped; `None` if
+/// the line is not a NAMESPACE response or uses literals.
+pub fn parse_namespace(line: &str) -> Option<Namespaces> {
+    let rest = line.trim_end().strip_prefix("* ")?;
+    let (keyword, rest) = rest.split_once(' ')?;
+    if !keyword.eq_ignore_ascii_case("NAMESPACE") {
+        return None;
+    }
+
+    let mut tokens = namespace_tokens(rest)?.into_iter();
+    let namespaces = Namespaces {
+        personal: namespace_list(&mut tokens)?,
+        other_users: namespace_list(&mut tokens)?,
+        shared: namespace_list(&mut tokens)?,
+    };
+    tokens.next().is_none().then_some(namespaces)
+}
+
+/// Token of a NAMESPACE response
+#[derive(Debug, PartialEq, Eq)]
+enum NamespaceToken {
+    Open,
+    Close,
+    Nil,
+    String(String),
+}
+
+fn namespace_tokens(input: &str) -> Option<Vec<NamespaceToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => {}
+            '(' => tokens.push(NamespaceToken::Open),
+            ')' => tokens.push(NamespaceToken::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' => value.push(chars.next()?),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(NamespaceToken::String(value));
+            }
+            _ => {
+                let mut atom = c.to_string();
+                while let Some(c) = chars.next_if(|c| !matches!(c, ' ' | '(' | ')')) {
+                    atom.push(c);
+                }
+                if !atom.eq_ignore_ascii_case("NIL") {
+                    return None;
+                }
+                tokens.push(NamespaceToken::Nil);
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// One of the three namespace lists: `NIL` or `(("prefix" "delimiter") ...)`
+fn namespace_list(tokens: &mut impl Iterator<Item = NamespaceToken>) -> Option<Vec<Namespace>> {
+    match tokens.next()? {
+        NamespaceToken::Nil => return Some(Vec::new()),
+        NamespaceToken::Open => {}
+        _ => return None,
+    }
+
+    let mut namespaces = Vec::new();
+    loop {
+        match tokens.next()? {
+            NamespaceToken::Close => return Some(namespaces),
+            NamespaceToken::Open => {}
+            _ => return None,
+        }
+        let NamespaceToken::String(prefix) = tokens.next()? else {
+            return None;
+        };
+        let delimiter = match tokens.next()? {
+            NamespaceToken::String(delimiter) => delimiter.chars().next(),
+            NamespaceToken::Nil => None,
+            _ => return None,
+        };
+
+        // Skip extensions up to the end of this namespace
+        let mut depth = 1;
+        while depth > 0 {
+            match tokens.next()? {
+                NamespaceToken::Open => depth += 1,
+                NamespaceToken::Close => depth -= 1,
+                _ => {}
+            }
+        }
+        namespaces.push(Namespace { prefix, delimiter });
+    }
+}
+
+/// Personal namespace inferred from the listed mailboxes
+///
+/// Used for servers without the NAMESPACE capability: servers that keep
+/// every folder below INBOX (Courier, older Cyrus) are recognised by the
+/// hierarchy instead.
+pub fn infer_namespaces(entries: &[ListEntry]) -> Option<Namespaces> {
+    let delimiter = entries.iter().find_map(|entry| entry.delimiter)?;
+    let inbox_prefix = format!("INBOX{}", delimiter);
+
+    let mut others = entries.iter().filter(|entry| !entry.name.eq_ignore_ascii_case("INBOX")).peekable();
+    let prefix = if others.peek().is_some() && others.all(|entry| entry.name.starts_with(&inbox_prefix)) {
+        inbox_prefix
+    } else {
+        String::new()
+    };
+
+    Some(Namespaces {
+        personal: vec![Namespace { prefix, delimiter: Some(delimiter) }],
+        ..Namespaces::default()
+    })
+}
+
+/// Decode a modified UTF-7 mailbox name (RFC 3501 section 5.1.3)
+pub fn decode_modified_utf7(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut chars = name.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '&' {
+            result.push(c);
+            continue;
+        }
+
+        let mut encoded = String::new();
+        let mut terminated = false;
+        for c in chars.by_ref() {
+            if c == '-' {
+                terminated = true;
+                break;
+            }
+            encoded.push(c);
+        }
+
+        if encoded.is_empty() {
+            result.push('&');
+            continue;
+        }
+
+        match decode_utf7_segment(&encoded) {
+            Some(decoded) if terminated => result.push_str(&decoded),
+            _ => {
+                // Not valid modified UTF-7, keep the raw text
+                result.push('&');
+                result.push_str(&encoded);
+                if terminated {
+                    result.push('-');
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Encode a mailbox name segment as modified UTF-7
+pub fn encode_modified_utf7(name: &str) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+,";
+
+    let mut result = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+
+    let flush = |pending: &mut Vec<u16>, result: &mut String| {
+        if pending.is_empty() {
+            return;
+        }
+        let bytes: Vec<u8> = pending.iter().flat_map(|unit| unit.to_be_bytes()).collect();
+        result.push('&');
+        for chunk in bytes.chunks(3) {
+            let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+            let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+            let count = chunk.len() + 1;
+            for i in 0..count {
+                result.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            }
+        }
+        result.push('-');
+        pending.clear();
+    };
+
+    for c in name.chars() {
+        if c == '&' {
+            flush(&mut pending, &mut result);
+            result.push_str("&-");
+        } else if (' '..='~').contains(&c) {
+            flush(&mut pending, &mut result);
+            result.push(c);
+        } else {
+            let mut buffer = [0u16; 2];
+            pending.extend_from_slice(c.encode_utf16(&mut buffer));
+        }
+    }
+    flush(&mut pending, &mut result);
+
+    result
+}
+
+fn decode_utf7_segment(encoded: &str) -> Option<String> {
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    let mut bytes = Vec::new();
+
+    for c in encoded.chars() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            ',' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+
+    String::from_utf16(&units).ok()
+}
+
+/// Determine the mailbox type from flags, falling back to well-known names
+pub fn determine_mailbox_type(
+    name: &str,
+    delimiter: Option<char>,
+    flags: &[MailboxFlags],
+    is_gmail: bool,
+) -> MailboxType {
+    if name.eq_ignore_ascii_case("INBOX") {
+        return MailboxType::Inbox;
+    }
+
+    if let Some(mailbox_type) = MailboxType::from_special_use(flags) {
+        return mailbox_type;
+    }
+
+    if let Some(mailbox_type) = mailbox_type_from_name(&display_name(name, delimiter)) {
+        return mailbox_type;
+    }
+
+    if is_gmail && !flags.contains(&MailboxFlags::NoSelect) {
+        MailboxType::Label
+    } else {
+        MailboxType::Custom
+    }
+}
+
+/// Name-based detection for servers without SPECIAL-USE
+fn mailbox_type_from_name(leaf_name: &str) -> Option<MailboxType> {
+    let name = leaf_name.to_lowercase();
+    let mailbox_type = match name.as_str() {
+        "sent" | "sent items" | "sent mail" | "sent messages" | "gesendet"
+        | "gesendete objekte" | "gesendete elemente" | "envoyés" | "éléments envoyés"
+        | "enviados" | "inviati" | "posta inviata" | "verzonden" | "skickat" => MailboxType::Sent,
+        "drafts" | "draft" | "entwürfe" | "brouillons" | "borradores" | "bozze"
+        | "concepten" | "utkast" => MailboxType::Drafts,
+        "trash" | "deleted items" | "deleted messages" | "bin" | "papierkorb"
+        | "gelöschte elemente" | "corbeille" | "éléments supprimés" | "papelera"
+        | "cestino" | "prullenbak" | "papperskorgen" => MailboxType::Trash,
+        "spam" | "junk" | "junk e-mail" | "junk email" | "bulk mail" | "spamverdacht"
+        | "indésirables" | "correo no deseado" | "posta indesiderata" => MailboxType::Spam,
+        "archive" | "archives" | "archiv" | "archivio" | "archivo" | "arkiv" => MailboxType::Archive,
+        _ => return None,
+    };
+    Some(mailbox_type)
+}
+
+/// Build mailboxes with parent links from LIST entries
+///
+/// Parents that the server did not list are created as `\NonExistent`
+/// placeholders so the hierarchy stays connected.
+pub fn build_mailboxes(
+    account_id: Uuid,
+    entries: &[ListEntry],
+    namespaces: Option<&Namespaces>,
+    is_gmail: bool,
+) -> Vec<Mailbox> {
+    let prefix = namespaces
+        .and_then(|ns| ns.personal_prefix())
+        .map(|ns| ns.prefix.clone())
+        .unwrap_or_default();
+
+    // Parents must exist before their children
+    let mut sorted: Vec<&ListEntry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| {
+        entry
+            .delimiter
+            .map(|d| entry.name.matches(d).count())
+            .unwrap_or(0)
+    });
+
+    let mut mailboxes: Vec<Mailbox> = Vec::new();
+    let mut ids: HashMap<String, Uuid> = HashMap::new();
+
+    for entry in sorted {
+        if ids.contains_key(&entry.name) {
+            continue;
+        }
+
+        let parent_id = parent_name(&entry.name, entry.delimiter, &prefix).map(|parent| {
+            ensure_mailbox(account_id, &parent, entry.delimiter, &prefix, &mut mailboxes, &mut ids)
+        });
+
+        let flags = entry.flags();
+        let mailbox_type = determine_mailbox_type(&entry.name, entry.delimiter, &flags, is_gmail);
+        let display_name = if mailbox_type == MailboxType::Inbox {
+            "Inbox".to_string()
+        } else {
+            display_name(&entry.name, entry.delimiter)
+        };
+
+        let mut mailbox = Mailbox::new(
+            account_id,
+            entry.name.clone(),
+            Some(display_name),
+            mailbox_type,
+            parent_id,
+        );
+        mailbox.attributes.flags = flags;
+        mailbox.attributes.delimiter = entry.delimiter;
+
+        ids.insert(entry.name.clone(), mailbox.id);
+        mailboxes.push(mailbox);
+    }
+
+    mailboxes
+}
+
+fn ensure_mailbox(
+    account_id: Uuid,
+    name: &str,
+    delimiter: Option<char>,
+    prefix: &str,
+    mailboxes: &mut Vec<Mailbox>,
+    ids: &mut HashMap<String, Uuid>,
+) -> Uuid {
+    if let Some(id) = ids.get(name) {
+        return *id;
+    }
+
+    let parent_id = parent_name(name, delimiter, prefix)
+        .map(|parent| ensure_mailbox(account_id, &parent, delimiter, prefix, mailboxes, ids));
+
+    let mut placeholder = Mailbox::new(
+        account_id,
+        name.to_string(),
+        Some(display_name(name, delimiter)),
+        MailboxType::Custom,
+        parent_id,
+    );
+    placeholder.attributes.flags = vec![MailboxFlags::NoSelect, MailboxFlags::NonExistent];
+    placeholder.attributes.delimiter = delimiter;
+
+    let id = placeholder.id;
+    ids.insert(name.to_string(), id);
+    mailboxes.push(placeholder);
+    id
+}
+
+/// Raw name of the parent mailbox, ignoring the personal namespace prefix
+fn parent_name(name: &str, delimiter: Option<char>, prefix: &str) -> Option<String> {
+    let delimiter = delimiter?;
+    let relative = if !prefix.is_empty() && name.len() > prefix.len() && name.starts_with(prefix) {
+        &name[prefix.len()..]
+    } else {
+        name
+    };
+
+    let index = relative.rfind(delimiter)?;
+    if index == 0 {
+        return None;
+    }
+
+    let offset = name.len() - relative.len();
+    Some(name[..offset + index].to_string())
+}
+
+/// Decoded last segment of a mailbox name
+fn display_name(name: &str, delimiter: Option<char>) -> String {
+    let leaf = match delimiter {
+        Some(d) => name.rsplit(d).find(|segment| !segment.is_empty()).unwrap_or(name),
+        None => name,
+    };
+    decode_modified_utf7(leaf)
+}
+
+/// Parse raw response text into responses, as read by the session
+#[cfg(test)]
+pub(crate) fn parse_responses(text: &str) -> Vec<Response<'_>> {
+    let mut input = text.as_bytes();
+    let mut responses = Vec::new();
+    while !input.is_empty() {
+        let (rest, response) = async_imap::imap_proto::parser::parse_response(input).unwrap();
+        responses.push(response);
+        input = rest;
+    }
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_list_response(response: &str) -> Vec<ListEntry> {
+        parse_responses(response).iter().filter_map(list_entry).collect()
+    }
+
+    #[test]
+    fn test_parse_list_response() {
+        let response = "* LIST (\\HasNoChildren) \"/\" \"INBOX\"\r\n\
+                        * LIST (\\HasNoChildren \\Sent) \"/\" \"Gesendet\"\r\n\
+                        * LIST (\\HasChildren) \"/\" {12}\r\nProjects/Foo\r\n\
+                        * LIST (\\Noselect) NIL Flat\r\n\
+                        A1 OK LIST completed\r\n";
+
+        let entries = parse_list_response(response);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].name, "INBOX");
+        assert_eq!(entries[1].flags(), vec![MailboxFlags::HasNoChildren, MailboxFlags::Sent]);
+        assert_eq!(entries[2].name, "Projects/Foo");
+        assert_eq!(entries[3].delimiter, None);
+        assert_eq!(entries[3].name, "Flat");
+    }
+
+    #[test]
+    fn test_parse_namespace() {
+        let namespaces = parse_namespace(
+            "* NAMESPACE ((\"\" \"/\")) ((\"~\" \"/\")) ((\"#shared/\" \"/\")(\"#public/\" \"/\" \"X-PARAM\" (\"FLAG1\" \"FLAG2\")))\r\n",
+        ).unwrap();
+        assert_eq!(namespaces.personal, vec![Namespace { prefix: String::new(), delimiter: Some('/') }]);
+        assert_eq!(namespaces.other_users[0].prefix, "~");
+        assert_eq!(namespaces.shared.len(), 2);
+        assert_eq!(namespaces.shared[1].prefix, "#public/");
+
+        let namespaces = parse_namespace("* NAMESPACE ((\"INBOX.\" \".\")) NIL NIL\r\n").unwrap();
+        assert_eq!(namespaces.personal_prefix().unwrap().prefix, "INBOX.");
+        assert_eq!(namespaces.personal_prefix().unwrap().delimiter, Some('.'));
+        assert!(namespaces.other_users.is_empty() && namespaces.shared.is_empty());
+
+        let namespaces = parse_namespace("* NAMESPACE ((\"\" NIL)) NIL NIL").unwrap();
+        assert_eq!(namespaces.personal_prefix().unwrap().delimiter, None);
+
+        assert!(parse_namespace("* LIST (\\Noselect) \"/\" \"\"\r\n").is_none());
+        assert!(parse_namespace("* NAMESPACE ((\"\" \"/\")) NIL\r\n").is_none());
+        assert!(parse_namespace("* NAMESPACE (({5}\r\n").is_none());
+    }
+
+    #[test]
+    fn test_infer_namespaces() {
+        let entries = parse_list_response(
+            "* LIST (\\HasChildren) \".\" \"INBOX\"\r\n\
+             * LIST (\\HasNoChildren) \".\" \"INBOX.Sent\"\r\n",
+        );
+        let namespaces = infer_namespaces(&entries).unwrap();
+        assert_eq!(namespaces.personal_prefix().unwrap().prefix, "INBOX.");
+        assert_eq!(namespaces.personal_prefix().unwrap().delimiter, Some('.'));
+
+        let entries = parse_list_response(
+            "* LIST (\\HasNoChildren) \"/\" \"INBOX\"\r\n\
+             * LIST (\\HasNoChildren \\Sent) \"/\" \"Sent\"\r\n",
+        );
+        assert_eq!(infer_namespaces(&entries).unwrap().personal_prefix().unwrap().prefix, "");
+        assert!(infer_namespaces(&parse_list_response("* LIST (\\Noselect) NIL Flat\r\n")).is_none());
+    }
+
+    #[test]
+    fn test_decode_modified_utf7() {
+        assert_eq!(decode_modified_utf7("Envoy&AOk-s"), "Envoyés");
+        assert_eq!(decode_modified_utf7("Entw&APw-rfe"), "Entwürfe");
+        assert_eq!(decode_modified_utf7("Tom &- Jerry"), "Tom & Jerry");
+        assert_eq!(decode_modified_utf7("plain"), "plain");
+
+        assert_eq!(encode_modified_utf7("Envoyés"), "Envoy&AOk-s");
+        assert_eq!(encode_modified_utf7("Tom & Jerry"), "Tom &- Jerry");
+        assert_eq!(decode_modified_utf7(&encode_modified_utf7("日本語 🦀")), "日本語 🦀");
+    }
+
+    #[test]
+    fn test_special_use_over_name() {
+        let account_id = Uuid::new_v4();
+        let entries = parse_list_response(
+            "* LIST (\\HasNoChildren \\Junk) \"/\" \"Courrier ind&AOk-sirable\"\r\n\
+             * LIST (\\HasNoChildren) \"/\" \"Envoy&AOk-s\"\r\n",
+        );
+
+        let mailboxes = build_mailboxes(account_id, &entries, None, false);
+        let junk = mailboxes.iter().find(|m| m.name == "Courrier ind&AOk-sirable").unwrap();
+        assert_eq!(junk.mailbox_type, MailboxType::Spam);
+        assert_eq!(junk.display_name, "Courrier indésirable");
+
+        let sent = mailboxes.iter().find(|m| m.name == "Envoy&AOk-s").unwrap();
+        assert_eq!(sent.mailbox_type, MailboxType::Sent);
+    }
+
+    #[test]
+    fn test_gmail_hierarchy() {
+        let account_id = Uuid::new_v4();
+        let entries = parse_list_response(
+            "* LIST (\\HasNoChildren) \"/\" \"INBOX\"\r\n\
+             * LIST (\\HasChildren \\Noselect) \"/\" \"[Gmail]\"\r\n\
+             * LIST (\\All \\HasNoChildren) \"/\" \"[Gmail]/All Mail\"\r\n\
+             * LIST (\\HasNoChildren \\Sent) \"/\" \"[Gmail]/Sent Mail\"\r\n\
+             * LIST (\\HasNoChildren) \"/\" \"Work/Clients\"\r\n",
+        );
+
+        let mailboxes = build_mailboxes(account_id, &entries, None, true);
+        let gmail = mailboxes.iter().find(|m| m.name == "[Gmail]").unwrap();
+        assert!(!gmail.can_select());
+
+        let all_mail = mailboxes.iter().find(|m| m.name == "[Gmail]/All Mail").unwrap();
+        assert_eq!(all_mail.mailbox_type, MailboxType::All);
+        assert_eq!(all_mail.parent_id, Some(gmail.id));
+        assert_eq!(all_mail.display_name, "All Mail");
+
+        // "Work" was never listed, so a placeholder parent is created
+        let work = mailboxes.iter().find(|m| m.name == "Work").unwrap();
+        assert!(work.has_flag(MailboxFlags::NonExistent));
+        let clients = mailboxes.iter().find(|m| m.name == "Work/Clients").unwrap();
+        assert_eq!(clients.parent_id, Some(work.id));
+        assert_eq!(clients.mailbox_type, MailboxType::Label);
+
+        let mut hierarchy = crate::mailbox::MailboxHierarchy::new();
+        for mailbox in mailboxes {
+            hierarchy.add_mailbox(mailbox);
+        }
+        assert_eq!(hierarchy.build_tree(account_id).len(), 3);
+    }
+
+    #[test]
+    fn test_namespace_prefix_is_not_a_parent() {
+        let account_id = Uuid::new_v4();
+        let entries = parse_list_response(
+            "* LIST (\\HasChildren) \".\" \"INBOX\"\r\n\
+             * LIST (\\HasNoChildren) \".\" \"INBOX.Sent\"\r\n\
+             * LIST (\\HasNoChildren) \".\" \"INBOX.Lists.Rust\"\r\n",
+        );
+        let namespaces = infer_namespaces(&entries);
+
+        let mailboxes = build_mailboxes(account_id, &entries, namespaces.as_ref(), false);
+        let sent = mailboxes.iter().find(|m| m.name == "INBOX.Sent").unwrap();
+        assert_eq!(sent.parent_id, None);
+        assert_eq!(sent.mailbox_type, MailboxType::Sent);
+
+        let lists = mailboxes.iter().find(|m| m.name == "INBOX.Lists").unwrap();
+        let rust = mailboxes.iter().find(|m| m.name == "INBOX.Lists.Rust").unwrap();
+        assert_eq!(rust.parent_id, Some(lists.id));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("Sent Mail"), "\"Sent Mail\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[test]
+    fn test_list_command() {
+        let capabilities = vec!["IMAP4rev1".to_string(), "LIST-EXTENDED".to_string(), "SPECIAL-USE".to_string()];
+        assert_eq!(list_command(&capabilities), "LIST \"\" \"*\" RETURN (SPECIAL-USE CHILDREN SUBSCRIBED)");
+        assert_eq!(list_command(&["IMAP4rev1".to_string()]), "LIST \"\" \"*\"");
+    }
+}
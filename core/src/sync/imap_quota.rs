@@ -0,0 +1,59 @@
+//! IMAP QUOTA (RFC 9208) conversion
+
+use crate::storage::usage::{Quota, QuotaResource};
+use async_imap::types::{Quota as ImapQuota, QuotaResourceName};
+
+/// Convert a QUOTA response as read by async-imap
+pub fn quota_from_imap(quota: &ImapQuota) -> Quota {
+    let resources = quota.resources
+        .iter()
+        .map(|resource| QuotaResource {
+            name: match &resource.name {
+                QuotaResourceName::Storage => "STORAGE".to_string(),
+                QuotaResourceName::Message => "MESSAGE".to_string(),
+                QuotaResourceName::Atom(name) => name.to_ascii_uppercase(),
+            },
+            usage: resource.usage,
+            limit: resource.limit,
+        })
+        .collect();
+
+    Quota { root: quota.root_name.clone(), resources }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::imap_list::parse_responses;
+    use async_imap::imap_proto::Response;
+
+    fn parse_quotas(response: &str) -> Vec<Quota> {
+        parse_responses(response)
+            .into_iter()
+            .filter_map(|response| match response {
+                Response::Quota(quota) => Some(quota_from_imap(&ImapQuota::from(quota))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quota_from_imap() {
+        let quotas = parse_quotas(
+            "* QUOTAROOT INBOX \"\"\r\n\
+             * QUOTA \"\" (STORAGE 10 512 MESSAGE 3 1000)\r\n\
+             A1 OK Getquotaroot completed\r\n",
+        );
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].root, "");
+        assert_eq!(quotas[0].storage_bytes(), Some((10 * 1024, 512 * 1024)));
+        assert_eq!(quotas[0].resource("message").unwrap().limit, 1000);
+    }
+
+    #[test]
+    fn test_quota_without_storage() {
+        let quotas = parse_quotas("* QUOTA \"User quota\" (MESSAGE 3 1000)\r\n");
+        assert_eq!(quotas[0].root, "User quota");
+        assert_eq!(quotas[0].storage_bytes(), None);
+    }
+}
@@ -1,14 +1,21 @@
 //! Sync engines for Asgard Mail
 
-// pub mod imap_sync;  // Temporarily disabled due to async trait conflicts
+pub mod imap_sync;
+pub mod gmail_api_sync;
+pub mod imap_body;
+pub mod imap_list;
+pub mod imap_quota;
 pub mod smtp_send;
 pub mod pop3_sync;
 pub mod sync_manager;
+pub mod token_service;
 
-// pub use imap_sync::ImapSync;  // Temporarily disabled
+pub use imap_sync::ImapSync;
+pub use gmail_api_sync::GmailApiSync;
 pub use smtp_send::SmtpSend;
 pub use pop3_sync::Pop3Sync;
 pub use sync_manager::{SyncManager, SyncEngine};
+pub use token_service::{TokenRefresher, TokenService};
 
 /// Sync status
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
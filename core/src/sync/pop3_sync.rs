@@ -80,6 +80,10 @@ impl SyncEngine for Pop3Sync {
         Pop3Sync::disconnect(self).await
     }
     
+    fn update_credentials(&mut self, account: &Account) {
+        self.account = account.clone();
+    }
+    
     async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
         // TODO: Implement actual mailbox sync
         Ok(vec![])
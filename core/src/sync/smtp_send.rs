@@ -2,11 +2,16 @@
 
 use crate::error::{AsgardError, AsgardResult};
 use crate::account::Account;
+use crate::ical::Calendar;
 use crate::message::Message;
+use crate::autocrypt::{self, AutocryptAccount, AutocryptHeader, PeerState};
 use crate::gmail::XOAUTH2;
+use crate::openpgp::{ComposeOptions, PgpKeyring};
+use crate::smime::SmimeContext;
+use crate::sync::TokenService;
 use lettre::{
     message::{header, Mailbox as LettreMailbox, MultiPart, SinglePart},
-    transport::smtp::authentication::Credentials,
+    transport::smtp::authentication::{Credentials, Mechanism},
     AsyncSmtpTransport, AsyncTransport, Message as LettreMessage, Tokio1Executor,
 };
 use std::sync::Arc;
@@ -17,6 +22,14 @@ pub struct SmtpSend {
     account: Account,
     /// SMTP transport
     transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    /// OpenPGP keyring for signed and encrypted messages
+    keyring: Option<Arc<PgpKeyring>>,
+    /// S/MIME identity and certificates for signed and encrypted messages
+    smime: Option<Arc<SmimeContext>>,
+    /// Autocrypt key advertised on every message
+    autocrypt: Option<Arc<AutocryptAccount>>,
+    /// Autocrypt state of the recipients of the next messages
+    autocrypt_peers: Vec<PeerState>,
 }
 
 impl SmtpSend {
@@ -25,9 +38,33 @@ impl SmtpSend {
         Self {
             account,
             transport: None,
+            keyring: None,
+            smime: None,
+            autocrypt: None,
+            autocrypt_peers: Vec::new(),
         }
     }
 
+    /// Use `keyring` to sign and encrypt messages
+    pub fn set_keyring(&mut self, keyring: Arc<PgpKeyring>) {
+        self.keyring = Some(keyring);
+    }
+
+    /// Use `smime` to sign and encrypt messages
+    pub fn set_smime(&mut self, smime: Arc<SmimeContext>) {
+        self.smime = Some(smime);
+    }
+
+    /// Advertise `account` in an `Autocrypt:` header and use its key
+    pub fn set_autocrypt(&mut self, account: Arc<AutocryptAccount>) {
+        self.autocrypt = Some(account);
+    }
+
+    /// Encrypt to the Autocrypt keys of `peers` and gossip them to each other
+    pub fn set_autocrypt_peers(&mut self, peers: Vec<PeerState>) {
+        self.autocrypt_peers = peers;
+    }
+
     /// Connect to SMTP server
     pub async fn connect(&mut self) -> AsgardResult<()> {
         let smtp_config = self.account.smtp_config()
@@ -41,6 +78,14 @@ impl SmtpSend {
             builder = builder.tls(Tls::Required(tls_params));
         }
 
+        if let crate::account::AuthMethod::OAuth2 = smtp_config.auth_method {
+            let xoauth2 = self.xoauth2()?;
+            // lettre builds the XOAUTH2 initial response from these credentials
+            builder = builder
+                .credentials(Credentials::new(xoauth2.email().to_string(), xoauth2.access_token().to_string()))
+                .authentication(vec![Mechanism::Xoauth2]);
+        }
+
         let transport = builder.build();
 
         self.transport = Some(transport);
@@ -49,61 +94,37 @@ impl SmtpSend {
 
     /// Send a message
     pub async fn send_message(&self, message: &Message) -> AsgardResult<()> {
+        self.send_protected(message, ComposeOptions::default()).await
+    }
+
+    /// Send a message, signing and/or encrypting it with OpenPGP or S/MIME
+    pub async fn send_protected(&self, message: &Message, options: ComposeOptions) -> AsgardResult<()> {
         let transport = self.transport.as_ref()
             .ok_or_else(|| AsgardError::invalid_state("Not connected to SMTP server"))?;
 
         let smtp_config = self.account.smtp_config()
             .ok_or_else(|| AsgardError::account("SMTP configuration not found"))?;
 
-        // Build email message
-        let mut email_builder = LettreMessage::builder()
-            .from(self.parse_email_address(&message.headers.from[0])?)
-            .subject(&message.headers.subject);
-
-        // Add recipients
-        for to_addr in &message.headers.to {
-            email_builder = email_builder.to(self.parse_email_address(to_addr)?);
-        }
-
-        for cc_addr in &message.headers.cc {
-            email_builder = email_builder.cc(self.parse_email_address(cc_addr)?);
-        }
-
-        for bcc_addr in &message.headers.bcc {
-            email_builder = email_builder.bcc(self.parse_email_address(bcc_addr)?);
-        }
-
-        // Add message body - simplified for now
-        let body = if let Some(text_content) = message.text_content() {
-            String::from_utf8_lossy(text_content).to_string()
-        } else if let Some(html_content) = message.html_content() {
-            String::from_utf8_lossy(html_content).to_string()
-        } else {
-            "".to_string()
+        let protection = Protection {
+            keyring: self.keyring.as_deref(),
+            smime: self.smime.as_deref(),
+            autocrypt: self.autocrypt.as_deref(),
+            peers: &self.autocrypt_peers,
+            options,
         };
-
-        let email = email_builder.body(body)?;
+        let email = build_protected_email(message, &protection)?;
 
         // Send email
         match smtp_config.auth_method {
             crate::account::AuthMethod::OAuth2 => {
-                if let Some(oauth_config) = self.account.gmail_oauth_config() {
-                    if let Some(access_token) = &oauth_config.access_token {
-                        let xoauth2 = XOAUTH2::new(
-                            self.account.email().to_string(),
-                            access_token.clone(),
-                        );
-                        
-                        let auth_string = xoauth2.generate_smtp_auth_string()?;
-                        // Note: XOAUTH2 for SMTP would require additional implementation
-                        // This is a simplified version
-                        transport.send(email).await?;
+                transport.send(email).await.map_err(|e| {
+                    // 535: the server rejected the XOAUTH2 token
+                    if e.status().is_some_and(|code| code.to_string() == "535") {
+                        AsgardError::auth(format!("XOAUTH2 token rejected: {}", e))
                     } else {
-                        return Err(AsgardError::auth("No access token available"));
+                        AsgardError::from(e)
                     }
-                } else {
-                    return Err(AsgardError::auth("OAuth configuration not found"));
-                }
+                })?;
             }
             crate::account::AuthMethod::Password => {
                 // For password auth, we'd need to get the password from keyring
@@ -118,15 +139,247 @@ impl SmtpSend {
         Ok(())
     }
 
-    // Helper methods
+    /// Send a message, refreshing the OAuth token when it is about to expire
+    /// and once more if the server rejects it
+    pub async fn send_message_with_refresh(&mut self, message: &Message, options: ComposeOptions, token_service: &TokenService) -> AsgardResult<()> {
+        if let Some(account) = token_service.ensure_fresh(self.account.id).await? {
+            self.update_credentials(&account);
+        }
+        if self.transport.is_none() {
+            self.connect().await?;
+        }
+
+        match self.send_protected(message, options).await {
+            Err(e) if e.is_auth_error() => {
+                let Some(account) = token_service.force_refresh(self.account.id).await? else {
+                    return Err(e);
+                };
+                self.update_credentials(&account);
+                self.connect().await?;
+                self.send_protected(message, options).await
+            }
+            result => result,
+        }
+    }
+
+    /// Use an updated account (e.g. a refreshed OAuth token)
+    ///
+    /// The transport carries the old credentials, so the next send reconnects.
+    pub fn update_credentials(&mut self, account: &Account) {
+        self.account = account.clone();
+        self.transport = None;
+    }
+
+    /// XOAUTH2 credentials of the account
+    fn xoauth2(&self) -> AsgardResult<XOAUTH2> {
+        let oauth_config = self.account.oauth_config()
+            .ok_or_else(|| AsgardError::auth("OAuth configuration not found"))?;
+        let access_token = oauth_config.access_token.clone()
+            .ok_or_else(|| AsgardError::auth("No access token available"))?;
+        Ok(XOAUTH2::new(self.account.email().to_string(), access_token))
+    }
+}
+
+/// Build the RFC 5322 message for a [`Message`]
+pub(crate) fn build_email(message: &Message) -> AsgardResult<LettreMessage> {
+    build_protected_email(message, &Protection::default())
+}
+
+/// Keys and toggles for signing and encrypting an outgoing message
+#[derive(Default)]
+pub(crate) struct Protection<'a> {
+    /// OpenPGP keys
+    pub keyring: Option<&'a PgpKeyring>,
+    /// S/MIME identity and certificates
+    pub smime: Option<&'a SmimeContext>,
+    /// Our Autocrypt key
+    pub autocrypt: Option<&'a AutocryptAccount>,
+    /// Autocrypt state of the recipients
+    pub peers: &'a [PeerState],
+    /// Sign and encrypt toggles
+    pub options: ComposeOptions,
+}
+
+/// Build the RFC 5322 message for a [`Message`], signed and/or encrypted
+/// with PGP/MIME or S/MIME according to the compose options
+///
+/// Encrypted messages are also encrypted to the sender so the copy in Sent
+/// stays readable. With an Autocrypt key for the sender the message carries
+/// an `Autocrypt:` header, and encrypted messages to several recipients
+/// gossip their keys to each other.
+pub(crate) fn build_protected_email(message: &Message, protection: &Protection<'_>) -> AsgardResult<LettreMessage> {
+    let from = message.headers.from.first()
+        .ok_or_else(|| AsgardError::validation("Message has no sender"))?;
+    let mut email_builder = LettreMessage::builder()
+        .from(parse_email_address(from)?)
+        .subject(&message.headers.subject);
+
+    // Add recipients
+    for to_addr in &message.headers.to {
+        email_builder = email_builder.to(parse_email_address(to_addr)?);
+    }
+
+    for cc_addr in &message.headers.cc {
+        email_builder = email_builder.cc(parse_email_address(cc_addr)?);
+    }
+
+    for bcc_addr in &message.headers.bcc {
+        email_builder = email_builder.bcc(parse_email_address(bcc_addr)?);
+    }
+
+    if let Some(in_reply_to) = &message.headers.in_reply_to {
+        email_builder = email_builder.in_reply_to(in_reply_to.clone());
+    }
+    if let Some(references) = &message.headers.references {
+        email_builder = email_builder.references(references.clone());
+    }
+
+    let autocrypt = protection.autocrypt
+        .filter(|account| account.email().eq_ignore_ascii_case(&from.email));
+    if let Some(account) = autocrypt {
+        email_builder = email_builder.header(account.header()?);
+    }
+
+    // Add message body - simplified for now
+    let body = if let Some(text_content) = message.text_content() {
+        String::from_utf8_lossy(text_content).to_string()
+    } else if let Some(html_content) = message.html_content() {
+        String::from_utf8_lossy(html_content).to_string()
+    } else {
+        "".to_string()
+    };
+
+    // Meeting invitations and replies carry the calendar next to the text
+    let calendar = calendar_part(message)?;
+    let options = protection.options;
+    if !options.is_enabled() {
+        if let Some(calendar) = calendar {
+            let alternative = MultiPart::alternative()
+                .singlepart(SinglePart::plain(body))
+                .singlepart(calendar);
+            return Ok(email_builder.multipart(alternative)?);
+        }
+        return Ok(email_builder.body(body)?);
+    }
+
+    let content = match calendar {
+        Some(calendar) => Content::Alternative(MultiPart::alternative()
+            .singlepart(SinglePart::plain(body))
+            .singlepart(calendar)),
+        None => Content::Plain(SinglePart::plain(body)),
+    };
+    let recipients: Vec<&str> = message.headers.to.iter()
+        .chain(&message.headers.cc)
+        .chain(&message.headers.bcc)
+        .chain(std::iter::once(from))
+        .map(|addr| addr.email.as_str())
+        .collect();
 
-    fn parse_email_address(&self, addr: &crate::message::EmailAddress) -> AsgardResult<LettreMailbox> {
-        if let Some(name) = &addr.name {
-            Ok(LettreMailbox::new(Some(name.clone()), addr.email.parse()?))
+    if options.smime {
+        let smime = protection.smime
+            .ok_or_else(|| AsgardError::invalid_state("No S/MIME identity configured"))?;
+        return if options.encrypt {
+            let envelope = match content {
+                Content::Plain(part) => smime.encrypt_part(&recipients, options.sign, part)?,
+                Content::Alternative(part) => smime.encrypt_multipart(&recipients, options.sign, part)?,
+            };
+            Ok(email_builder.singlepart(envelope)?)
         } else {
-            Ok(LettreMailbox::new(None, addr.email.parse()?))
+            let signed = match content {
+                Content::Plain(part) => smime.sign_part(part)?,
+                Content::Alternative(part) => smime.sign_multipart(part)?,
+            };
+            Ok(email_builder.multipart(signed)?)
+        };
+    }
+
+    let autocrypt_keyring = autocrypt
+        .map(|account| account.keyring(protection.keyring, protection.peers))
+        .transpose()?;
+    let keyring = autocrypt_keyring.as_ref()
+        .or(protection.keyring)
+        .ok_or_else(|| AsgardError::invalid_state("No OpenPGP keyring configured"))?;
+    let multipart = if options.encrypt {
+        // Bcc recipients stay hidden; the sender counts, so gossip needs three
+        let visible: Vec<&str> = message.headers.to.iter()
+            .chain(&message.headers.cc)
+            .chain(std::iter::once(from))
+            .map(|addr| addr.email.as_str())
+            .collect();
+        let gossip = match autocrypt {
+            Some(account) if visible.len() > 2 => gossip_for(account, protection.peers, &visible)?,
+            _ => Vec::new(),
+        };
+        let entity = autocrypt::with_gossip(&content.formatted(), &gossip);
+        keyring.encrypt_entity(&recipients, options.sign.then_some(from.email.as_str()), &entity)?
+    } else {
+        match content {
+            Content::Plain(part) => keyring.sign_part(&from.email, part)?,
+            Content::Alternative(part) => keyring.sign_multipart(&from.email, part)?,
+        }
+    };
+
+    Ok(email_builder.multipart(multipart)?)
+}
+
+/// Body of a message before it gets signed or encrypted
+enum Content {
+    /// Just the text
+    Plain(SinglePart),
+    /// Text with a calendar next to it
+    Alternative(MultiPart),
+}
+
+impl Content {
+    fn formatted(&self) -> Vec<u8> {
+        match self {
+            Content::Plain(part) => part.formatted(),
+            Content::Alternative(part) => part.formatted(),
+        }
+    }
+}
+
+/// `text/calendar` part of a message, labelled with its iTIP method
+fn calendar_part(message: &Message) -> AsgardResult<Option<SinglePart>> {
+    let Some(content) = message.calendar_content() else {
+        return Ok(None);
+    };
+    let text = String::from_utf8_lossy(content).into_owned();
+    let content_type = match Calendar::parse(&text)?.method {
+        Some(method) => format!("text/calendar; charset=utf-8; method={}", method.as_str()),
+        None => "text/calendar; charset=utf-8".to_string(),
+    };
+    let content_type = header::ContentType::parse(&content_type)
+        .map_err(|e| AsgardError::validation(format!("Invalid calendar content type: {}", e)))?;
+    Ok(Some(SinglePart::builder().header(content_type).body(text)))
+}
+
+/// Autocrypt keys of `recipients` to gossip inside an encrypted message
+fn gossip_for(account: &AutocryptAccount, peers: &[PeerState], recipients: &[&str]) -> AsgardResult<Vec<AutocryptHeader>> {
+    let mut gossip = Vec::new();
+    for recipient in recipients {
+        if recipient.eq_ignore_ascii_case(account.email()) {
+            gossip.push(account.header()?);
+        } else if let Some(keydata) = peers.iter()
+            .find(|peer| peer.email.eq_ignore_ascii_case(recipient))
+            .and_then(PeerState::key)
+        {
+            gossip.push(AutocryptHeader {
+                addr: recipient.to_ascii_lowercase(),
+                prefer_encrypt: Default::default(),
+                keydata: keydata.to_vec(),
+            });
         }
     }
+    Ok(gossip)
+}
+
+fn parse_email_address(addr: &crate::message::EmailAddress) -> AsgardResult<LettreMailbox> {
+    if let Some(name) = &addr.name {
+        Ok(LettreMailbox::new(Some(name.clone()), addr.email.parse()?))
+    } else {
+        Ok(LettreMailbox::new(None, addr.email.parse()?))
+    }
 }
 
 #[cfg(test)]
@@ -154,4 +407,87 @@ mod tests {
         let smtp_send = SmtpSend::new(account);
         assert!(smtp_send.transport.is_none());
     }
+
+    #[test]
+    fn test_autocrypt_header_only_for_own_address() {
+        let account = AutocryptAccount::generate("alice@example.com").unwrap();
+        let message = Message::from_rfc822(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            b"From: Alice <alice@example.com>\r\nTo: bob@example.com\r\nSubject: Hi\r\n\r\nHello",
+        ).unwrap();
+        let protection = Protection { autocrypt: Some(&account), ..Protection::default() };
+
+        let raw = String::from_utf8(build_protected_email(&message, &protection).unwrap().formatted()).unwrap();
+        let (headers, _) = mailparse::parse_headers(raw.as_bytes()).unwrap();
+        assert_eq!(autocrypt::incoming_header(&headers, "alice@example.com"), Some(account.header().unwrap()));
+
+        let other = AutocryptAccount::generate("carol@example.com").unwrap();
+        let protection = Protection { autocrypt: Some(&other), ..Protection::default() };
+        let raw = String::from_utf8(build_protected_email(&message, &protection).unwrap().formatted()).unwrap();
+        assert!(!raw.contains("Autocrypt:"));
+    }
+
+    fn calendar_reply() -> Message {
+        let mut message = Message::from_rfc822(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            b"From: bob@example.org\r\nTo: alice@example.com\r\nSubject: Accepted: Planning\r\n\r\nBob has accepted.",
+        ).unwrap();
+        message.headers.in_reply_to = Some("<invite@example.com>".to_string());
+        let calendar = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nMETHOD:REPLY\r\nBEGIN:VEVENT\r\nUID:1\r\n\
+            ATTENDEE;PARTSTAT=ACCEPTED:mailto:bob@example.org\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        message.add_part(crate::message::MessagePart {
+            id: "2".to_string(),
+            part_type: crate::message::MessagePartType::Calendar,
+            mime_type: "text/calendar".to_string(),
+            disposition: None,
+            filename: None,
+            size: calendar.len(),
+            encoding: None,
+            content_id: None,
+            content_location: None,
+            content: Some(calendar.as_bytes().to_vec()),
+            children: Vec::new(),
+        });
+        message
+    }
+
+    #[test]
+    fn test_calendar_reply_is_alternative() {
+        let message = calendar_reply();
+
+        let raw = build_email(&message).unwrap().formatted();
+        let parsed = mailparse::parse_mail(&raw).unwrap();
+        assert_eq!(parsed.ctype.mimetype, "multipart/alternative");
+        assert_eq!(parsed.subparts[0].ctype.mimetype, "text/plain");
+        assert_eq!(parsed.subparts[1].ctype.mimetype, "text/calendar");
+        assert_eq!(parsed.subparts[1].ctype.params.get("method").map(String::as_str), Some("REPLY"));
+        assert!(parsed.subparts[1].get_body().unwrap().contains("PARTSTAT=ACCEPTED"));
+
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.contains("In-Reply-To: <invite@example.com>"));
+    }
+
+    #[test]
+    fn test_signed_calendar_reply_keeps_calendar() {
+        let message = calendar_reply();
+        let account = AutocryptAccount::generate("bob@example.org").unwrap();
+        let protection = Protection {
+            autocrypt: Some(&account),
+            options: ComposeOptions { sign: true, ..ComposeOptions::default() },
+            ..Protection::default()
+        };
+
+        let raw = build_protected_email(&message, &protection).unwrap().formatted();
+        let parsed = mailparse::parse_mail(&raw).unwrap();
+        assert_eq!(parsed.ctype.mimetype, "multipart/signed");
+        let alternative = &parsed.subparts[0];
+        assert_eq!(alternative.ctype.mimetype, "multipart/alternative");
+        assert_eq!(alternative.subparts[1].ctype.mimetype, "text/calendar");
+        assert_eq!(alternative.subparts[1].ctype.params.get("method").map(String::as_str), Some("REPLY"));
+
+        let signed = Message::from_rfc822(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), &raw).unwrap();
+        assert_eq!(crate::openpgp::detect(&signed), crate::openpgp::PgpKind::MimeSigned);
+    }
 }
@@ -2,10 +2,15 @@
 
 use crate::error::{AsgardError, AsgardResult};
 use crate::account::Account;
-use crate::mailbox::Mailbox;
-use crate::message::Message;
-use crate::storage::StorageManager;
-use crate::search::SimpleSearchIndex;
+use crate::autocrypt::{self, AutocryptAccount};
+use crate::gmail;
+use crate::calendar;
+use crate::ical::{self, Calendar, CalendarChange, PartStat};
+use crate::mailbox::{Mailbox, MailboxHierarchy, MailboxType};
+use crate::message::{self, Attachment, Message};
+use crate::openpgp::ComposeOptions;
+use crate::storage::{Quota, StorageManager, StorageReport};
+use crate::search::{self, SearchQuery, SearchResult, SimpleSearchIndex};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
@@ -13,8 +18,7 @@ use tokio::time::{interval, Duration};
 use tracing::{info, warn, error};
 use uuid::Uuid;
 
-use super::{SmtpSend, Pop3Sync, SyncStatus, SyncResult, SyncStats};
-// use super::{ImapSync, SmtpSend, Pop3Sync, SyncStatus, SyncResult, SyncStats};  // ImapSync temporarily disabled
+use super::{GmailApiSync, ImapSync, SmtpSend, Pop3Sync, SyncStatus, SyncResult, SyncStats, TokenService};
 
 /// Sync manager for coordinating all sync operations
 pub struct SyncManager {
@@ -23,15 +27,26 @@ pub struct SyncManager {
     /// Search index
     search_index: Arc<Mutex<SimpleSearchIndex>>,
     /// Active sync engines
-    sync_engines: Arc<RwLock<HashMap<Uuid, Box<dyn SyncEngine + Send + Sync>>>>,
+    sync_engines: Arc<RwLock<HashMap<Uuid, SharedEngine>>>,
     /// Sync statistics
     stats: Arc<RwLock<SyncStats>>,
     /// Sync interval
     sync_interval: Duration,
+    /// OAuth token refresh
+    token_service: Arc<TokenService>,
     /// Background sync task handle
     background_task: Option<tokio::task::JoinHandle<()>>,
+    /// Background token refresh task handle
+    token_task: Option<tokio::task::JoinHandle<()>>,
 }
 
+/// A sync engine behind its own lock, so server work for one account
+/// never blocks the others
+type SharedEngine = Arc<Mutex<Box<dyn SyncEngine + Send + Sync>>>;
+
+/// How often the background task looks for tokens about to expire
+const TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Trait for sync engines
 #[async_trait::async_trait]
 pub trait SyncEngine {
@@ -49,51 +64,164 @@ pub trait SyncEngine {
     
     /// Disconnect from the server
     async fn disconnect(&mut self) -> AsgardResult<()>;
+
+    /// Use an updated account (e.g. a refreshed OAuth token) from now on
+    fn update_credentials(&mut self, _account: &Account) {}
     
     /// Sync mailboxes
     async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>>;
     
+    /// Take over the IDs of the synced mailboxes once they were matched to the stored ones
+    fn use_mailboxes(&mut self, _mailboxes: &[Mailbox]) {}
+    
     /// Sync messages in a mailbox
     async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>>;
+
+    /// Account settings to store after a sync, e.g. where the next one starts
+    fn sync_settings(&self) -> HashMap<String, serde_json::Value> {
+        HashMap::new()
+    }
+
+    /// Take the Gmail IDs of messages the server deleted since the last call
+    fn take_deleted_message_ids(&mut self) -> Vec<u64> {
+        Vec::new()
+    }
+
+    /// Create a mailbox on the server
+    async fn create_mailbox(&mut self, _parent: Option<&Mailbox>, _name: &str) -> AsgardResult<Mailbox> {
+        Err(AsgardError::unsupported("Mailbox creation is not supported by this account"))
+    }
+
+    /// Rename a mailbox on the server, returning its new full name
+    async fn rename_mailbox(&mut self, _mailbox: &Mailbox, _new_name: &str) -> AsgardResult<String> {
+        Err(AsgardError::unsupported("Mailbox renaming is not supported by this account"))
+    }
+
+    /// Delete a mailbox on the server
+    async fn delete_mailbox(&mut self, _mailbox: &Mailbox) -> AsgardResult<()> {
+        Err(AsgardError::unsupported("Mailbox deletion is not supported by this account"))
+    }
+
+    /// Subscribe to or unsubscribe from a mailbox
+    async fn set_subscribed(&mut self, _mailbox: &Mailbox, _subscribed: bool) -> AsgardResult<()> {
+        Err(AsgardError::unsupported("Mailbox subscriptions are not supported by this account"))
+    }
+
+    /// Fetch the storage quota of the account, if the server has one
+    async fn fetch_quota(&mut self) -> AsgardResult<Option<Quota>> {
+        Ok(None)
+    }
+
+    /// Get the size of a mailbox as reported by the server
+    async fn mailbox_size(&mut self, _mailbox: &Mailbox) -> AsgardResult<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Add and remove labels of a message on the server
+    async fn store_labels(&mut self, _mailbox: &Mailbox, _message: &Message, _add: &[String], _remove: &[String]) -> AsgardResult<()> {
+        Err(AsgardError::unsupported("Labels are not supported by this account"))
+    }
+
+    /// Search a mailbox on the server, returning the matching messages
+    async fn search_server(&mut self, _mailbox: &Mailbox, _query: &SearchQuery) -> AsgardResult<Vec<Message>> {
+        Err(AsgardError::unsupported("Server-side search is not supported by this account"))
+    }
+
+    /// Fetch the complete message as stored on the server
+    async fn fetch_raw_message(&mut self, _mailbox: &Mailbox, _message: &Message) -> AsgardResult<Vec<u8>> {
+        Err(AsgardError::unsupported("Fetching the original message is not supported by this account"))
+    }
+
+    /// Fetch the decoded content of parts of a message, by part ID
+    async fn fetch_parts(&mut self, _mailbox: &Mailbox, _message: &Message, _part_ids: &[String]) -> AsgardResult<Vec<(String, Vec<u8>)>> {
+        Err(AsgardError::unsupported("Partial message download is not supported by this account"))
+    }
+
+    /// Send a message through the server's own API instead of SMTP
+    async fn send_message(&mut self, _message: &Message) -> AsgardResult<()> {
+        Err(AsgardError::unsupported("Sending without SMTP is not supported by this account"))
+    }
 }
 
 /// Wrapper for IMAP sync engine
-// Temporarily disabled due to async trait conflicts
-// pub struct ImapSyncEngine {
-//     engine: ImapSync,
-// }
-
-// Temporarily disabled due to async trait conflicts
-// #[async_trait::async_trait]
-// impl SyncEngine for ImapSyncEngine {
-//     fn account_id(&self) -> Uuid {
-//         self.engine.account_id()
-//     }
-//     
-//     fn status(&self) -> SyncStatus {
-//         self.engine.status()
-//     }
-//     
-//     fn last_sync_result(&self) -> Option<&SyncResult> {
-//         self.engine.last_sync_result()
-//     }
-//     
-//     async fn connect(&mut self) -> AsgardResult<()> {
-//         self.engine.connect().await
-//     }
-//     
-//     async fn disconnect(&mut self) -> AsgardResult<()> {
-//         self.engine.disconnect().await
-//     }
-//     
-//     async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
-//         self.engine.sync_mailboxes().await
-//     }
-//     
-//     async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
-//         self.engine.sync_mailbox_messages(mailbox).await
-//     }
-// }
+pub struct ImapSyncEngine {
+    engine: ImapSync,
+}
+
+#[async_trait::async_trait]
+impl SyncEngine for ImapSyncEngine {
+    fn account_id(&self) -> Uuid {
+        self.engine.account_id()
+    }
+
+    fn status(&self) -> SyncStatus {
+        self.engine.status()
+    }
+
+    fn last_sync_result(&self) -> Option<&SyncResult> {
+        self.engine.last_sync_result()
+    }
+
+    async fn connect(&mut self) -> AsgardResult<()> {
+        self.engine.connect().await
+    }
+
+    async fn disconnect(&mut self) -> AsgardResult<()> {
+        self.engine.disconnect().await
+    }
+
+    fn update_credentials(&mut self, account: &Account) {
+        self.engine.update_credentials(account)
+    }
+
+    async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+        self.engine.sync_mailboxes().await
+    }
+
+    async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+        self.engine.sync_mailbox_messages(mailbox).await
+    }
+
+    async fn create_mailbox(&mut self, parent: Option<&Mailbox>, name: &str) -> AsgardResult<Mailbox> {
+        self.engine.create_mailbox(parent, name).await
+    }
+
+    async fn rename_mailbox(&mut self, mailbox: &Mailbox, new_name: &str) -> AsgardResult<String> {
+        self.engine.rename_mailbox(mailbox, new_name).await
+    }
+
+    async fn delete_mailbox(&mut self, mailbox: &Mailbox) -> AsgardResult<()> {
+        self.engine.delete_mailbox(mailbox).await
+    }
+
+    async fn set_subscribed(&mut self, mailbox: &Mailbox, subscribed: bool) -> AsgardResult<()> {
+        self.engine.set_subscribed(mailbox, subscribed).await
+    }
+
+    async fn fetch_quota(&mut self) -> AsgardResult<Option<Quota>> {
+        self.engine.fetch_quota().await
+    }
+
+    async fn mailbox_size(&mut self, mailbox: &Mailbox) -> AsgardResult<Option<u64>> {
+        self.engine.mailbox_size(mailbox).await
+    }
+
+    async fn store_labels(&mut self, mailbox: &Mailbox, message: &Message, add: &[String], remove: &[String]) -> AsgardResult<()> {
+        self.engine.store_labels(mailbox, message, add, remove).await
+    }
+
+    async fn search_server(&mut self, mailbox: &Mailbox, query: &SearchQuery) -> AsgardResult<Vec<Message>> {
+        self.engine.search_server(mailbox, query).await
+    }
+
+    async fn fetch_raw_message(&mut self, mailbox: &Mailbox, message: &Message) -> AsgardResult<Vec<u8>> {
+        self.engine.fetch_raw_message(mailbox, message).await
+    }
+
+    async fn fetch_parts(&mut self, mailbox: &Mailbox, message: &Message, part_ids: &[String]) -> AsgardResult<Vec<(String, Vec<u8>)>> {
+        self.engine.fetch_parts(mailbox, message, part_ids).await
+    }
+}
 
 impl SyncManager {
     /// Create a new sync manager
@@ -101,6 +229,17 @@ impl SyncManager {
         storage: Arc<Mutex<StorageManager>>,
         search_index: Arc<Mutex<SimpleSearchIndex>>,
         sync_interval: Duration,
+    ) -> Self {
+        let token_service = Arc::new(TokenService::new(storage.clone()));
+        Self::with_token_service(storage, search_index, sync_interval, token_service)
+    }
+
+    /// Create a sync manager with a custom token service
+    pub fn with_token_service(
+        storage: Arc<Mutex<StorageManager>>,
+        search_index: Arc<Mutex<SimpleSearchIndex>>,
+        sync_interval: Duration,
+        token_service: Arc<TokenService>,
     ) -> Self {
         Self {
             storage,
@@ -108,20 +247,30 @@ impl SyncManager {
             sync_engines: Arc::new(RwLock::new(HashMap::new())),
             stats: Arc::new(RwLock::new(SyncStats::default())),
             sync_interval,
+            token_service,
             background_task: None,
+            token_task: None,
         }
     }
 
+    /// Get the token service, e.g. for sending through [`SmtpSend`]
+    pub fn token_service(&self) -> Arc<TokenService> {
+        self.token_service.clone()
+    }
+
     /// Add an account for syncing
     pub async fn add_account(&self, account: Account) -> AsgardResult<()> {
         let account_id = account.id;
         
         // Create appropriate sync engine based on account type
         let sync_engine: Box<dyn SyncEngine + Send + Sync> = match account.account_type() {
-            crate::account::AccountType::Gmail | crate::account::AccountType::ImapSmtp => {
-                // ImapSyncEngine temporarily disabled due to async trait conflicts
-                // For now, use POP3 as fallback
-                Box::new(Pop3Sync::new(account.clone()))
+            crate::account::AccountType::Gmail if Self::uses_gmail_api(&account) => {
+                Box::new(GmailApiSync::new(account.clone()))
+            }
+            crate::account::AccountType::Gmail
+            | crate::account::AccountType::Microsoft365
+            | crate::account::AccountType::ImapSmtp => {
+                Box::new(ImapSyncEngine { engine: ImapSync::new(account.clone()) })
             }
             crate::account::AccountType::Pop3 => {
                 Box::new(Pop3Sync::new(account.clone()))
@@ -137,7 +286,7 @@ impl SyncManager {
         // Add sync engine
         {
             let mut engines = self.sync_engines.write().await;
-            engines.insert(account_id, sync_engine);
+            engines.insert(account_id, Arc::new(Mutex::new(sync_engine)));
         }
         
         info!("Added account for syncing: {}", account_id);
@@ -147,12 +296,11 @@ impl SyncManager {
     /// Remove an account from syncing
     pub async fn remove_account(&self, account_id: Uuid) -> AsgardResult<()> {
         // Disconnect and remove sync engine
-        {
-            let mut engines = self.sync_engines.write().await;
-            if let Some(mut engine) = engines.remove(&account_id) {
-                if let Err(e) = engine.disconnect().await {
-                    warn!("Failed to disconnect sync engine for account {}: {}", account_id, e);
-                }
+        let engine = self.sync_engines.write().await.remove(&account_id);
+        if let Some(engine) = engine {
+            let mut engine = engine.lock().await;
+            if let Err(e) = engine.disconnect().await {
+                warn!("Failed to disconnect sync engine for account {}: {}", account_id, e);
             }
         }
         
@@ -177,6 +325,7 @@ impl SyncManager {
         let search_index = self.search_index.clone();
         let stats = self.stats.clone();
         let sync_interval = self.sync_interval;
+        let token_service = self.token_service.clone();
         
         let task = tokio::spawn(async move {
             let mut interval = interval(sync_interval);
@@ -192,13 +341,12 @@ impl SyncManager {
                 
                 // Sync each account individually to avoid holding mutable references across await
                 for account_id in account_ids {
+                    let Some(engine) = sync_engines.read().await.get(&account_id).cloned() else {
+                        continue; // Account was removed
+                    };
                     let result = {
-                        let mut engines = sync_engines.write().await;
-                        if let Some(engine) = engines.get_mut(&account_id) {
-                            Self::sync_account_engine(&mut **engine, &storage, &search_index).await
-                        } else {
-                            continue; // Account was removed
-                        }
+                        let mut engine = engine.lock().await;
+                        Self::sync_account_engine(&mut **engine, &storage, &search_index, &token_service).await
                     };
                     
                     match result {
@@ -220,26 +368,511 @@ impl SyncManager {
         });
         
         self.background_task = Some(task);
+        self.token_task = Some(self.spawn_token_refresh());
         info!("Started background sync with interval: {:?}", sync_interval);
         Ok(())
     }
 
+    /// Refresh OAuth tokens ahead of expiry and hand them to the engines
+    fn spawn_token_refresh(&self) -> tokio::task::JoinHandle<()> {
+        let sync_engines = self.sync_engines.clone();
+        let token_service = self.token_service.clone();
+
+        tokio::spawn(async move {
+            let mut interval = interval(TOKEN_CHECK_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let refreshed = match token_service.refresh_due().await {
+                    Ok(refreshed) => refreshed,
+                    Err(e) => {
+                        warn!("Failed to check OAuth tokens: {}", e);
+                        continue;
+                    }
+                };
+
+                for account in refreshed {
+                    let Some(engine) = sync_engines.read().await.get(&account.id).cloned() else {
+                        continue;
+                    };
+                    engine.lock().await.update_credentials(&account);
+                }
+            }
+        })
+    }
+
     /// Stop background sync
     pub async fn stop_background_sync(&mut self) -> AsgardResult<()> {
         if let Some(task) = self.background_task.take() {
             task.abort();
             info!("Stopped background sync");
         }
+        if let Some(task) = self.token_task.take() {
+            task.abort();
+        }
         Ok(())
     }
 
     /// Sync a specific account
     pub async fn sync_account(&self, account_id: Uuid) -> AsgardResult<SyncResult> {
-        let mut engines = self.sync_engines.write().await;
-        let engine = engines.get_mut(&account_id)
-            .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", account_id)))?;
+        let engine = self.engine(account_id).await?;
+        let mut engine = engine.lock().await;
         
-        Self::sync_account_engine(&mut **engine, &self.storage, &self.search_index).await
+        Self::sync_account_engine(&mut **engine, &self.storage, &self.search_index, &self.token_service).await
+    }
+
+    /// Create a mailbox under `parent_id` (or at the top level)
+    pub async fn create_mailbox(&self, account_id: Uuid, parent_id: Option<Uuid>, name: &str) -> AsgardResult<Mailbox> {
+        let hierarchy = self.load_hierarchy(account_id).await?;
+        let parent = match parent_id {
+            Some(id) => Some(hierarchy.get_mailbox(id).cloned()
+                .ok_or_else(|| AsgardError::not_found(format!("Mailbox not found: {}", id)))?),
+            None => None,
+        };
+
+        let mailbox = {
+            let engine = self.engine(account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.create_mailbox(parent.as_ref(), name).await;
+            Self::disconnect_engine(&mut **engine, result).await?
+        };
+
+        let mut storage = self.storage.lock().await;
+        storage.database_mut().create_mailbox(&mailbox).await?;
+        if let Some(mut parent) = parent {
+            parent.remove_flag(crate::mailbox::MailboxFlags::HasNoChildren);
+            parent.add_flag(crate::mailbox::MailboxFlags::HasChildren);
+            storage.database_mut().update_mailbox(&parent).await?;
+        }
+
+        info!("Created mailbox {} for account {}", mailbox.name, account_id);
+        Ok(mailbox)
+    }
+
+    /// Rename a mailbox, keeping the names of its children in sync
+    pub async fn rename_mailbox(&self, mailbox_id: Uuid, new_name: &str) -> AsgardResult<()> {
+        let mailbox = self.load_mailbox(mailbox_id).await?;
+        if mailbox.is_system() {
+            return Err(AsgardError::validation("System mailboxes cannot be renamed"));
+        }
+        let mut hierarchy = self.load_hierarchy(mailbox.account_id).await?;
+
+        let full_name = {
+            let engine = self.engine(mailbox.account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.rename_mailbox(&mailbox, new_name).await;
+            Self::disconnect_engine(&mut **engine, result).await?
+        };
+
+        let changed = hierarchy.rename_mailbox(mailbox_id, full_name, new_name.to_string())?;
+        let storage = self.storage.lock().await;
+        storage.database().update_mailboxes(&changed).await?;
+
+        info!("Renamed mailbox {} ({} mailboxes updated)", mailbox_id, changed.len());
+        Ok(())
+    }
+
+    /// Delete a mailbox together with its children
+    pub async fn delete_mailbox(&self, mailbox_id: Uuid) -> AsgardResult<()> {
+        let mailbox = self.load_mailbox(mailbox_id).await?;
+        if mailbox.is_system() {
+            return Err(AsgardError::validation("System mailboxes cannot be deleted"));
+        }
+        let mut hierarchy = self.load_hierarchy(mailbox.account_id).await?;
+        let removed = hierarchy.remove_mailbox(mailbox_id);
+
+        // Children come first, so a failure leaves the server's tree intact
+        let mut deleted = Vec::new();
+        let result = {
+            let engine = self.engine(mailbox.account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let mut result = Ok(());
+            for mailbox in &removed {
+                result = engine.delete_mailbox(mailbox).await;
+                if result.is_err() {
+                    break;
+                }
+                deleted.push(mailbox.id);
+            }
+            Self::disconnect_engine(&mut **engine, result).await
+        };
+
+        // Drop locally only what the server deleted, even if it then failed
+        {
+            let storage = self.storage.lock().await;
+            let mut search_index = self.search_index.lock().await;
+            for message_id in storage.database().delete_mailboxes(&deleted).await? {
+                search_index.remove_message(message_id)?;
+            }
+        }
+        result?;
+
+        info!("Deleted mailbox {} ({} mailboxes removed)", mailbox_id, deleted.len());
+        Ok(())
+    }
+
+    /// Subscribe to or unsubscribe from a mailbox
+    pub async fn set_mailbox_subscribed(&self, mailbox_id: Uuid, subscribed: bool) -> AsgardResult<()> {
+        let mut mailbox = self.load_mailbox(mailbox_id).await?;
+
+        {
+            let engine = self.engine(mailbox.account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.set_subscribed(&mailbox, subscribed).await;
+            Self::disconnect_engine(&mut **engine, result).await?;
+        }
+
+        if subscribed {
+            mailbox.add_flag(crate::mailbox::MailboxFlags::Subscribed);
+        } else {
+            mailbox.remove_flag(crate::mailbox::MailboxFlags::Subscribed);
+        }
+
+        let storage = self.storage.lock().await;
+        storage.database().update_mailbox(&mailbox).await?;
+        Ok(())
+    }
+
+    /// Get the messages shown in a mailbox, newest first
+    ///
+    /// Gmail folders other than All Mail are labels, so their messages are
+    /// the ones carrying the folder's label wherever they are stored.
+    pub async fn mailbox_messages(&self, mailbox_id: Uuid, limit: Option<usize>, offset: Option<usize>) -> AsgardResult<Vec<Message>> {
+        let mailbox = self.load_mailbox(mailbox_id).await?;
+        let storage = self.storage.lock().await;
+        let database = storage.database();
+        let account = database.get_account(mailbox.account_id).await?
+            .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", mailbox.account_id)))?;
+
+        match gmail::imap_ext::mailbox_label(&mailbox).filter(|_| account.is_gmail()) {
+            Some(label) => database.get_messages_with_label(account.id, &label, limit, offset).await,
+            None => database.get_messages(mailbox.id, limit, offset).await,
+        }
+    }
+
+    /// Add and remove labels of a message, pushing the change to the server
+    pub async fn update_message_labels(&self, message_id: Uuid, add: &[String], remove: &[String]) -> AsgardResult<Message> {
+        let mut message = {
+            let storage = self.storage.lock().await;
+            storage.database().get_message(message_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Message not found: {}", message_id)))?
+        };
+        let mailbox = self.load_mailbox(message.mailbox_id).await?;
+
+        {
+            let engine = self.engine(message.account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.store_labels(&mailbox, &message, add, remove).await;
+            Self::disconnect_engine(&mut **engine, result).await?;
+        }
+
+        for label in add {
+            message.add_label(label.clone());
+        }
+        for label in remove {
+            message.remove_label(label);
+        }
+
+        let storage = self.storage.lock().await;
+        storage.database().set_message_labels(message.id, &message.labels).await?;
+        Ok(message)
+    }
+
+    /// Get the original RFC 822 source of a message
+    ///
+    /// Uses the stored raw content when sync kept it, then the cache, and
+    /// finally fetches the message from the server and caches the result.
+    pub async fn message_source(&self, message_id: Uuid) -> AsgardResult<Vec<u8>> {
+        let message = {
+            let storage = self.storage.lock().await;
+            let message = storage.database().get_message(message_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Message not found: {}", message_id)))?;
+            if let Some(raw) = message.raw_content.clone() {
+                return Ok(raw);
+            }
+            if let Some(raw) = storage.cache().retrieve_message(message_id).await? {
+                return Ok(raw);
+            }
+            message
+        };
+        let mailbox = self.load_mailbox(message.mailbox_id).await?;
+
+        let raw = {
+            let engine = self.engine(message.account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.fetch_raw_message(&mailbox, &message).await;
+            Self::disconnect_engine(&mut **engine, result).await?
+        };
+
+        let storage = self.storage.lock().await;
+        storage.cache().store_message(message_id, &raw, "message/rfc822").await?;
+        Ok(raw)
+    }
+
+    /// Get the content of an attachment, downloading it when needed
+    ///
+    /// Content kept with the message or in the cache, including cached
+    /// content of another attachment with the same hash, is reused only when
+    /// it matches the attachment's content hash. Otherwise the part is
+    /// downloaded and cached.
+    pub async fn attachment_content(&self, attachment: &Attachment) -> AsgardResult<Vec<u8>> {
+        if let Some(content) = attachment.content.as_ref().filter(|content| attachment.matches_hash(content)) {
+            return Ok(content.clone());
+        }
+
+        {
+            let storage = self.storage.lock().await;
+            let cache = storage.cache();
+            if let Some(content) = cache.retrieve_attachment(attachment.id).await? {
+                if attachment.matches_hash(&content) {
+                    return Ok(content);
+                }
+                warn!("Cached attachment {} does not match its hash, downloading it again", attachment.id);
+            }
+            if !attachment.content_hash.is_empty() {
+                if let Some(content) = cache.retrieve_by_hash(&attachment.content_hash).await? {
+                    if attachment.matches_hash(&content) {
+                        cache.store_attachment(attachment.id, &content, &attachment.mime_type).await?;
+                        return Ok(content);
+                    }
+                }
+            }
+        }
+
+        let content = self.download_part(attachment.message_id, &attachment.part_id).await?;
+        if !attachment.matches_hash(&content) {
+            return Err(AsgardError::validation(format!("Attachment {} does not match its content hash", attachment.filename)));
+        }
+
+        let storage = self.storage.lock().await;
+        storage.cache().store_attachment(attachment.id, &content, &attachment.mime_type).await?;
+        Ok(content)
+    }
+
+    /// Load a message, downloading text bodies that sync left on the server
+    ///
+    /// Downloaded bodies are stored with the message.
+    pub async fn load_message_body(&self, message_id: Uuid) -> AsgardResult<Message> {
+        let mut message = {
+            let storage = self.storage.lock().await;
+            storage.database().get_message(message_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Message not found: {}", message_id)))?
+        };
+        let missing = message.missing_body_parts();
+        if missing.is_empty() {
+            return Ok(message);
+        }
+
+        let fetched = self.fetch_parts(&message, &missing).await?;
+        for (part_id, content) in &fetched {
+            message.set_part_content(part_id, content.clone());
+        }
+
+        let storage = self.storage.lock().await;
+        storage.database().update_part_contents(message_id, &fetched).await?;
+        Ok(message)
+    }
+
+    /// Get the meeting invitation, reply or cancellation in a message
+    ///
+    /// Downloads the calendar part or `.ics` attachment when sync left it on
+    /// the server.
+    pub async fn message_calendar(&self, message_id: Uuid) -> AsgardResult<Option<Calendar>> {
+        let message = self.load_calendar_message(message_id).await?;
+        message.calendar_content()
+            .map(|content| Calendar::parse(&String::from_utf8_lossy(content)))
+            .transpose()
+    }
+
+    /// Answer the meeting invitation in a message
+    ///
+    /// The iTIP reply goes to the organizer like any other message, see
+    /// [`SyncManager::send_message`].
+    pub async fn respond_to_invitation(&self, message_id: Uuid, status: PartStat) -> AsgardResult<()> {
+        let invitation = self.load_calendar_message(message_id).await?;
+        let account = {
+            let storage = self.storage.lock().await;
+            storage.database().get_account(invitation.account_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", invitation.account_id)))?
+        };
+        let reply = ical::reply_message(&invitation, &account, status)?;
+
+        self.send_from(account, &reply).await?;
+        info!("Answered invitation in message {} with {}", message_id, status.as_str());
+        Ok(())
+    }
+
+    /// Send a message from its account
+    ///
+    /// Gmail accounts set to the REST API send through messages.send, all
+    /// others through their SMTP server.
+    pub async fn send_message(&self, message: &Message) -> AsgardResult<()> {
+        let account = {
+            let storage = self.storage.lock().await;
+            storage.database().get_account(message.account_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", message.account_id)))?
+        };
+        self.send_from(account, message).await
+    }
+
+    /// Add the meeting in a message to the account's calendar
+    ///
+    /// Invitations and updates are stored, cancellations remove the event
+    /// or occurrence from the `.ics` file or CalDAV collection set in the
+    /// account configuration.
+    pub async fn add_to_calendar(&self, message_id: Uuid) -> AsgardResult<CalendarChange> {
+        let message = self.load_calendar_message(message_id).await?;
+        let invitation = message.calendar_content()
+            .map(|content| Calendar::parse(&String::from_utf8_lossy(content)))
+            .transpose()?
+            .ok_or_else(|| AsgardError::not_found(format!("No calendar data in message {}", message_id)))?;
+        let account = {
+            let storage = self.storage.lock().await;
+            storage.database().get_account(message.account_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", message.account_id)))?
+        };
+        let target = account.config.calendar
+            .ok_or_else(|| AsgardError::config(format!("No calendar configured for {}", account.config.email)))?;
+
+        let change = calendar::add_to_calendar(&target, &invitation).await?;
+        info!("Applied calendar data of message {} to the calendar: {:?}", message_id, change);
+        Ok(change)
+    }
+
+    /// Search messages, falling back to the server for Gmail accounts
+    ///
+    /// When the account is only partly synced the query is also sent to Gmail
+    /// as X-GM-RAW; messages found there are fetched, stored and merged into
+    /// the local results.
+    pub async fn search(&self, query: &SearchQuery) -> AsgardResult<Vec<SearchResult>> {
+        let local = self.search_index.lock().await.search(query)?;
+
+        let Some(account_id) = query.account_id else {
+            return Ok(local);
+        };
+        let account = {
+            let storage = self.storage.lock().await;
+            storage.database().get_account(account_id).await?
+        };
+        let Some(account) = account.filter(|a| a.is_gmail()) else {
+            return Ok(local);
+        };
+        if !gmail::needs_server_search(query, &account.config.sync_settings, local.len()) {
+            return Ok(local);
+        }
+
+        let hierarchy = self.load_hierarchy(account_id).await?;
+        let Some(all_mail) = hierarchy.mailboxes_by_type(MailboxType::All).first().map(|m| (*m).clone()) else {
+            return Ok(local);
+        };
+
+        let found = {
+            let engine = self.engine(account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.search_server(&all_mail, query).await;
+            Self::disconnect_engine(&mut **engine, result).await
+        };
+        let found = match found {
+            Ok(found) => found,
+            Err(e) => {
+                // Local results are still useful when the server is unreachable
+                warn!("Server search failed for account {}: {}", account_id, e);
+                return Ok(local);
+            }
+        };
+
+        let mut remote = Vec::new();
+        {
+            let mut storage = self.storage.lock().await;
+            let mut search_index = self.search_index.lock().await;
+
+            for mut message in found {
+                if let Some(existing_id) = storage.database().find_existing_message(&message).await? {
+                    message.id = existing_id;
+                } else {
+                    storage.database_mut().create_message(&message).await?;
+                    search_index.add_message(&message)?;
+                }
+
+                remote.push(SearchResult {
+                    message_id: message.id,
+                    account_id: message.account_id,
+                    mailbox_id: message.mailbox_id,
+                    score: 0.5,
+                    snippets: vec![format!("Subject: {}", message.subject())],
+                });
+            }
+        }
+
+        Ok(search::merge_results(local, remote, query.limit))
+    }
+
+    /// Refresh quota and mailbox sizes for an account
+    ///
+    /// Sizes come from the server where the engine can report them and from
+    /// locally stored messages otherwise.
+    pub async fn refresh_storage_usage(&self, account_id: Uuid) -> AsgardResult<StorageReport> {
+        let (mut account, mut mailboxes, local_sizes) = {
+            let storage = self.storage.lock().await;
+            let database = storage.database();
+            let account = database.get_account(account_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", account_id)))?;
+            (account, database.get_mailboxes(account_id).await?, database.get_mailbox_sizes(account_id).await?)
+        };
+
+        let quota = {
+            let engine = self.engine(account_id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+
+            let quota = engine.fetch_quota().await.unwrap_or_else(|e| {
+                warn!("Failed to fetch quota for account {}: {}", account_id, e);
+                None
+            });
+            for mailbox in mailboxes.iter_mut().filter(|m| m.can_select()) {
+                let server_size = engine.mailbox_size(mailbox).await.unwrap_or(None);
+                mailbox.stats.size = server_size
+                    .or_else(|| local_sizes.get(&mailbox.id).copied())
+                    .unwrap_or(0);
+            }
+
+            Self::disconnect_engine(&mut **engine, Ok(quota)).await?
+        };
+
+        let largest_messages = {
+            let storage = self.storage.lock().await;
+            let database = storage.database();
+            for mailbox in &mailboxes {
+                database.update_mailbox(mailbox).await?;
+            }
+            database.get_largest_messages(account_id, 10).await?
+        };
+
+        let report = StorageReport::new(account_id, quota, mailboxes, largest_messages);
+        account.stats.storage_used = report.used_bytes;
+        account.stats.storage_quota = report.limit_bytes;
+        {
+            let storage = self.storage.lock().await;
+            storage.database().update_account(&account).await?;
+        }
+
+        if report.is_near_quota() {
+            warn!(
+                "Account {} is using {:.0}% of its storage quota",
+                account.email(),
+                report.usage_ratio().unwrap_or(0.0) * 100.0
+            );
+        }
+
+        Ok(report)
     }
 
     /// Get sync statistics
@@ -251,35 +884,168 @@ impl SyncManager {
     pub async fn get_all_status(&self) -> HashMap<Uuid, SyncStatus> {
         let engines = self.sync_engines.read().await;
         engines.iter()
-            .map(|(id, engine)| (*id, engine.status()))
+            .map(|(id, engine)| {
+                // A locked engine is busy talking to its server
+                let status = engine.try_lock().map_or(SyncStatus::Running, |engine| engine.status());
+                (*id, status)
+            })
             .collect()
     }
 
     // Helper methods
 
+    /// Check if a Gmail account is set to sync through the REST API
+    fn uses_gmail_api(account: &Account) -> bool {
+        account.config.settings
+            .get(super::gmail_api_sync::GMAIL_API_SETTING)
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Send a message through the Gmail API engine or the SMTP server of `account`
+    async fn send_from(&self, account: Account, message: &Message) -> AsgardResult<()> {
+        if Self::uses_gmail_api(&account) {
+            let engine = self.engine(account.id).await?;
+            let mut engine = engine.lock().await;
+            Self::connect_engine(&mut **engine, &self.token_service).await?;
+            let result = engine.send_message(message).await;
+            return Self::disconnect_engine(&mut **engine, result).await;
+        }
+
+        let mut smtp = SmtpSend::new(account);
+        smtp.send_message_with_refresh(message, ComposeOptions::default(), &self.token_service).await
+    }
+
+    /// Fetch parts of a message from its account
+    async fn fetch_parts(&self, message: &Message, part_ids: &[String]) -> AsgardResult<Vec<(String, Vec<u8>)>> {
+        let mailbox = self.load_mailbox(message.mailbox_id).await?;
+        let engine = self.engine(message.account_id).await?;
+        let mut engine = engine.lock().await;
+        Self::connect_engine(&mut **engine, &self.token_service).await?;
+        let result = engine.fetch_parts(&mailbox, message, part_ids).await;
+        Self::disconnect_engine(&mut **engine, result).await
+    }
+
+    /// Download one part of a message, from the server when the account
+    /// supports partial download and from the message source otherwise
+    async fn download_part(&self, message_id: Uuid, part_id: &str) -> AsgardResult<Vec<u8>> {
+        let message = {
+            let storage = self.storage.lock().await;
+            storage.database().get_message(message_id).await?
+                .ok_or_else(|| AsgardError::not_found(format!("Message not found: {}", message_id)))?
+        };
+        if message.raw_content.is_none() {
+            match self.fetch_parts(&message, &[part_id.to_string()]).await {
+                Ok(mut parts) if !parts.is_empty() => return Ok(parts.remove(0).1),
+                Ok(_) => {}
+                Err(AsgardError::Unsupported(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let raw = self.message_source(message_id).await?;
+        message::extract_part(&raw, part_id)?
+            .ok_or_else(|| AsgardError::not_found(format!("Part {} not found in message {}", part_id, message_id)))
+    }
+
+    /// Load a message with its body and calendar attachment downloaded
+    async fn load_calendar_message(&self, message_id: Uuid) -> AsgardResult<Message> {
+        let mut message = self.load_message_body(message_id).await?;
+        if message.calendar_content().is_none() {
+            if let Some(attachment) = message.attachments.iter().find(|a| a.is_calendar()).cloned() {
+                let content = self.attachment_content(&attachment).await?;
+                message.set_part_content(&attachment.part_id, content);
+            }
+        }
+        Ok(message)
+    }
+
+    async fn load_mailbox(&self, mailbox_id: Uuid) -> AsgardResult<Mailbox> {
+        let storage = self.storage.lock().await;
+        storage.database().get_mailbox(mailbox_id).await?
+            .ok_or_else(|| AsgardError::not_found(format!("Mailbox not found: {}", mailbox_id)))
+    }
+
+    async fn load_hierarchy(&self, account_id: Uuid) -> AsgardResult<MailboxHierarchy> {
+        let storage = self.storage.lock().await;
+        let mut hierarchy = MailboxHierarchy::new();
+        for mailbox in storage.database().get_mailboxes(account_id).await? {
+            hierarchy.add_mailbox(mailbox);
+        }
+        Ok(hierarchy)
+    }
+
+    /// Get an account's engine, holding the engine map only for the lookup
+    async fn engine(&self, account_id: Uuid) -> AsgardResult<SharedEngine> {
+        self.sync_engines.read().await.get(&account_id).cloned()
+            .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", account_id)))
+    }
+
+    /// Disconnect an engine after a server operation, passing on its result
+    ///
+    /// A failed disconnect is only logged: the server has already applied
+    /// a successful operation, and a failed one reports its own error.
+    async fn disconnect_engine<T>(engine: &mut (dyn SyncEngine + Send), result: AsgardResult<T>) -> AsgardResult<T> {
+        if let Err(e) = engine.disconnect().await {
+            warn!("Failed to disconnect sync engine for account {}: {}", engine.account_id(), e);
+        }
+        result
+    }
+
+    /// Connect an engine with a fresh token, refreshing and retrying once if
+    /// the server still rejects it
+    async fn connect_engine(engine: &mut (dyn SyncEngine + Send), token_service: &TokenService) -> AsgardResult<()> {
+        let account_id = engine.account_id();
+        if let Some(account) = token_service.ensure_fresh(account_id).await? {
+            engine.update_credentials(&account);
+        }
+
+        match engine.connect().await {
+            Err(e) if e.is_auth_error() => {
+                warn!("Authentication failed for account {}, refreshing token: {}", account_id, e);
+                let Some(account) = token_service.force_refresh(account_id).await? else {
+                    return Err(e);
+                };
+                engine.update_credentials(&account);
+                engine.connect().await
+            }
+            result => result,
+        }
+    }
+
     async fn sync_account_engine(
         engine: &mut (dyn SyncEngine + Send),
         storage: &Arc<Mutex<StorageManager>>,
         search_index: &Arc<Mutex<SimpleSearchIndex>>,
+        token_service: &TokenService,
     ) -> AsgardResult<SyncResult> {
         let start_time = std::time::Instant::now();
         let mut messages_synced = 0;
         let mut new_messages = 0;
         let mut updated_messages = 0;
+        let mut deleted_messages = 0;
         
-        // Connect to server
-        engine.connect().await?;
+        // Connect to server with a fresh token
+        Self::connect_engine(engine, token_service).await?;
+
+        // Our Autocrypt key lets us read keys gossiped in encrypted mail
+        let autocrypt_account = {
+            let storage = storage.lock().await;
+            AutocryptAccount::load(storage.database().secrets(), engine.account_id()).unwrap_or_else(|e| {
+                warn!("Autocrypt key unavailable for account {}: {}", engine.account_id(), e);
+                None
+            })
+        };
         
         // Sync mailboxes
-        let mailboxes = engine.sync_mailboxes().await?;
+        let mut mailboxes = engine.sync_mailboxes().await?;
         
-        // Store mailboxes in database
+        // Store mailboxes in database, reusing the IDs of known ones
         {
-            let mut storage = storage.lock().await;
-            for mailbox in &mailboxes {
-                storage.database_mut().create_mailbox(mailbox).await?;
-            }
+            let storage = storage.lock().await;
+            storage.database().store_synced_mailboxes(engine.account_id(), &mut mailboxes).await?;
         }
+        engine.use_mailboxes(&mailboxes);
         
         // Sync messages in each mailbox
         for mailbox in &mailboxes {
@@ -289,18 +1055,30 @@ impl SyncManager {
             {
                 let mut storage = storage.lock().await;
                 let mut search_index = search_index.lock().await;
+
+                // A new UIDVALIDITY invalidates every UID stored for the mailbox
+                if let Some(uid_validity) = messages.first().and_then(|m| m.uid_validity) {
+                    for message_id in storage.database().delete_stale_messages(mailbox.id, uid_validity).await? {
+                        search_index.remove_message(message_id)?;
+                        deleted_messages += 1;
+                    }
+                }
                 
-                for message in messages {
-                    // Check if message already exists
-                    let existing_message = storage.database().get_message(message.id).await?;
+                for mut message in messages {
+                    // Check if message already exists (Gmail messages by X-GM-MSGID)
+                    let existing_id = storage.database().find_existing_message(&message).await?;
                     
-                    if existing_message.is_some() {
+                    if let Some(existing_id) = existing_id {
+                        message.id = existing_id;
                         storage.database_mut().update_message(&message).await?;
                         search_index.update_message(&message)?;
                         updated_messages += 1;
                     } else {
                         storage.database_mut().create_message(&message).await?;
                         search_index.add_message(&message)?;
+                        if let Err(e) = autocrypt::process_incoming(storage.database(), &message, autocrypt_account.as_ref()).await {
+                            warn!("Autocrypt processing failed for message {}: {}", message.id, e);
+                        }
                         new_messages += 1;
                     }
                     
@@ -309,6 +1087,27 @@ impl SyncManager {
             }
         }
         
+        // Drop the messages deleted on the server
+        let deleted_ids = engine.take_deleted_message_ids();
+        if !deleted_ids.is_empty() {
+            let storage = storage.lock().await;
+            let mut search_index = search_index.lock().await;
+            for message_id in storage.database().delete_gmail_messages(engine.account_id(), &deleted_ids).await? {
+                search_index.remove_message(message_id)?;
+                deleted_messages += 1;
+            }
+        }
+        
+        // Keep the engine's sync state across restarts
+        let settings = engine.sync_settings();
+        if !settings.is_empty() {
+            let storage = storage.lock().await;
+            if let Some(mut account) = storage.database().get_account(engine.account_id()).await? {
+                account.config.settings.extend(settings);
+                storage.database().update_account(&account).await?;
+            }
+        }
+        
         // Disconnect from server
         engine.disconnect().await?;
         
@@ -318,7 +1117,7 @@ impl SyncManager {
             messages_synced,
             new_messages,
             updated_messages,
-            deleted_messages: 0, // TODO: Implement message deletion detection
+            deleted_messages,
             duration,
             error: None,
         };
@@ -332,6 +1131,9 @@ impl Drop for SyncManager {
         if let Some(task) = self.background_task.take() {
             task.abort();
         }
+        if let Some(task) = self.token_task.take() {
+            task.abort();
+        }
     }
 }
 
@@ -351,7 +1153,7 @@ mod tests {
         ));
         
         let search_index = Arc::new(Mutex::new(
-            SimpleSearchIndex::new(temp_dir.path().join("search")).unwrap()
+            SimpleSearchIndex::new()
         ));
         
         let sync_manager = SyncManager::new(
@@ -363,4 +1165,344 @@ mod tests {
         let stats = sync_manager.get_stats().await;
         assert_eq!(stats.total_syncs, 0);
     }
+    #[tokio::test]
+    async fn test_imap_sync_engine() {
+        let oauth_config = crate::account::GmailOAuthConfig {
+            client_id: "test-client-id".to_string(),
+            client_secret: "test-client-secret".to_string(),
+            access_token: Some("test-access-token".to_string()),
+            refresh_token: None,
+            token_expires_at: None,
+            scopes: vec!["https://mail.google.com/".to_string()],
+        };
+        let account = Account::new_gmail(
+            "test@gmail.com".to_string(),
+            Some("Test Account".to_string()),
+            oauth_config,
+        ).unwrap();
+        let account_id = account.id;
+
+        let mut engine: Box<dyn SyncEngine + Send + Sync> = Box::new(ImapSyncEngine {
+            engine: ImapSync::new(account),
+        });
+        assert_eq!(engine.account_id(), account_id);
+        assert_eq!(engine.status(), SyncStatus::Idle);
+        assert!(engine.sync_mailboxes().await.is_err());
+        assert!(engine.fetch_quota().await.unwrap().is_none());
+    }
+    #[tokio::test]
+    async fn test_gmail_mailbox_messages_by_label() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = StorageManager::new(temp_dir.path().join("test.db"), temp_dir.path().join("cache"))
+            .await
+            .unwrap();
+        storage.initialize().await.unwrap();
+
+        let account = Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            crate::account::GmailOAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: String::new(),
+                access_token: None,
+                refresh_token: None,
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        ).unwrap();
+        let inbox = Mailbox::new_inbox(account.id);
+        let all_mail = Mailbox::new(account.id, "[Gmail]/All Mail".to_string(), None, MailboxType::All, None);
+        let work = Mailbox::new(account.id, "Work".to_string(), None, MailboxType::Label, None);
+
+        let headers = crate::message::MessageHeaders {
+            message_id: None,
+            in_reply_to: None,
+            references: None,
+            subject: "Quarterly report".to_string(),
+            from: vec![],
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            reply_to: vec![],
+            date: None,
+            received_date: None,
+            importance: crate::message::MessageImportance::Normal,
+            custom: HashMap::new(),
+        };
+        let mut message = Message::new(account.id, all_mail.id, headers);
+        message.add_label("INBOX".to_string());
+
+        {
+            let database = storage.database();
+            database.create_account(&account).await.unwrap();
+            for mailbox in [&inbox, &all_mail, &work] {
+                database.create_mailbox(mailbox).await.unwrap();
+            }
+            database.create_message(&message).await.unwrap();
+        }
+
+        let search_index = SimpleSearchIndex::new();
+        let sync_manager = SyncManager::new(
+            Arc::new(Mutex::new(storage)),
+            Arc::new(Mutex::new(search_index)),
+            Duration::from_secs(300),
+        );
+
+        let listed = |mailbox_id| {
+            let sync_manager = &sync_manager;
+            async move {
+                sync_manager.mailbox_messages(mailbox_id, None, None).await.unwrap()
+                    .into_iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(listed(inbox.id).await, vec![message.id]);
+        assert_eq!(listed(all_mail.id).await, vec![message.id]);
+        assert!(listed(work.id).await.is_empty());
+    }
+
+    /// Lists the same folders with fresh IDs on every sync, like `imap_list` does
+    struct RelistingEngine {
+        account_id: Uuid,
+        uid_validity: u32,
+    }
+
+    #[async_trait::async_trait]
+    impl SyncEngine for RelistingEngine {
+        fn account_id(&self) -> Uuid {
+            self.account_id
+        }
+
+        fn status(&self) -> SyncStatus {
+            SyncStatus::Idle
+        }
+
+        fn last_sync_result(&self) -> Option<&SyncResult> {
+            None
+        }
+
+        async fn connect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn disconnect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+            let projects = Mailbox::new(self.account_id, "Projects".to_string(), None, MailboxType::Custom, None);
+            let asgard = Mailbox::new(self.account_id, "Projects/Asgard".to_string(), None, MailboxType::Custom, Some(projects.id));
+            Ok(vec![Mailbox::new_inbox(self.account_id), projects, asgard])
+        }
+
+        async fn sync_mailbox_messages(&mut self, mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+            let raw = format!("From: alice@example.com\r\nSubject: In {}\r\n\r\nHello", mailbox.name);
+            let mut message = Message::from_rfc822(self.account_id, mailbox.id, raw.as_bytes())?;
+            message.set_uid(1, self.uid_validity);
+            Ok(vec![message])
+        }
+
+        fn sync_settings(&self) -> HashMap<String, serde_json::Value> {
+            HashMap::from([("uid_validity".to_string(), self.uid_validity.into())])
+        }
+    }
+
+    /// Engine that lists an empty inbox and reports messages deleted on the server
+    struct DeletingEngine {
+        account_id: Uuid,
+        deleted: Vec<u64>,
+    }
+
+    #[async_trait::async_trait]
+    impl SyncEngine for DeletingEngine {
+        fn account_id(&self) -> Uuid {
+            self.account_id
+        }
+
+        fn status(&self) -> SyncStatus {
+            SyncStatus::Idle
+        }
+
+        fn last_sync_result(&self) -> Option<&SyncResult> {
+            None
+        }
+
+        async fn connect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn disconnect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+            Ok(vec![Mailbox::new_inbox(self.account_id)])
+        }
+
+        async fn sync_mailbox_messages(&mut self, _mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        fn take_deleted_message_ids(&mut self) -> Vec<u64> {
+            std::mem::take(&mut self.deleted)
+        }
+    }
+
+    /// Engine that records the subjects of the messages sent through it
+    struct OutboxEngine {
+        account_id: Uuid,
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SyncEngine for OutboxEngine {
+        fn account_id(&self) -> Uuid {
+            self.account_id
+        }
+
+        fn status(&self) -> SyncStatus {
+            SyncStatus::Idle
+        }
+
+        fn last_sync_result(&self) -> Option<&SyncResult> {
+            None
+        }
+
+        async fn connect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn disconnect(&mut self) -> AsgardResult<()> {
+            Ok(())
+        }
+
+        async fn sync_mailboxes(&mut self) -> AsgardResult<Vec<Mailbox>> {
+            Ok(Vec::new())
+        }
+
+        async fn sync_mailbox_messages(&mut self, _mailbox: &Mailbox) -> AsgardResult<Vec<Message>> {
+            Ok(Vec::new())
+        }
+
+        async fn send_message(&mut self, message: &Message) -> AsgardResult<()> {
+            self.sent.lock().unwrap().push(message.subject().to_string());
+            Ok(())
+        }
+    }
+
+    /// Storage holding a Gmail account without tokens
+    async fn gmail_storage(temp_dir: &TempDir) -> (Arc<Mutex<StorageManager>>, Account) {
+        let mut storage = StorageManager::new(temp_dir.path().join("test.db"), temp_dir.path().join("cache"))
+            .await
+            .unwrap();
+        storage.initialize().await.unwrap();
+
+        let account = Account::new_gmail(
+            "test@gmail.com".to_string(),
+            None,
+            crate::account::GmailOAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: String::new(),
+                access_token: None,
+                refresh_token: None,
+                token_expires_at: None,
+                scopes: Vec::new(),
+            },
+        ).unwrap();
+        storage.database().create_account(&account).await.unwrap();
+        (Arc::new(Mutex::new(storage)), account)
+    }
+
+    #[tokio::test]
+    async fn test_repeated_sync_keeps_mailboxes_and_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let (storage, account) = gmail_storage(&temp_dir).await;
+        let search_index = Arc::new(Mutex::new(SimpleSearchIndex::new()));
+        let token_service = TokenService::new(storage.clone());
+        let mut engine = RelistingEngine { account_id: account.id, uid_validity: 7 };
+
+        let counts = || async {
+            let storage = storage.lock().await;
+            let mailboxes = storage.database().get_mailboxes(account.id).await.unwrap();
+            let mut messages = 0;
+            for mailbox in &mailboxes {
+                messages += storage.database().get_messages(mailbox.id, None, None).await.unwrap().len();
+            }
+            (mailboxes, messages)
+        };
+
+        let first = SyncManager::sync_account_engine(&mut engine, &storage, &search_index, &token_service).await.unwrap();
+        assert_eq!(first.new_messages, 3);
+        let (mailboxes, messages) = counts().await;
+        assert_eq!((mailboxes.len(), messages), (3, 3));
+
+        let second = SyncManager::sync_account_engine(&mut engine, &storage, &search_index, &token_service).await.unwrap();
+        assert_eq!((second.new_messages, second.updated_messages), (0, 3));
+        let (again, messages) = counts().await;
+        assert_eq!((again.len(), messages), (3, 3));
+
+        let ids = |mailboxes: &[Mailbox]| mailboxes.iter().map(|m| (m.id, m.parent_id)).collect::<Vec<_>>();
+        assert_eq!(ids(&again), ids(&mailboxes));
+        let projects = again.iter().find(|m| m.name == "Projects").unwrap();
+        let asgard = again.iter().find(|m| m.name == "Projects/Asgard").unwrap();
+        assert_eq!(asgard.parent_id, Some(projects.id));
+
+        // A new UIDVALIDITY replaces the stored copies instead of matching them
+        engine.uid_validity = 8;
+        let third = SyncManager::sync_account_engine(&mut engine, &storage, &search_index, &token_service).await.unwrap();
+        assert_eq!((third.new_messages, third.deleted_messages), (3, 3));
+        assert_eq!(counts().await.1, 3);
+
+        // The engine's sync state is stored with the account
+        let stored = storage.lock().await.database().get_account(account.id).await.unwrap().unwrap();
+        assert_eq!(stored.config.settings.get("uid_validity"), Some(&serde_json::json!(8)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_removes_messages_deleted_on_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let (storage, account) = gmail_storage(&temp_dir).await;
+        let search_index = Arc::new(Mutex::new(SimpleSearchIndex::new()));
+        let token_service = TokenService::new(storage.clone());
+        let mut engine = DeletingEngine { account_id: account.id, deleted: Vec::new() };
+
+        SyncManager::sync_account_engine(&mut engine, &storage, &search_index, &token_service).await.unwrap();
+        let inbox = storage.lock().await.database().get_mailboxes(account.id).await.unwrap().remove(0);
+        for (gmail_id, subject) in [(0x11a, "Kept"), (0x11b, "Deleted")] {
+            let raw = format!("From: alice@example.com\r\nSubject: {}\r\n\r\nHello", subject);
+            let mut message = Message::from_rfc822(account.id, inbox.id, raw.as_bytes()).unwrap();
+            message.gmail_message_id = Some(gmail_id);
+            storage.lock().await.database().create_message(&message).await.unwrap();
+            search_index.lock().await.add_message(&message).unwrap();
+        }
+
+        // Deletions of messages never stored locally are ignored
+        engine.deleted = vec![0x11b, 0x999];
+        let result = SyncManager::sync_account_engine(&mut engine, &storage, &search_index, &token_service).await.unwrap();
+        assert_eq!(result.deleted_messages, 1);
+
+        let messages = storage.lock().await.database().get_messages(inbox.id, None, None).await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.gmail_message_id).collect::<Vec<_>>(), [Some(0x11a)]);
+        let found = search_index.lock().await.search(&SearchQuery { query: "Deleted".to_string(), ..Default::default() }).unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gmail_api_accounts_send_through_their_engine() {
+        let temp_dir = TempDir::new().unwrap();
+        let (storage, mut account) = gmail_storage(&temp_dir).await;
+        account.config.settings.insert(crate::sync::gmail_api_sync::GMAIL_API_SETTING.to_string(), true.into());
+        storage.lock().await.database().update_account(&account).await.unwrap();
+
+        let manager = SyncManager::new(storage, Arc::new(Mutex::new(SimpleSearchIndex::new())), Duration::from_secs(300));
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let engine: Box<dyn SyncEngine + Send + Sync> = Box::new(OutboxEngine { account_id: account.id, sent: sent.clone() });
+        manager.sync_engines.write().await.insert(account.id, Arc::new(Mutex::new(engine)));
+
+        let raw = b"From: test@gmail.com\r\nTo: bob@example.com\r\nSubject: Lunch\r\n\r\nNoon?";
+        let message = Message::from_rfc822(account.id, Uuid::new_v4(), raw).unwrap();
+        manager.send_message(&message).await.unwrap();
+        assert_eq!(*sent.lock().unwrap(), ["Lunch"]);
+    }
 }
@@ -0,0 +1,323 @@
+//! OAuth token refresh for sync engines
+//!
+//! Access tokens are refreshed shortly before they expire and whenever a
+//! server rejects one. Rotated refresh tokens are written back to the
+//! account; a revoked refresh token marks the account as needing sign-in.
+
+use crate::account::{Account, AccountStatus};
+use crate::error::{AsgardError, AsgardResult};
+use crate::gmail::xoauth2::{RefreshedToken, XOAUTH2TokenRefresh};
+use crate::storage::StorageManager;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How long before expiry an access token is refreshed
+pub const DEFAULT_REFRESH_MARGIN: Duration = Duration::minutes(5);
+
+/// Exchanges a refresh token for a new access token
+#[async_trait::async_trait]
+pub trait TokenRefresher: Send + Sync {
+    /// Refresh the access token of an account
+    async fn refresh(&self, account: &Account, refresh_token: &str) -> AsgardResult<RefreshedToken>;
+}
+
+/// Refresher that calls the provider's token endpoint
+pub struct HttpTokenRefresher;
+
+#[async_trait::async_trait]
+impl TokenRefresher for HttpTokenRefresher {
+    async fn refresh(&self, account: &Account, refresh_token: &str) -> AsgardResult<RefreshedToken> {
+        let config = account.oauth_config()
+            .ok_or_else(|| AsgardError::auth("OAuth configuration not found"))?;
+        let token_url = account.oauth_token_url()
+            .ok_or_else(|| AsgardError::unsupported(format!("{} accounts do not use OAuth", account.account_type())))?;
+
+        XOAUTH2TokenRefresh::new(
+            account.email().to_string(),
+            refresh_token.to_string(),
+            config.client_id.clone(),
+            config.client_secret.clone(),
+        )
+        .with_token_url(token_url)
+        .refresh()
+        .await
+    }
+}
+
+/// Keeps OAuth access tokens of stored accounts fresh
+pub struct TokenService {
+    /// Storage manager the accounts are loaded from and saved to
+    storage: Arc<Mutex<StorageManager>>,
+    /// Token endpoint client
+    refresher: Arc<dyn TokenRefresher>,
+    /// How long before expiry a token is refreshed
+    refresh_margin: Duration,
+    /// Serializes refreshes so rotated refresh tokens are not used twice
+    refresh_lock: Mutex<()>,
+}
+
+impl TokenService {
+    /// Create a token service that talks to the providers' token endpoints
+    pub fn new(storage: Arc<Mutex<StorageManager>>) -> Self {
+        Self::with_refresher(storage, Arc::new(HttpTokenRefresher))
+    }
+
+    /// Create a token service with a custom refresher
+    pub fn with_refresher(storage: Arc<Mutex<StorageManager>>, refresher: Arc<dyn TokenRefresher>) -> Self {
+        Self {
+            storage,
+            refresher,
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    /// Set how long before expiry tokens are refreshed
+    pub fn set_refresh_margin(&mut self, margin: Duration) {
+        self.refresh_margin = margin;
+    }
+
+    /// Check whether an account's access token should be refreshed at `now`
+    pub fn needs_refresh(&self, account: &Account, now: OffsetDateTime) -> bool {
+        self.next_refresh_at(account).is_some_and(|due| due <= now)
+    }
+
+    /// When the account's access token is next due for a refresh
+    ///
+    /// `None` for accounts without OAuth, without a refresh token, or that
+    /// need the user to sign in again.
+    pub fn next_refresh_at(&self, account: &Account) -> Option<OffsetDateTime> {
+        let config = account.oauth_config()?;
+        if account.needs_reauth() || config.refresh_token.is_none() {
+            return None;
+        }
+
+        match (&config.access_token, config.token_expires_at) {
+            (None, _) => Some(OffsetDateTime::UNIX_EPOCH),
+            (Some(_), Some(expires_at)) => Some(expires_at - self.refresh_margin),
+            (Some(_), None) => None,
+        }
+    }
+
+    /// Refresh the account's token if it expires soon
+    ///
+    /// Returns the updated account when a refresh happened.
+    pub async fn ensure_fresh(&self, account_id: Uuid) -> AsgardResult<Option<Account>> {
+        let _guard = self.refresh_lock.lock().await;
+        let account = self.load_account(account_id).await?;
+        if !self.needs_refresh(&account, OffsetDateTime::now_utc()) {
+            return Ok(None);
+        }
+        self.refresh(account).await.map(Some)
+    }
+
+    /// Refresh the account's token now, e.g. after the server rejected it
+    ///
+    /// Returns `None` when the account has no refresh token to use.
+    pub async fn force_refresh(&self, account_id: Uuid) -> AsgardResult<Option<Account>> {
+        let _guard = self.refresh_lock.lock().await;
+        let account = self.load_account(account_id).await?;
+        let has_refresh_token = account.oauth_config()
+            .is_some_and(|c| c.refresh_token.is_some());
+        if !has_refresh_token || account.needs_reauth() {
+            return Ok(None);
+        }
+        self.refresh(account).await.map(Some)
+    }
+
+    /// Refresh every account whose token is due, returning the refreshed accounts
+    pub async fn refresh_due(&self) -> AsgardResult<Vec<Account>> {
+        let account_ids: Vec<Uuid> = {
+            let storage = self.storage.lock().await;
+            let now = OffsetDateTime::now_utc();
+            storage.database().get_accounts().await?
+                .into_iter()
+                .filter(|account| self.needs_refresh(account, now))
+                .map(|account| account.id)
+                .collect()
+        };
+
+        let mut refreshed = Vec::new();
+        for account_id in account_ids {
+            match self.ensure_fresh(account_id).await {
+                Ok(Some(account)) => refreshed.push(account),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to refresh token for account {}: {}", account_id, e),
+            }
+        }
+        Ok(refreshed)
+    }
+
+    async fn refresh(&self, mut account: Account) -> AsgardResult<Account> {
+        let refresh_token = account.oauth_config()
+            .and_then(|c| c.refresh_token.clone())
+            .ok_or_else(|| AsgardError::auth("No refresh token available"))?;
+
+        match self.refresher.refresh(&account, &refresh_token).await {
+            Ok(token) => {
+                apply_refresh(&mut account, token, OffsetDateTime::now_utc());
+                if account.status == AccountStatus::AuthError {
+                    account.update_status(AccountStatus::Active);
+                    account.set_last_error(None);
+                }
+                self.save_account(&account).await?;
+                info!("Refreshed access token for {}", account.email());
+                Ok(account)
+            }
+            Err(e @ AsgardError::OAuthToken(_)) => {
+                account.update_status(AccountStatus::ReauthRequired);
+                account.set_last_error(Some(e.to_string()));
+                self.save_account(&account).await?;
+                warn!("Refresh token for {} was revoked; sign-in required", account.email());
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn load_account(&self, account_id: Uuid) -> AsgardResult<Account> {
+        let storage = self.storage.lock().await;
+        storage.database().get_account(account_id).await?
+            .ok_or_else(|| AsgardError::not_found(format!("Account not found: {}", account_id)))
+    }
+
+    async fn save_account(&self, account: &Account) -> AsgardResult<()> {
+        let storage = self.storage.lock().await;
+        storage.database().update_account(account).await
+    }
+}
+
+/// Store a refreshed token on an account
+///
+/// The old refresh token is kept unless the provider rotated it.
+pub fn apply_refresh(account: &mut Account, token: RefreshedToken, now: OffsetDateTime) {
+    let Some(config) = account.config.gmail_oauth.as_mut() else {
+        return;
+    };
+
+    config.access_token = Some(token.access_token);
+    if let Some(refresh_token) = token.refresh_token {
+        config.refresh_token = Some(refresh_token);
+    }
+    config.token_expires_at = token.expires_in.map(|secs| now + Duration::seconds(secs as i64));
+    account.updated_at = now;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::GmailOAuthConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    struct MockRefresher {
+        result: fn() -> AsgardResult<RefreshedToken>,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl TokenRefresher for MockRefresher {
+        async fn refresh(&self, _account: &Account, refresh_token: &str) -> AsgardResult<RefreshedToken> {
+            assert_eq!(refresh_token, "old-refresh");
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            (self.result)()
+        }
+    }
+
+    fn account(expires_in: Duration) -> Account {
+        Account::new_microsoft365(
+            "test@contoso.com".to_string(),
+            None,
+            GmailOAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: String::new(),
+                access_token: Some("old-access".to_string()),
+                refresh_token: Some("old-refresh".to_string()),
+                token_expires_at: Some(OffsetDateTime::now_utc() + expires_in),
+                scopes: Vec::new(),
+            },
+        ).unwrap()
+    }
+
+    async fn setup(account: &Account, result: fn() -> AsgardResult<RefreshedToken>) -> (TempDir, TokenService, Arc<MockRefresher>) {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = StorageManager::new(temp_dir.path().join("test.db"), temp_dir.path().join("cache"))
+            .await
+            .unwrap();
+        storage.initialize().await.unwrap();
+        storage.database().create_account(account).await.unwrap();
+
+        let refresher = Arc::new(MockRefresher { result, calls: AtomicUsize::new(0) });
+        let service = TokenService::with_refresher(Arc::new(Mutex::new(storage)), refresher.clone());
+        (temp_dir, service, refresher)
+    }
+
+    fn rotated() -> AsgardResult<RefreshedToken> {
+        Ok(RefreshedToken {
+            access_token: "new-access".to_string(),
+            refresh_token: Some("new-refresh".to_string()),
+            expires_in: Some(3600),
+        })
+    }
+
+    fn revoked() -> AsgardResult<RefreshedToken> {
+        Err(AsgardError::OAuthToken("invalid_grant".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_refresh_ahead_of_expiry_persists_rotated_token() {
+        let fresh = account(Duration::hours(1));
+        let (_dir, service, refresher) = setup(&fresh, rotated).await;
+        assert!(!service.needs_refresh(&fresh, OffsetDateTime::now_utc()));
+        assert!(service.ensure_fresh(fresh.id).await.unwrap().is_none());
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 0);
+
+        let expiring = account(Duration::minutes(2));
+        let (_dir, service, refresher) = setup(&expiring, rotated).await;
+        assert!(service.needs_refresh(&expiring, OffsetDateTime::now_utc()));
+
+        let refreshed = service.ensure_fresh(expiring.id).await.unwrap().unwrap();
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        let config = refreshed.oauth_config().unwrap();
+        assert_eq!(config.access_token.as_deref(), Some("new-access"));
+        assert_eq!(config.refresh_token.as_deref(), Some("new-refresh"));
+
+        let stored = service.load_account(expiring.id).await.unwrap();
+        assert_eq!(stored.oauth_config().unwrap().refresh_token.as_deref(), Some("new-refresh"));
+        assert!(!service.needs_refresh(&stored, OffsetDateTime::now_utc()));
+    }
+
+    #[tokio::test]
+    async fn test_revoked_refresh_token_requires_reauth() {
+        let account = account(Duration::hours(1));
+        let (_dir, service, _refresher) = setup(&account, revoked).await;
+
+        let result = service.force_refresh(account.id).await;
+        assert!(matches!(result, Err(AsgardError::OAuthToken(_))));
+
+        let stored = service.load_account(account.id).await.unwrap();
+        assert_eq!(stored.status, AccountStatus::ReauthRequired);
+        assert!(stored.last_error.is_some());
+        assert!(service.next_refresh_at(&stored).is_none());
+        assert!(service.force_refresh(account.id).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_apply_refresh_keeps_refresh_token() {
+        let mut account = account(Duration::minutes(1));
+        let now = OffsetDateTime::now_utc();
+        apply_refresh(&mut account, RefreshedToken {
+            access_token: "new-access".to_string(),
+            refresh_token: None,
+            expires_in: Some(600),
+        }, now);
+
+        let config = account.oauth_config().unwrap();
+        assert_eq!(config.access_token.as_deref(), Some("new-access"));
+        assert_eq!(config.refresh_token.as_deref(), Some("old-refresh"));
+        assert_eq!(config.token_expires_at, Some(now + Duration::seconds(600)));
+    }
+}
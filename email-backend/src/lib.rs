@@ -1,12 +1,31 @@
 //! Simplified email backend for Asgard Mail
 
 use anyhow::Result;
+use asgard_core::SecretStore;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+/// Name of the account password in the secret store
+const PASSWORD_SECRET: &str = "password";
+
+/// Open the secret store in the application data directory
+fn secret_store() -> Option<SecretStore> {
+    let store = asgard_core::get_data_dir().and_then(|data_dir| SecretStore::open(&data_dir));
+    match store {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log::warn!("Secret store unavailable: {}", e);
+            None
+        }
+    }
+}
+
 /// Email account configuration
+///
+/// The password itself never gets serialized: it lives in the secret store
+/// and the account only keeps a reference to it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailAccount {
     pub id: Uuid,
@@ -17,9 +36,17 @@ pub struct EmailAccount {
     pub smtp_server: String,
     pub smtp_port: u16,
     pub username: String,
-    pub password: String,
+    /// Opaque reference to the password in the secret store
+    #[serde(default)]
+    pub password_ref: Option<String>,
     pub use_tls: bool,
     pub created_at: OffsetDateTime,
+    /// Password kept for this session only, when the secret store cannot be used
+    #[serde(skip)]
+    session_password: Option<String>,
+    /// Plain-text password written by older versions, see `migrate_legacy_password`
+    #[serde(default, rename = "password", skip_serializing)]
+    legacy_password: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -47,7 +74,7 @@ impl EmailAccount {
         username: String,
         password: String,
     ) -> Self {
-        Self {
+        let mut account = Self {
             id: Uuid::new_v4(),
             email,
             display_name,
@@ -56,9 +83,78 @@ impl EmailAccount {
             smtp_server,
             smtp_port,
             username,
-            password,
+            password_ref: None,
             use_tls: true,
             created_at: OffsetDateTime::now_utc(),
+            session_password: None,
+            legacy_password: None,
+        };
+        account.set_password(&password);
+        account
+    }
+
+    /// Store the password in the secret store
+    ///
+    /// That is the Secret Service, or an encrypted file in the data directory
+    /// without a keyring daemon. Only if neither works is the password kept in
+    /// memory for this session. An empty password removes the stored one.
+    pub fn set_password(&mut self, password: &str) {
+        self.delete_password();
+        if password.is_empty() {
+            return;
+        }
+
+        let reference = SecretStore::reference(self.id, PASSWORD_SECRET);
+        let stored = secret_store().map(|store| store.store(&reference, password));
+        match stored {
+            Some(Ok(())) => self.password_ref = Some(reference),
+            Some(Err(e)) => {
+                log::warn!("Could not store password, keeping it for this session only: {}", e);
+                self.session_password = Some(password.to_string());
+            }
+            None => self.session_password = Some(password.to_string()),
+        }
+    }
+
+    /// Get the password from the secret store
+    pub fn password(&self) -> Option<String> {
+        if let Some(password) = &self.session_password {
+            return Some(password.clone());
+        }
+
+        let reference = self.password_ref.as_deref()?;
+        match secret_store()?.resolve(reference) {
+            Ok(password) => password,
+            Err(e) => {
+                log::warn!("Could not read password for {}: {}", self.email, e);
+                None
+            }
+        }
+    }
+
+    /// Remove the password from the secret store
+    pub fn delete_password(&mut self) {
+        self.session_password = None;
+        if let Some(reference) = self.password_ref.take() {
+            if let Some(store) = secret_store() {
+                if let Err(e) = store.delete(&reference) {
+                    log::warn!("Could not remove password for {}: {}", self.email, e);
+                }
+            }
+        }
+    }
+
+    /// Move a plain-text password loaded from an older file into the secret store
+    ///
+    /// Returns true when there was one; the account should then be saved
+    /// again so the password disappears from disk.
+    pub fn migrate_legacy_password(&mut self) -> bool {
+        match self.legacy_password.take() {
+            Some(password) => {
+                self.set_password(&password);
+                true
+            }
+            None => false,
         }
     }
 }
@@ -166,7 +262,8 @@ impl EmailBackend {
     }
 
     /// Add an email account
-    pub fn add_account(&mut self, account: EmailAccount) -> Uuid {
+    pub fn add_account(&mut self, mut account: EmailAccount) -> Uuid {
+        account.migrate_legacy_password();
         let account_id = account.id;
         self.accounts.insert(account_id, account);
         self.mailboxes.insert(account_id, vec![]);
@@ -187,9 +284,11 @@ impl EmailBackend {
             smtp_server: gnome_account.smtp_host,
             smtp_port: gnome_account.smtp_port,
             username: email, // Use email as username
-            password: "".to_string(), // GOA handles authentication
+            password_ref: None, // GOA handles authentication
             use_tls: gnome_account.smtp_use_tls,
             created_at: OffsetDateTime::now_utc(),
+            session_password: None,
+            legacy_password: None,
         };
         
         self.accounts.insert(account_id, account);
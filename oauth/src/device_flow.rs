@@ -0,0 +1,252 @@
+//! OAuth 2.0 device authorization grant (RFC 8628)
+//!
+//! Lets the user approve access from another device by entering a short
+//! code, so no browser or loopback listener is needed on this machine.
+
+use crate::provider::{token_from_response, OAuthClient};
+use crate::OAuthToken;
+use asgard_core::error::{AsgardError, AsgardResult};
+use oauth2::basic::BasicTokenResponse;
+use oauth2::reqwest::async_http_client;
+use oauth2::{
+    DeviceCodeErrorResponseType, HttpRequest, HttpResponse, RequestTokenError, Scope,
+    StandardDeviceAuthorizationResponse,
+};
+use std::future::Future;
+use std::time::Duration;
+
+/// Pending device authorization, shown to the user while polling
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization {
+    /// Server response, needed to poll the token endpoint
+    response: StandardDeviceAuthorizationResponse,
+}
+
+impl DeviceAuthorization {
+    /// Code the user enters on the verification page
+    pub fn user_code(&self) -> &str {
+        self.response.user_code().secret()
+    }
+
+    /// Page where the user enters the code
+    pub fn verification_uri(&self) -> &str {
+        self.response.verification_uri().as_str()
+    }
+
+    /// Verification page with the code already filled in, if offered
+    pub fn verification_uri_complete(&self) -> Option<&str> {
+        self.response.verification_uri_complete().map(|uri| uri.secret().as_str())
+    }
+
+    /// How long the code stays valid
+    pub fn expires_in(&self) -> Duration {
+        self.response.expires_in()
+    }
+
+    /// Initial polling interval
+    pub fn interval(&self) -> Duration {
+        self.response.interval()
+    }
+}
+
+impl OAuthClient {
+    /// Check whether the provider supports the device authorization grant
+    pub fn supports_device_flow(&self) -> bool {
+        self.provider().device_authorization_endpoint().is_some()
+    }
+
+    /// Request a device code and user code
+    pub async fn start_device_authorization(&self) -> AsgardResult<DeviceAuthorization> {
+        self.start_device_authorization_with(async_http_client).await
+    }
+
+    /// Poll the token endpoint until the user approves, denies or the code expires
+    ///
+    /// `authorization_pending` keeps the interval, `slow_down` adds five
+    /// seconds to it as RFC 8628 section 3.5 requires. Drop the future to
+    /// cancel.
+    pub async fn poll_device_token(&self, authorization: &DeviceAuthorization) -> AsgardResult<OAuthToken> {
+        self.poll_device_token_with(authorization, async_http_client, tokio::time::sleep).await
+    }
+
+    /// Run the whole device flow, handing the user code to `on_code` before polling
+    pub async fn complete_device_flow<F>(&self, on_code: F) -> AsgardResult<OAuthToken>
+    where
+        F: FnOnce(&DeviceAuthorization),
+    {
+        let authorization = self.start_device_authorization().await?;
+        on_code(&authorization);
+        self.poll_device_token(&authorization).await
+    }
+
+    async fn start_device_authorization_with<C, F, RE>(&self, http_client: C) -> AsgardResult<DeviceAuthorization>
+    where
+        C: Fn(HttpRequest) -> F,
+        F: Future<Output = Result<HttpResponse, RE>>,
+        RE: std::error::Error + 'static,
+    {
+        let response: StandardDeviceAuthorizationResponse = self.client
+            .exchange_device_code()
+            .map_err(|_| AsgardError::OAuth(format!(
+                "{} does not support device authorization",
+                self.provider().name()
+            )))?
+            .add_scopes(self.scopes().into_iter().map(Scope::new))
+            .request_async(http_client)
+            .await
+            .map_err(|e| AsgardError::OAuth(format!("Device authorization failed: {}", e)))?;
+
+        Ok(DeviceAuthorization { response })
+    }
+
+    async fn poll_device_token_with<C, F, RE, S, SF>(
+        &self,
+        authorization: &DeviceAuthorization,
+        http_client: C,
+        sleep_fn: S,
+    ) -> AsgardResult<OAuthToken>
+    where
+        C: Fn(HttpRequest) -> F,
+        F: Future<Output = Result<HttpResponse, RE>>,
+        RE: std::error::Error + 'static,
+        S: Fn(Duration) -> SF,
+        SF: Future<Output = ()>,
+    {
+        let token_response: BasicTokenResponse = self.client
+            .exchange_device_access_token(&authorization.response)
+            .request_async(http_client, sleep_fn, None)
+            .await
+            .map_err(|e| match e {
+                RequestTokenError::ServerResponse(response) => match response.error() {
+                    DeviceCodeErrorResponseType::AccessDenied => {
+                        AsgardError::OAuth("Authorization was denied".to_string())
+                    }
+                    DeviceCodeErrorResponseType::ExpiredToken => {
+                        AsgardError::OAuth("The device code expired before it was approved".to_string())
+                    }
+                    _ => AsgardError::OAuth(response.to_string()),
+                },
+                other => AsgardError::OAuth(other.to_string()),
+            })?;
+
+        Ok(token_from_response(&token_response, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microsoft_oauth::MicrosoftProvider;
+    use crate::OAuthConfig;
+    use oauth2::http::{HeaderMap, StatusCode};
+    use std::sync::{Arc, Mutex};
+
+    fn client() -> OAuthClient {
+        OAuthClient::new(
+            Box::new(MicrosoftProvider::default()),
+            OAuthConfig {
+                client_id: "test-client-id".to_string(),
+                client_secret: String::new(),
+                redirect_uri: "http://127.0.0.1:8080".to_string(),
+                scopes: Vec::new(),
+            },
+        )
+    }
+
+    fn json_response(status: StatusCode, body: &str) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/json".parse().unwrap());
+        HttpResponse { status_code: status, headers, body: body.as_bytes().to_vec() }
+    }
+
+    async fn authorization(client: &OAuthClient) -> DeviceAuthorization {
+        client
+            .start_device_authorization_with(|request: HttpRequest| async move {
+                assert!(request.url.as_str().ends_with("/common/oauth2/v2.0/devicecode"));
+                let body = String::from_utf8(request.body).unwrap();
+                assert!(body.contains("client_id=test-client-id"));
+                assert!(body.contains("offline_access"));
+                Ok::<_, std::io::Error>(json_response(StatusCode::OK, r#"{
+                    "device_code": "device-123",
+                    "user_code": "ABCD-EFGH",
+                    "verification_uri": "https://microsoft.com/devicelogin",
+                    "expires_in": 900,
+                    "interval": 5
+                }"#))
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_device_authorization_response() {
+        let client = client();
+        assert!(client.supports_device_flow());
+
+        let authorization = authorization(&client).await;
+        assert_eq!(authorization.user_code(), "ABCD-EFGH");
+        assert_eq!(authorization.verification_uri(), "https://microsoft.com/devicelogin");
+        assert_eq!(authorization.verification_uri_complete(), None);
+        assert_eq!(authorization.expires_in(), Duration::from_secs(900));
+        assert_eq!(authorization.interval(), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_device_polling_honours_slow_down() {
+        let client = client();
+        let authorization = authorization(&client).await;
+
+        let replies = Arc::new(Mutex::new(vec![
+            (StatusCode::BAD_REQUEST, r#"{"error": "authorization_pending"}"#),
+            (StatusCode::BAD_REQUEST, r#"{"error": "slow_down"}"#),
+            (StatusCode::BAD_REQUEST, r#"{"error": "authorization_pending"}"#),
+            (StatusCode::OK, r#"{"access_token": "at", "refresh_token": "rt", "token_type": "Bearer", "expires_in": 3600}"#),
+        ]));
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+
+        let token = client
+            .poll_device_token_with(
+                &authorization,
+                |request: HttpRequest| {
+                    let replies = replies.clone();
+                    async move {
+                        let body = String::from_utf8(request.body).unwrap();
+                        assert!(body.contains("device_code=device-123"));
+                        let (status, body) = replies.lock().unwrap().remove(0);
+                        Ok::<_, std::io::Error>(json_response(status, body))
+                    }
+                },
+                |duration| {
+                    sleeps.lock().unwrap().push(duration);
+                    async {}
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token.access_token, "at");
+        assert_eq!(token.refresh_token.as_deref(), Some("rt"));
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            vec![Duration::from_secs(5), Duration::from_secs(10), Duration::from_secs(10)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_polling_denied() {
+        let client = client();
+        let authorization = authorization(&client).await;
+
+        let result = client
+            .poll_device_token_with(
+                &authorization,
+                |_request: HttpRequest| async {
+                    Ok::<_, std::io::Error>(json_response(StatusCode::BAD_REQUEST, r#"{"error": "access_denied"}"#))
+                },
+                |_duration| async {},
+            )
+            .await;
+
+        assert!(matches!(result, Err(AsgardError::OAuth(msg)) if msg.contains("denied")));
+    }
+}
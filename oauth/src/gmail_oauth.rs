@@ -1,212 +1,106 @@
 //! Gmail OAuth2 implementation
 
-use crate::{OAuthConfig, AuthorizationUrl, AuthorizationCode, OAuthToken, TokenResponse};
-use asgard_core::error::{AsgardError, AsgardResult};
-use oauth2::{
-    AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, PkceCodeVerifier,
-    RedirectUrl, Scope, TokenResponse as OAuth2TokenResponse,
-};
-use oauth2::basic::BasicClient;
-use oauth2::reqwest::async_http_client;
-use rand::Rng;
-use sha2::{Sha256, Digest};
-use std::collections::HashMap;
-use tokio::net::TcpListener;
-use tokio::sync::oneshot;
-use url::Url;
+use crate::loopback::LoopbackListener;
+use crate::provider::{OAuthClient, OAuthProvider};
+use crate::{OAuthConfig, AuthorizationUrl, AuthorizationCode, OAuthToken};
+use asgard_core::error::AsgardResult;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Google authorization endpoint
+pub const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+
+/// Google token endpoint
+pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// Google identity provider
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GoogleProvider;
+
+impl OAuthProvider for GoogleProvider {
+    fn name(&self) -> &str {
+        "Google"
+    }
+
+    fn authorization_endpoint(&self) -> String {
+        GOOGLE_AUTH_URL.to_string()
+    }
+
+    fn token_endpoint(&self) -> String {
+        GOOGLE_TOKEN_URL.to_string()
+    }
+
+    // No device authorization endpoint: Google's device grant rejects the
+    // Gmail scopes, so Gmail accounts must use the browser flow.
+
+    fn scopes(&self) -> Vec<String> {
+        vec![
+            "https://www.googleapis.com/auth/gmail.readonly".to_string(),
+            "https://www.googleapis.com/auth/gmail.send".to_string(),
+            "https://www.googleapis.com/auth/gmail.modify".to_string(),
+            "https://www.googleapis.com/auth/gmail.labels".to_string(),
+        ]
+    }
+}
 
 /// Gmail OAuth2 client
 pub struct GmailOAuth {
     /// OAuth2 client
-    client: BasicClient,
-    /// Configuration
-    config: OAuthConfig,
+    client: OAuthClient,
 }
 
 impl GmailOAuth {
     /// Create a new Gmail OAuth2 client
     pub fn new(config: OAuthConfig) -> Self {
-        let client_id = ClientId::new(config.client_id.clone());
-        let client_secret = ClientSecret::new(config.client_secret.clone());
-        let redirect_url = RedirectUrl::new(config.redirect_uri.clone())
-            .expect("Invalid redirect URI");
-
-        let client = BasicClient::new(client_id, Some(client_secret), oauth2::AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string()).unwrap(), Some(oauth2::TokenUrl::new("https://oauth2.googleapis.com/token".to_string()).unwrap()))
-            .set_redirect_uri(redirect_url);
-
-        Self { client, config }
+        Self {
+            client: OAuthClient::new(Box::new(GoogleProvider), config),
+        }
     }
 
     /// Generate authorization URL with PKCE
     pub fn get_authorization_url(&self) -> AsgardResult<AuthorizationUrl> {
-        // Generate PKCE code verifier and challenge
-        let code_verifier = PkceCodeVerifier::new_random();
-        let code_challenge = PkceCodeChallenge::from_code_verifier_sha256(&code_verifier);
-
-        // Generate state parameter
-        let state = CsrfToken::new_random();
-
-        // Build authorization URL
-        let (auth_url, _) = self.client
-            .authorize_url(CsrfToken::new(state.secret().clone()))
-            .add_scope(Scope::new("https://www.googleapis.com/auth/gmail.readonly".to_string()))
-            .add_scope(Scope::new("https://www.googleapis.com/auth/gmail.send".to_string()))
-            .add_scope(Scope::new("https://www.googleapis.com/auth/gmail.modify".to_string()))
-            .add_scope(Scope::new("https://www.googleapis.com/auth/gmail.labels".to_string()))
-            .set_pkce_challenge(code_challenge)
-            .url();
-
-        Ok(AuthorizationUrl {
-            url: auth_url.to_string(),
-            state: state.secret().clone(),
-            code_verifier: code_verifier.secret().clone(),
-        })
+        self.client.get_authorization_url()
     }
 
     /// Exchange authorization code for access token
     pub async fn exchange_code(&self, auth_code: &AuthorizationCode, code_verifier: &str) -> AsgardResult<OAuthToken> {
-        let code = AuthorizationCode::new(auth_code.code.clone());
-        let pkce_verifier = PkceCodeVerifier::new(code_verifier.to_string());
-
-        let token_response = self.client
-            .exchange_code(code)
-            .set_pkce_verifier(pkce_verifier)
-            .request_async(async_http_client)
-            .await
-            .map_err(|e| AsgardError::oauth(e))?;
-
-        let access_token = token_response.access_token().secret().clone();
-        let refresh_token = token_response.refresh_token().map(|t| t.secret().clone());
-        let token_type = token_response.token_type().and_then(|t| t.as_str()).unwrap_or("Bearer").to_string();
-        let expires_in = token_response.expires_in();
-        let scope = token_response.scopes().map(|scopes| {
-            scopes.iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<_>>()
-                .join(" ")
-        });
-
-        let expires_at = expires_in.map(|duration| {
-            time::OffsetDateTime::now_utc() + time::Duration::seconds(duration.as_secs() as i64)
-        });
-
-        Ok(OAuthToken {
-            access_token,
-            refresh_token,
-            token_type,
-            expires_at,
-            scope,
-        })
+        self.client.exchange_code(auth_code, code_verifier).await
     }
 
     /// Refresh access token
     pub async fn refresh_token(&self, refresh_token: &str) -> AsgardResult<OAuthToken> {
-        let refresh_token = oauth2::RefreshToken::new(refresh_token.to_string());
-
-        let token_response = self.client
-            .exchange_refresh_token(&refresh_token)
-            .request_async(async_http_client)
-            .await
-            .map_err(|e| AsgardError::oauth(e))?;
-
-        let access_token = token_response.access_token().secret().clone();
-        let new_refresh_token = token_response.refresh_token().map(|t| t.secret().clone());
-        let token_type = token_response.token_type().and_then(|t| t.as_str()).unwrap_or("Bearer").to_string();
-        let expires_in = token_response.expires_in();
-        let scope = token_response.scopes().map(|scopes| {
-            scopes.iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<_>>()
-                .join(" ")
-        });
-
-        let expires_at = expires_in.map(|duration| {
-            time::OffsetDateTime::now_utc() + time::Duration::seconds(duration.as_secs() as i64)
-        });
-
-        Ok(OAuthToken {
-            access_token,
-            refresh_token: new_refresh_token.or(Some(refresh_token.secret().clone())),
-            token_type,
-            expires_at,
-            scope,
-        })
+        self.client.refresh_token(refresh_token).await
     }
 
-    /// Start local server to handle OAuth callback
-    pub async fn start_callback_server(&self, port: u16) -> AsgardResult<oneshot::Receiver<AuthorizationCode>> {
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
-        let (tx, rx) = oneshot::channel();
-
-        tokio::spawn(async move {
-            if let Ok((stream, _)) = listener.accept().await {
-                let mut stream = tokio::io::BufReader::new(stream);
-                let mut request_line = String::new();
-                
-                if let Ok(_) = tokio::io::AsyncBufReadExt::read_line(&mut stream, &mut request_line).await {
-                    if let Some((method, path)) = request_line.split_once(' ') {
-                        if method == "GET" && path.starts_with("/?") {
-                            let query_string = &path[2..];
-                            let params: HashMap<String, String> = url::form_urlencoded::parse(query_string.as_bytes())
-                                .into_owned()
-                                .collect();
-
-                            if let (Some(code), Some(state)) = (params.get("code"), params.get("state")) {
-                                let auth_code = AuthorizationCode {
-                                    code: code.clone(),
-                                    state: state.clone(),
-                                };
-
-                                // Send success response
-                                let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><body><h1>Authorization successful!</h1><p>You can close this window and return to Asgard Mail.</p></body></html>";
-                                let _ = stream.write_all(response.as_bytes()).await;
-
-                                let _ = tx.send(auth_code);
-                                return;
-                            }
-                        }
-                    }
-                }
-
-                // Send error response
-                let response = "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html\r\n\r\n<html><body><h1>Authorization failed!</h1><p>Please try again.</p></body></html>";
-                let _ = stream.write_all(response.as_bytes()).await;
-            }
-        });
+    /// Exchange the code returned for `auth_url`, checking its state
+    pub async fn exchange_authorization(&self, auth_url: &AuthorizationUrl, auth_code: &AuthorizationCode) -> AsgardResult<OAuthToken> {
+        self.client.exchange_authorization(auth_url, auth_code).await
+    }
 
-        Ok(rx)
+    /// Start a loopback listener for the OAuth callback
+    pub async fn start_callback_server(&self) -> AsgardResult<LoopbackListener> {
+        self.client.start_callback_server().await
     }
 
     /// Open authorization URL in default browser
     pub fn open_authorization_url(&self, auth_url: &AuthorizationUrl) -> AsgardResult<()> {
-        open::that(&auth_url.url)?;
-        Ok(())
+        self.client.open_authorization_url(auth_url)
     }
 
     /// Complete OAuth flow
     pub async fn complete_oauth_flow(&self) -> AsgardResult<OAuthToken> {
-        // Generate authorization URL
-        let auth_url = self.get_authorization_url()?;
-
-        // Start callback server
-        let callback_rx = self.start_callback_server(8080).await?;
-
-        // Open authorization URL in browser
-        self.open_authorization_url(&auth_url)?;
-
-        // Wait for callback
-        let auth_code = callback_rx.await
-            .map_err(|_| AsgardError::oauth("OAuth callback failed"))?;
-
-        // Verify state parameter
-        if auth_code.state != auth_url.state {
-            return Err(AsgardError::oauth("Invalid state parameter"));
-        }
+        self.client.complete_oauth_flow().await
+    }
 
-        // Exchange code for token
-        let token = self.exchange_code(&auth_code, &auth_url.code_verifier).await?;
+    /// Complete OAuth flow with a custom timeout and cancellation token
+    pub async fn complete_oauth_flow_with(&self, timeout: Duration, cancel: CancellationToken) -> AsgardResult<OAuthToken> {
+        self.client.complete_oauth_flow_with(timeout, cancel).await
+    }
+}
 
-        Ok(token)
+impl AsRef<OAuthClient> for GmailOAuth {
+    fn as_ref(&self) -> &OAuthClient {
+        &self.client
     }
 }
 
@@ -224,7 +118,7 @@ mod tests {
         };
 
         let oauth = GmailOAuth::new(config);
-        assert!(oauth.client.client_id().secret() == "test-client-id");
+        assert_eq!(oauth.client.client_id().as_str(), "test-client-id");
     }
 
     #[test]
@@ -1,9 +1,17 @@
 //! OAuth2 implementation for Asgard Mail
 
+pub mod device_flow;
 pub mod gmail_oauth;
+pub mod loopback;
+pub mod microsoft_oauth;
+pub mod provider;
 pub mod token_manager;
 
-pub use gmail_oauth::GmailOAuth;
+pub use device_flow::DeviceAuthorization;
+pub use gmail_oauth::{GmailOAuth, GoogleProvider};
+pub use loopback::LoopbackListener;
+pub use microsoft_oauth::{MicrosoftOAuth, MicrosoftProvider};
+pub use provider::{OAuthClient, OAuthProvider};
 pub use token_manager::TokenManager;
 
 /// OAuth2 configuration
@@ -54,10 +62,12 @@ pub struct AuthorizationUrl {
     pub state: String,
     /// Code verifier (for PKCE)
     pub code_verifier: String,
+    /// Redirect URI the authorization request was made with
+    pub redirect_uri: String,
 }
 
 /// OAuth2 authorization code
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AuthorizationCode {
     /// Authorization code
     pub code: String,
@@ -0,0 +1,319 @@
+//! Loopback redirect listener for the authorization code flow
+//!
+//! Each flow binds its own ephemeral port on 127.0.0.1, so concurrent
+//! sign-ins never collide and nothing depends on a fixed port being free.
+
+use crate::AuthorizationCode;
+use asgard_core::error::{AsgardError, AsgardResult};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// How long to wait for the browser to come back by default
+pub const DEFAULT_CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest request line accepted from the browser
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+
+/// Listener waiting for the OAuth redirect on an ephemeral loopback port
+pub struct LoopbackListener {
+    /// Bound listener
+    listener: TcpListener,
+    /// Redirect URI pointing at the listener
+    redirect_uri: String,
+}
+
+impl LoopbackListener {
+    /// Bind a listener on 127.0.0.1 with a port chosen by the OS
+    pub async fn bind() -> AsgardResult<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+
+        Ok(Self {
+            listener,
+            redirect_uri: format!("http://127.0.0.1:{}/", port),
+        })
+    }
+
+    /// Redirect URI to put in the authorization request
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Port the listener is bound to
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|addr| addr.port()).unwrap_or(0)
+    }
+
+    /// Wait for the redirect carrying `expected_state`
+    ///
+    /// Requests for other paths (e.g. `/favicon.ico`) are answered with 404
+    /// and ignored. A redirect with an error, a missing code or a state that
+    /// does not match ends the flow with an error page and an error.
+    pub async fn wait_for_code(
+        self,
+        expected_state: &str,
+        timeout: Duration,
+        cancel: CancellationToken,
+    ) -> AsgardResult<AuthorizationCode> {
+        let wait = async {
+            loop {
+                let (stream, _) = self.listener.accept().await?;
+                if let Some(result) = handle_connection(stream, expected_state).await? {
+                    return result;
+                }
+            }
+        };
+
+        tokio::select! {
+            result = tokio::time::timeout(timeout, wait) => {
+                result.map_err(|_| AsgardError::timeout("Timed out waiting for the authorization response"))?
+            }
+            _ = cancel.cancelled() => Err(AsgardError::OAuth("Authorization was cancelled".to_string())),
+        }
+    }
+}
+
+/// Outcome of a redirect request
+#[derive(Debug, PartialEq, Eq)]
+enum Callback {
+    /// Not the redirect, keep waiting
+    Ignored,
+    /// Authorization code with a matching state
+    Code(AuthorizationCode),
+    /// The flow failed
+    Failed(String),
+}
+
+/// Handle one browser connection; `None` means keep waiting
+async fn handle_connection(stream: TcpStream, expected_state: &str) -> AsgardResult<Option<AsgardResult<AuthorizationCode>>> {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    let read = (&mut stream).take(MAX_REQUEST_LINE as u64).read_line(&mut request_line).await;
+    if read.is_err() {
+        return Ok(None);
+    }
+
+    let (status, page, result) = match parse_callback(&request_line, expected_state) {
+        Callback::Ignored => {
+            debug!("Ignoring loopback request: {}", request_line.trim_end());
+            ("404 Not Found", String::new(), None)
+        }
+        Callback::Code(code) => (
+            "200 OK",
+            callback_page(true, "You're signed in", "You can close this window and return to Asgard Mail."),
+            Some(Ok(code)),
+        ),
+        Callback::Failed(reason) => (
+            "400 Bad Request",
+            callback_page(false, "Sign-in failed", &reason),
+            Some(Err(AsgardError::OAuth(reason))),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        page.len(),
+        page
+    );
+    let mut stream = stream.into_inner();
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+
+    Ok(result)
+}
+
+/// Parse the request line of a redirect
+fn parse_callback(request_line: &str, expected_state: &str) -> Callback {
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Callback::Ignored;
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/" {
+        return Callback::Ignored;
+    }
+
+    let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    if let Some(error) = params.get("error") {
+        let description = params.get("error_description").map(String::as_str).unwrap_or(error);
+        return Callback::Failed(format!("The provider returned an error: {}", description));
+    }
+
+    match (params.get("code"), params.get("state")) {
+        (Some(code), Some(state)) if constant_time_eq(state.as_bytes(), expected_state.as_bytes()) => {
+            Callback::Code(AuthorizationCode {
+                code: code.clone(),
+                state: state.clone(),
+            })
+        }
+        (Some(_), _) => Callback::Failed("The response did not match this sign-in request".to_string()),
+        (None, _) if params.is_empty() => Callback::Ignored,
+        (None, _) => Callback::Failed("The response did not contain an authorization code".to_string()),
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Render the page shown in the browser after the redirect
+fn callback_page(success: bool, title: &str, message: &str) -> String {
+    let (icon, accent) = if success { ("✓", "#26a269") } else { ("✕", "#c01c28") };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} — Asgard Mail</title>
+<style>
+  :root {{ color-scheme: light dark; }}
+  body {{ margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center;
+         font-family: "Cantarell", "Inter", system-ui, sans-serif; background: #fafafa; color: #2e3436; }}
+  main {{ max-width: 420px; padding: 48px 40px; text-align: center; background: #ffffff;
+         border-radius: 12px; box-shadow: 0 2px 12px rgba(0, 0, 0, 0.12); }}
+  .icon {{ width: 64px; height: 64px; margin: 0 auto 24px; border-radius: 50%; background: {accent};
+          color: #ffffff; font-size: 36px; line-height: 64px; }}
+  h1 {{ margin: 0 0 12px; font-size: 22px; }}
+  p {{ margin: 0; opacity: 0.8; line-height: 1.5; }}
+  @media (prefers-color-scheme: dark) {{
+    body {{ background: #242424; color: #ffffff; }}
+    main {{ background: #303030; box-shadow: none; }}
+  }}
+</style>
+</head>
+<body>
+<main>
+  <div class="icon">{icon}</div>
+  <h1>{title}</h1>
+  <p>{message}</p>
+</main>
+</body>
+</html>
+"#,
+        title = escape_html(title),
+        message = escape_html(message),
+        icon = icon,
+        accent = accent,
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_parse_callback() {
+        assert_eq!(
+            parse_callback("GET /?code=abc&state=xyz HTTP/1.1\r\n", "xyz"),
+            Callback::Code(AuthorizationCode { code: "abc".to_string(), state: "xyz".to_string() })
+        );
+        assert_eq!(parse_callback("GET /favicon.ico HTTP/1.1\r\n", "xyz"), Callback::Ignored);
+        assert_eq!(parse_callback("GET / HTTP/1.1\r\n", "xyz"), Callback::Ignored);
+        assert_eq!(parse_callback("POST /?code=abc&state=xyz HTTP/1.1\r\n", "xyz"), Callback::Ignored);
+        assert!(matches!(parse_callback("GET /?code=abc&state=evil HTTP/1.1\r\n", "xyz"), Callback::Failed(_)));
+        assert!(matches!(parse_callback("GET /?code=abc HTTP/1.1\r\n", "xyz"), Callback::Failed(_)));
+        assert!(matches!(
+            parse_callback("GET /?error=access_denied&error_description=User%20declined HTTP/1.1\r\n", "xyz"),
+            Callback::Failed(reason) if reason.contains("User declined")
+        ));
+    }
+
+    #[test]
+    fn test_callback_page_escapes_message() {
+        let page = callback_page(false, "Sign-in failed", "<script>alert(1)</script>");
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(!page.contains("<script>"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_listeners_use_distinct_ports() {
+        let first = LoopbackListener::bind().await.unwrap();
+        let second = LoopbackListener::bind().await.unwrap();
+
+        assert_ne!(first.port(), 0);
+        assert_ne!(first.port(), second.port());
+        assert_eq!(first.redirect_uri(), format!("http://127.0.0.1:{}/", first.port()));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_code() {
+        let listener = LoopbackListener::bind().await.unwrap();
+        let port = listener.port();
+        let wait = tokio::spawn(listener.wait_for_code("xyz", Duration::from_secs(5), CancellationToken::new()));
+
+        let favicon = get(port, "/favicon.ico").await;
+        assert!(favicon.starts_with("HTTP/1.1 404"));
+
+        let response = get(port, "/?code=abc&state=xyz").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("signed in"));
+
+        let code = wait.await.unwrap().unwrap();
+        assert_eq!(code.code, "abc");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_code_rejects_state_mismatch() {
+        let listener = LoopbackListener::bind().await.unwrap();
+        let port = listener.port();
+        let wait = tokio::spawn(listener.wait_for_code("xyz", Duration::from_secs(5), CancellationToken::new()));
+
+        let response = get(port, "/?code=abc&state=forged").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response.contains("Sign-in failed"));
+        assert!(matches!(wait.await.unwrap(), Err(AsgardError::OAuth(_))));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_code_timeout_and_cancel() {
+        let listener = LoopbackListener::bind().await.unwrap();
+        let result = listener.wait_for_code("xyz", Duration::from_millis(20), CancellationToken::new()).await;
+        assert!(matches!(result, Err(AsgardError::Timeout(_))));
+
+        let listener = LoopbackListener::bind().await.unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = listener.wait_for_code("xyz", DEFAULT_CALLBACK_TIMEOUT, cancel).await;
+        assert!(matches!(result, Err(AsgardError::OAuth(msg)) if msg.contains("cancelled")));
+    }
+}
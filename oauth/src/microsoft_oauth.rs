@@ -0,0 +1,214 @@
+//! Microsoft identity platform (Microsoft 365 / Outlook) OAuth2 implementation
+
+use crate::device_flow::DeviceAuthorization;
+use crate::loopback::LoopbackListener;
+use crate::provider::{OAuthClient, OAuthProvider};
+use crate::{OAuthConfig, AuthorizationUrl, AuthorizationCode, OAuthToken};
+use asgard_core::error::AsgardResult;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Microsoft identity platform login host
+pub const MICROSOFT_LOGIN_URL: &str = "https://login.microsoftonline.com";
+
+/// Tenant accepting both work/school and personal Microsoft accounts
+pub const COMMON_TENANT: &str = "common";
+
+/// Scope required to receive a refresh token
+pub const OFFLINE_ACCESS_SCOPE: &str = "offline_access";
+
+/// Scope for IMAP access via XOAUTH2
+pub const IMAP_SCOPE: &str = "https://outlook.office.com/IMAP.AccessAsUser.All";
+
+/// Scope for SMTP AUTH submission via XOAUTH2
+pub const SMTP_SCOPE: &str = "https://outlook.office.com/SMTP.Send";
+
+/// Microsoft identity platform (v2.0 endpoints)
+#[derive(Debug, Clone)]
+pub struct MicrosoftProvider {
+    /// Tenant: `common`, `organizations`, `consumers`, a domain or a tenant ID
+    tenant: String,
+}
+
+impl MicrosoftProvider {
+    /// Create a provider for a specific tenant
+    pub fn new(tenant: impl Into<String>) -> Self {
+        Self { tenant: tenant.into() }
+    }
+
+    /// Get the tenant
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+}
+
+impl Default for MicrosoftProvider {
+    fn default() -> Self {
+        Self::new(COMMON_TENANT)
+    }
+}
+
+impl OAuthProvider for MicrosoftProvider {
+    fn name(&self) -> &str {
+        "Microsoft"
+    }
+
+    fn authorization_endpoint(&self) -> String {
+        format!("{}/{}/oauth2/v2.0/authorize", MICROSOFT_LOGIN_URL, self.tenant)
+    }
+
+    fn token_endpoint(&self) -> String {
+        format!("{}/{}/oauth2/v2.0/token", MICROSOFT_LOGIN_URL, self.tenant)
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec![
+            OFFLINE_ACCESS_SCOPE.to_string(),
+            IMAP_SCOPE.to_string(),
+            SMTP_SCOPE.to_string(),
+        ]
+    }
+
+    fn device_authorization_endpoint(&self) -> Option<String> {
+        Some(format!("{}/{}/oauth2/v2.0/devicecode", MICROSOFT_LOGIN_URL, self.tenant))
+    }
+}
+
+/// Microsoft 365 OAuth2 client
+///
+/// Tokens issued here are used with XOAUTH2 against outlook.office365.com
+/// (IMAP) and smtp.office365.com (SMTP).
+pub struct MicrosoftOAuth {
+    /// OAuth2 client
+    client: OAuthClient,
+}
+
+impl MicrosoftOAuth {
+    /// Create a new Microsoft OAuth2 client for the `common` tenant
+    pub fn new(config: OAuthConfig) -> Self {
+        Self::with_tenant(config, COMMON_TENANT)
+    }
+
+    /// Create a new Microsoft OAuth2 client for a specific tenant
+    pub fn with_tenant(config: OAuthConfig, tenant: impl Into<String>) -> Self {
+        Self {
+            client: OAuthClient::new(Box::new(MicrosoftProvider::new(tenant)), config),
+        }
+    }
+
+    /// Generate authorization URL with PKCE
+    pub fn get_authorization_url(&self) -> AsgardResult<AuthorizationUrl> {
+        self.client.get_authorization_url()
+    }
+
+    /// Exchange authorization code for access token
+    pub async fn exchange_code(&self, auth_code: &AuthorizationCode, code_verifier: &str) -> AsgardResult<OAuthToken> {
+        self.client.exchange_code(auth_code, code_verifier).await
+    }
+
+    /// Refresh access token
+    ///
+    /// Microsoft rotates refresh tokens, so the returned token should always
+    /// replace the stored one.
+    pub async fn refresh_token(&self, refresh_token: &str) -> AsgardResult<OAuthToken> {
+        self.client.refresh_token(refresh_token).await
+    }
+
+    /// Exchange the code returned for `auth_url`, checking its state
+    pub async fn exchange_authorization(&self, auth_url: &AuthorizationUrl, auth_code: &AuthorizationCode) -> AsgardResult<OAuthToken> {
+        self.client.exchange_authorization(auth_url, auth_code).await
+    }
+
+    /// Start a loopback listener for the OAuth callback
+    pub async fn start_callback_server(&self) -> AsgardResult<LoopbackListener> {
+        self.client.start_callback_server().await
+    }
+
+    /// Open authorization URL in default browser
+    pub fn open_authorization_url(&self, auth_url: &AuthorizationUrl) -> AsgardResult<()> {
+        self.client.open_authorization_url(auth_url)
+    }
+
+    /// Complete OAuth flow
+    pub async fn complete_oauth_flow(&self) -> AsgardResult<OAuthToken> {
+        self.client.complete_oauth_flow().await
+    }
+
+    /// Complete OAuth flow with a custom timeout and cancellation token
+    pub async fn complete_oauth_flow_with(&self, timeout: Duration, cancel: CancellationToken) -> AsgardResult<OAuthToken> {
+        self.client.complete_oauth_flow_with(timeout, cancel).await
+    }
+
+    /// Request a device code for signing in from another device
+    pub async fn start_device_authorization(&self) -> AsgardResult<DeviceAuthorization> {
+        self.client.start_device_authorization().await
+    }
+
+    /// Poll until the device code is approved
+    pub async fn poll_device_token(&self, authorization: &DeviceAuthorization) -> AsgardResult<OAuthToken> {
+        self.client.poll_device_token(authorization).await
+    }
+
+    /// Complete OAuth flow using the device authorization grant
+    pub async fn complete_device_flow<F>(&self, on_code: F) -> AsgardResult<OAuthToken>
+    where
+        F: FnOnce(&DeviceAuthorization),
+    {
+        self.client.complete_device_flow(on_code).await
+    }
+}
+
+impl AsRef<OAuthClient> for MicrosoftOAuth {
+    fn as_ref(&self) -> &OAuthClient {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OAuthConfig {
+        OAuthConfig {
+            client_id: "test-client-id".to_string(),
+            client_secret: String::new(),
+            redirect_uri: "http://localhost:8080".to_string(),
+            scopes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_microsoft_endpoints() {
+        let provider = MicrosoftProvider::default();
+        assert_eq!(provider.tenant(), "common");
+        assert_eq!(
+            provider.authorization_endpoint(),
+            "https://login.microsoftonline.com/common/oauth2/v2.0/authorize"
+        );
+        assert_eq!(
+            provider.token_endpoint(),
+            "https://login.microsoftonline.com/common/oauth2/v2.0/token"
+        );
+
+        assert_eq!(
+            provider.device_authorization_endpoint().as_deref(),
+            Some("https://login.microsoftonline.com/common/oauth2/v2.0/devicecode")
+        );
+
+        let provider = MicrosoftProvider::new("contoso.onmicrosoft.com");
+        assert!(provider.token_endpoint().contains("/contoso.onmicrosoft.com/oauth2/v2.0/"));
+    }
+
+    #[test]
+    fn test_microsoft_authorization_url() {
+        let oauth = MicrosoftOAuth::with_tenant(config(), "organizations");
+        let auth_url = oauth.get_authorization_url().unwrap();
+
+        assert!(auth_url.url.starts_with("https://login.microsoftonline.com/organizations/oauth2/v2.0/authorize?"));
+        assert!(auth_url.url.contains("client_id=test-client-id"));
+        assert!(auth_url.url.contains("code_challenge_method=S256"));
+        assert!(auth_url.url.contains("offline_access"));
+        assert!(auth_url.url.contains("IMAP.AccessAsUser.All"));
+        assert!(auth_url.url.contains("SMTP.Send"));
+    }
+}
@@ -0,0 +1,323 @@
+//! OAuth2 provider abstraction
+//!
+//! An [`OAuthProvider`] describes an identity platform (endpoints and the
+//! scopes it needs for mail access); [`OAuthClient`] runs the authorization
+//! code + PKCE flow against any provider.
+
+use crate::loopback::{constant_time_eq, LoopbackListener, DEFAULT_CALLBACK_TIMEOUT};
+use crate::{AuthorizationCode, AuthorizationUrl, OAuthConfig, OAuthToken};
+use asgard_core::error::{AsgardError, AsgardResult};
+use oauth2::basic::{BasicClient, BasicTokenResponse};
+use oauth2::reqwest::async_http_client;
+use oauth2::{
+    AuthUrl, ClientId, ClientSecret, CsrfToken, DeviceAuthorizationUrl, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, TokenResponse as OAuth2TokenResponse,
+    TokenUrl,
+};
+use std::borrow::Cow;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// An OAuth2 identity provider
+pub trait OAuthProvider: Send + Sync {
+    /// Human-readable provider name
+    fn name(&self) -> &str;
+
+    /// Authorization endpoint URL
+    fn authorization_endpoint(&self) -> String;
+
+    /// Token endpoint URL
+    fn token_endpoint(&self) -> String;
+
+    /// Scopes always requested from this provider
+    fn scopes(&self) -> Vec<String>;
+
+    /// Device authorization endpoint (RFC 8628), if the provider offers one
+    fn device_authorization_endpoint(&self) -> Option<String> {
+        None
+    }
+}
+
+/// OAuth2 client for a specific provider
+pub struct OAuthClient {
+    /// OAuth2 client
+    pub(crate) client: BasicClient,
+    /// Configuration
+    config: OAuthConfig,
+    /// Identity provider
+    provider: Box<dyn OAuthProvider>,
+}
+
+impl OAuthClient {
+    /// Create a new OAuth2 client for the given provider
+    ///
+    /// An empty client secret registers a public client, which is what
+    /// desktop apps on the Microsoft identity platform are.
+    pub fn new(provider: Box<dyn OAuthProvider>, config: OAuthConfig) -> Self {
+        let client_id = ClientId::new(config.client_id.clone());
+        let client_secret = if config.client_secret.is_empty() {
+            None
+        } else {
+            Some(ClientSecret::new(config.client_secret.clone()))
+        };
+        let auth_url = AuthUrl::new(provider.authorization_endpoint())
+            .expect("Invalid authorization endpoint");
+        let token_url = TokenUrl::new(provider.token_endpoint())
+            .expect("Invalid token endpoint");
+        let redirect_url = RedirectUrl::new(config.redirect_uri.clone())
+            .expect("Invalid redirect URI");
+
+        let mut client = BasicClient::new(client_id, client_secret, auth_url, Some(token_url))
+            .set_redirect_uri(redirect_url);
+        if let Some(endpoint) = provider.device_authorization_endpoint() {
+            let device_url = DeviceAuthorizationUrl::new(endpoint)
+                .expect("Invalid device authorization endpoint");
+            client = client.set_device_authorization_url(device_url);
+        }
+
+        Self { client, config, provider }
+    }
+
+    /// Get the provider this client talks to
+    pub fn provider(&self) -> &dyn OAuthProvider {
+        self.provider.as_ref()
+    }
+
+    /// Get the client ID
+    pub fn client_id(&self) -> &ClientId {
+        self.client.client_id()
+    }
+
+    /// Scopes to request: the provider's own plus any extra from the config
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes = self.provider.scopes();
+        for scope in &self.config.scopes {
+            if !scopes.contains(scope) {
+                scopes.push(scope.clone());
+            }
+        }
+        scopes
+    }
+
+    /// Generate authorization URL with PKCE for the configured redirect URI
+    pub fn get_authorization_url(&self) -> AsgardResult<AuthorizationUrl> {
+        self.get_authorization_url_for(&self.config.redirect_uri)
+    }
+
+    /// Generate authorization URL with PKCE for a specific redirect URI
+    ///
+    /// Used with a [`LoopbackListener`], whose port is only known once it
+    /// has been bound.
+    pub fn get_authorization_url_for(&self, redirect_uri: &str) -> AsgardResult<AuthorizationUrl> {
+        let redirect_url = RedirectUrl::new(redirect_uri.to_string())
+            .map_err(|e| AsgardError::OAuth(format!("Invalid redirect URI: {}", e)))?;
+        let (code_challenge, code_verifier) = PkceCodeChallenge::new_random_sha256();
+        let state = CsrfToken::new_random();
+
+        let (auth_url, _) = self.client
+            .authorize_url(|| CsrfToken::new(state.secret().clone()))
+            .add_scopes(self.scopes().into_iter().map(Scope::new))
+            .set_pkce_challenge(code_challenge)
+            .set_redirect_uri(Cow::Owned(redirect_url))
+            .url();
+
+        Ok(AuthorizationUrl {
+            url: auth_url.to_string(),
+            state: state.secret().clone(),
+            code_verifier: code_verifier.secret().clone(),
+            redirect_uri: redirect_uri.to_string(),
+        })
+    }
+
+    /// Exchange authorization code for access token
+    pub async fn exchange_code(&self, auth_code: &AuthorizationCode, code_verifier: &str) -> AsgardResult<OAuthToken> {
+        let code = oauth2::AuthorizationCode::new(auth_code.code.clone());
+        let pkce_verifier = PkceCodeVerifier::new(code_verifier.to_string());
+
+        let token_response = self.client
+            .exchange_code(code)
+            .set_pkce_verifier(pkce_verifier)
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| AsgardError::OAuth(e.to_string()))?;
+
+        Ok(token_from_response(&token_response, None))
+    }
+
+    /// Exchange the code returned for `auth_url`
+    ///
+    /// Checks the state against the one sent and uses the PKCE verifier and
+    /// redirect URI of that request.
+    pub async fn exchange_authorization(&self, auth_url: &AuthorizationUrl, auth_code: &AuthorizationCode) -> AsgardResult<OAuthToken> {
+        if !constant_time_eq(auth_code.state.as_bytes(), auth_url.state.as_bytes()) {
+            return Err(AsgardError::OAuth("Invalid state parameter".to_string()));
+        }
+
+        let redirect_url = RedirectUrl::new(auth_url.redirect_uri.clone())
+            .map_err(|e| AsgardError::OAuth(format!("Invalid redirect URI: {}", e)))?;
+        let code = oauth2::AuthorizationCode::new(auth_code.code.clone());
+        let pkce_verifier = PkceCodeVerifier::new(auth_url.code_verifier.clone());
+
+        let token_response = self.client
+            .exchange_code(code)
+            .set_pkce_verifier(pkce_verifier)
+            .set_redirect_uri(Cow::Owned(redirect_url))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| AsgardError::OAuth(e.to_string()))?;
+
+        Ok(token_from_response(&token_response, None))
+    }
+
+    /// Refresh access token
+    ///
+    /// Providers that rotate refresh tokens return a new one; otherwise the
+    /// existing refresh token is kept.
+    pub async fn refresh_token(&self, refresh_token: &str) -> AsgardResult<OAuthToken> {
+        let refresh_token = RefreshToken::new(refresh_token.to_string());
+
+        let token_response = self.client
+            .exchange_refresh_token(&refresh_token)
+            .add_scopes(self.scopes().into_iter().map(Scope::new))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| AsgardError::OAuth(e.to_string()))?;
+
+        Ok(token_from_response(&token_response, Some(refresh_token.secret())))
+    }
+
+    /// Start a loopback listener for the OAuth callback on an ephemeral port
+    pub async fn start_callback_server(&self) -> AsgardResult<LoopbackListener> {
+        LoopbackListener::bind().await
+    }
+
+    /// Open authorization URL in default browser
+    pub fn open_authorization_url(&self, auth_url: &AuthorizationUrl) -> AsgardResult<()> {
+        open::that(&auth_url.url)?;
+        Ok(())
+    }
+
+    /// Complete OAuth flow, giving the user five minutes to sign in
+    pub async fn complete_oauth_flow(&self) -> AsgardResult<OAuthToken> {
+        self.complete_oauth_flow_with(DEFAULT_CALLBACK_TIMEOUT, CancellationToken::new()).await
+    }
+
+    /// Complete OAuth flow with a custom timeout and cancellation token
+    pub async fn complete_oauth_flow_with(&self, timeout: Duration, cancel: CancellationToken) -> AsgardResult<OAuthToken> {
+        let listener = self.start_callback_server().await?;
+        let auth_url = self.get_authorization_url_for(listener.redirect_uri())?;
+        self.open_authorization_url(&auth_url)?;
+
+        let auth_code = listener.wait_for_code(&auth_url.state, timeout, cancel).await?;
+        self.exchange_authorization(&auth_url, &auth_code).await
+    }
+}
+
+impl AsRef<OAuthClient> for OAuthClient {
+    fn as_ref(&self) -> &OAuthClient {
+        self
+    }
+}
+
+/// Convert a token endpoint response into an [`OAuthToken`]
+pub(crate) fn token_from_response(response: &BasicTokenResponse, previous_refresh_token: Option<&String>) -> OAuthToken {
+    let scope = response.scopes().map(|scopes| {
+        scopes.iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+
+    let expires_at = response.expires_in().map(|duration| {
+        time::OffsetDateTime::now_utc() + time::Duration::seconds(duration.as_secs() as i64)
+    });
+
+    OAuthToken {
+        access_token: response.access_token().secret().clone(),
+        refresh_token: response.refresh_token()
+            .map(|t| t.secret().clone())
+            .or_else(|| previous_refresh_token.cloned()),
+        token_type: response.token_type().as_ref().to_string(),
+        expires_at,
+        scope,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestProvider;
+
+    impl OAuthProvider for TestProvider {
+        fn name(&self) -> &str {
+            "Test"
+        }
+
+        fn authorization_endpoint(&self) -> String {
+            "https://id.example.com/authorize".to_string()
+        }
+
+        fn token_endpoint(&self) -> String {
+            "https://id.example.com/token".to_string()
+        }
+
+        fn scopes(&self) -> Vec<String> {
+            vec!["offline_access".to_string(), "mail".to_string()]
+        }
+    }
+
+    fn config(scopes: Vec<String>) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "test-client-id".to_string(),
+            client_secret: String::new(),
+            redirect_uri: "http://127.0.0.1:8080".to_string(),
+            scopes,
+        }
+    }
+
+    #[test]
+    fn test_scopes_merge_provider_and_config() {
+        let client = OAuthClient::new(
+            Box::new(TestProvider),
+            config(vec!["mail".to_string(), "calendar".to_string()]),
+        );
+
+        assert_eq!(client.scopes(), vec!["offline_access", "mail", "calendar"]);
+    }
+
+    #[test]
+    fn test_authorization_url_uses_provider_endpoint() {
+        let client = OAuthClient::new(Box::new(TestProvider), config(Vec::new()));
+        let auth_url = client.get_authorization_url().unwrap();
+
+        assert!(auth_url.url.starts_with("https://id.example.com/authorize?"));
+        assert!(auth_url.url.contains("code_challenge_method=S256"));
+        assert!(auth_url.url.contains("scope=offline_access+mail"));
+        assert!(auth_url.url.contains(&format!("state={}", auth_url.state)));
+        assert_eq!(client.provider().name(), "Test");
+        assert_eq!(auth_url.redirect_uri, "http://127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_authorization_url_for_loopback_redirect() {
+        let client = OAuthClient::new(Box::new(TestProvider), config(Vec::new()));
+        let auth_url = client.get_authorization_url_for("http://127.0.0.1:49152/").unwrap();
+
+        assert!(auth_url.url.contains("redirect_uri=http%3A%2F%2F127.0.0.1%3A49152%2F"));
+        assert_eq!(auth_url.redirect_uri, "http://127.0.0.1:49152/");
+    }
+
+    #[tokio::test]
+    async fn test_exchange_rejects_state_mismatch() {
+        let client = OAuthClient::new(Box::new(TestProvider), config(Vec::new()));
+        let auth_url = client.get_authorization_url().unwrap();
+        let auth_code = AuthorizationCode {
+            code: "code".to_string(),
+            state: "forged".to_string(),
+        };
+
+        let result = client.exchange_authorization(&auth_url, &auth_code).await;
+        assert!(matches!(result, Err(AsgardError::OAuth(msg)) if msg.contains("state")));
+    }
+}
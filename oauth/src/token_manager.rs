@@ -186,7 +186,7 @@ impl TokenManager {
     }
 
     /// Refresh a token if it's expired or about to expire
-    pub async fn refresh_token_if_needed(&self, account_id: Uuid, oauth_client: &crate::GmailOAuth) -> AsgardResult<Option<OAuthToken>> {
+    pub async fn refresh_token_if_needed(&self, account_id: Uuid, oauth_client: &impl AsRef<crate::OAuthClient>) -> AsgardResult<Option<OAuthToken>> {
         let token = match self.get_token(account_id).await? {
             Some(token) => token,
             None => return Ok(None),
@@ -194,7 +194,7 @@ impl TokenManager {
 
         if token.is_expired() || token.expires_soon() {
             if let Some(refresh_token) = &token.refresh_token {
-                let new_token = oauth_client.refresh_token(refresh_token).await?;
+                let new_token = oauth_client.as_ref().refresh_token(refresh_token).await?;
                 self.store_token(account_id, new_token.clone()).await?;
                 Ok(Some(new_token))
             } else {